@@ -0,0 +1,12 @@
+#!/usr/bin/env bash
+
+# Applies the mods in corpus/ with both Slipstream and ftlman and checks that every patched file
+# is equivalent after normalization. Files listed in corpus/expected_mismatches.txt are expected
+# to differ. Prints a pass/fail line per file and exits with a non-zero status on any unexpected result.
+
+set -euo pipefail
+
+cd "$(dirname "$0")"
+
+./patch.sh corpus
+cargo run --package=normalize_xml -- --check corpus/expected_mismatches.txt data-slipstream/ftl data-ftlman/ftl
@@ -0,0 +1,24 @@
+#!/usr/bin/env bash
+
+# Extracts two patched data archives, normalizes the XML files in both and prints
+# a diff of every file that differs. Exits with a non-zero status if any file differs.
+
+set -euo pipefail
+
+if [[ $# -ne 2 ]]; then
+	echo "usage: $0 <slipstream ftl.dat> <ftlman ftl.dat>" >&2
+	exit 2
+fi
+
+slipstream_dat="$(realpath "$1")"
+ftlman_dat="$(realpath "$2")"
+
+cd "$(dirname "$0")"
+
+tmp="$(mktemp -d)"
+trap 'rm -r "$tmp"' EXIT
+
+cargo build --release
+../target/release/ftlman extract --all "$tmp/slipstream" "$slipstream_dat"
+../target/release/ftlman extract --all "$tmp/ftlman" "$ftlman_dat"
+cargo run --package=normalize_xml -- --diff "$tmp/slipstream" "$tmp/ftlman"
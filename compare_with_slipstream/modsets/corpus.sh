@@ -0,0 +1,9 @@
+#!/usr/bin/env bash
+
+# The in-repo corpus, every subdirectory of corpus/ is a mod applied in alphabetical order.
+for dir in corpus/*/; do
+	name="corpus-$(basename "$dir")"
+	# Always rezip so that changes to the corpus are picked up.
+	rm -f "$MODS_ROOT/$name.zip"
+	download_mod ziplocal "$name" "$PWD/$dir"
+done
@@ -1,12 +1,15 @@
 use std::{
+    collections::BTreeSet,
     env::args_os,
     ffi::OsStr,
     path::{Path, PathBuf},
+    process::ExitCode,
 };
 
 use lazy_static::lazy_static;
 use quick_xml::events::{BytesEnd, BytesStart, BytesText, Event};
 use regex::bytes::Regex;
+use similar::TextDiff;
 
 lazy_static! {
     static ref XML_VER_REGEX: Regex = Regex::new(r#"<[?]xml version="1.0" encoding="[uU][tT][fF]-8"[?]>"#).unwrap();
@@ -24,53 +27,62 @@ fn sort_attributes<'a>(start: &BytesStart<'a>) -> BytesStart<'a> {
     new
 }
 
-fn process_one(file: &Path) {
-    if file.extension() == Some(OsStr::new("xml")) {
-        println!("Normalising XML file {}", file.display());
-        let content = std::fs::read_to_string(file).unwrap().replace("\r\n", "\n");
-        let mut reader = quick_xml::Reader::from_str(&content);
-        let mut output_buffer: std::io::Cursor<Vec<u8>> = Default::default();
-        let mut writer = quick_xml::Writer::new_with_indent(&mut output_buffer, b'\t', 1);
-
-        let mut event_buffer = vec![];
-        loop {
-            match reader.read_event_into(&mut event_buffer).unwrap() {
-                Event::Text(content)
-                    if std::str::from_utf8(&content)
-                        .unwrap()
-                        .chars()
-                        .all(|c| c.is_ascii_whitespace()) => {}
-                Event::Text(content) => {
-                    writer
-                        .write_event(Event::Text(BytesText::new(&content.unescape().unwrap())))
-                        .unwrap();
-                }
-                Event::Comment(..) => (),
-                Event::PI(..) => (),
-                Event::Start(start) => writer.write_event(Event::Start(sort_attributes(&start))).unwrap(),
-                Event::Empty(start) => {
-                    writer.write_event(Event::Start(sort_attributes(&start))).unwrap();
-                    writer
-                        .write_event(Event::End(BytesEnd::new(std::str::from_utf8(start.name().0).unwrap())))
-                        .unwrap();
-                }
-                Event::Eof => break,
-                other => writer.write_event(other).unwrap(),
-            };
-            event_buffer.clear();
-        }
+fn normalize(content: &str) -> Vec<u8> {
+    let content = content.replace("\r\n", "\n");
+    let mut reader = quick_xml::Reader::from_str(&content);
+    let mut output_buffer: std::io::Cursor<Vec<u8>> = Default::default();
+    let mut writer = quick_xml::Writer::new_with_indent(&mut output_buffer, b'\t', 1);
 
-        let mut result = output_buffer.into_inner();
-        if !XML_VER_REGEX.is_match_at(&result, 0) {
-            let mut new_result = vec![];
-            new_result.extend_from_slice(XML_VER);
-            new_result.extend_from_slice(&result);
-            result = new_result;
-        } else {
-            result[0..XML_VER.len()].copy_from_slice(XML_VER);
-        }
+    let mut event_buffer = vec![];
+    loop {
+        match reader.read_event_into(&mut event_buffer).unwrap() {
+            Event::Text(content)
+                if std::str::from_utf8(&content)
+                    .unwrap()
+                    .chars()
+                    .all(|c| c.is_ascii_whitespace()) => {}
+            Event::Text(content) => {
+                writer
+                    .write_event(Event::Text(BytesText::new(&content.unescape().unwrap())))
+                    .unwrap();
+            }
+            Event::Comment(..) => (),
+            Event::PI(..) => (),
+            Event::Start(start) => writer.write_event(Event::Start(sort_attributes(&start))).unwrap(),
+            Event::Empty(start) => {
+                writer.write_event(Event::Start(sort_attributes(&start))).unwrap();
+                writer
+                    .write_event(Event::End(BytesEnd::new(std::str::from_utf8(start.name().0).unwrap())))
+                    .unwrap();
+            }
+            Event::Eof => break,
+            other => writer.write_event(other).unwrap(),
+        };
+        event_buffer.clear();
+    }
+
+    let mut result = output_buffer.into_inner();
+    if !XML_VER_REGEX.is_match_at(&result, 0) {
+        let mut new_result = vec![];
+        new_result.extend_from_slice(XML_VER);
+        new_result.extend_from_slice(&result);
+        result = new_result;
+    } else {
+        result[0..XML_VER.len()].copy_from_slice(XML_VER);
+    }
+
+    result
+}
+
+fn is_xml(file: &Path) -> bool {
+    file.extension() == Some(OsStr::new("xml"))
+}
 
-        std::fs::write(file, result).unwrap();
+fn process_one(file: &Path) {
+    if is_xml(file) {
+        println!("Normalising XML file {}", file.display());
+        let content = std::fs::read_to_string(file).unwrap();
+        std::fs::write(file, normalize(&content)).unwrap();
         // NOTE: ErrorKind::NotADirectory is in io_error_more, thus this cannot be checked on read_dir
         //       error
     } else if file.is_dir() {
@@ -80,8 +92,153 @@ fn process_one(file: &Path) {
     }
 }
 
-fn main() {
-    for path in args_os().skip(1).map(PathBuf::from) {
+fn collect_files(root: &Path, current: &Path, output: &mut BTreeSet<PathBuf>) {
+    for entry in current.read_dir().unwrap().map(Result::unwrap) {
+        let path = entry.path();
+        if path.is_dir() {
+            collect_files(root, &path, output);
+        } else {
+            output.insert(path.strip_prefix(root).unwrap().to_owned());
+        }
+    }
+}
+
+fn read_normalized(file: &Path) -> Vec<u8> {
+    let content = std::fs::read(file).unwrap();
+    if is_xml(file) {
+        // Some vanilla files aren't valid UTF-8, those are compared byte by byte instead.
+        if let Ok(text) = std::str::from_utf8(&content) {
+            return normalize(text);
+        }
+    }
+    content
+}
+
+/// Compares two extracted data directories file by file after normalizing XML files in memory and
+/// prints a diff of every mismatching file.
+/// Returns every file present in either directory along with the mismatching ones.
+fn diff_dirs(expected_root: &Path, actual_root: &Path) -> (BTreeSet<PathBuf>, Vec<(PathBuf, &'static str)>) {
+    let mut expected_files = BTreeSet::new();
+    collect_files(expected_root, expected_root, &mut expected_files);
+    let mut actual_files = BTreeSet::new();
+    collect_files(actual_root, actual_root, &mut actual_files);
+
+    let all_files = expected_files.union(&actual_files).cloned().collect::<BTreeSet<_>>();
+    let mut mismatched = Vec::new();
+    for path in &all_files {
+        match (expected_files.contains(path), actual_files.contains(path)) {
+            (true, false) => mismatched.push((path.clone(), "missing")),
+            (false, true) => mismatched.push((path.clone(), "unexpected")),
+            _ => {
+                let expected = read_normalized(&expected_root.join(path));
+                let actual = read_normalized(&actual_root.join(path));
+                if expected == actual {
+                    continue;
+                }
+
+                mismatched.push((path.clone(), "differs"));
+                match (std::str::from_utf8(&expected), std::str::from_utf8(&actual)) {
+                    (Ok(expected), Ok(actual)) => {
+                        let name = path.display().to_string();
+                        print!(
+                            "{}",
+                            TextDiff::from_lines(expected, actual)
+                                .unified_diff()
+                                .context_radius(3)
+                                .header(&format!("a/{name}"), &format!("b/{name}"))
+                        );
+                    }
+                    _ => println!("Binary files a/{0} and b/{0} differ", path.display()),
+                }
+            }
+        }
+    }
+
+    (all_files, mismatched)
+}
+
+/// Reads a list of files that are known to differ, one path per line with `#` starting a comment.
+fn read_expected_mismatches(file: &Path) -> BTreeSet<PathBuf> {
+    std::fs::read_to_string(file)
+        .unwrap()
+        .lines()
+        .map(|line| line.split_once('#').map_or(line, |(path, _)| path).trim())
+        .filter(|path| !path.is_empty())
+        .map(PathBuf::from)
+        .collect()
+}
+
+/// Prints a pass/fail line for every file, files listed in `expected_mismatches` are expected to differ.
+/// Returns whether every file matched the expectation.
+fn check_dirs(expected_mismatches: &BTreeSet<PathBuf>, expected_root: &Path, actual_root: &Path) -> bool {
+    let (all_files, mismatched) = diff_dirs(expected_root, actual_root);
+    let mismatched = mismatched.into_iter().map(|(path, _)| path).collect::<BTreeSet<_>>();
+
+    println!();
+    let mut failed = 0;
+    for path in all_files.union(expected_mismatches) {
+        let (status, ok) = match (mismatched.contains(path), expected_mismatches.contains(path)) {
+            (false, false) => ("pass", true),
+            (true, true) => ("xfail", true),
+            (true, false) => ("FAIL", false),
+            // Fixed differences have to be removed from the list so it doesn't go stale.
+            (false, true) => ("XPASS", false),
+        };
+        if !ok {
+            failed += 1;
+        }
+        println!("{status:>6} {}", path.display());
+    }
+    println!(
+        "{failed} of {} files failed",
+        all_files.union(expected_mismatches).count()
+    );
+
+    failed == 0
+}
+
+fn main() -> ExitCode {
+    let mut args = args_os().skip(1).peekable();
+    if args.peek().is_some_and(|arg| arg == "--diff") {
+        let paths = args.skip(1).map(PathBuf::from).collect::<Vec<_>>();
+        let [expected, actual] = &paths[..] else {
+            eprintln!("usage: normalize_xml --diff <slipstream data directory> <ftlman data directory>");
+            return ExitCode::from(2);
+        };
+
+        let (all_files, mismatched) = diff_dirs(expected, actual);
+        println!();
+        for (path, status) in &mismatched {
+            println!("{status:>10} {}", path.display());
+        }
+        println!("{} of {} files mismatched", mismatched.len(), all_files.len());
+
+        return if mismatched.is_empty() {
+            ExitCode::SUCCESS
+        } else {
+            ExitCode::FAILURE
+        };
+    }
+
+    if args.peek().is_some_and(|arg| arg == "--check") {
+        let paths = args.skip(1).map(PathBuf::from).collect::<Vec<_>>();
+        let [expected_mismatches, expected, actual] = &paths[..] else {
+            eprintln!(
+                "usage: normalize_xml --check <expected mismatches file> <slipstream data directory> <ftlman data directory>"
+            );
+            return ExitCode::from(2);
+        };
+
+        return if check_dirs(&read_expected_mismatches(expected_mismatches), expected, actual) {
+            ExitCode::SUCCESS
+        } else {
+            ExitCode::FAILURE
+        };
+    }
+
+    for path in args.map(PathBuf::from) {
         process_one(&path)
     }
+
+    ExitCode::SUCCESS
 }
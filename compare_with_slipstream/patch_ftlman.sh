@@ -13,5 +13,5 @@ set -euo pipefail
 cargo build --release
 time ../target/release/ftlman patch -d ./data-ftlman "$@"
 [[ -e data-ftlman/ftl ]] && rm -r ./data-ftlman/ftl
-time ../target/release/ftlman extract ./data-ftlman/ftl ./data-ftlman/ftl.dat
+time ../target/release/ftlman extract --all ./data-ftlman/ftl ./data-ftlman/ftl.dat
 cargo run --package=normalize_xml ./data-ftlman/ftl >/dev/null
@@ -1,3 +1,5 @@
+language-name = English
+
 name = FTL Manager v{$version}
 
 state-yes = Yes
@@ -6,8 +8,33 @@ state-no = No
 mods-title = Mods
 mods-unselect-all = Unselect all
 mods-select-all = Select all
+mods-copy-list = Copy mod list
+mods-copy-list-tooltip = Copies the enabled mods and Hyperspace version to the clipboard, useful when asking for help.
+mods-import-order = Import Slipstream order
+mods-import-order-tooltip =
+    Enables and reorders mods according to Slipstream's modorder.txt in the mod directory.
+    Mods not listed in it are disabled.
+mods-import-order-missing = Some mods from modorder.txt are not installed
+mods-import-order-failed = Could not import Slipstream mod order
 mods-apply-button = Apply
 mods-apply-tooltip = Apply mods to FTL
+modified-archive-title = ftl.dat is already modified
+modified-archive-explanation =
+    There is no backup of the unmodified game data, but ftl.dat appears to already contain mods: { $reason }.
+    Applying now would add your mods on top of the previous changes.
+    To start from the unmodified data, verify the game files through Steam (or reinstall FTL) and apply again.
+modified-archive-adopt = Use current data as vanilla and apply
+modified-archive-adopt-tooltip =
+    Backs up the current ftl.dat as the unmodified game data.
+    Every future apply will start from it, including any mods it already contains.
+modified-archive-adopt-failed = Failed to back up ftl.dat
+modified-archive-cancel = Cancel
+mods-last-applied = Last applied { $time } ago, mods enabled: { $count }
+mods-apply-hyperspace-only-button = Install Hyperspace only
+mods-apply-hyperspace-only-tooltip =
+    Installs the selected Hyperspace version, or uninstalls Hyperspace if none is selected, without reapplying mods.
+    Hyperspace.ftl is applied on top of the current game data instead of the vanilla data,
+    use Apply instead if the current data already contains a different Hyperspace version.
 mods-scan-button = Scan
 mods-scan-tooltip = Rescan mod folder
 
@@ -19,21 +46,57 @@ status-patch-download2 = Downloading patch for {$version} ({$done}/{$total})
 status-hyperspace-install = Installing Hyperspace
 status-applying-mod = Applying {$mod}
 status-repacking = Repacking archive
+status-cancel = Cancel
 status-scanning-mods = Scanning mod folder
+status-scanning-mods2 = Scanning mod folder ({$done}/{$total})
 
 invalid-ftl-directory = Invalid FTL directory specified
+ftl-directory-does-not-exist = The FTL directory does not exist.
+ftl-directory-not-a-directory = The FTL directory is a file, select the folder containing ftl.dat instead.
+ftl-directory-windows-path = This looks like a Windows path, FTL directories on this system look like /path/to/FTL instead.
+ftl-directory-in-subdirectory = ftl.dat is not in this folder, did you mean { $path }?
+ftl-directory-legacy-data-files =
+    This folder contains data.dat and resource.dat instead of ftl.dat.
+    These are used by FTL versions older than 1.6, which ftlman does not support, please update FTL.
+ftl-directory-no-ftl-dat = ftl.dat was not found in the FTL directory or any folder inside it.
 hyperspace-fetch-releases-failed = Failed to fetch hyperspace releases
 
 hyperspace = Hyperspace
 hyperspace-releases-loading = Loading...
 hyperspace-fetching-releases = Fetching hyperspace releases...
+hyperspace-offline = (offline)
+hyperspace-patch = Patches { $from } to { $to }
+hyperspace-patch-download = Patches { $from } to { $to } (patch will be downloaded)
+hyperspace-patch-tooltip =
+    Hyperspace does not support this version of FTL directly.
+    The game executable will be downgraded with a binary patch when applying.
+
+mods-drop-hint = Drop mods here to install them
+mods-install-failed = Could not install mod
+
+mod-open-folder = Open containing folder
+mod-copy-path = Copy path
 
 mod-meta-authors = Authors:
+mod-meta-update-available = Version {$version} is available
 mod-meta-hs-req = Required hyperspace version:
 mod-meta-hs-req-fallback = Requires hyperspace
 mod-meta-hs-overwrites = Overwrites hyperspace.xml:
+mod-meta-ftl-req = Required FTL version:
+mod-meta-ftl-mismatch = This mod may not work with the detected FTL version {$version}
+mod-meta-missing-bases = These append files target files that don't exist and will be ignored:
+mod-meta-dependencies = Dependencies:
+mod-meta-dependency-missing = Requires {$dependency} which is not installed
+mod-meta-dependency-disabled = Requires {$dependency} which is not enabled
+mod-meta-dependency-order = Requires {$dependency} which must be placed above this mod
+mod-meta-duplicate-filename =
+    Other mods have the same filename, their enabled state and order will be mixed up.
+    Rename or remove one of them:
 mod-meta-none = No metadata available for this mod
 mod-meta-hint = Hover over a mod and its description will appear here.
+mod-meta-view-raw = View metadata.xml
+mod-meta-raw-title = metadata.xml of { $mod }
+mod-meta-invalid-version = The { $field } "{ $value }" is not a valid version, versions should look like 1.2.3.
 
 pathedit-tooltip =
     Use Tab and Shift+Tab to cycle suggestions
@@ -41,10 +104,26 @@ pathedit-tooltip =
 
 findftl-failed-title = FTL directory autodetection failed
 
+onboarding-title = Welcome to ftlman
+onboarding-intro = Your FTL installation could not be found automatically, please enter the folder it is installed in.
+onboarding-hint-windows =
+    Steam installs FTL to C:\Program Files (x86)\Steam\steamapps\common\FTL Faster Than Light by default.
+    You can find it by right clicking FTL in your Steam library and choosing Manage, Browse local files.
+onboarding-hint-linux =
+    Steam installs FTL to ~/.local/share/Steam/steamapps/common/FTL Faster Than Light by default.
+    You can find it by right clicking FTL in your Steam library and choosing Manage, Browse local files.
+onboarding-hint-macos =
+    FTL's data is located inside the application bundle, for Steam this is
+    ~/Library/Application Support/Steam/steamapps/common/FTL Faster Than Light/FTL.app/Contents/Resources.
+onboarding-valid = FTL data found.
+onboarding-continue = Continue
+onboarding-skip = Skip
+
 sandbox-button = XML Sandbox
 sandbox-title = {sandbox-button}
 sandbox-open-failed = Failed to open XML Sandbox
 sandbox-editor-hint-xml-append = Type XML append code here to apply it to the selected file
+    Use <mod:comment>text</mod:comment> to leave a comment in the patched file, these are kept even if other comments are stripped
 sandbox-editor-hint-lua-append = Type Lua append code here to apply it to the selected file
 sandbox-mode-label = Mode
 sandbox-mode-xml = XML append
@@ -52,13 +131,41 @@ sandbox-mode-lua = Lua append
 sandbox-patch = Patch
 sandbox-patch-on-change = Patch on change
 sandbox-diagnostics-panel = Diagnostics panel
+sandbox-keep-comments = Keep comments
+sandbox-keep-doctype = Keep DOCTYPE
+sandbox-script-output = Script output
+sandbox-copy-output = Copy output
+sandbox-copy-output-tooltip = Copy the whole patched file to the clipboard.
+sandbox-file-tree = Show as tree
+sandbox-use-vanilla = Test against vanilla
+sandbox-use-vanilla-tooltip = Use the unmodded ftl.dat.vanilla backup instead of the current ftl.dat.
+sandbox-use-vanilla-missing = No ftl.dat.vanilla backup exists yet, it is created the first time mods are applied.
+sandbox-file-label = File
+sandbox-file-open = Open
+sandbox-file-save = Save
+sandbox-file-no-base = { $name } has no corresponding base file in ftl.dat
+sandbox-goto-line-title = Go to line
+sandbox-goto-line-hint = Line number (1-{ $count })
+sandbox-goto-line-invalid = Enter a line number between 1 and { $count }
+sandbox-find-matches = { $find } matches { $count } elements in the source file
+sandbox-find-show-source = Show source
+sandbox-find-show-source-tooltip = Show the unpatched source file with the elements matched by the find under the cursor highlighted.
 
 settings-button = Settings
 settings-title = {settings-button}
+settings-load-failed = Could not load settings
 settings-mod-dir = Mod directory
 settings-dirs-are-mods = Treat directories as mods
 settings-ftl-is-zip = Treat .ftl files as zipped mods
 settings-zips-are-mods = Treat zips as mods
+settings-scan-recursively = Search subdirectories for mods
+settings-scan-recursively-tooltip =
+    Directories that don't contain a data or mod-appendix directory
+    are searched for mods instead of being treated as one.
+settings-follow-symlinks = Follow symbolic links
+settings-follow-symlinks-tooltip =
+    Follows symbolic links inside unpacked mods, they are ignored otherwise. Links to mods in the mod directory are always followed.
+    Links can point anywhere, only enable this if you trust the mods you install.
 settings-disable-hs-installer = Disable Hyperspace installer
 settings-ftl-dir = FTL data directory
 settings-repack-archive = Repack FTL data archive
@@ -66,5 +173,109 @@ settings-repack-archive-tooltip =
     Turning this off will slightly speed up patching but
     make the archive larger and potentially slow down startup.
     The impact mostly depends on the number of applied mods.
+settings-skip-unneeded-repack = Only repack when necessary
+settings-skip-unneeded-repack-tooltip =
+    Skips repacking if mods replaced few enough files that
+    the space wasted by the old versions is negligible.
+settings-keep-xml-comments = Keep comments in appended files
+settings-keep-xml-comments-tooltip =
+    By default comments are removed from XML files modified by appends.
+    Keeping them can make it easier to find where changes came from.
+settings-keep-xml-doctype = Keep DOCTYPE declarations in patched files
+settings-keep-xml-doctype-tooltip =
+    FTL ignores DOCTYPE declarations, if this is disabled
+    they are removed from every XML file written by a mod.
+settings-download-connections = Download connections
+settings-download-connections-tooltip = 
+    Maximum number of simultaneous connections used to download
+    Hyperspace and its patches, if the server supports it.
+settings-max-threads = Maximum threads
+settings-max-threads-tooltip =
+    Maximum number of files read or downloaded at the same time.
+    Lower this if your mods or game are on slow storage.
+    Zero uses the number of available CPU cores.
+settings-hyperspace-mirrors = Hyperspace download mirrors
+settings-hyperspace-mirrors-tooltip =
+    GitHub mirrors tried in order if downloading Hyperspace from GitHub fails, one per line.
+    Each address replaces https://github.com in the download link.
+settings-cache = Cache: { $path }
+settings-clear-cache = Clear cache
+settings-clear-cache-tooltip =
+    Removes downloaded Hyperspace versions, patches and release lists.
+    They will be downloaded again when needed.
+settings-clear-cache-confirm = Remove everything from the cache?
+settings-clear-cache-yes = Clear
+settings-clear-cache-no = Cancel
+settings-clear-stale-cache = Clear stale entries
+settings-clear-stale-cache-tooltip =
+    Removes temporary files older than a day and cache entries older than 30 days.
+settings-clear-cache-failed = Failed to clear cache
+settings-open-config-dir = Open config folder
+settings-open-data-dir = Open data folder
+settings-open-settings-dir = Open settings file location
+settings-editor-font = Sandbox editor font
+settings-editor-font-tooltip =
+    Path to a TrueType or OpenType font file used for code in the XML Sandbox.
+    Leave empty to use the default monospace font.
+settings-editor-font-size = Sandbox editor font size
+settings-github-token = GitHub token
+settings-github-token-tooltip = 
+    Optional GitHub API token used to avoid rate limits when fetching releases.
+    The FTLMAN_GITHUB_TOKEN environment variable takes precedence over this.
+settings-auto-apply = Automatically apply changes
+settings-auto-apply-tooltip =
+    Applies mods automatically a few seconds after
+    mods are enabled, disabled or reordered.
+settings-lua-time-limit = Lua script time limit
+settings-lua-time-limit-tooltip =
+    Scripts running for longer than this are stopped with an error.
+    Set to 0 to disable the limit.
+settings-lua-memory-limit = Lua memory limit
+settings-lua-memory-limit-tooltip =
+    Scripts are stopped with an error once Lua and the XML documents
+    they modify use more memory than this. Set to 0 to disable the limit.
+settings-offline = Offline mode
+settings-offline-tooltip =
+    Disables all network access.
+    Only already downloaded Hyperspace versions and patches can be installed.
+settings-check-mod-updates = Check for mod updates
+settings-check-mod-updates-tooltip =
+    Looks for newer releases of mods whose thread link points to GitHub after scanning.
+    Uses cached results in offline mode.
+settings-log-to-file = Write logs to a file
+settings-log-to-file-tooltip =
+    Saves logs to {$path} so they can be attached to bug reports.
+    Takes effect after restarting.
+settings-check-updates = Check for updates
+settings-up-to-date = ftlman {$version} is up to date
+settings-update-available = ftlman {$version} is available
+settings-install-update = Download and install
+settings-restart-update = Restart to finish updating
+settings-update-failed = Could not restart ftlman
+settings-language = Language
+settings-language-system = System default
+settings-display-backend = Display backend
+settings-display-backend-tooltip = Takes effect after restarting.
+settings-display-backend-auto = Automatic
+settings-display-backend-wayland = Wayland
+settings-display-backend-x11 = X11
 settings-colorscheme = Colorscheme
 settings-background-opacity = Background opacity
+settings-background-opacity-tooltip =
+    The window is only made transparent if opacity is below 100% at startup,
+    changing it from or to 100% takes effect after restarting.
+
+decimal-separator = .
+size-format = {$value}{$unit}
+size-unit-b = B
+size-unit-kib = KiB
+size-unit-mib = MiB
+size-unit-gib = GiB
+size-unit-tib = TiB
+size-unit-pib = PiB
+size-unit-yib = YiB
+
+duration-under-minute = less than a minute
+duration-minutes = { $value } min
+duration-hours = { $value } h
+duration-days = { $value } d
@@ -1,3 +1,5 @@
+language-name = Polski
+
 state-yes = Tak
 state-no = Nie
 
@@ -61,3 +63,6 @@ settings-repack-archive-tooltip =
     Efekt w praktyce zależy od liczby aplikowanych modów.
 settings-colorscheme = Schemat kolorów
 settings-background-opacity = Nieprzezroczystość tła
+
+decimal-separator = ,
+size-format = {$value} {$unit}
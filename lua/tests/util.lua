@@ -22,3 +22,32 @@ local count_b = mod.iter.count(mod.table.iter_array(b))
 mod.debug.assert_equal(count, math.min(count_a, count_b))
 mod.debug.assert_equal(count_a, 3)
 mod.debug.assert_equal(count_b, 4)
+
+local map = { ["a"] = 1, ["b"] = 2, ["c"] = 3 }
+local keys = mod.iter.collect(mod.table.keys(map))
+table.sort(keys)
+mod.debug.assert_equal(keys, { "a", "b", "c" })
+local values = mod.iter.collect(mod.table.values(map))
+table.sort(values)
+mod.debug.assert_equal(values, { 1, 2, 3 })
+local empty = mod.table.keys({})
+mod.debug.assert_equal(empty(), nil)
+mod.debug.assert_equal(empty(), nil)
+
+local template = { ["name"] = "a", ["list"] = { 1, 2 } }
+template.cycle = template
+local copy = mod.util.deepcopy(template)
+copy.list[1] = 5
+mod.debug.assert_equal(template.list[1], 1)
+assert(rawequal(copy.cycle, copy))
+
+local parent = mod.xml.element("parent")
+local element = mod.xml.element("root", { ["a"] = "1" })
+element:append(mod.xml.element("child"))
+parent:append(element)
+local before = mod.xml.stringify(element)
+local element_copy = mod.util.deepcopy(element)
+mod.debug.assert_equal(element_copy.parent, nil)
+mod.debug.assert_equal(mod.xml.stringify(element_copy), before)
+element_copy:firstElementChild():append("changed")
+mod.debug.assert_equal(mod.xml.stringify(element), before)
@@ -8,7 +8,9 @@ root:append("third", fourth)
 
 local function mapNode(node)
   local element = node:as("element")
+  local comment = node:as("comment")
   if element then return "<" .. element.name .. ">"
+  elseif comment then return "<!--" .. comment.content .. "-->"
   else
     local text = node:as("text")
     return assert(text).content
@@ -79,3 +81,123 @@ mod.debug._assert_throws(
 mod.debug._assert_throws(
   function() second.rawattrs() end
 )
+
+local list = mod.xml.element("list")
+local a = mod.xml.element("a")
+local b = mod.xml.element("b")
+local c = mod.xml.element("c")
+list:append(a, b, c)
+
+-- removing an orphan is a no-op
+mod.xml.element("orphan"):remove()
+
+-- removing the current node while iterating keeps the iteration going
+local seen = {}
+for child in list:children() do
+  table.insert(seen, child.name)
+  if child.name ~= "c" then child:remove() end
+end
+
+mod.debug.assert_equal(seen, { "a", "b", "c" })
+mod.debug.assert_equal(
+  mod.iter.collect(mod.iter.map(list:childNodes(), mapNode)),
+  { "<c>" }
+)
+mod.debug.assert_equal(a.parent, nil)
+mod.debug.assert_equal(a.nextSibling, nil)
+
+list:prepend(b)
+list:append(a)
+mod.debug.assert_equal(
+  mod.iter.collect(mod.iter.map(list:childNodes(), mapNode)),
+  { "<b>", "<c>", "<a>" }
+)
+
+local attributed = mod.xml.element("attributed", { b = "2", a = "1" })
+mod.debug.assert_equal(attributed:getAttribute("a"), "1")
+mod.debug.assert_equal(attributed:getAttribute("missing"), nil)
+
+attributed:setAttribute("mod:c", "3")
+mod.debug.assert_equal(attributed:getAttribute("mod:c"), "3")
+mod.debug.assert_equal(attributed:getAttribute("c"), nil)
+
+mod.debug.assert_equal(attributed:removeAttribute("b"), true)
+mod.debug.assert_equal(attributed:removeAttribute("b"), false)
+
+mod.debug.assert_equal(
+  mod.iter._collectpack(attributed:attributes()),
+  { { "a", "1" }, { "mod:c", "3" } }
+)
+
+mod.debug._assert_throws(
+  function() attributed:setAttribute("not valid", "x") end
+)
+
+local container = mod.xml.element("container")
+local x = mod.xml.element("x")
+local y = mod.xml.element("y")
+container:append(x)
+container:insertBefore(y, x)
+container:insertAfter("text", x)
+container:insertBefore(mod.xml.element("z"))
+
+mod.debug.assert_equal(
+  mod.iter.collect(mod.iter.map(container:childNodes(), mapNode)),
+  { "<y>", "<x>", "text", "<z>" }
+)
+
+-- moving an existing child
+container:insertAfter(y, x)
+mod.debug.assert_equal(
+  mod.iter.collect(mod.iter.map(container:childNodes(), mapNode)),
+  { "<x>", "<y>", "text", "<z>" }
+)
+
+mod.debug._assert_throws(
+  function() container:insertBefore("oops", mod.xml.element("stranger")) end
+)
+mod.debug._assert_throws(
+  function() x:insertBefore(container) end
+)
+
+local generated = mod.xml.element("generated")
+generated:append(mod.xml.comment(" generated "), mod.xml.text("explicit"), "implicit")
+mod.debug.assert_equal(
+  mod.iter.collect(mod.iter.map(generated:childNodes(), mapNode)),
+  { "<!-- generated -->", "explicit", "implicit" }
+)
+mod.debug.assert_equal(mod.xml.stringify(generated), "<generated><!-- generated -->explicitimplicit</generated>")
+
+mod.debug._assert_throws(
+  function() mod.xml.comment("a -- b") end
+)
+
+local inspected = mod.xml.element("inspected", { ["a"] = "1", ["b"] = "2" })
+inspected:append("text", mod.xml.element("child"))
+mod.debug.assert_equal(mod.debug.inspect(inspected), "element <inspected> (2 attributes, 2 children)")
+mod.debug.assert_equal(mod.debug.inspect(inspected:firstChild()), 'text "text" (4 characters)')
+mod.debug.assert_equal(mod.debug.inspect(12), "integer: 12")
+
+local original = mod.xml.element("original", { ["a"] = "1" })
+original:append("text", mod.xml.element("child"))
+root:append(original)
+
+local deep = original:clone("deep")
+local shallow = original:clone("shallow")
+mod.debug.assert_equal(deep.parent, nil)
+mod.debug.assert_equal(shallow.parent, nil)
+mod.debug.assert_equal(mod.xml.stringify(deep), mod.xml.stringify(original))
+mod.debug.assert_equal(mod.xml.stringify(shallow), '<original a="1"/>')
+
+deep:firstElementChild():append("changed")
+deep.rawattrs.a = "2"
+mod.debug.assert_equal(mod.xml.stringify(original), '<original a="1">text<child/></original>')
+
+mod.debug._assert_throws(
+  function() original:clone("wrong") end
+)
+
+local nested = mod.xml.parse("<a>one<b>two<c>three</c><!-- skipped -->four</b><![CDATA[five]]>six</a>")
+mod.debug.assert_equal(nested.textContent, "onetwothreefourfivesix")
+nested:firstElementChild().textContent = "replaced"
+mod.debug.assert_equal(nested.textContent, "onereplacedfivesix")
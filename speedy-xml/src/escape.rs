@@ -1,4 +1,4 @@
-use std::borrow::Cow;
+use std::{borrow::Cow, ops::Range};
 
 use memchr::memchr2;
 
@@ -15,7 +15,7 @@ fn resolve_entity(text: &str) -> Option<(char, &str)> {
         },
         'q' if peek.next()?.1 == 'u' && peek.next()?.1 == 'o' && peek.next()?.1 == 't' => '"',
         '#' => {
-            let mut code = 0;
+            let mut code = 0u32;
             let mut next = peek.next()?.1;
             let radix = if next == 'x' {
                 next = peek.next()?.1;
@@ -24,8 +24,7 @@ fn resolve_entity(text: &str) -> Option<(char, &str)> {
                 10
             };
             while next != ';' {
-                code *= radix;
-                code += next.to_digit(radix)?;
+                code = code.checked_mul(radix)?.checked_add(next.to_digit(radix)?)?;
                 next = peek.next()?.1;
             }
 
@@ -48,43 +47,77 @@ fn resolve_entity(text: &str) -> Option<(char, &str)> {
 // Ignores all errors and keeps invalid sequences unexpanded.
 pub fn unescape(string: &str) -> Cow<str> {
     let mut replaced = String::new();
+    // Start of the part of `string` that hasn't been copied into `replaced` yet.
+    let mut copied = 0;
 
-    let mut current = string;
-    while let Some(next) = memchr2(b'&', b'\0', current.as_bytes()) {
-        match current.as_bytes()[next] {
+    let mut current = 0;
+    while let Some(next) = memchr2(b'&', b'\0', &string.as_bytes()[current..]).map(|i| i + current) {
+        match string.as_bytes()[next] {
             b'&' => {
-                if let Some((chr, rest)) = resolve_entity(&current[next + 1..]) {
-                    replaced.push_str(&current[..next]);
+                if let Some((chr, rest)) = resolve_entity(&string[next + 1..]) {
+                    replaced.push_str(&string[copied..next]);
 
                     if chr == '\0' {
                         return Cow::Owned(replaced);
                     }
 
                     replaced.push(chr);
-                    current = rest;
+                    copied = string.len() - rest.len();
+                    current = copied;
                 } else {
-                    current = &current[1..];
+                    current = next + 1;
                 }
             }
             _ => {
-                return if replaced.is_empty() {
-                    Cow::Borrowed(string)
+                return if copied == 0 {
+                    Cow::Borrowed(&string[..next])
                 } else {
-                    replaced.push_str(&current[..next]);
+                    replaced.push_str(&string[copied..next]);
                     Cow::Owned(replaced)
                 };
             }
         }
     }
 
-    if replaced.is_empty() {
+    if copied == 0 {
         Cow::Borrowed(string)
     } else {
-        replaced.push_str(current);
+        replaced.push_str(&string[copied..]);
         Cow::Owned(replaced)
     }
 }
 
+/// Returns the byte ranges of everything in `string` that looks like an entity or character reference
+/// (`&` followed by name characters and a `;`) but is left unexpanded by [`unescape`].
+///
+/// RapidXML silently keeps such references as is, so this can be used to warn about them.
+pub fn unknown_entities(string: &str) -> impl Iterator<Item = Range<usize>> + '_ {
+    let end = memchr::memchr(b'\0', string.as_bytes()).unwrap_or(string.len());
+    let mut current = 0;
+
+    std::iter::from_fn(move || {
+        while let Some(idx) = memchr::memchr(b'&', &string.as_bytes()[current..end]) {
+            let start = current + idx;
+            let rest = &string[start + 1..end];
+            if let Some((_, after)) = resolve_entity(rest) {
+                current = end - after.len();
+                continue;
+            }
+
+            current = start + 1;
+            let name_len = rest
+                .bytes()
+                .position(|b| !(b.is_ascii_alphanumeric() || matches!(b, b'#' | b'_' | b'-' | b'.' | b':')))
+                .unwrap_or(rest.len());
+            if name_len > 0 && rest.as_bytes().get(name_len) == Some(&b';') {
+                return Some(start..start + name_len + 2);
+            }
+        }
+
+        None
+    })
+}
+
 fn escape(string: &str, next: impl Fn(&str) -> Option<usize>) -> Cow<'_, str> {
     let mut replaced = String::new();
 
@@ -116,13 +149,24 @@ pub fn content_escape(string: &str) -> Cow<str> {
     escape(string, |text| memchr::memchr2(b'<', b'&', text.as_bytes()))
 }
 
+// Comments are never unescaped, so this only breaks up the sequences that would end the comment early
+// and leaves everything else untouched.
 pub fn comment_escape(string: &str) -> Cow<str> {
-    escape(string, |text| memchr::memchr(b'>', text.as_bytes()))
+    if memchr::memmem::find(string.as_bytes(), b"-->").is_none() && !string.ends_with('-') {
+        return Cow::Borrowed(string);
+    }
+
+    let mut replaced = string.replace("-->", "--&gt;");
+    if replaced.ends_with('-') {
+        replaced.pop();
+        replaced.push_str("&#45;");
+    }
+    Cow::Owned(replaced)
 }
 
 #[cfg(test)]
 mod test {
-    use super::{content_escape, unescape};
+    use super::{comment_escape, content_escape, unescape, unknown_entities};
 
     #[test]
     fn simple_unescape_escape() {
@@ -146,4 +190,44 @@ mod test {
             assert_eq!(&content_escape(&unescaped), expected_escaped);
         }
     }
+
+    #[test]
+    fn comment_escape_ends_comment_once() {
+        assert_eq!(comment_escape(" a -> b "), " a -> b ");
+        assert_eq!(comment_escape("a-->b--->"), "a--&gt;b---&gt;");
+        assert_eq!(comment_escape("trailing --"), "trailing -&#45;");
+    }
+
+    #[test]
+    fn character_references() {
+        const STRINGS: &[(&str, &str)] = &[
+            ("&lt;&gt;&amp;&quot;&apos;", "<>&\"'"),
+            ("&#65;&#x42;&#x6a;&#0067;", "ABjC"),
+            ("&#x1F600; &#128512;", "😀 😀"),
+            // Out of range, surrogates and overflowing values are kept as is.
+            (
+                "&#x110000; &#xD800; &#99999999999;",
+                "&#x110000; &#xD800; &#99999999999;",
+            ),
+            // RapidXML only recognizes a lowercase `x` and requires the `;`.
+            ("&#X41; &#65 &lt", "&#X41; &#65 &lt"),
+            // Unknown references after expanded ones must be kept intact.
+            ("&amp;ab&nbsp;c&lt;&#xZZ;", "&ab&nbsp;c<&#xZZ;"),
+            ("a&#0;b", "a"),
+            ("a\0&lt;", "a"),
+        ];
+
+        for (string, expected) in STRINGS {
+            assert_eq!(&unescape(string), expected);
+        }
+    }
+
+    #[test]
+    fn unknown_entity_ranges() {
+        let string = "&nbsp; &amp; a & b &#xZZ; &#65; &copy &hellip;\0&euro;";
+        assert_eq!(
+            unknown_entities(string).map(|range| &string[range]).collect::<Vec<_>>(),
+            ["&nbsp;", "&#xZZ;", "&hellip;"]
+        );
+    }
 }
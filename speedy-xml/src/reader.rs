@@ -15,10 +15,15 @@ use std::{
 };
 
 use crate::{
-    escape::unescape,
+    escape::{unescape, unknown_entities},
     lut::{is_invalid_attribute_name, is_invalid_name, is_whitespace},
 };
 
+#[cfg(feature = "stream")]
+mod stream;
+#[cfg(feature = "stream")]
+pub use stream::{OwnedEvent, StreamError, StreamReader};
+
 #[derive(Debug, Clone, Copy)]
 pub struct StartEvent<'a> {
     text: &'a str,
@@ -36,7 +41,8 @@ impl<'a> StartEvent<'a> {
     }
 
     pub fn is_empty(&self) -> bool {
-        self.text.as_bytes()[self.text.len() - 1] == b'/'
+        // Empty element tags end with `/>`, the `>` of a start tag can't be preceded by a `/`.
+        self.text.as_bytes()[self.text.len() - 2] == b'/'
     }
 
     pub fn position_in(&self, parser: &Reader) -> Range<usize> {
@@ -179,6 +185,8 @@ pub enum ErrorKind {
     UnclosedCData,
     UnclosedUnknownSpecial,
     DoctypeEof,
+
+    UnknownEntity,
 }
 
 impl ErrorKind {
@@ -204,6 +212,8 @@ impl ErrorKind {
             Self::UnclosedCData => "unclosed cdata",
             Self::UnclosedUnknownSpecial => "unclosed unknown <! tag",
             Self::DoctypeEof => "unexpected end of file in <!DOCTYPE",
+
+            Self::UnknownEntity => "unknown entity or invalid character reference",
         }
     }
 }
@@ -353,6 +363,7 @@ impl<'a> Iterator for Attributes<'a> {
 pub struct Options {
     allow_top_level_text: bool,
     allow_unmatched_closing_tags: bool,
+    reject_unknown_entities: bool,
 }
 
 impl Options {
@@ -365,6 +376,14 @@ impl Options {
         self.allow_unmatched_closing_tags = value;
         self
     }
+
+    /// Makes references that [`unescape`] would leave unexpanded in text and attribute values an error.
+    ///
+    /// RapidXML keeps them as is, which is also the default here.
+    pub fn reject_unknown_entities(mut self, value: bool) -> Self {
+        self.reject_unknown_entities = value;
+        self
+    }
 }
 
 pub struct Reader<'a> {
@@ -394,6 +413,13 @@ impl<'a> Reader<'a> {
         self.buffer.text
     }
 
+    /// Returns the byte offset into [`Self::buffer`] at which parsing will continue.
+    ///
+    /// After an event is returned this points just past the end of that event.
+    pub fn byte_offset(&self) -> usize {
+        self.buffer.current
+    }
+
     fn range_for_ptrs(&self, range: Range<*const u8>) -> Range<usize> {
         let self_range = self.buffer.as_bytes().as_ptr_range();
         assert!(
@@ -409,6 +435,23 @@ impl<'a> Reader<'a> {
         self.depth = 0;
     }
 
+    fn check_entities(&mut self, range: Range<usize>) -> Result<(), Error> {
+        if !self.options.reject_unknown_entities {
+            return Ok(());
+        }
+
+        match unknown_entities(&self.buffer.text[range.clone()]).next() {
+            Some(entity) => {
+                self.set_error_state();
+                Err(Error::new(
+                    ErrorKind::UnknownEntity,
+                    range.start + entity.start..range.start + entity.end,
+                ))
+            }
+            None => Ok(()),
+        }
+    }
+
     #[inline]
     fn bytes(&self) -> &'a [u8] {
         self.buffer.as_bytes()
@@ -466,6 +509,8 @@ impl<'a> Reader<'a> {
                 return Err(Error::new(ErrorKind::InvalidAttributeValue, value_start..value_end + 1));
             }
 
+            self.check_entities(value_start..value_end)?;
+
             self.buffer.current = value_end + 1;
         }
     }
@@ -562,7 +607,7 @@ impl<'a> Reader<'a> {
 
             b'!' => match self.byte(self.buffer.current + 1) {
                 Some(b'-') if self.byte(self.buffer.current + 2) == Some(b'-') => {
-                    self.buffer.current += 2;
+                    self.buffer.current += 3;
                     let Some(end) = self.buffer.memmem(b"-->") else {
                         let span = start..self.buffer.current;
                         self.set_error_state();
@@ -706,6 +751,10 @@ impl<'a> Iterator for Reader<'a> {
                         }
                     }
 
+                    if let Err(error) = self.check_entities(text_range.clone()) {
+                        return Some(Err(error));
+                    }
+
                     Some(Ok(Event::Text(TextEvent {
                         // SAFETY: See above
                         text: unsafe { self.buffer.text.get_unchecked(text_range) },
@@ -726,7 +775,7 @@ impl<'a> Iterator for Reader<'a> {
 
 #[cfg(test)]
 mod test {
-    use super::Reader;
+    use super::{ErrorKind, Options, Reader};
 
     macro_rules! unwrap {
         ($event: expr, Some($($what: tt)*)) => {
@@ -785,6 +834,31 @@ mod test {
         }
     }
 
+    #[test]
+    fn attribute_references() {
+        let code = r#"<a v="&#65;&#x42;&lt;&amp;&quot;&apos;&gt;" w='&nbsp;&#xD800;'>&amp;&#x2B50;&hellip;</a>"#;
+
+        let mut reader = Reader::new(code);
+        let start = unwrap!(reader.next(), Some(Ok(Start)));
+        let mut attributes = start.attributes();
+        assert_eq!(attributes.next().unwrap().value(), "AB<&\"'>");
+        assert_eq!(attributes.next().unwrap().value(), "&nbsp;&#xD800;");
+        let text = unwrap!(reader.next(), Some(Ok(Text)));
+        assert_eq!(text.content(), "&⭐&hellip;");
+
+        let mut reader = Reader::with_options(code, Options::default().reject_unknown_entities(true));
+        let error = reader.next().unwrap().unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::UnknownEntity);
+        assert_eq!(&code[error.span()], "&nbsp;");
+        assert!(reader.next().is_none());
+
+        let code = "<a v='&#x2B50;'>&amp;&hellip;</a>";
+        let mut reader = Reader::with_options(code, Options::default().reject_unknown_entities(true));
+        unwrap!(reader.next(), Some(Ok(Start)));
+        let error = reader.next().unwrap().unwrap_err();
+        assert_eq!(&code[error.span()], "&hellip;");
+    }
+
     #[test]
     fn comments() {
         let comment_text = " this is a &comment -- text ";
@@ -793,6 +867,12 @@ mod test {
 
         let comment = unwrap!(reader.next(), Some(Ok(Comment)));
         assert_eq!(comment.content(), comment_text);
+
+        // The end of the comment can't overlap with its start.
+        let mut reader = Reader::new("<!--->-->");
+        let comment = unwrap!(reader.next(), Some(Ok(Comment)));
+        assert_eq!(comment.content(), "->");
+        assert!(reader.next().is_none());
     }
 
     #[test]
@@ -870,4 +950,23 @@ mod test {
             assert_eq!(end.content(), content);
         }
     }
+
+    #[test]
+    fn byte_offset() {
+        let code = "<a>text<!-- comment --><b/></a>";
+        let mut reader = Reader::new(code);
+        assert_eq!(reader.byte_offset(), 0);
+
+        unwrap!(reader.next(), Some(Ok(Start)));
+        assert_eq!(&code[..reader.byte_offset()], "<a>");
+        unwrap!(reader.next(), Some(Ok(Text)));
+        assert_eq!(&code[..reader.byte_offset()], "<a>text");
+        unwrap!(reader.next(), Some(Ok(Comment)));
+        assert_eq!(&code[..reader.byte_offset()], "<a>text<!-- comment -->");
+        assert!(unwrap!(reader.next(), Some(Ok(Empty))).is_empty());
+        assert_eq!(&code[..reader.byte_offset()], "<a>text<!-- comment --><b/>");
+        unwrap!(reader.next(), Some(Ok(End)));
+        assert_eq!(reader.byte_offset(), code.len());
+        assert!(reader.next().is_none());
+    }
 }
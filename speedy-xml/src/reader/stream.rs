@@ -0,0 +1,354 @@
+//! A [`Reader`] that pulls its input incrementally from an [`BufRead`] instead of a borrowed string.
+
+use std::{
+    fmt::Display,
+    io::{BufRead, ErrorKind as IoErrorKind},
+    ops::Range,
+};
+
+use super::{
+    CDataEvent, CommentEvent, DoctypeEvent, EndEvent, Error, Event, Options, ParsingBuffer, Reader, StartEvent,
+    TextEvent,
+};
+
+/// Minimum number of bytes read from the underlying reader at once.
+const MIN_READ_SIZE: usize = 8192;
+
+#[derive(Debug, Clone, Copy)]
+enum OwnedEventKind {
+    Start { prefix_end: usize, name_end: usize },
+    End { prefix_end: usize, name_end: usize },
+    Empty { prefix_end: usize, name_end: usize },
+    Text,
+    CData,
+    Comment,
+    Doctype,
+}
+
+/// An owned version of [`Event`] returned by [`StreamReader`].
+#[derive(Debug, Clone)]
+pub struct OwnedEvent {
+    kind: OwnedEventKind,
+    text: String,
+    offset: usize,
+}
+
+impl OwnedEvent {
+    fn new(event: Event<'_>, offset: usize) -> Self {
+        let (kind, text) = match event {
+            Event::Start(StartEvent {
+                text,
+                prefix_end,
+                name_end,
+            }) => (OwnedEventKind::Start { prefix_end, name_end }, text),
+            Event::End(EndEvent {
+                text,
+                prefix_end,
+                name_end,
+            }) => (OwnedEventKind::End { prefix_end, name_end }, text),
+            Event::Empty(StartEvent {
+                text,
+                prefix_end,
+                name_end,
+            }) => (OwnedEventKind::Empty { prefix_end, name_end }, text),
+            Event::Text(TextEvent { text }) => (OwnedEventKind::Text, text),
+            Event::CData(CDataEvent { text }) => (OwnedEventKind::CData, text),
+            Event::Comment(CommentEvent { text }) => (OwnedEventKind::Comment, text),
+            Event::Doctype(DoctypeEvent { text }) => (OwnedEventKind::Doctype, text),
+        };
+
+        Self {
+            kind,
+            text: text.to_owned(),
+            offset,
+        }
+    }
+
+    /// Borrows this event as a regular [`Event`].
+    ///
+    /// Note that the `position_in` methods of the returned event cannot be used,
+    /// use [`Self::position`] instead.
+    pub fn as_event(&self) -> Event<'_> {
+        let text = self.text.as_str();
+        match self.kind {
+            OwnedEventKind::Start { prefix_end, name_end } => Event::Start(StartEvent {
+                text,
+                prefix_end,
+                name_end,
+            }),
+            OwnedEventKind::End { prefix_end, name_end } => Event::End(EndEvent {
+                text,
+                prefix_end,
+                name_end,
+            }),
+            OwnedEventKind::Empty { prefix_end, name_end } => Event::Empty(StartEvent {
+                text,
+                prefix_end,
+                name_end,
+            }),
+            OwnedEventKind::Text => Event::Text(TextEvent { text }),
+            OwnedEventKind::CData => Event::CData(CDataEvent { text }),
+            OwnedEventKind::Comment => Event::Comment(CommentEvent { text }),
+            OwnedEventKind::Doctype => Event::Doctype(DoctypeEvent { text }),
+        }
+    }
+
+    /// Byte range of this event in the whole input.
+    pub fn position(&self) -> Range<usize> {
+        self.offset..self.offset + self.text.len()
+    }
+}
+
+#[derive(Debug)]
+pub enum StreamError {
+    Io(std::io::Error),
+    /// A parse error, its span is relative to the start of the whole input.
+    Xml(Error),
+}
+
+impl Display for StreamError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            StreamError::Io(error) => write!(f, "read error: {error}"),
+            StreamError::Xml(error) => Display::fmt(error, f),
+        }
+    }
+}
+
+impl std::error::Error for StreamError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            StreamError::Io(error) => Some(error),
+            StreamError::Xml(error) => Some(error),
+        }
+    }
+}
+
+/// Like [`Reader`] but reads its input from a [`BufRead`] as needed.
+///
+/// Only the data belonging to the event that is currently being parsed is kept in memory,
+/// which means events have to be returned as [`OwnedEvent`]s.
+pub struct StreamReader<R: BufRead> {
+    input: R,
+    options: Options,
+    depth: u32,
+    /// Input that has been read but not yet returned as part of an event.
+    buffer: String,
+    /// Trailing bytes of an incomplete UTF-8 sequence.
+    pending: Vec<u8>,
+    /// Offset of the start of `buffer` in the whole input.
+    consumed: usize,
+    eof: bool,
+    finished: bool,
+}
+
+impl<R: BufRead> StreamReader<R> {
+    pub fn new(input: R) -> Self {
+        Self::with_options(input, Options::default())
+    }
+
+    pub fn with_options(input: R, options: Options) -> Self {
+        Self {
+            input,
+            options,
+            depth: 0,
+            buffer: String::new(),
+            pending: Vec::new(),
+            consumed: 0,
+            eof: false,
+            finished: false,
+        }
+    }
+
+    /// Returns the byte offset in the whole input at which parsing will continue.
+    pub fn byte_offset(&self) -> usize {
+        self.consumed
+    }
+
+    fn read_more(&mut self) -> std::io::Result<()> {
+        // Read at least as much as is already buffered so that reparsing a large event is amortized.
+        let target = self.buffer.len().max(MIN_READ_SIZE);
+        let mut read = 0;
+
+        while read < target {
+            let chunk = match self.input.fill_buf() {
+                Ok(chunk) => chunk,
+                Err(error) if error.kind() == IoErrorKind::Interrupted => continue,
+                Err(error) => return Err(error),
+            };
+
+            if chunk.is_empty() {
+                self.eof = true;
+                break;
+            }
+
+            let length = chunk.len();
+            self.pending.extend_from_slice(chunk);
+            self.input.consume(length);
+            read += length;
+
+            let valid = match std::str::from_utf8(&self.pending) {
+                Ok(valid) => valid,
+                Err(error) if error.error_len().is_none() => {
+                    // SAFETY: valid_up_to guarantees that this prefix is valid UTF-8.
+                    unsafe { std::str::from_utf8_unchecked(&self.pending[..error.valid_up_to()]) }
+                }
+                Err(error) => return Err(std::io::Error::new(IoErrorKind::InvalidData, error)),
+            };
+
+            let valid_length = valid.len();
+            self.buffer.push_str(valid);
+            self.pending.drain(..valid_length);
+        }
+
+        if self.eof && !self.pending.is_empty() {
+            return Err(std::io::Error::new(
+                IoErrorKind::InvalidData,
+                "stream ended in the middle of a UTF-8 sequence",
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+impl<R: BufRead> Iterator for StreamReader<R> {
+    type Item = Result<OwnedEvent, StreamError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.finished {
+            return None;
+        }
+
+        loop {
+            let mut reader = Reader {
+                buffer: ParsingBuffer::new(&self.buffer),
+                depth: self.depth,
+                options: self.options.clone(),
+            };
+
+            let result = reader.next();
+            let end = reader.byte_offset();
+
+            // Anything that reaches the end of the buffer may be incomplete (or an error only because
+            // it was cut off), so it has to be parsed again after more input is available.
+            if !self.eof && (end == self.buffer.len() || !matches!(result, Some(Ok(_)))) {
+                if let Err(error) = self.read_more() {
+                    self.finished = true;
+                    return Some(Err(StreamError::Io(error)));
+                }
+                continue;
+            }
+
+            return match result {
+                Some(Ok(event)) => {
+                    let offset = self.consumed
+                        + reader
+                            .range_for_ptrs(event_text(&event).as_bytes().as_ptr_range())
+                            .start;
+                    let owned = OwnedEvent::new(event, offset);
+                    self.depth = reader.depth;
+                    self.buffer.drain(..end);
+                    self.consumed += end;
+                    Some(Ok(owned))
+                }
+                Some(Err(mut error)) => {
+                    error.span = error.span.start + self.consumed..error.span.end + self.consumed;
+                    self.finished = true;
+                    Some(Err(StreamError::Xml(error)))
+                }
+                None => {
+                    self.consumed += self.buffer.len();
+                    self.buffer.clear();
+                    self.finished = true;
+                    None
+                }
+            };
+        }
+    }
+}
+
+fn event_text<'a>(event: &Event<'a>) -> &'a str {
+    match *event {
+        Event::Start(StartEvent { text, .. })
+        | Event::End(EndEvent { text, .. })
+        | Event::Empty(StartEvent { text, .. }) => text,
+        Event::Text(TextEvent { text })
+        | Event::CData(CDataEvent { text })
+        | Event::Comment(CommentEvent { text })
+        | Event::Doctype(DoctypeEvent { text }) => text,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::BufReader;
+
+    use super::{super::Reader, StreamError, StreamReader};
+
+    const CODE: &str = r#"<?xml version="1.0"?>
+<!DOCTYPE root [<!ENTITY a "b">]>
+<root attr="välue">
+    text &amp; more text
+    <!-- a comment with ünïcödé -->
+    <![CDATA[ <not a tag> ]]>
+    <mod:child name='x'/>
+    <other></other>
+</root>
+"#;
+
+    fn collect_borrowed(code: &str) -> Vec<(String, std::ops::Range<usize>)> {
+        let mut reader = Reader::new(code);
+        let mut out = vec![];
+        while let Some(event) = reader.next() {
+            let event = event.unwrap();
+            let text = super::event_text(&event);
+            out.push((
+                format!("{event:?}"),
+                reader.range_for_ptrs(text.as_bytes().as_ptr_range()),
+            ));
+        }
+        out
+    }
+
+    #[test]
+    fn matches_borrowed_reader() {
+        let expected = collect_borrowed(CODE);
+
+        // A capacity of 1 forces every event to be split across multiple reads.
+        for capacity in [1, 3, 16, 8192] {
+            let events = StreamReader::new(BufReader::with_capacity(capacity, CODE.as_bytes()))
+                .map(|event| {
+                    let event = event.unwrap();
+                    (format!("{:?}", event.as_event()), event.position())
+                })
+                .collect::<Vec<_>>();
+
+            assert_eq!(events, expected, "capacity {capacity}");
+        }
+    }
+
+    #[test]
+    fn error_span_is_absolute() {
+        let code = "<root><a></a><b attr=></b></root>";
+        let mut reader = StreamReader::new(BufReader::with_capacity(2, code.as_bytes()));
+        let error = loop {
+            match reader.next() {
+                Some(Ok(_)) => continue,
+                Some(Err(StreamError::Xml(error))) => break error,
+                other => panic!("expected a parse error, got {other:?}"),
+            }
+        };
+
+        assert_eq!(&code[error.span()], "attr=");
+        assert!(reader.next().is_none());
+    }
+
+    #[test]
+    fn invalid_utf8() {
+        let data = b"<root>\xff</root>";
+        let mut reader = StreamReader::new(BufReader::new(&data[..]));
+        assert!(matches!(reader.next(), Some(Err(StreamError::Io(_)))));
+        assert!(reader.next().is_none());
+    }
+}
@@ -4,7 +4,7 @@ use std::{
 };
 
 use crate::{
-    escape::{comment_escape, content_escape},
+    escape::{attribute_value_escape, comment_escape, content_escape},
     lut::{is_invalid_attribute_name, is_invalid_name},
     reader::{self, AttributeEvent, AttributeQuote, CDataEvent, DoctypeEvent, TextEvent},
 };
@@ -97,7 +97,7 @@ impl<W: Write> Writer<W> {
         if self.depth_and_flags & 1 > 0 {
             if self.in_empty_tag() {
                 self.writer.write_all(b"/>")?;
-                self.depth_and_flags += 0b001;
+                self.depth_and_flags -= 0b011;
             } else {
                 self.writer.write_all(b">")?;
                 self.depth_and_flags += 0b011;
@@ -107,15 +107,21 @@ impl<W: Write> Writer<W> {
         Ok(())
     }
 
-    pub fn write_start(&mut self, prefix: Option<&str>, name: &str) -> Result<(), Error> {
-        if prefix.is_some_and(|pfx| pfx.bytes().any(is_invalid_name)) {
-            return Err(Error::InvalidElementName);
+    fn check_element_name(prefix: Option<&str>, name: &str) -> Result<(), Error> {
+        if prefix.is_some_and(|pfx| pfx.is_empty() || pfx.bytes().any(is_invalid_name)) {
+            return Err(Error::InvalidElementPrefix);
         }
 
-        if name.bytes().any(is_invalid_name) {
+        if name.is_empty() || name.bytes().any(is_invalid_name) {
             return Err(Error::InvalidElementName);
         }
 
+        Ok(())
+    }
+
+    pub fn write_start(&mut self, prefix: Option<&str>, name: &str) -> Result<(), Error> {
+        Self::check_element_name(prefix, name)?;
+
         self.ensure_tag_closed()?;
 
         self.depth_and_flags += 0b1;
@@ -131,9 +137,7 @@ impl<W: Write> Writer<W> {
     }
 
     pub fn write_empty(&mut self, prefix: Option<&str>, name: &str) -> Result<(), Error> {
-        if name.bytes().any(is_invalid_name) {
-            return Err(Error::InvalidElementName);
-        }
+        Self::check_element_name(prefix, name)?;
 
         self.ensure_tag_closed()?;
 
@@ -154,12 +158,12 @@ impl<W: Write> Writer<W> {
             return Err(Error::AttributeOutsideTag);
         }
 
-        if name.bytes().any(is_invalid_attribute_name) {
+        if name.is_empty() || name.bytes().any(is_invalid_attribute_name) {
             return Err(Error::InvalidAttributeName);
         }
 
         let quote = quote as u8;
-        if name.bytes().any(|b| [b'\0', quote].contains(&b)) {
+        if value.bytes().any(|b| [b'\0', quote].contains(&b)) {
             return Err(Error::InvalidAttributeValue);
         }
 
@@ -174,14 +178,12 @@ impl<W: Write> Writer<W> {
     }
 
     pub fn write_attribute(&mut self, name: &str, value: &str) -> Result<(), Error> {
-        let escaped = content_escape(value);
+        let escaped = attribute_value_escape(value);
         self.write_raw_attribute(name, AttributeQuote::Double, &escaped)
     }
 
     pub fn write_end(&mut self, prefix: Option<&str>, name: &str) -> Result<(), Error> {
-        if name.bytes().any(is_invalid_name) {
-            return Err(Error::InvalidElementName);
-        }
+        Self::check_element_name(prefix, name)?;
 
         self.ensure_tag_closed()?;
 
@@ -218,6 +220,10 @@ impl<W: Write> Writer<W> {
     }
 
     pub fn write_text(&mut self, content: &str) -> Result<(), Error> {
+        if memchr::memchr(b'\0', content.as_bytes()).is_some() {
+            return Err(Error::InvalidValue);
+        }
+
         let escaped = content_escape(content);
         self.write_raw_text_unchecked(&escaped).map_err(Into::into)
     }
@@ -251,7 +257,8 @@ impl<W: Write> Writer<W> {
     }
 
     pub fn write_raw_comment(&mut self, text: &str) -> Result<(), Error> {
-        if memchr::memmem::find(text.as_bytes(), b"-->").is_some() {
+        // A trailing `-` would form a `-->` together with the end of the comment.
+        if memchr::memmem::find(text.as_bytes(), b"-->").is_some() || text.ends_with('-') {
             return Err(Error::ImproperlyEscacped);
         }
 
@@ -280,7 +287,7 @@ impl<W: Write> Writer<W> {
 
     pub fn write_event(&mut self, event: &reader::Event) -> Result<(), Error> {
         match event {
-            reader::Event::Start(start) => {
+            reader::Event::Start(start) | reader::Event::Empty(start) => {
                 if start.is_empty() {
                     self.write_empty(start.prefix(), start.name())?;
                 } else {
@@ -294,7 +301,6 @@ impl<W: Write> Writer<W> {
                 Ok(())
             }
             reader::Event::End(end) => self.write_end(end.prefix(), end.name()),
-            reader::Event::Empty(empty) => self.write_empty(empty.prefix(), empty.name()),
             &reader::Event::CData(CDataEvent { text })
             | &reader::Event::Doctype(DoctypeEvent { text })
             | &reader::Event::Text(TextEvent { text }) => {
@@ -304,7 +310,7 @@ impl<W: Write> Writer<W> {
 
                 Ok(())
             }
-            reader::Event::Comment(comment) => self.write_raw_comment_unchecked(comment.text).map_err(Into::into),
+            reader::Event::Comment(comment) => self.write_raw_comment_unchecked(comment.content()).map_err(Into::into),
         }
     }
 
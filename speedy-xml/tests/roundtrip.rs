@@ -0,0 +1,272 @@
+//! Randomized round-trip test for the reader and writer.
+//!
+//! Random trees are written with [`Writer`], read back with [`Reader`] and compared to what was
+//! written, then the read events are written again with [`Writer::write_event`] and read once more.
+//!
+//! By default a small fixed set of cases is run as part of `cargo test`, longer runs can be done with:
+//! ```sh
+//! SPEEDY_XML_ROUNDTRIP_ITERATIONS=1000000 cargo test --release -p speedy-xml --test roundtrip
+//! ```
+//! `SPEEDY_XML_ROUNDTRIP_SEED` sets the initial seed, a failing case prints its own seed which can be
+//! used with `SPEEDY_XML_ROUNDTRIP_ITERATIONS=1` to reproduce it.
+
+use speedy_xml::{
+    Reader,
+    escape::comment_escape,
+    reader::Event,
+    writer::{self, Writer},
+};
+
+const DEFAULT_ITERATIONS: u64 = 2000;
+const DEFAULT_SEED: u64 = 0x5eed_f71a;
+
+const MAX_DEPTH: usize = 4;
+const MAX_CHILDREN: usize = 5;
+
+const NAME_CHARS: &[char] = &['a', 'b', 'x', 'Y', 'Z', '_', '-', '.', '0', '9', 'ł'];
+// Pieces that strings are made of, chosen to hit escaping edge cases.
+const STRING_PIECES: &[&str] = &[
+    "a", "b", " ", "\n", "\t", "\r\n", "&", "<", ">", "\"", "'", "-", "--", "-->", "]", "]]>", "&amp;", "&lt;",
+    "&#65;", "&#x41;", "&quot", "ł", "⭐",
+];
+
+// xorshift64*, good enough for picking test cases.
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    }
+
+    fn below(&mut self, n: usize) -> usize {
+        (self.next() % n as u64) as usize
+    }
+
+    fn chance(&mut self, one_in: usize) -> bool {
+        self.below(one_in) == 0
+    }
+
+    fn name(&mut self) -> String {
+        (0..1 + self.below(6))
+            .map(|_| NAME_CHARS[self.below(NAME_CHARS.len())])
+            .collect()
+    }
+
+    fn string(&mut self) -> String {
+        (0..self.below(8))
+            .map(|_| STRING_PIECES[self.below(STRING_PIECES.len())])
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Node {
+    Element(Element),
+    Text(String),
+    CData(String),
+    Comment(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Element {
+    prefix: Option<String>,
+    name: String,
+    attributes: Vec<(String, String)>,
+    children: Vec<Node>,
+}
+
+/// Pushes `node` onto `nodes` the way the reader would produce it, merging adjacent text.
+fn push_node(nodes: &mut Vec<Node>, node: Node) {
+    match (nodes.last_mut(), node) {
+        (_, Node::Text(text)) if text.is_empty() => (),
+        (Some(Node::Text(last)), Node::Text(text)) => last.push_str(&text),
+        (_, node) => nodes.push(node),
+    }
+}
+
+fn generate_element(rng: &mut Rng, depth: usize) -> Element {
+    let mut children = Vec::new();
+    if depth < MAX_DEPTH {
+        for _ in 0..rng.below(MAX_CHILDREN + 1) {
+            children.push(match rng.below(6) {
+                0 | 1 => Node::Element(generate_element(rng, depth + 1)),
+                2 | 3 => Node::Text(rng.string()),
+                4 => Node::CData(rng.string()),
+                _ => Node::Comment(rng.string()),
+            });
+        }
+    }
+
+    Element {
+        prefix: rng.chance(4).then(|| rng.name()),
+        name: rng.name(),
+        attributes: (0..rng.below(4)).map(|_| (rng.name(), rng.string())).collect(),
+        children,
+    }
+}
+
+/// Writes `node` and returns what it should look like when read back.
+fn write_node(writer: &mut Writer<Vec<u8>>, rng: &mut Rng, node: &Node) -> Option<Node> {
+    match node {
+        Node::Element(element) => {
+            let prefix = element.prefix.as_deref();
+            let empty = element.children.is_empty() && rng.chance(2);
+            if empty {
+                writer.write_empty(prefix, &element.name).unwrap();
+            } else {
+                writer.write_start(prefix, &element.name).unwrap();
+            }
+
+            for (name, value) in &element.attributes {
+                writer.write_attribute(name, value).unwrap();
+            }
+
+            let mut children = Vec::new();
+            for child in &element.children {
+                if let Some(child) = write_node(writer, rng, child) {
+                    push_node(&mut children, child);
+                }
+            }
+
+            if !empty {
+                writer.write_end(prefix, &element.name).unwrap();
+            }
+
+            Some(Node::Element(Element {
+                children,
+                ..element.clone()
+            }))
+        }
+        Node::Text(text) => {
+            writer.write_text(text).unwrap();
+            Some(Node::Text(text.clone()))
+        }
+        Node::CData(content) => match writer.write_cdata(content) {
+            Ok(()) => Some(Node::CData(content.clone())),
+            Err(writer::Error::InvalidCData) => {
+                assert!(content.contains("]]>"));
+                None
+            }
+            Err(error) => panic!("unexpected error writing cdata: {error}"),
+        },
+        Node::Comment(content) => {
+            writer.write_comment(content).unwrap();
+            Some(Node::Comment(comment_escape(content).into_owned()))
+        }
+    }
+}
+
+fn read_nodes(text: &str) -> Vec<Node> {
+    let mut stack: Vec<(Element, Vec<Node>)> = Vec::new();
+    let mut top_level = Vec::new();
+
+    for event in Reader::new(text) {
+        let event = event.unwrap_or_else(|error| panic!("failed to read back written XML: {error}"));
+        let node = match event {
+            Event::Start(start) | Event::Empty(start) => {
+                let element = Element {
+                    prefix: start.prefix().map(str::to_owned),
+                    name: start.name().to_owned(),
+                    attributes: start
+                        .attributes()
+                        .map(|attr| (attr.name().to_owned(), attr.value().into_owned()))
+                        .collect(),
+                    children: Vec::new(),
+                };
+
+                if !start.is_empty() {
+                    stack.push((element, Vec::new()));
+                    continue;
+                }
+
+                Node::Element(element)
+            }
+            Event::End(end) => {
+                let (mut element, children) = stack.pop().expect("unmatched end tag");
+                assert_eq!(
+                    (end.prefix(), end.name()),
+                    (element.prefix.as_deref(), element.name.as_str()),
+                    "mismatched end tag"
+                );
+                element.children = children;
+                Node::Element(element)
+            }
+            Event::Text(text) => Node::Text(text.content().into_owned()),
+            Event::CData(cdata) => Node::CData(cdata.content().to_owned()),
+            Event::Comment(comment) => Node::Comment(comment.content().to_owned()),
+            Event::Doctype(_) => panic!("unexpected doctype"),
+        };
+
+        push_node(stack.last_mut().map_or(&mut top_level, |(_, children)| children), node);
+    }
+
+    assert!(stack.is_empty(), "unclosed elements");
+    top_level
+}
+
+fn check_case(seed: u64) {
+    let mut rng = Rng(seed);
+
+    let mut top_level = Vec::new();
+    for _ in 0..rng.below(3) {
+        top_level.push(Node::Comment(rng.string()));
+    }
+    top_level.push(Node::Element(generate_element(&mut rng, 0)));
+    for _ in 0..rng.below(3) {
+        top_level.push(Node::Comment(rng.string()));
+    }
+
+    let mut writer = Writer::new(Vec::new());
+    let mut expected = Vec::new();
+    for node in &top_level {
+        if let Some(node) = write_node(&mut writer, &mut rng, node) {
+            expected.push(node);
+        }
+        // Whitespace between top-level nodes is skipped by the reader.
+        if rng.chance(2) {
+            writer.write_raw_text("\n").unwrap();
+        }
+    }
+    let written = String::from_utf8(writer.finish().unwrap()).unwrap();
+
+    let context = format!("seed {seed:#x}, written XML:\n{written}");
+    let read = std::panic::catch_unwind(|| read_nodes(&written)).unwrap_or_else(|_| panic!("{context}"));
+    assert_eq!(read, expected, "{context}");
+
+    let mut writer = Writer::new(Vec::new());
+    for event in Reader::new(&written) {
+        writer
+            .write_event(&event.unwrap())
+            .unwrap_or_else(|error| panic!("failed to write event: {error}\n{context}"));
+    }
+    let rewritten = String::from_utf8(writer.finish().unwrap()).unwrap();
+
+    let reread = std::panic::catch_unwind(|| read_nodes(&rewritten))
+        .unwrap_or_else(|_| panic!("{context}\nrewritten XML:\n{rewritten}"));
+    assert_eq!(reread, expected, "{context}\nrewritten XML:\n{rewritten}");
+}
+
+fn env_u64(name: &str) -> Option<u64> {
+    let value = std::env::var(name).ok()?;
+    let parsed = match value.strip_prefix("0x") {
+        Some(hex) => u64::from_str_radix(hex, 16),
+        None => value.parse(),
+    };
+    Some(parsed.unwrap_or_else(|_| panic!("{name} is not a valid integer")))
+}
+
+#[test]
+fn roundtrip() {
+    let iterations = env_u64("SPEEDY_XML_ROUNDTRIP_ITERATIONS").unwrap_or(DEFAULT_ITERATIONS);
+    let mut seeds = Rng(env_u64("SPEEDY_XML_ROUNDTRIP_SEED").unwrap_or(DEFAULT_SEED));
+
+    // The first case uses the seed directly so that a failing case can be rerun by itself.
+    let mut seed = seeds.0;
+    for _ in 0..iterations {
+        check_case(seed);
+        seed = seeds.next().max(1);
+    }
+}
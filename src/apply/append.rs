@@ -1,4 +1,5 @@
 use std::{
+    borrow::Cow,
     collections::{HashMap, HashSet},
     fmt::Write,
     mem::offset_of,
@@ -11,22 +12,27 @@ use regex::Regex;
 
 type XMLNode = Node;
 
+/// Names of the mod tags that select elements for the commands inside of them.
+pub const FIND_TAGS: &[&str] = &["findName", "findLike", "findWithChildLike", "findComposite"];
+
 // FIXME: This is a giant hack
 const REMOVE_MARKER: &str = "_FTLMAN_INTERNAL_REMOVE_MARKER";
 
 pub fn patch(context: &mut Element, patch: Vec<XMLNode>) -> Result<()> {
     for mut node in patch {
         match node {
+            XMLNode::Element(el) if el.prefix.as_deref() == Some("mod") && el.name == "comment" => {
+                context.children.push(comment_node(&el));
+            }
             XMLNode::Element(el) if el.prefix.as_deref() == Some("mod") => {
-                let Some(matches) = mod_find(context, &el)? else {
+                let Some(matches) = mod_find_with_captures(context, &el)? else {
                     bail!("Unrecognised mod find tag {}", el.name);
                 };
 
-                for element in matches {
-                    mod_commands(element, &el)?;
+                for (element, captures) in matches {
+                    mod_commands(element, &el, &captures)?;
                 }
             }
-            XMLNode::Comment(..) => (),
             _ => {
                 if let Some(e) = node.as_mut_element() {
                     cleanup(e)
@@ -41,6 +47,50 @@ pub fn patch(context: &mut Element, patch: Vec<XMLNode>) -> Result<()> {
     Ok(())
 }
 
+/// Runs a chain of nested find tags against `context` without executing any commands and returns
+/// the paths to the elements matched by the last one in document order.
+///
+/// Paths consist of indices into the element children of each ancestor, starting from `context`.
+pub fn find_paths(context: &mut Element, finds: &[&Element]) -> Result<Vec<Vec<usize>>> {
+    fn collect(context: &mut Element, finds: &[&Element], out: &mut HashSet<*const Element>) -> Result<()> {
+        let Some((find, rest)) = finds.split_first() else {
+            return Ok(());
+        };
+
+        let Some(matches) = mod_find(context, find)? else {
+            bail!("Unrecognised mod find tag {}", find.name);
+        };
+
+        for element in matches {
+            if rest.is_empty() {
+                out.insert(element as *const Element);
+            } else {
+                collect(element, rest, out)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn walk(element: &Element, matched: &HashSet<*const Element>, path: &mut Vec<usize>, out: &mut Vec<Vec<usize>>) {
+        for (i, child) in element.children.iter().filter_map(XMLNode::as_element).enumerate() {
+            path.push(i);
+            if matched.contains(&(child as *const Element)) {
+                out.push(path.clone());
+            }
+            walk(child, matched, path, out);
+            path.pop();
+        }
+    }
+
+    let mut matched = HashSet::new();
+    collect(context, finds, &mut matched)?;
+
+    let mut paths = Vec::new();
+    walk(context, &matched, &mut Vec::new(), &mut paths);
+    Ok(paths)
+}
+
 fn cleanup(element: &mut Element) {
     const MOD_NAMESPACES: &[&str] = &["mod", "mod-append", "mod-prepend", "mod-overwrite"];
 
@@ -73,12 +123,30 @@ fn cleanup(element: &mut Element) {
                 cleanup(&mut e);
                 element.children.push(XMLNode::Element(e))
             }
-            XMLNode::Comment(..) => {}
             n => element.children.push(n),
         }
     }
 }
 
+/// Recursively removes all comments from a list of nodes.
+///
+/// This has to be done before patching, comments added by `mod:comment` are meant to be kept.
+pub fn strip_comments(nodes: &mut Vec<XMLNode>) {
+    nodes.retain_mut(|child| match child {
+        XMLNode::Comment(..) => false,
+        XMLNode::Element(e) => {
+            strip_comments(&mut e.children);
+            true
+        }
+        _ => true,
+    });
+}
+
+/// Creates the comment added by a `mod:comment` command.
+fn comment_node(command: &Element) -> XMLNode {
+    XMLNode::Comment(format!(" {} ", command.get_text_trim()))
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 enum ParOperator {
     And,
@@ -117,6 +185,26 @@ impl FromStr for ParOperation {
     }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SetValueMode {
+    Replace,
+    Append,
+    Prepend,
+}
+
+impl FromStr for SetValueMode {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "replace" => Ok(Self::Replace),
+            "append" => Ok(Self::Append),
+            "prepend" => Ok(Self::Prepend),
+            _ => bail!("Invalid setValue mode: {s}"),
+        }
+    }
+}
+
 macro_rules! get_attr {
     ($node: ident, $type: ty, $name: literal, $default: expr) => {{
         get_attr!($node, $type, $name).transpose().unwrap_or(Ok($default))
@@ -147,14 +235,89 @@ macro_rules! get_attr {
     }};
 }
 
+/// Groups captured by the regex filters of a find tag, made available to the commands executed on each match.
+#[derive(Debug, Default)]
+struct Captures {
+    /// Groups of all regexes that matched, numbered consecutively in the order the filters were checked.
+    groups: Vec<String>,
+    named: HashMap<String, String>,
+}
+
+impl Captures {
+    fn is_empty(&self) -> bool {
+        self.groups.is_empty()
+    }
+
+    /// Replaces `$n`, `${n}` and `${name}` with the corresponding captured groups, `$$` is a literal `$`.
+    ///
+    /// Text is left untouched if nothing was captured and references to groups that don't exist are kept
+    /// as they are, so that values containing a literal `$` keep working.
+    fn substitute<'a>(&self, text: &'a str) -> Cow<'a, str> {
+        if self.is_empty() || !text.contains('$') {
+            return Cow::Borrowed(text);
+        }
+
+        let mut result = String::with_capacity(text.len());
+        let mut rest = text;
+        while let Some(idx) = rest.find('$') {
+            result.push_str(&rest[..idx]);
+            let original = &rest[idx..];
+            rest = &rest[idx + 1..];
+
+            let reference = if let Some(braced) = rest.strip_prefix('{') {
+                let Some(end) = braced.find('}') else {
+                    result.push('$');
+                    continue;
+                };
+                rest = &braced[end + 1..];
+                &braced[..end]
+            } else if let Some(after) = rest.strip_prefix('$') {
+                result.push('$');
+                rest = after;
+                continue;
+            } else {
+                let end = rest.find(|c: char| !c.is_ascii_digit()).unwrap_or(rest.len());
+                if end == 0 {
+                    result.push('$');
+                    continue;
+                }
+                let (digits, after) = rest.split_at(end);
+                rest = after;
+                digits
+            };
+
+            let value = match reference.parse::<usize>() {
+                Ok(index) => index.checked_sub(1).and_then(|i| self.groups.get(i)),
+                Err(_) => self.named.get(reference),
+            };
+
+            match value {
+                Some(value) => result.push_str(value),
+                None => result.push_str(&original[..original.len() - rest.len()]),
+            }
+        }
+        result.push_str(rest);
+
+        Cow::Owned(result)
+    }
+}
+
 trait ElementFilter {
     fn filter_one(&self, element: &Element) -> bool;
 
-    fn filter_children<'a>(&self, context: &'a mut Element) -> Vec<&'a mut Element> {
+    /// Collects the regex groups captured when matching an element that passed [`Self::filter_one`].
+    fn captures(&self, _element: &Element, _out: &mut Captures) {}
+
+    fn filter_children<'a>(&self, context: &'a mut Element) -> Vec<(&'a mut Element, Captures)> {
         context
             .children
             .iter_mut()
             .filter_map(|x| x.as_mut_element().filter(|child| self.filter_one(child)))
+            .map(|child| {
+                let mut captures = Captures::default();
+                self.captures(child, &mut captures);
+                (child, captures)
+            })
             .collect()
     }
 }
@@ -179,11 +342,68 @@ impl StringFilter {
             StringFilter::Regex(regex) => regex.find(value).is_some_and(|m| m.len() == value.len()),
         }
     }
+
+    fn captures(&self, value: &str, out: &mut Captures) {
+        let StringFilter::Regex(regex) = self else {
+            return;
+        };
+
+        let Some(captures) = regex.captures(value) else {
+            return;
+        };
+
+        for (i, name) in regex.capture_names().enumerate().skip(1) {
+            let text = captures.get(i).map_or("", |m| m.as_str());
+            out.groups.push(text.to_owned());
+            if let Some(name) = name {
+                out.named.insert(name.to_owned(), text.to_owned());
+            }
+        }
+    }
+}
+
+/// Matches an element's name and, if a prefix filter is present, its prefix.
+/// Elements without a prefix are treated as having an empty one.
+fn is_name_match(name: Option<&StringFilter>, prefix: Option<&StringFilter>, element: &Element) -> bool {
+    name.is_none_or(|filter| filter.is_match(&element.name))
+        && prefix.is_none_or(|filter| filter.is_match(element.prefix.as_deref().unwrap_or("")))
+}
+
+fn name_captures(name: Option<&StringFilter>, prefix: Option<&StringFilter>, element: &Element, out: &mut Captures) {
+    if let Some(filter) = name {
+        filter.captures(&element.name, out);
+    }
+    if let Some(filter) = prefix {
+        filter.captures(element.prefix.as_deref().unwrap_or(""), out);
+    }
+}
+
+/// Splits a fixed `prefix:name` type filter into separate prefix and name filters,
+/// an explicitly specified prefix takes precedence over one in the type.
+fn split_qualified_type(
+    node: &Element,
+    kind: Option<StringFilter>,
+    prefix: Option<StringFilter>,
+) -> Result<(Option<StringFilter>, Option<StringFilter>)> {
+    match kind {
+        Some(StringFilter::Fixed(text)) if text.contains(':') => {
+            if prefix.is_some() {
+                bail!("mod:{} has both a prefix attribute and a prefixed type", node.name)
+            }
+            let (type_prefix, name) = text.split_once(':').unwrap();
+            Ok((
+                Some(StringFilter::Fixed(type_prefix.to_owned())),
+                Some(StringFilter::Fixed(name.to_owned())),
+            ))
+        }
+        kind => Ok((prefix, kind)),
+    }
 }
 
 #[derive(Default)]
 struct SelectorFilter {
     pub name: Option<StringFilter>,
+    pub prefix: Option<StringFilter>,
     pub attrs: Vec<(String, StringFilter)>,
     pub value: Option<StringFilter>,
 }
@@ -215,7 +435,7 @@ impl SelectorFilter {
 
 impl ElementFilter for SelectorFilter {
     fn filter_one(&self, element: &Element) -> bool {
-        if self.name.as_ref().is_some_and(|filter| !filter.is_match(&element.name)) {
+        if !is_name_match(self.name.as_ref(), self.prefix.as_ref(), element) {
             return false;
         }
 
@@ -235,25 +455,69 @@ impl ElementFilter for SelectorFilter {
 
         true
     }
+
+    fn captures(&self, element: &Element, out: &mut Captures) {
+        name_captures(self.name.as_ref(), self.prefix.as_ref(), element, out);
+
+        for (key, value) in self.attrs.iter() {
+            if let Some(attribute) = element.attributes.get(key) {
+                value.captures(attribute, out);
+            }
+        }
+
+        if let Some(value) = self.value.as_ref() {
+            value.captures(&element.get_text_trim(), out);
+        }
+    }
 }
 
 #[derive(Default)]
 struct WithChildFilter<F: ElementFilter> {
     pub name: Option<StringFilter>,
+    pub prefix: Option<StringFilter>,
+    /// Whether the child filter should be matched against all descendants instead of just direct children.
+    pub recursive: bool,
     pub child_filter: F,
 }
 
+impl<F: ElementFilter> WithChildFilter<F> {
+    fn find_child<'e>(&self, element: &'e Element) -> Option<&'e Element> {
+        if self.recursive {
+            // Explicit stack so that deeply nested documents can't overflow the call stack,
+            // the search stops at the first matching descendant.
+            let mut stack: Vec<&Element> = element.children.iter().filter_map(XMLNode::as_element).collect();
+            while let Some(current) = stack.pop() {
+                if self.child_filter.filter_one(current) {
+                    return Some(current);
+                }
+                stack.extend(current.children.iter().filter_map(XMLNode::as_element));
+            }
+            None
+        } else {
+            element
+                .children
+                .iter()
+                .filter_map(XMLNode::as_element)
+                .find(|child| self.child_filter.filter_one(child))
+        }
+    }
+}
+
 impl<F: ElementFilter> ElementFilter for WithChildFilter<F> {
     fn filter_one(&self, element: &Element) -> bool {
-        if self.name.as_ref().is_some_and(|filter| !filter.is_match(&element.name)) {
+        if !is_name_match(self.name.as_ref(), self.prefix.as_ref(), element) {
             return false;
         }
 
-        element
-            .children
-            .iter()
-            .filter_map(XMLNode::as_element)
-            .any(|child| self.child_filter.filter_one(child))
+        self.find_child(element).is_some()
+    }
+
+    fn captures(&self, element: &Element, out: &mut Captures) {
+        name_captures(self.name.as_ref(), self.prefix.as_ref(), element, out);
+
+        if let Some(child) = self.find_child(element) {
+            self.child_filter.captures(child, out);
+        }
     }
 }
 
@@ -331,8 +595,15 @@ fn mod_par<'a>(context: &'a mut Element, node: &Element) -> Result<Option<Vec<&'
 }
 
 fn mod_find<'a>(context: &'a mut Element, node: &Element) -> Result<Option<Vec<&'a mut Element>>> {
+    Ok(mod_find_with_captures(context, node)?.map(|matches| matches.into_iter().map(|(element, _)| element).collect()))
+}
+
+fn mod_find_with_captures<'a>(
+    context: &'a mut Element,
+    node: &Element,
+) -> Result<Option<Vec<(&'a mut Element, Captures)>>> {
     if node.prefix.as_ref().is_some_and(|x| x == "mod") {
-        if !["findName", "findLike", "findWithChildLike", "findComposite"].contains(&node.name.as_str()) {
+        if !FIND_TAGS.contains(&node.name.as_str()) {
             return Ok(None);
         }
 
@@ -346,7 +617,7 @@ fn mod_find<'a>(context: &'a mut Element, node: &Element) -> Result<Option<Vec<&
 
         let panic = get_attr!(node, bool, "panic", false)?;
 
-        let mut matches: Vec<&'a mut Element> = match node.name.as_str() {
+        let mut matches: Vec<(&'a mut Element, Captures)> = match node.name.as_str() {
             "findName" => {
                 let search_regex = get_attr!(node, bool, "regex", false)?;
 
@@ -354,10 +625,15 @@ fn mod_find<'a>(context: &'a mut Element, node: &Element) -> Result<Option<Vec<&
                     bail!("findName requires a name attribute");
                 };
 
-                let search_type = get_attr!(node, StringFilter(search_regex), "type")?;
+                let (search_prefix, search_type) = split_qualified_type(
+                    node,
+                    get_attr!(node, StringFilter(search_regex), "type")?,
+                    get_attr!(node, StringFilter(search_regex), "prefix")?,
+                )?;
 
                 SelectorFilter {
                     name: search_type,
+                    prefix: search_prefix,
                     attrs: vec![("name".to_string(), search_name)],
                     value: None,
                 }
@@ -366,10 +642,15 @@ fn mod_find<'a>(context: &'a mut Element, node: &Element) -> Result<Option<Vec<&
             "findLike" => {
                 let search_regex = get_attr!(node, bool, "regex", false)?;
 
-                let search_type = get_attr!(node, StringFilter(search_regex), "type")?;
+                let (search_prefix, search_type) = split_qualified_type(
+                    node,
+                    get_attr!(node, StringFilter(search_regex), "type")?,
+                    get_attr!(node, StringFilter(search_regex), "prefix")?,
+                )?;
 
                 SelectorFilter {
                     name: search_type,
+                    prefix: search_prefix,
                     ..SelectorFilter::from_selector_parent(node, search_regex)
                         .context("Failed to parse selector element")?
                 }
@@ -378,14 +659,27 @@ fn mod_find<'a>(context: &'a mut Element, node: &Element) -> Result<Option<Vec<&
             "findWithChildLike" => {
                 let search_regex = get_attr!(node, bool, "regex", false)?;
 
-                let search_type = get_attr!(node, StringFilter(search_regex), "type")?;
+                let (search_prefix, search_type) = split_qualified_type(
+                    node,
+                    get_attr!(node, StringFilter(search_regex), "type")?,
+                    get_attr!(node, StringFilter(search_regex), "prefix")?,
+                )?;
 
-                let search_child_type = get_attr!(node, StringFilter(search_regex), "child-type")?;
+                let search_recursive = get_attr!(node, bool, "recursive", false)?;
+
+                let (search_child_prefix, search_child_type) = split_qualified_type(
+                    node,
+                    get_attr!(node, StringFilter(search_regex), "child-type")?,
+                    get_attr!(node, StringFilter(search_regex), "child-prefix")?,
+                )?;
 
                 WithChildFilter {
                     name: search_type,
+                    prefix: search_prefix,
+                    recursive: search_recursive,
                     child_filter: SelectorFilter {
                         name: search_child_type,
+                        prefix: search_child_prefix,
                         ..SelectorFilter::from_selector_parent(node, search_regex)
                             .context("Failed to parse selector element")?
                     },
@@ -402,15 +696,15 @@ fn mod_find<'a>(context: &'a mut Element, node: &Element) -> Result<Option<Vec<&
 
                 vec.sort_by_key(|x| index.get(&(*x as *const Element)).unwrap());
 
-                vec
+                vec.into_iter().map(|element| (element, Captures::default())).collect()
             }
             _ => unreachable!(),
         };
 
         let it = if search_reverse {
-            Box::new(matches.into_iter().rev()) as Box<dyn Iterator<Item = &mut Element>>
+            Box::new(matches.into_iter().rev()) as Box<dyn Iterator<Item = (&mut Element, Captures)>>
         } else {
-            Box::new(matches.into_iter()) as Box<dyn Iterator<Item = &mut Element>>
+            Box::new(matches.into_iter()) as Box<dyn Iterator<Item = (&mut Element, Captures)>>
         };
 
         matches = it
@@ -438,36 +732,72 @@ fn mod_find<'a>(context: &'a mut Element, node: &Element) -> Result<Option<Vec<&
     }
 }
 
-fn mod_commands(context: &mut Element, element: &Element) -> Result<()> {
+fn mod_commands(context: &mut Element, element: &Element, captures: &Captures) -> Result<()> {
     for command in element.children.iter().filter_map(|x| x.as_element()) {
         match command.prefix.as_deref() {
             Some("mod") => {
-                if let Some(matches) = mod_find(context, command)? {
-                    for matched in matches {
-                        mod_commands(matched, command)?;
+                if let Some(matches) = mod_find_with_captures(context, command)? {
+                    for (matched, inner_captures) in matches {
+                        // Nested finds without any regex groups keep referring to the enclosing ones.
+                        let captures = if inner_captures.is_empty() {
+                            captures
+                        } else {
+                            &inner_captures
+                        };
+                        mod_commands(matched, command, captures)?;
                     }
                 } else {
                     match command.name.as_str() {
                         "selector" | "par" => {}
+                        "comment" => context.children.push(comment_node(command)),
                         "setAttributes" => {
-                            context
-                                .attributes
-                                .extend(command.attributes.iter().map(|(k, v)| (k.to_owned(), v.to_owned())));
+                            for (key, value) in command.attributes.iter() {
+                                context
+                                    .attributes
+                                    .insert(key.to_owned(), captures.substitute(value).into_owned());
+                            }
                         }
                         "removeAttributes" => {
                             for key in command.attributes.keys() {
-                                let _ = context.attributes.remove(key);
+                                let _ = context.attributes.shift_remove(key);
                             }
                         }
                         "setValue" => {
-                            context
-                                .children
-                                .retain(|node| !matches!(node, XMLNode::CData(_) | XMLNode::Text(_)));
-                            context.children.push(XMLNode::Text(command.get_text_trim()))
+                            let text = command.get_text_trim();
+                            match get_attr!(command, SetValueMode, "mode", SetValueMode::Replace)? {
+                                SetValueMode::Replace => {
+                                    context
+                                        .children
+                                        .retain(|node| !matches!(node, XMLNode::CData(_) | XMLNode::Text(_)));
+                                    context.children.push(XMLNode::Text(text))
+                                }
+                                // Only text nodes are touched here, child elements and CDATA stay where they are.
+                                SetValueMode::Append => {
+                                    match context.children.iter_mut().rev().find_map(Node::as_mut_text) {
+                                        Some(existing) => existing.push_str(&text),
+                                        None => context.children.push(XMLNode::Text(text)),
+                                    }
+                                }
+                                SetValueMode::Prepend => {
+                                    match context.children.iter_mut().find_map(Node::as_mut_text) {
+                                        Some(existing) => existing.insert_str(0, &text),
+                                        None => context.children.insert(0, XMLNode::Text(text)),
+                                    }
+                                }
+                            }
                         }
                         "removeTag" => {
                             context.prefix = Some(REMOVE_MARKER.to_string());
                         }
+                        "removeChildren" => {
+                            // Children are dropped immediately instead of being marked for `cleanup`,
+                            // the element itself may still be marked by a removeTag which is unaffected by this.
+                            if get_attr!(command, bool, "elementsOnly", false)? {
+                                context.children.retain(|node| !matches!(node, XMLNode::Element(_)));
+                            } else {
+                                context.children.clear();
+                            }
+                        }
                         "insertByFind" => {
                             let elements = command.children.iter().filter_map(Node::as_element);
 
@@ -592,3 +922,292 @@ fn mod_commands(context: &mut Element, element: &Element) -> Result<()> {
 
     Ok(())
 }
+
+#[cfg(test)]
+mod test {
+    use super::{find_paths, patch};
+    use crate::{
+        apply::{apply_one_lua, apply_one_xml, XmlAppendType},
+        lua::{LuaOutput, ModLuaRuntime},
+        xmltree::{builder, Element, SimpleTreeBuilder},
+    };
+
+    const DOCUMENT: &str = r#"<root><event name="a"/><hs:event name="a"/><event name="b"><text/></event><hs:event name="b"><hs:text/></hs:event></root>"#;
+
+    fn patch_document(code: &str) -> Element {
+        let mut document = builder::parse(&mut SimpleTreeBuilder, DOCUMENT).unwrap().unwrap();
+        let nodes = builder::parse_all(&mut SimpleTreeBuilder, code).unwrap();
+        patch(&mut document, nodes).unwrap();
+        document
+    }
+
+    /// Applies `code` to `document` like a regular append and returns the patched text.
+    fn apply(document: &str, code: &str) -> String {
+        apply_one_xml(document, code, XmlAppendType::Append, true, true).unwrap()
+    }
+
+    fn marked(document: &Element) -> Vec<String> {
+        document
+            .children
+            .iter()
+            .filter_map(|node| node.as_element())
+            .filter(|element| element.attributes.contains_key("marked"))
+            .map(|element| {
+                format!(
+                    "{}{}",
+                    element.prefix.as_deref().map_or(String::new(), |x| format!("{x}:")),
+                    element.attributes["name"]
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn find_ignores_prefix_by_default() {
+        let document = patch_document(
+            r#"<mod:findName type="event" name="a" limit="-1"><mod:setAttributes marked="1"/></mod:findName>"#,
+        );
+        assert_eq!(marked(&document), ["a", "hs:a"]);
+    }
+
+    #[test]
+    fn find_name_prefixed_type() {
+        let document =
+            patch_document(r#"<mod:findName type="hs:event" name="a"><mod:setAttributes marked="1"/></mod:findName>"#);
+        assert_eq!(marked(&document), ["hs:a"]);
+    }
+
+    #[test]
+    fn find_like_prefix_attribute() {
+        let document =
+            patch_document(r#"<mod:findLike type="event" prefix="hs"><mod:setAttributes marked="1"/></mod:findLike>"#);
+        assert_eq!(marked(&document), ["hs:a", "hs:b"]);
+
+        let document =
+            patch_document(r#"<mod:findLike type="event" prefix=""><mod:setAttributes marked="1"/></mod:findLike>"#);
+        assert_eq!(marked(&document), ["a", "b"]);
+
+        let document =
+            patch_document(r#"<mod:findLike prefix="h." regex="true"><mod:setAttributes marked="1"/></mod:findLike>"#);
+        assert_eq!(marked(&document), ["hs:a", "hs:b"]);
+    }
+
+    #[test]
+    fn find_with_child_like_child_prefix() {
+        let document = patch_document(
+            r#"<mod:findWithChildLike child-type="text" child-prefix="hs"><mod:setAttributes marked="1"/></mod:findWithChildLike>"#,
+        );
+        assert_eq!(marked(&document), ["hs:b"]);
+
+        let document = patch_document(
+            r#"<mod:findWithChildLike child-type="text"><mod:setAttributes marked="1"/></mod:findWithChildLike>"#,
+        );
+        assert_eq!(marked(&document), ["b", "hs:b"]);
+    }
+
+    #[test]
+    fn set_value_modes() {
+        let set_value = |document: &str, mode: &str| {
+            apply(
+                document,
+                &format!(r#"<mod:findLike type="a"><mod:setValue mode="{mode}">new</mod:setValue></mod:findLike>"#),
+            )
+        };
+
+        assert_eq!(
+            set_value("<root><a>old</a></root>", "replace"),
+            "<root><a>new</a></root>"
+        );
+        assert_eq!(
+            set_value("<root><a>old</a></root>", "append"),
+            "<root><a>oldnew</a></root>"
+        );
+        assert_eq!(
+            set_value("<root><a>old</a></root>", "prepend"),
+            "<root><a>newold</a></root>"
+        );
+        assert_eq!(set_value("<root><a/></root>", "append"), "<root><a>new</a></root>");
+        assert_eq!(
+            set_value("<root><a>one<b/>two</a></root>", "append"),
+            "<root><a>one<b/>twonew</a></root>"
+        );
+        assert_eq!(
+            set_value("<root><a><b/>two</a></root>", "prepend"),
+            "<root><a><b/>newtwo</a></root>"
+        );
+    }
+
+    #[test]
+    fn remove_children() {
+        let document = "<root><list>text<a/><b><c/></b></list></root>";
+
+        assert_eq!(
+            apply(
+                document,
+                r#"<mod:findLike type="list"><mod:removeChildren/></mod:findLike>"#
+            ),
+            "<root><list/></root>"
+        );
+        assert_eq!(
+            apply(
+                document,
+                r#"<mod:findLike type="list"><mod:removeChildren elementsOnly="true"/></mod:findLike>"#
+            ),
+            "<root><list>text</list></root>"
+        );
+
+        // Children added after clearing are kept.
+        assert_eq!(
+            apply(
+                document,
+                r#"<mod:findLike type="list"><mod:findLike type="a"><mod:removeTag/></mod:findLike><mod:removeChildren/><mod-append:d/></mod:findLike>"#,
+            ),
+            "<root><list><d/></list></root>"
+        );
+    }
+
+    #[test]
+    fn set_attributes_captures() {
+        let document =
+            r#"<root><weapon name="LASER_1" damage="1"/><weapon name="ION_2" damage="2"/><drone name="X"/></root>"#;
+
+        assert_eq!(
+            apply(
+                document,
+                r#"<mod:findLike type="(w)eapon" regex="true"><mod:selector name="(?<kind>[A-Z]+)_(\d)"/><mod:setAttributes copy="${kind}$3$$" type="$1"/></mod:findLike>"#
+            ),
+            r#"<root><weapon name="LASER_1" damage="1" copy="LASER1$" type="w"/><weapon name="ION_2" damage="2" copy="ION2$" type="w"/><drone name="X"/></root>"#
+        );
+
+        // Values are untouched without any captures.
+        let output = apply(
+            document,
+            r#"<mod:findLike type="drone"><mod:setAttributes cost="$1"/></mod:findLike>"#,
+        );
+        assert!(output.contains(r#"<drone name="X" cost="$1"/>"#), "{output}");
+
+        // References that don't refer to a group are kept as literal text.
+        let output = apply(
+            document,
+            r#"<mod:findLike type="(weapon)" regex="true"><mod:setAttributes a="$5 ${x} $ ${1" b="cost: $1$"/></mod:findLike>"#,
+        );
+        assert!(
+            output.contains(r#"<weapon name="LASER_1" damage="1" a="$5 ${x} $ ${1" b="cost: weapon$"/>"#),
+            "{output}"
+        );
+    }
+
+    #[test]
+    fn find_with_child_like_recursive() {
+        let document = r#"<root><event name="a"><choice><text id="x"/></choice></event><event name="b"><text id="x"/></event></root>"#;
+
+        assert_eq!(
+            apply(
+                document,
+                r#"<mod:findWithChildLike child-type="text"><mod:selector id="x"/><mod:setAttributes marked="1"/></mod:findWithChildLike>"#
+            ),
+            r#"<root><event name="a"><choice><text id="x"/></choice></event><event name="b" marked="1"><text id="x"/></event></root>"#
+        );
+        assert_eq!(
+            apply(
+                document,
+                r#"<mod:findWithChildLike child-type="text" recursive="true"><mod:selector id="x"/><mod:setAttributes marked="1"/></mod:findWithChildLike>"#
+            ),
+            r#"<root><event name="a" marked="1"><choice><text id="x"/></choice></event><event name="b" marked="1"><text id="x"/></event></root>"#
+        );
+    }
+
+    #[test]
+    fn conflicting_prefixes() {
+        let mut document = builder::parse(&mut SimpleTreeBuilder, DOCUMENT).unwrap().unwrap();
+        let nodes = builder::parse_all(
+            &mut SimpleTreeBuilder,
+            r#"<mod:findLike type="hs:event" prefix="hs"><mod:removeTag/></mod:findLike>"#,
+        )
+        .unwrap();
+        assert!(patch(&mut document, nodes).is_err());
+    }
+
+    #[test]
+    fn attribute_order_is_preserved() {
+        let source = r#"<weapon name="A" damage="1" cost="5"/><drone name="B" type="X" power="2"/>"#;
+        let order = |text: &str, names: &[&str]| {
+            let positions = names
+                .iter()
+                .map(|name| text.find(&format!(" {name}=")).unwrap())
+                .collect::<Vec<_>>();
+            assert!(positions.is_sorted(), "{names:?} out of order in {text}");
+        };
+
+        let output = apply_one_xml(source, "", XmlAppendType::Append, false, true).unwrap();
+        order(&output, &["name", "damage", "cost"]);
+        order(&output[output.find("<drone").unwrap()..], &["name", "type", "power"]);
+
+        let code = r#"<mod:findLike type="weapon"><mod:setAttributes damage="2" accuracy="3"/><mod:removeAttributes name=""/></mod:findLike>"#;
+        let output = apply_one_xml(source, code, XmlAppendType::Append, false, true).unwrap();
+        order(&output, &["damage", "cost", "accuracy"]);
+        assert!(!output.contains(r#"name="A""#));
+    }
+
+    #[test]
+    fn cdata_survives_noop_patch() {
+        let source = "<FTL>\n<script name=\"a\"><![CDATA[if (a < b && c) { return \"<tag>\"; }]]></script>\n</FTL>";
+
+        let output = apply_one_xml(source, "", XmlAppendType::Append, false, true).unwrap();
+        assert_eq!(output, source);
+
+        let runtime = ModLuaRuntime::new().unwrap();
+        let output = apply_one_lua(source, "", &runtime, &mut LuaOutput::Log, true).unwrap();
+        assert_eq!(output, source);
+    }
+
+    #[test]
+    fn comments_are_stripped_unless_kept() {
+        let source = "<event name=\"A\"><!-- base --><text>hi</text></event>";
+        let code = "<mod:findName name=\"A\"><mod-append:choice><!-- appended --></mod-append:choice></mod:findName>";
+
+        let stripped = apply_one_xml(source, code, XmlAppendType::Append, false, true).unwrap();
+        assert!(!stripped.contains("<!--"));
+
+        let kept = apply_one_xml(source, code, XmlAppendType::Append, true, true).unwrap();
+        assert!(kept.contains("<!-- base -->"));
+        assert!(kept.contains("<choice><!-- appended --></choice>"));
+    }
+
+    #[test]
+    fn mod_comment_survives_stripping() {
+        let source = "<event name=\"A\"><!-- base --><text>hi</text></event>";
+        let code = "<!-- patch --><mod:comment>top level</mod:comment><mod:findName name=\"A\"><mod:comment>\n  changed by test\n</mod:comment></mod:findName>";
+
+        let output = apply_one_xml(source, code, XmlAppendType::Append, false, true).unwrap();
+        assert_eq!(
+            output,
+            "<event name=\"A\"><text>hi</text><!-- changed by test --></event><!-- top level -->"
+        );
+    }
+
+    #[test]
+    fn unknown_entities_are_rejected_in_patches() {
+        let code = r#"<mod:findLike type="a"><mod:setValue>&nbsp;</mod:setValue></mod:findLike>"#;
+        let error = apply_one_xml("<a/>", code, XmlAppendType::Append, false, true).unwrap_err();
+        assert!(format!("{error:#}").contains("unknown entity"), "{error:#}");
+
+        // Documents being patched are still accepted.
+        assert!(apply_one_xml("<a>&nbsp;</a>", "", XmlAppendType::Append, false, true).is_ok());
+    }
+
+    #[test]
+    fn find_paths_nested() {
+        let mut document = builder::parse(&mut SimpleTreeBuilder, DOCUMENT).unwrap().unwrap();
+        let nodes = builder::parse_all(
+            &mut SimpleTreeBuilder,
+            r#"<mod:findName type="event" name="b"><mod:findLike type="text"/></mod:findName>"#,
+        )
+        .unwrap();
+        let outer = nodes[0].as_element().unwrap();
+        let inner = outer.children[0].as_element().unwrap();
+
+        assert_eq!(find_paths(&mut document, &[outer]).unwrap(), [[3]]);
+        assert_eq!(find_paths(&mut document, &[outer, inner]).unwrap(), [[3, 0]]);
+    }
+}
@@ -3,6 +3,7 @@ use std::{
     collections::{btree_map::Entry, BTreeMap, HashSet},
     fs::File,
     io::{Cursor, Read, Seek, Write},
+    ops::Range,
     path::{Path, PathBuf},
     sync::Arc,
 };
@@ -13,7 +14,10 @@ use log::{info, trace, warn};
 use parking_lot::Mutex;
 use regex::Regex;
 use silpkg::sync::Pkg;
-use speedy_xml::reader::Options;
+use speedy_xml::{
+    reader::{Event, Options},
+    Reader,
+};
 use zip::ZipArchive;
 
 use crate::{
@@ -21,13 +25,17 @@ use crate::{
     hyperspace,
     lua::{
         io::{LuaDirEnt, LuaDirectoryFS, LuaFS, LuaFileStats, LuaFileType},
-        LuaContext, ModLuaRuntime,
+        LuaContext, LuaOutput, ModLuaRuntime,
     },
     xmltree::{self, SimpleTreeBuilder, SimpleTreeEmitter},
-    HyperspaceState, Mod, ModSource, OpenModHandle, Settings, SharedState,
+    HyperspaceState, LastApply, Mod, ModSource, OpenModHandle, Settings, SharedState,
 };
 
 mod append;
+pub mod preview;
+mod timing;
+
+use timing::{timed, ApplyTimings, PhaseTimings};
 
 lazy_static! {
     // from: https://github.com/Vhati/Slipstream-Mod-Manager/blob/85cad4ffbef8583d908b189204d7d22a26be43f8/src/main/java/net/vhati/modmanager/core/ModUtilities.java#L267
@@ -40,6 +48,12 @@ lazy_static! {
     static ref KNOWN_TOP_LEVEL_DIRS: Regex = Regex::new(r"^(audio|data|fonts|img)/").unwrap();
 }
 
+pub const BACKUP_FILENAME: &str = "ftl.dat.vanilla";
+/// Part of the comment inserted between raw appended XML, see [`raw_append_xml`].
+const APPEND_MARKER: &str = "<!-- Appended by ftlman";
+/// Lua scripts in this directory of a mod are ran once after all mods have been applied.
+pub const POST_SCRIPT_DIR: &str = "mod-scripts/";
+
 #[derive(Debug)]
 pub enum ApplyStage {
     Downloading {
@@ -55,15 +69,55 @@ pub enum ApplyStage {
         file_idx: usize,
         files_total: usize,
     },
-    Repacking,
+    Repacking {
+        entry_idx: usize,
+        entries_total: usize,
+    },
 }
 
 pub fn unwrap_xml_text(xml_text: &str) -> Cow<'_, str> {
     WRAPPER_TAG_REGEX.replace_all(xml_text, "")
 }
 
+/// Written in front of raw appended documents that don't have an XML declaration of their own.
+const DEFAULT_XML_DECLARATION: &str = r#"<?xml version="1.0" encoding="utf-8"?>"#;
+
+/// Returns the XML declaration of `text` exactly as written.
+fn xml_declaration(text: &str) -> Option<&str> {
+    WRAPPER_TAG_REGEX
+        .captures_iter(text)
+        .find_map(|captures| captures.get(1))
+        .map(|declaration| declaration.as_str().trim_end_matches('\n'))
+}
+
+/// Returns the span of the DOCTYPE declaration in `text` if there is one before the first element.
+fn doctype_span(text: &str) -> Option<Range<usize>> {
+    let mut reader = Reader::with_options(text, Options::default().allow_top_level_text(true));
+    while let Some(Ok(event)) = reader.next() {
+        match event {
+            Event::Doctype(doctype) => return Some(doctype.position_in(&reader)),
+            Event::Start(_) | Event::Empty(_) => return None,
+            _ => (),
+        }
+    }
+    None
+}
+
+fn strip_doctype(text: Cow<'_, str>) -> Cow<'_, str> {
+    match doctype_span(&text) {
+        Some(span) => {
+            let mut text = text.into_owned();
+            text.replace_range(span, "");
+            Cow::Owned(text)
+        }
+        None => text,
+    }
+}
+
 fn unwrap_rewrap_single<E>(
     lower: &str,
+    keep_doctype: bool,
+    timings: &mut PhaseTimings,
     parse: impl FnOnce(&str) -> Result<Option<E>>,
     combine: impl FnOnce(E) -> Result<xmltree::Element>,
 ) -> Result<String> {
@@ -71,16 +125,27 @@ fn unwrap_rewrap_single<E>(
     let had_ftl_root = WRAPPER_TAG_REGEX.captures_iter(lower).any(|x| x.get(2).is_some());
     let lower_without_root = unwrap_xml_text(lower);
 
+    // The tree contains neither the declaration nor the DOCTYPE so they have to be copied over separately.
+    let mut output = Vec::new();
+    if let Some(declaration) = xml_declaration(lower) {
+        output.extend_from_slice(declaration.as_bytes());
+        output.push(b'\n');
+    }
+    if let Some(span) = doctype_span(&lower_without_root).filter(|_| keep_doctype) {
+        output.extend_from_slice(lower_without_root[span].as_bytes());
+        output.push(b'\n');
+    }
+
     let lower_wrapped = format!("<FTL>{lower_without_root}</FTL>");
 
-    let lower_parsed = parse(&lower_wrapped)
+    let lower_parsed = timed(&mut timings.parse, || parse(&lower_wrapped))
         .context("Could not parse XML document")?
         .ok_or_else(|| anyhow!("XML document does not contain a root element"))?;
 
-    let result = combine(lower_parsed)?;
+    let result = timed(&mut timings.append, || combine(lower_parsed))?;
 
-    Ok({
-        let mut writer = speedy_xml::writer::Writer::new(Cursor::new(Vec::new()));
+    timed(&mut timings.stringify, || -> Result<String> {
+        let mut writer = speedy_xml::writer::Writer::new(output);
 
         if had_ftl_root {
             xmltree::emitter::write_element(&mut writer, &SimpleTreeEmitter, &&result)
@@ -90,17 +155,22 @@ fn unwrap_rewrap_single<E>(
                 .context("Failed to write patched XML")?;
         }
 
-        String::from_utf8(writer.finish()?.into_inner())?
+        Ok(String::from_utf8(writer.finish()?)?)
     })
 }
 
-fn raw_append_xml(lower: &str, upper: &str) -> String {
+fn raw_append_xml(lower: &str, upper: &str, keep_doctype: bool) -> String {
     // FIXME: this can be made quicker
     let had_ftl_root = WRAPPER_TAG_REGEX.captures_iter(lower).any(|x| x.get(2).is_some());
-    let lower_without_root = unwrap_xml_text(lower);
-    let upper_without_root = unwrap_xml_text(upper);
+    let mut lower_without_root = unwrap_xml_text(lower);
+    let mut upper_without_root = unwrap_xml_text(upper);
+    if !keep_doctype {
+        lower_without_root = strip_doctype(lower_without_root);
+        upper_without_root = strip_doctype(upper_without_root);
+    }
 
-    const XML_DECLARATION: &str = concat!(r#"<?xml version="1.0" encoding="utf-8"?>"#, "\n");
+    let declaration = xml_declaration(lower).unwrap_or(DEFAULT_XML_DECLARATION);
+    // Starts with APPEND_MARKER
     const SEPARATOR: &str = concat!("\n\n<!-- Appended by ftlman ", env!("CARGO_PKG_VERSION"), " -->\n\n");
     const WRAPPER_OPEN: &str = "<FTL>\n";
     const WRAPPER_CLOSE: &str = "</FTL>\n";
@@ -112,10 +182,11 @@ fn raw_append_xml(lower: &str, upper: &str) -> String {
     };
 
     let capacity =
-        XML_DECLARATION.len() + wrapper_len + lower_without_root.len() + SEPARATOR.len() + upper_without_root.len() + 1;
+        declaration.len() + 1 + wrapper_len + lower_without_root.len() + SEPARATOR.len() + upper_without_root.len() + 1;
     let mut result = String::with_capacity(capacity);
 
-    result.push_str(XML_DECLARATION);
+    result.push_str(declaration);
+    result.push('\n');
     if had_ftl_root {
         result.push_str(WRAPPER_OPEN);
     }
@@ -135,18 +206,28 @@ fn raw_append_xml(lower: &str, upper: &str) -> String {
 fn unwrap_rewrap_xml(
     lower: &str,
     upper: &str,
+    keep_doctype: bool,
+    timings: &mut PhaseTimings,
     combine: impl FnOnce(&mut xmltree::Element, Vec<xmltree::Node>) -> Result<()>,
 ) -> Result<String> {
     let upper_without_root = unwrap_xml_text(upper);
-    let upper_elements = xmltree::builder::parse_all_with_options(
-        &mut SimpleTreeBuilder,
-        &upper_without_root,
-        Options::default().allow_top_level_text(true),
-    )
+    // Unknown entities in the patched document are kept as is like RapidXML does, but in a patch they are
+    // most likely a mistake (like an HTML `&nbsp;`) that would otherwise silently end up in the game files.
+    let upper_elements = timed(&mut timings.parse, || {
+        xmltree::builder::parse_all_with_options(
+            &mut SimpleTreeBuilder,
+            &upper_without_root,
+            Options::default()
+                .allow_top_level_text(true)
+                .reject_unknown_entities(true),
+        )
+    })
     .context("Could not parse XML append document")?;
 
     unwrap_rewrap_single(
         lower,
+        keep_doctype,
+        timings,
         |text| xmltree::builder::parse(&mut SimpleTreeBuilder, text).map_err(Into::into),
         |mut lower| {
             combine(&mut lower, upper_elements)?;
@@ -205,7 +286,7 @@ fn read_exact_n(reader: &mut impl Read, buf: &mut [u8]) -> Result<(), (usize, st
 
 // Some modders helpfully save their files as UTF-16 or with a UTF-8 BOM
 // TODO: This could be made a reader instead, probably won't change performance though.
-fn read_encoded_text(mut reader: impl Read) -> Result<String> {
+pub fn read_encoded_text(mut reader: impl Read) -> Result<String> {
     let mut peek = [0; 2];
     match read_exact_n(&mut reader, &mut peek) {
         Err((nread, err)) if err.kind() == std::io::ErrorKind::UnexpectedEof => {
@@ -276,16 +357,93 @@ impl AppendType {
     }
 }
 
-pub fn apply_one_xml(document: &str, patch: &str, kind: XmlAppendType) -> Result<String> {
+/// Returns the append files in `paths` whose base file is not present in `known_paths`.
+///
+/// Files outside of the top-level directories that are actually patched are not considered.
+pub fn missing_append_bases(paths: &[String], known_paths: &HashSet<String>) -> Vec<String> {
+    paths
+        .iter()
+        .filter(|name| KNOWN_TOP_LEVEL_DIRS.is_match(name))
+        .filter(|name| {
+            AppendType::from_filename(name).is_some_and(|(stem, _)| !known_paths.contains(&format!("{stem}.xml")))
+        })
+        .cloned()
+        .collect()
+}
+
+/// Comments in the patched document are only kept if `keep_comments` is true, raw appends always keep them.
+///
+/// The DOCTYPE declaration of the patched document is removed unless `keep_doctype` is true. A DOCTYPE in an
+/// XML append patch is never copied to the output, raw appends keep it along with the rest of the patch.
+pub fn apply_one_xml(
+    document: &str,
+    patch: &str,
+    kind: XmlAppendType,
+    keep_comments: bool,
+    keep_doctype: bool,
+) -> Result<String> {
+    apply_one_xml_timed(
+        document,
+        patch,
+        kind,
+        keep_comments,
+        keep_doctype,
+        &mut PhaseTimings::default(),
+    )
+}
+
+fn apply_one_xml_timed(
+    document: &str,
+    patch: &str,
+    kind: XmlAppendType,
+    keep_comments: bool,
+    keep_doctype: bool,
+    timings: &mut PhaseTimings,
+) -> Result<String> {
     Ok(match kind {
-        XmlAppendType::Append => unwrap_rewrap_xml(document, patch, append::patch)?,
-        XmlAppendType::RawAppend => raw_append_xml(document, patch),
+        XmlAppendType::Append => unwrap_rewrap_xml(document, patch, keep_doctype, timings, |context, mut nodes| {
+            if !keep_comments {
+                append::strip_comments(&mut context.children);
+                append::strip_comments(&mut nodes);
+            }
+            append::patch(context, nodes)
+        })?,
+        XmlAppendType::RawAppend => timed(&mut timings.append, || raw_append_xml(document, patch, keep_doctype)),
     })
 }
 
-pub fn apply_one_lua(document: &str, patch: &str, runtime: &ModLuaRuntime) -> Result<String> {
+/// Script output is written to `output`, which is left as is if the patch doesn't print anything.
+///
+/// The DOCTYPE declaration of the patched document is removed unless `keep_doctype` is true.
+pub fn apply_one_lua(
+    document: &str,
+    patch: &str,
+    runtime: &ModLuaRuntime,
+    output: &mut LuaOutput,
+    keep_doctype: bool,
+) -> Result<String> {
+    apply_one_lua_timed(
+        document,
+        patch,
+        runtime,
+        output,
+        keep_doctype,
+        &mut PhaseTimings::default(),
+    )
+}
+
+fn apply_one_lua_timed(
+    document: &str,
+    patch: &str,
+    runtime: &ModLuaRuntime,
+    output: &mut LuaOutput,
+    keep_doctype: bool,
+    timings: &mut PhaseTimings,
+) -> Result<String> {
     unwrap_rewrap_single(
         document,
+        keep_doctype,
+        timings,
         |text| {
             runtime
                 .arena()
@@ -300,8 +458,11 @@ pub fn apply_one_lua(document: &str, patch: &str, runtime: &ModLuaRuntime) -> Re
             let mut context = LuaContext {
                 document_root: Some(lower),
                 print_arena_stats: false,
+                output: std::mem::take(output),
             };
-            runtime.run(patch, "<patch>", &mut context)?;
+            let result = runtime.run(patch, "<patch>", &mut context);
+            *output = context.output;
+            result?;
             Ok(unsafe {
                 (*context.document_root.unwrap().as_ptr())
                     .as_ref_cell()
@@ -502,12 +663,13 @@ impl LuaFS for LuaPkgFS<'_> {
     }
 }
 
-struct LuaZipFS<'a, S: Read + Seek>(&'a mut ZipArchive<S>, VirtualFileTree);
+// The last field is the mod root prefix from `OpenModHandle::Zip`.
+struct LuaZipFS<'a, S: Read + Seek>(&'a mut ZipArchive<S>, VirtualFileTree, String);
 
 impl<S: Read + Seek> LuaFS for LuaZipFS<'_, S> {
     fn stat(&mut self, path: &str) -> std::io::Result<Option<LuaFileStats>> {
         self.1.stat(path, || {
-            let f = self.0.by_name(path)?;
+            let f = self.0.by_name(&format!("{}{path}", self.2))?;
             let length = f.size();
             Ok(LuaFileStats {
                 length: Some(length),
@@ -523,7 +685,7 @@ impl<S: Read + Seek> LuaFS for LuaZipFS<'_, S> {
     fn read_whole(&mut self, path: &str) -> std::io::Result<Vec<u8>> {
         self.1.read(path, || {
             let mut out = Vec::new();
-            let mut reader = self.0.by_name(path)?;
+            let mut reader = self.0.by_name(&format!("{}{path}", self.2))?;
             reader.read_to_end(&mut out)?;
             Ok(out)
         })
@@ -544,26 +706,166 @@ fn make_lua_filesystems<'a, 'b>(
             OpenModHandle::Directory { path } => Box::new(
                 LuaDirectoryFS::new(path.clone()).context("Failed to create virtual filesystem for directory")?,
             ),
-            OpenModHandle::Zip { archive } => {
-                let zip_vft = VirtualFileTree::from_paths(archive.file_names())
-                    .context("Failed to create virtual file tree for zip file")?;
-                Box::new(LuaZipFS(archive, zip_vft))
+            OpenModHandle::Zip { archive, root } => {
+                let zip_vft = VirtualFileTree::from_paths(
+                    archive.file_names().filter_map(|name| name.strip_prefix(root.as_str())),
+                )
+                .context("Failed to create virtual file tree for zip file")?;
+                Box::new(LuaZipFS(archive, zip_vft, root.clone()))
             }
         },
     ))
 }
 
-pub fn apply_ftl(ftl_path: &Path, mods: Vec<Mod>, mut on_progress: impl FnMut(ApplyStage), repack: bool) -> Result<()> {
+/// What [`apply_ftl`] applies mods on top of.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ApplyBase {
+    /// The vanilla archive backed up in [`BACKUP_FILENAME`], this is what normal patching uses.
+    Vanilla,
+    /// The current, possibly already patched, ftl.dat.
+    Current,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RepackMode {
+    Never,
+    /// Only repack if replaced entries left behind too much unused space.
+    IfFragmented,
+    Always,
+}
+
+/// Options controlling how [`apply_ftl`] patches the archive.
+#[derive(Debug, Clone, Copy)]
+pub struct ApplyOptions {
+    pub base: ApplyBase,
+    pub repack: RepackMode,
+    /// Whether comments in XML files should be kept.
+    pub keep_comments: bool,
+    /// Whether DOCTYPE declarations in XML files should be kept.
+    pub keep_doctype: bool,
+}
+
+impl ApplyOptions {
+    /// Options for applying on top of `base` as configured in `settings`.
+    pub fn from_settings(settings: &Settings, base: ApplyBase) -> Self {
+        Self {
+            base,
+            repack: repack_mode(settings),
+            keep_comments: settings.keep_xml_comments,
+            keep_doctype: settings.keep_xml_doctype,
+        }
+    }
+}
+
+/// Fraction of the archive that can be taken up by space freed by removed entries
+/// before [`RepackMode::IfFragmented`] will repack it.
+const MAX_FRAGMENTED_FRACTION: f64 = 0.1;
+
+/// Removes `name` from `pkg` if it exists, returning the number of bytes of data it contained.
+///
+/// Removing an entry leaves a hole in the archive that only gets reclaimed by a repack,
+/// the returned size is used to keep track of how much space has been wasted this way.
+fn remove_entry(pkg: &mut Pkg<File>, name: &str) -> Result<Option<u64>> {
+    let Some(size) = pkg.metadata(name).map(|metadata| u64::from(metadata.uncompressed_size)) else {
+        return Ok(None);
+    };
+
+    pkg.remove(name)
+        .with_context(|| format!("Failed to remove {name} from ftl.dat"))?;
+
+    Ok(Some(size))
+}
+
+const INSERT_FLAGS: silpkg::Flags = silpkg::Flags {
+    compression: silpkg::EntryCompression::None,
+};
+
+/// Repacks `pkg` by copying all of its entries into a fresh archive at `out_path`.
+///
+/// Unlike [`Pkg::repack`] this reports progress after every entry and checks `is_cancelled`
+/// in between them, returns `false` if the repack was cancelled.
+fn repack_with_progress(
+    pkg: &mut Pkg<File>,
+    out_path: &Path,
+    mut on_progress: impl FnMut(ApplyStage),
+    is_cancelled: impl Fn() -> bool,
+) -> Result<bool> {
+    let paths = pkg.paths().cloned().collect::<Vec<_>>();
+    let mut out = Pkg::create(
+        std::fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(out_path)
+            .with_context(|| format!("Failed to create {}", out_path.display()))?,
+    )
+    .context("Failed to create repacked archive")?;
+
+    for (i, path) in paths.iter().enumerate() {
+        if is_cancelled() {
+            return Ok(false);
+        }
+
+        on_progress(ApplyStage::Repacking {
+            entry_idx: i,
+            entries_total: paths.len(),
+        });
+
+        std::io::copy(&mut pkg.open(path)?, &mut out.insert(path.clone(), INSERT_FLAGS)?)
+            .with_context(|| format!("Failed to copy {path} into repacked archive"))?;
+    }
+
+    out.flush()?;
+    Ok(true)
+}
+
+/// Sorts `mods` into the order they are applied in, which may differ from their order in the mod list.
+///
+/// Hyperspace.ftl is always applied first, then mods are sorted by their [`Mod::apply_priority`].
+/// The sort is stable so mods with equal priorities stay in list order.
+pub fn apply_order(mods: Vec<Mod>) -> Result<Vec<Mod>> {
+    let mut mods = mods
+        .into_iter()
+        .map(|m| Ok((!m.is_hyperspace_ftl, m.apply_priority()?, m)))
+        .collect::<Result<Vec<_>>>()?;
+    mods.sort_by_key(|&(not_hyperspace, priority, _)| (not_hyperspace, priority));
+    Ok(mods.into_iter().map(|(_, _, m)| m).collect())
+}
+
+pub fn apply_ftl(
+    ftl_path: &Path,
+    mods: Vec<Mod>,
+    mut on_progress: impl FnMut(ApplyStage),
+    is_cancelled: impl Fn() -> bool,
+    options: ApplyOptions,
+) -> Result<()> {
+    let ApplyOptions {
+        base,
+        repack,
+        keep_comments,
+        keep_doctype,
+    } = options;
     on_progress(ApplyStage::Preparing);
+    let mut apply_timings = ApplyTimings::from_env();
 
     let data_file = {
-        const BACKUP_FILENAME: &str = "ftl.dat.vanilla";
         let vanilla_path = ftl_path.join(BACKUP_FILENAME);
         let original_path = ftl_path.join("ftl.dat");
 
+        if !original_path.exists() && crate::findftl::has_legacy_data_files(ftl_path) {
+            bail!(
+                "{} contains {} instead of ftl.dat, these files are used by FTL versions older than 1.6 which are not supported",
+                ftl_path.display(),
+                crate::findftl::LEGACY_DATA_FILES.join(" and ")
+            );
+        }
+
         if vanilla_path.exists() {
-            std::fs::copy(vanilla_path, &original_path)
-                .with_context(|| format!("Failed to copy {BACKUP_FILENAME} to ftl.dat"))?;
+            if base == ApplyBase::Vanilla {
+                std::fs::copy(vanilla_path, &original_path)
+                    .with_context(|| format!("Failed to copy {BACKUP_FILENAME} to ftl.dat"))?;
+            }
         } else {
             std::fs::copy(&original_path, vanilla_path).context("Failed to backup ftl.dat")?;
         }
@@ -577,14 +879,22 @@ pub fn apply_ftl(ftl_path: &Path, mods: Vec<Mod>, mut on_progress: impl FnMut(Ap
 
     let lua = ModLuaRuntime::new().context("Failed to initiailize Lua runtime")?;
     let mut pkg = silpkg::sync::Pkg::parse(data_file).context("Failed to parse ftl.dat")?;
+    let mut freed_bytes = 0;
 
-    const INSERT_FLAGS: silpkg::Flags = silpkg::Flags {
-        compression: silpkg::EntryCompression::None,
-    };
+    for (idx, m) in mods.iter().enumerate().filter(|(_, m)| m.enabled) {
+        for problem in crate::dependency_problems(&mods, idx) {
+            warn!("{} {problem}", m.title_or_filename()?);
+        }
+    }
+
+    // This only affects the order in which mods are applied, the mod list itself is left as is.
+    let mods = apply_order(mods.into_iter().filter(|x| x.enabled).collect())?;
 
-    for m in mods.into_iter().filter(|x| x.enabled) {
+    let mut post_scripts = Vec::new();
+    for m in mods {
         let mod_name = m.title_or_filename()?.to_string();
         info!("Applying mod {}", mod_name);
+        let mut scripts = Vec::new();
 
         let mut handle = m.source.open()?;
         let mut skipped_top_level_dirs = HashSet::new();
@@ -601,6 +911,13 @@ pub fn apply_ftl(ftl_path: &Path, mods: Vec<Mod>, mut on_progress: impl FnMut(Ap
                 continue;
             }
 
+            if name.starts_with(POST_SCRIPT_DIR) {
+                if name.ends_with(".lua") {
+                    scripts.push(name);
+                }
+                continue;
+            }
+
             if !KNOWN_TOP_LEVEL_DIRS.is_match(&name) {
                 // Slipstream doesn't allow unknown top-level directories,
                 // but it does allow unknown top-level files.
@@ -647,8 +964,16 @@ pub fn apply_ftl(ftl_path: &Path, mods: Vec<Mod>, mut on_progress: impl FnMut(Ap
                 )
                 .with_context(|| format!("Could not read {real_name} from ftl.dat"))?;
 
+                let mut timings = PhaseTimings::default();
                 let new_text = match operation {
-                    AppendType::Xml(xml_append_type) => apply_one_xml(&original_text, &append_text, xml_append_type),
+                    AppendType::Xml(xml_append_type) => apply_one_xml_timed(
+                        &original_text,
+                        &append_text,
+                        xml_append_type,
+                        keep_comments,
+                        keep_doctype,
+                        &mut timings,
+                    ),
                     AppendType::LuaAppend => {
                         let (mut pkgfs, mut modfs) = make_lua_filesystems(&mut pkg, &mut handle)?;
                         match lua.with_filesystems(
@@ -656,7 +981,16 @@ pub fn apply_ftl(ftl_path: &Path, mods: Vec<Mod>, mut on_progress: impl FnMut(Ap
                                 ("pkg", &mut pkgfs as &mut dyn LuaFS),
                                 ("mod", &mut *modfs as &mut dyn LuaFS),
                             ],
-                            || Ok(apply_one_lua(&original_text, &append_text, &lua)),
+                            || {
+                                Ok(apply_one_lua_timed(
+                                    &original_text,
+                                    &append_text,
+                                    &lua,
+                                    &mut LuaOutput::Log,
+                                    keep_doctype,
+                                    &mut timings,
+                                ))
+                            },
                         ) {
                             Ok(Ok(text)) => Ok(text),
                             Ok(Err(other_error)) => Err(other_error),
@@ -666,12 +1000,17 @@ pub fn apply_ftl(ftl_path: &Path, mods: Vec<Mod>, mut on_progress: impl FnMut(Ap
                 }
                 .with_context(|| format!("Could not patch XML file {real_name} according to {name}"))?;
 
-                match pkg.remove(&real_name) {
-                    Ok(()) => {}
-                    Err(silpkg::sync::RemoveError::NotFound) => {}
-                    Err(x) => return Err(x).with_context(|| format!("Failed to remove {real_name} from ftl.dat"))?,
+                if let Some(apply_timings) = apply_timings.as_mut() {
+                    apply_timings.record(format!("{name} from {mod_name}"), timings);
+                }
+
+                if new_text == original_text {
+                    trace!("{real_name} was not changed by {name}");
+                    continue;
                 }
 
+                freed_bytes += remove_entry(&mut pkg, &real_name)?.unwrap_or(0);
+
                 pkg.insert(real_name.clone(), INSERT_FLAGS)
                     .map_err(|x| anyhow!(x))
                     .and_then(|mut x| x.write_all(new_text.as_bytes()).map_err(Into::into))
@@ -683,20 +1022,18 @@ pub fn apply_ftl(ftl_path: &Path, mods: Vec<Mod>, mut on_progress: impl FnMut(Ap
                 );
 
                 let text = read_encoded_text(&mut handle.open(&name)?)?;
-                if pkg.contains(&target_name) {
+                if let Some(size) = remove_entry(&mut pkg, &target_name)? {
                     trace!("Overwriting {target_name}");
-                    pkg.remove(&target_name)
-                        .with_context(|| format!("Failed to remove {target_name} from ftl.dat"))?
+                    freed_bytes += size;
                 } else {
                     trace!("Inserting {target_name}")
                 }
 
                 pkg.insert(target_name, INSERT_FLAGS)?.write_all(text.as_bytes())?;
             } else {
-                if pkg.contains(&name) {
+                if let Some(size) = remove_entry(&mut pkg, &name)? {
                     trace!("Overwriting {name}");
-                    pkg.remove(&name)
-                        .with_context(|| format!("Failed to remove {name} from ftl.dat"))?;
+                    freed_bytes += size;
                 } else {
                     trace!("Inserting {name}");
                 }
@@ -727,6 +1064,7 @@ pub fn apply_ftl(ftl_path: &Path, mods: Vec<Mod>, mut on_progress: impl FnMut(Ap
                             quick_xml::events::Event::End(_) => {
                                 writer.write_event(quick_xml::events::Event::End(element_stack.pop().unwrap()))?;
                             }
+                            quick_xml::events::Event::DocType(_) if !keep_doctype => (),
                             event => writer.write_event(event)?,
                         }
                     }
@@ -751,107 +1089,288 @@ pub fn apply_ftl(ftl_path: &Path, mods: Vec<Mod>, mut on_progress: impl FnMut(Ap
             }
         }
         trace!("Applied {}", m.filename());
+
+        if !scripts.is_empty() {
+            scripts.sort();
+            post_scripts.push((mod_name, m.source.clone(), scripts));
+        }
+    }
+
+    for (mod_name, source, scripts) in post_scripts {
+        let mut handle = source.open()?;
+        for (j, name) in scripts.iter().enumerate() {
+            on_progress(ApplyStage::Mod {
+                mod_name: mod_name.clone(),
+                file_idx: j,
+                files_total: scripts.len(),
+            });
+
+            info!("Running {name} from {mod_name}");
+            let code = read_encoded_text(
+                handle
+                    .open(name)
+                    .with_context(|| format!("Failed to open {name} from mod {mod_name}"))?,
+            )
+            .with_context(|| format!("Failed to read {name} from mod {mod_name}"))?;
+
+            let (mut pkgfs, mut modfs) = make_lua_filesystems(&mut pkg, &mut handle)?;
+            lua.with_filesystems(
+                [
+                    ("pkg", &mut pkgfs as &mut dyn LuaFS),
+                    ("mod", &mut *modfs as &mut dyn LuaFS),
+                ],
+                || {
+                    let mut context = LuaContext {
+                        document_root: None,
+                        print_arena_stats: false,
+                        output: LuaOutput::Log,
+                    };
+                    lua.run(&code, name, &mut context)
+                },
+            )
+            .with_context(|| format!("Failed to run {name} from mod {mod_name}"))?;
+        }
     }
 
-    trace!("Repacking");
+    info!("Peak Lua memory usage: {} bytes", lua.peak_memory_usage());
+    if let Some(apply_timings) = apply_timings {
+        apply_timings.log();
+    }
+
+    let repack = match repack {
+        RepackMode::Never => false,
+        RepackMode::IfFragmented => {
+            let archive_size = std::fs::metadata(ftl_path.join("ftl.dat"))
+                .context("Failed to stat ftl.dat")?
+                .len();
+            let fragmented = freed_bytes as f64 > archive_size as f64 * MAX_FRAGMENTED_FRACTION;
+            info!(
+                "Replaced entries take up {freed_bytes} out of {archive_size} bytes, {}",
+                if fragmented { "repacking" } else { "skipping repack" }
+            );
+            fragmented
+        }
+        RepackMode::Always => true,
+    };
+
     if repack {
-        on_progress(ApplyStage::Repacking);
-        pkg.repack().context("Failed to repack ftl.dat")?;
+        trace!("Repacking");
+        let repacked_path = ftl_path.join("ftl.dat.repacked");
+        if repack_with_progress(&mut pkg, &repacked_path, &mut on_progress, is_cancelled)
+            .context("Failed to repack ftl.dat")?
+        {
+            drop(pkg);
+            std::fs::rename(&repacked_path, ftl_path.join("ftl.dat"))
+                .context("Failed to replace ftl.dat with repacked archive")?;
+            return Ok(());
+        } else {
+            warn!("Repacking cancelled, ftl.dat will be left unpacked");
+            std::fs::remove_file(&repacked_path).context("Failed to remove partially repacked archive")?;
+        }
     }
     pkg.flush()?;
 
     Ok(())
 }
 
-pub fn apply(
-    ftl_path: PathBuf,
-    state: Arc<Mutex<SharedState>>,
-    hs: Option<hyperspace::Installer>,
-    settings: Settings,
-) -> Result<()> {
-    let mut lock = state.lock();
+/// Downloads and installs the selected Hyperspace release, or uninstalls Hyperspace if none is selected.
+///
+/// Returns Hyperspace.ftl as a mod that has to be applied before all other mods.
+fn install_hyperspace(
+    ftl_path: &Path,
+    state: &Mutex<SharedState>,
+    installer: hyperspace::Installer,
+    settings: &Settings,
+) -> Result<Option<Mod>> {
+    let (hyperspace, egui_ctx) = {
+        let lock = state.lock();
+        (lock.hyperspace.clone(), lock.ctx.clone())
+    };
 
-    if lock.locked {
-        bail!("Apply process already running");
-    }
-    lock.locked = true;
-    let mut mods = lock.mods.clone();
+    let Some(HyperspaceState { release }) = hyperspace else {
+        installer.disable(ftl_path)?;
+        return Ok(None);
+    };
 
-    if let Some(installer) = hs {
-        if let Some(HyperspaceState { release }) = lock.hyperspace.clone() {
-            let egui_ctx = lock.ctx.clone();
-            drop(lock);
-
-            let zip_data = CACHE.read_or_create_key("hyperspace", release.name(), || {
-                state.lock().apply_stage = Some(ApplyStage::Downloading {
-                    is_patch: false,
-                    version: Some(release.name().into()),
-                    progress: None,
-                });
-
-                release.fetch_zip(|current, max| {
+    let zip_data = CACHE.read_or_create_key(
+        "hyperspace",
+        &release.cache_key()?,
+        |data| {
+            let mut zip = ZipArchive::new(Cursor::new(data)).context("Not a valid zip archive")?;
+            release.extract_hyperspace_ftl(&mut zip).map(drop)
+        },
+        || {
+            // Entries used to be keyed by release name, reuse one if it still matches the release's asset.
+            if let Some(data) = CACHE.take_key("hyperspace", release.legacy_cache_key())? {
+                match release.verify_zip(&data) {
+                    Ok(()) => return Ok(data),
+                    Err(error) => warn!("Discarding outdated cached Hyperspace zip: {error:#}"),
+                }
+            }
+
+            state.lock().apply_stage = Some(ApplyStage::Downloading {
+                is_patch: false,
+                version: Some(release.name().into()),
+                progress: None,
+            });
+
+            release.fetch_zip(
+                settings.download_connections(),
+                &settings.hyperspace_mirrors,
+                |current, max| {
                     let Some(ApplyStage::Downloading { ref mut progress, .. }) = state.lock().apply_stage else {
                         unreachable!();
                     };
                     *progress = Some((current, max));
                     egui_ctx.request_repaint();
-                })
-            })?;
-            let mut zip = ZipArchive::new(Cursor::new(zip_data))?;
-
-            let patcher = if let Some(patch) = installer.required_patch() {
-                if patch.is_remote() {
-                    state.lock().apply_stage = Some(ApplyStage::Downloading {
-                        is_patch: true,
-                        version: Some(patch.source_version_name().into()),
-                        progress: None,
-                    });
-                }
-                Some(
-                    patch
-                        .fetch_or_load_cached(&mut zip, |current, total| {
-                            let Some(ApplyStage::Downloading { ref mut progress, .. }) = state.lock().apply_stage
-                            else {
-                                unreachable!();
-                            };
-                            *progress = Some((current, total));
-                            egui_ctx.request_repaint();
-                        })
-                        .context("Failed to download patch")?,
-                )
-            } else {
-                None
-            };
-
-            state.lock().apply_stage = Some(ApplyStage::InstallingHyperspace);
-            installer.install(&ftl_path, &mut zip, patcher.as_ref())?;
-            release.extract_hyperspace_ftl(&mut zip)?;
-
-            egui_ctx.request_repaint();
-            drop(egui_ctx);
-
-            mods.insert(
-                0,
-                Mod {
-                    is_hyperspace_ftl: true,
-                    ..Mod::new_with_enabled(
-                        ModSource::InMemoryZip {
-                            filename: "hyperspace.ftl".to_string(),
-                            data: release.extract_hyperspace_ftl(&mut zip)?,
-                        },
-                        true,
-                    )
                 },
-            );
-        } else {
-            drop(lock);
-
-            installer.disable(&ftl_path)?;
-        };
+            )
+        },
+    )?;
+    let mut zip = ZipArchive::new(Cursor::new(zip_data))?;
+
+    let patcher = if let Some(patch) = installer.required_patch() {
+        if patch.is_remote() {
+            state.lock().apply_stage = Some(ApplyStage::Downloading {
+                is_patch: true,
+                version: Some(patch.source_version_name().into()),
+                progress: None,
+            });
+        }
+        Some(
+            patch
+                .fetch_or_load_cached(&mut zip, settings.download_connections(), |current, total| {
+                    let Some(ApplyStage::Downloading { ref mut progress, .. }) = state.lock().apply_stage else {
+                        unreachable!();
+                    };
+                    *progress = Some((current, total));
+                    egui_ctx.request_repaint();
+                })
+                .context("Failed to download patch")?,
+        )
     } else {
-        drop(lock);
+        None
     };
 
+    state.lock().apply_stage = Some(ApplyStage::InstallingHyperspace);
+    installer.install(ftl_path, &mut zip, patcher.as_ref())?;
+    egui_ctx.request_repaint();
+
+    Ok(Some(Mod {
+        is_hyperspace_ftl: true,
+        ..Mod::new_with_enabled(
+            ModSource::InMemoryZip {
+                filename: "hyperspace.ftl".to_string(),
+                data: release.extract_hyperspace_ftl(&mut zip)?,
+            },
+            true,
+        )
+    }))
+}
+
+fn repack_mode(settings: &Settings) -> RepackMode {
+    match (settings.repack_ftl_data, settings.skip_unneeded_repack) {
+        (false, _) => RepackMode::Never,
+        (true, true) => RepackMode::IfFragmented,
+        (true, false) => RepackMode::Always,
+    }
+}
+
+fn lock_for_apply(state: &Mutex<SharedState>) -> Result<Vec<Mod>> {
+    let mut lock = state.lock();
+
+    if lock.locked {
+        bail!("Apply process already running");
+    }
+    lock.locked = true;
+    lock.cancel_requested = false;
+    Ok(lock.mods.clone())
+}
+
+fn unlock_after_apply(state: &Mutex<SharedState>) {
+    let mut lock = state.lock();
+    lock.apply_stage = None;
+    lock.locked = false;
+    lock.ctx.request_repaint();
+}
+
+/// Returned by [`apply`] when there is no vanilla backup to start from but ftl.dat looks like it has
+/// already been patched, applying would then stack changes on top of the previous ones.
+#[derive(Debug)]
+pub struct ModifiedArchiveError {
+    pub reason: String,
+}
+
+impl std::fmt::Display for ModifiedArchiveError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "ftl.dat appears to already be modified ({}) but there is no {BACKUP_FILENAME} to restore it from",
+            self.reason
+        )
+    }
+}
+
+impl std::error::Error for ModifiedArchiveError {}
+
+/// Checks whether ftl.dat looks like it has already been patched, returning why if it does.
+pub fn detect_modified_archive(ftl_path: &Path) -> Result<Option<String>> {
+    let path = ftl_path.join("ftl.dat");
+    let file = File::open(&path).with_context(|| format!("Failed to open {}", path.display()))?;
+    let mut pkg = silpkg::sync::Pkg::parse(file).with_context(|| format!("Failed to parse {}", path.display()))?;
+
+    if pkg.metadata("data/hyperspace.xml").is_some() {
+        return Ok(Some("it contains Hyperspace's data/hyperspace.xml".to_owned()));
+    }
+
+    let xml_paths = pkg
+        .paths()
+        .filter(|path| path.ends_with(".xml"))
+        .cloned()
+        .collect::<Vec<_>>();
+    for xml_path in xml_paths {
+        let mut content = String::new();
+        pkg.open(&xml_path)
+            .with_context(|| format!("Failed to open {xml_path} from ftl.dat"))?
+            .read_to_string(&mut content)
+            .with_context(|| format!("Failed to read {xml_path} from ftl.dat"))?;
+        if content.contains(APPEND_MARKER) {
+            return Ok(Some(format!("{xml_path} contains XML appended by ftlman")));
+        }
+    }
+
+    Ok(None)
+}
+
+/// Backs up the current ftl.dat as the vanilla archive that subsequent applies start from.
+pub fn adopt_current_archive(ftl_path: &Path) -> Result<()> {
+    std::fs::copy(ftl_path.join("ftl.dat"), ftl_path.join(BACKUP_FILENAME))
+        .with_context(|| format!("Failed to copy ftl.dat to {BACKUP_FILENAME}"))?;
+    info!("Using current ftl.dat as the new {BACKUP_FILENAME}");
+    Ok(())
+}
+
+pub fn apply(
+    ftl_path: PathBuf,
+    state: Arc<Mutex<SharedState>>,
+    hs: Option<hyperspace::Installer>,
+    settings: Settings,
+) -> Result<()> {
+    if !ftl_path.join(BACKUP_FILENAME).exists() && ftl_path.join("ftl.dat").exists() {
+        if let Some(reason) = detect_modified_archive(&ftl_path)? {
+            return Err(ModifiedArchiveError { reason }.into());
+        }
+    }
+
+    let mut mods = lock_for_apply(&state)?;
+    let mod_count = mods.iter().filter(|m| m.enabled).count();
+
+    if let Some(installer) = hs {
+        if let Some(hyperspace_ftl) = install_hyperspace(&ftl_path, &state, installer, &settings)? {
+            mods.insert(0, hyperspace_ftl);
+        }
+    }
+
     apply_ftl(
         &ftl_path,
         mods,
@@ -860,13 +1379,101 @@ pub fn apply(
             lock.apply_stage = Some(stage);
             lock.ctx.request_repaint();
         },
-        settings.repack_ftl_data,
+        || state.lock().cancel_requested,
+        ApplyOptions::from_settings(&settings, ApplyBase::Vanilla),
     )?;
 
-    let mut lock = state.lock();
-    lock.apply_stage = None;
-    lock.locked = false;
-    lock.ctx.request_repaint();
+    state.lock().last_apply = Some(LastApply::now(mod_count));
+    unlock_after_apply(&state);
+
+    Ok(())
+}
+
+/// Installs (or uninstalls) only Hyperspace, applying Hyperspace.ftl on top of the current ftl.dat.
+///
+/// Unlike [`apply`] this doesn't start from the vanilla archive, so any previously applied mods are kept.
+pub fn apply_hyperspace_only(
+    ftl_path: PathBuf,
+    state: Arc<Mutex<SharedState>>,
+    installer: hyperspace::Installer,
+    settings: Settings,
+) -> Result<()> {
+    lock_for_apply(&state)?;
+
+    if let Some(hyperspace_ftl) = install_hyperspace(&ftl_path, &state, installer, &settings)? {
+        apply_ftl(
+            &ftl_path,
+            vec![hyperspace_ftl],
+            |stage| {
+                let mut lock = state.lock();
+                lock.apply_stage = Some(stage);
+                lock.ctx.request_repaint();
+            },
+            || state.lock().cancel_requested,
+            ApplyOptions::from_settings(&settings, ApplyBase::Current),
+        )?;
+    }
+
+    unlock_after_apply(&state);
 
     Ok(())
 }
+
+#[cfg(test)]
+mod test {
+    use super::{raw_append_xml, unwrap_rewrap_xml, PhaseTimings, DEFAULT_XML_DECLARATION};
+
+    const DOCUMENT: &str = "<?xml version=\"1.0\"?>\n<!DOCTYPE FTL [<!ENTITY x \"y\">]>\n<FTL><a/></FTL>";
+
+    fn append(keep_doctype: bool) -> String {
+        unwrap_rewrap_xml(
+            DOCUMENT,
+            "<b/>",
+            keep_doctype,
+            &mut PhaseTimings::default(),
+            |context, nodes| {
+                context.children.extend(nodes);
+                Ok(())
+            },
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn doctype_policy() {
+        assert_eq!(
+            append(true),
+            "<?xml version=\"1.0\"?>\n<!DOCTYPE FTL [<!ENTITY x \"y\">]>\n<FTL>\n<a/><b/></FTL>"
+        );
+        assert_eq!(append(false), "<?xml version=\"1.0\"?>\n<FTL>\n<a/><b/></FTL>");
+
+        let raw = raw_append_xml(DOCUMENT, "<!DOCTYPE FTL><b/>", true);
+        assert_eq!(raw.matches("<!DOCTYPE").count(), 2);
+        let raw = raw_append_xml(DOCUMENT, "<!DOCTYPE FTL><b/>", false);
+        assert!(!raw.contains("<!DOCTYPE"), "{raw}");
+        assert!(raw.contains("<a/>") && raw.contains("<b/>"));
+    }
+
+    #[test]
+    fn xml_declaration_is_preserved() {
+        for declaration in [
+            r#"<?xml version="1.0" encoding="UTF-8"?>"#,
+            r#"<?xml version="1.0" encoding="utf-8" standalone="yes"?>"#,
+        ] {
+            let document = format!("{declaration}\n\n<FTL><a/></FTL>");
+            let patched = unwrap_rewrap_xml(&document, "", true, &mut PhaseTimings::default(), |_, _| Ok(())).unwrap();
+            assert_eq!(patched, format!("{declaration}\n<FTL><a/></FTL>"));
+            let patched = raw_append_xml(&document, "<b/>", true);
+            assert!(patched.starts_with(&format!("{declaration}\n<FTL>\n<a/>")), "{patched}");
+        }
+
+        // Only raw appends add a declaration to documents without one.
+        let patched = unwrap_rewrap_xml("<a/>", "", true, &mut PhaseTimings::default(), |_, _| Ok(())).unwrap();
+        assert_eq!(patched, "<a/>");
+        let patched = raw_append_xml("<a/>", "<b/>", true);
+        assert!(
+            patched.starts_with(&format!("{DEFAULT_XML_DECLARATION}\n<a/>")),
+            "{patched}"
+        );
+    }
+}
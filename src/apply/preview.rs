@@ -0,0 +1,217 @@
+//! Evaluation of single finds from an XML append patch, used by the sandbox to show what a find
+//! would match without applying the whole patch.
+
+use std::{collections::HashSet, ops::Range};
+
+use anyhow::{Context, Result};
+use speedy_xml::reader::{Event, Options, Reader};
+
+use super::{append, unwrap_xml_text, WRAPPER_TAG_REGEX};
+use crate::xmltree::{self, Element, SimpleTreeBuilder};
+
+pub struct FindPreview {
+    /// Qualified name of the innermost find tag around the cursor.
+    pub find: String,
+    /// Byte ranges of the opening tags of the matched elements in the document.
+    pub matches: Vec<Range<usize>>,
+}
+
+/// Byte ranges of the text removed from `text` by [`unwrap_xml_text`].
+fn wrapper_tag_ranges(text: &str) -> Vec<Range<usize>> {
+    WRAPPER_TAG_REGEX.find_iter(text).map(|m| m.range()).collect()
+}
+
+fn unwrapped_to_original(removed: &[Range<usize>], offset: usize) -> usize {
+    let mut result = offset;
+    for range in removed {
+        if range.start > result {
+            break;
+        }
+        result += range.len();
+    }
+    result
+}
+
+fn original_to_unwrapped(removed: &[Range<usize>], offset: usize) -> usize {
+    let mut result = offset;
+    for range in removed.iter().filter(|range| range.start < offset) {
+        result -= range.end.min(offset) - range.start;
+    }
+    result
+}
+
+/// Returns the path to the innermost element containing `cursor`, or None if `text` fails to
+/// parse before it.
+///
+/// See [`append::find_paths`] for the format of the path.
+fn element_path_at(text: &str, cursor: usize) -> Option<Vec<usize>> {
+    let mut reader = Reader::with_options(text, Options::default().allow_top_level_text(true));
+    let mut path = Vec::new();
+    let mut next_index = vec![0];
+
+    while let Some(event) = reader.next() {
+        let event = event.ok()?;
+        match event {
+            Event::Start(ref start) | Event::Empty(ref start) => {
+                let span = start.position_in(&reader);
+                if span.start > cursor {
+                    break;
+                }
+
+                let index = next_index.last_mut().unwrap();
+                path.push(*index);
+                *index += 1;
+
+                if matches!(event, Event::Empty(_)) {
+                    if span.end > cursor {
+                        break;
+                    }
+                    path.pop();
+                } else {
+                    next_index.push(0);
+                }
+            }
+            Event::End(end) => {
+                if end.position_in(&reader).end > cursor {
+                    break;
+                }
+
+                // Unmatched end tags are ignored when building the tree too.
+                if path.pop().is_some() {
+                    next_index.pop();
+                }
+            }
+            _ => (),
+        }
+    }
+
+    Some(path)
+}
+
+/// Returns the byte ranges of the opening tags of elements at `paths` in `text`.
+fn element_spans(text: &str, paths: &HashSet<Vec<usize>>) -> Result<Vec<Range<usize>>> {
+    let mut reader = Reader::with_options(text, Options::default().allow_top_level_text(true));
+    let mut path = Vec::new();
+    let mut next_index = vec![0];
+    let mut spans = Vec::new();
+
+    while let Some(event) = reader.next() {
+        let event = event?;
+        match event {
+            Event::Start(ref start) | Event::Empty(ref start) => {
+                let index = next_index.last_mut().unwrap();
+                path.push(*index);
+                *index += 1;
+
+                if paths.contains(&path) {
+                    spans.push(start.position_in(&reader));
+                }
+
+                if matches!(event, Event::Empty(_)) {
+                    path.pop();
+                } else {
+                    next_index.push(0);
+                }
+            }
+            Event::End(_) => {
+                if path.pop().is_some() {
+                    next_index.pop();
+                }
+            }
+            _ => (),
+        }
+    }
+
+    Ok(spans)
+}
+
+/// Runs the find tag around `cursor` in `patch` against `document`, along with any find tags
+/// it is nested in.
+///
+/// Commands are not executed, so finds nested in other finds run against the unmodified matches.
+/// Returns None if the cursor is not inside of a find tag.
+pub fn preview_find_at(document: &str, patch: &str, cursor: usize) -> Result<Option<FindPreview>> {
+    let patch_removed = wrapper_tag_ranges(patch);
+    let patch_without_root = unwrap_xml_text(patch);
+    let Some(path) = element_path_at(&patch_without_root, original_to_unwrapped(&patch_removed, cursor)) else {
+        return Ok(None);
+    };
+
+    let nodes = xmltree::builder::parse_all_with_options(
+        &mut SimpleTreeBuilder,
+        &patch_without_root,
+        Options::default().allow_top_level_text(true),
+    )
+    .context("Could not parse XML append document")?;
+
+    let mut finds: Vec<&Element> = Vec::new();
+    let mut children = &nodes;
+    for index in path {
+        let Some(element) = children.iter().filter_map(|node| node.as_element()).nth(index) else {
+            break;
+        };
+
+        if element.prefix.as_deref() != Some("mod") || !append::FIND_TAGS.contains(&element.name.as_str()) {
+            break;
+        }
+
+        finds.push(element);
+        children = &element.children;
+    }
+
+    let Some(innermost) = finds.last() else {
+        return Ok(None);
+    };
+
+    let document_removed = wrapper_tag_ranges(document);
+    let document_without_root = unwrap_xml_text(document);
+    let mut root = xmltree::builder::parse(&mut SimpleTreeBuilder, &format!("<FTL>{document_without_root}</FTL>"))
+        .context("Could not parse XML document")?
+        .context("XML document does not contain a root element")?;
+
+    let paths = append::find_paths(&mut root, &finds)?.into_iter().collect();
+    let matches = element_spans(&document_without_root, &paths)?
+        .into_iter()
+        .map(|span| {
+            // Wrapper tags can't appear inside of another tag so only the start needs to be mapped.
+            let start = unwrapped_to_original(&document_removed, span.start);
+            start..start + span.len()
+        })
+        .collect();
+
+    Ok(Some(FindPreview {
+        find: innermost.make_qualified_name(),
+        matches,
+    }))
+}
+
+#[cfg(test)]
+mod test {
+    use super::preview_find_at;
+
+    #[test]
+    fn matches_map_to_wrapped_document() {
+        let document = "<?xml version=\"1.0\"?>\n<FTL><event name=\"a\"/><event name=\"b\"><text/></event></FTL>";
+        let patch = "<FTL><mod:findName name=\"b\"><mod:findLike type=\"text\"/></mod:findName></FTL>";
+
+        let outer = preview_find_at(document, patch, patch.find("findName").unwrap())
+            .unwrap()
+            .unwrap();
+        assert_eq!(outer.find, "mod:findName");
+        assert_eq!(
+            outer.matches.iter().map(|m| &document[m.clone()]).collect::<Vec<_>>(),
+            ["<event name=\"b\">"]
+        );
+
+        let inner = preview_find_at(document, patch, patch.find("findLike").unwrap())
+            .unwrap()
+            .unwrap();
+        assert_eq!(inner.find, "mod:findLike");
+        assert_eq!(
+            inner.matches.iter().map(|m| &document[m.clone()]).collect::<Vec<_>>(),
+            ["<text/>"]
+        );
+
+        assert!(preview_find_at(document, "<event/>", 1).unwrap().is_none());
+    }
+}
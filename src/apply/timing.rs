@@ -0,0 +1,121 @@
+//! Opt-in per-file timing of applies, enabled by setting [`TIMINGS_ENV`].
+
+use std::time::{Duration, Instant};
+
+use log::info;
+
+/// Environment variable that enables timing, its value is the number of slowest files to log.
+pub const TIMINGS_ENV: &str = "FTLMAN_APPLY_TIMINGS";
+const DEFAULT_TOP: usize = 10;
+
+/// Time spent in each phase of patching a single file.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct PhaseTimings {
+    pub parse: Duration,
+    pub append: Duration,
+    pub stringify: Duration,
+}
+
+impl PhaseTimings {
+    pub fn total(&self) -> Duration {
+        self.parse + self.append + self.stringify
+    }
+}
+
+/// Runs `f` and adds the time it took to `phase`.
+pub fn timed<T>(phase: &mut Duration, f: impl FnOnce() -> T) -> T {
+    let start = Instant::now();
+    let result = f();
+    *phase += start.elapsed();
+    result
+}
+
+pub struct ApplyTimings {
+    start: Instant,
+    top: usize,
+    files: Vec<(String, PhaseTimings)>,
+}
+
+impl ApplyTimings {
+    /// Returns None unless timing was requested through [`TIMINGS_ENV`].
+    pub fn from_env() -> Option<Self> {
+        let value = std::env::var(TIMINGS_ENV).ok()?;
+        Some(Self {
+            start: Instant::now(),
+            top: value.trim().parse().unwrap_or(DEFAULT_TOP),
+            files: Vec::new(),
+        })
+    }
+
+    pub fn record(&mut self, name: String, timings: PhaseTimings) {
+        self.files.push((name, timings));
+    }
+
+    fn slowest(&self) -> Vec<&(String, PhaseTimings)> {
+        let mut files: Vec<_> = self.files.iter().collect();
+        files.sort_by_key(|(_, timings)| std::cmp::Reverse(timings.total()));
+        files.truncate(self.top);
+        files
+    }
+
+    pub fn log(&self) {
+        let mut totals = PhaseTimings::default();
+        for (_, timings) in &self.files {
+            totals.parse += timings.parse;
+            totals.append += timings.append;
+            totals.stringify += timings.stringify;
+        }
+
+        info!(
+            "Patching took {:.1}ms, {} files patched in {:.1}ms (parse {:.1}ms, append {:.1}ms, stringify {:.1}ms)",
+            ms(self.start.elapsed()),
+            self.files.len(),
+            ms(totals.total()),
+            ms(totals.parse),
+            ms(totals.append),
+            ms(totals.stringify)
+        );
+
+        for (name, timings) in self.slowest() {
+            info!(
+                "{:.1}ms {name} (parse {:.1}ms, append {:.1}ms, stringify {:.1}ms)",
+                ms(timings.total()),
+                ms(timings.parse),
+                ms(timings.append),
+                ms(timings.stringify)
+            );
+        }
+    }
+}
+
+fn ms(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::{Duration, Instant};
+
+    use super::{ApplyTimings, PhaseTimings};
+
+    #[test]
+    fn slowest_files_first() {
+        let mut timings = ApplyTimings {
+            start: Instant::now(),
+            top: 2,
+            files: Vec::new(),
+        };
+        for (name, millis) in [("a", 1), ("b", 5), ("c", 3)] {
+            timings.record(
+                name.to_owned(),
+                PhaseTimings {
+                    append: Duration::from_millis(millis),
+                    ..Default::default()
+                },
+            );
+        }
+
+        let names: Vec<_> = timings.slowest().into_iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(names, ["b", "c"]);
+    }
+}
@@ -6,11 +6,18 @@ use std::{
 
 use anyhow::{Context, Result};
 use lazy_static::lazy_static;
+use log::warn;
+use walkdir::WalkDir;
 
 pub struct Cache {
     root: PathBuf,
 }
 
+/// Entries that haven't been written to for this long are removed by [`Cache::clear_stale`].
+pub const STALE_AGE: Duration = Duration::from_secs(60 * 60 * 24 * 30);
+/// Temporary files older than this are assumed to be left over from an interrupted write.
+const STALE_TMP_AGE: Duration = Duration::from_secs(60 * 60 * 24);
+
 lazy_static! {
     pub static ref CACHE: Cache = Cache {
         root: dirs::cache_dir().unwrap().join("ftlman")
@@ -22,40 +29,71 @@ impl Cache {
         &self,
         path: PathBuf,
         check_path: impl FnOnce(&Path) -> Result<bool>,
+        validate: impl FnOnce(&[u8]) -> Result<()>,
         fun: impl FnOnce() -> Result<Vec<u8>>,
     ) -> Result<Vec<u8>> {
         std::fs::create_dir_all(path.parent().unwrap())?;
 
         if check_path(&path)? {
-            Ok(std::fs::read(path)?)
-        } else {
-            let data = fun()?;
-            let tmp_dir = self.root.join(".tmp");
-            std::fs::create_dir_all(&tmp_dir)?;
-            let mut tmp = tempfile::NamedTempFile::new_in(tmp_dir)?;
-            tmp.write_all(&data)?;
-            match std::fs::rename(tmp.into_temp_path(), path) {
-                Ok(()) => (),
-                Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => (),
-                Err(e) => Err(e)?,
+            let data = std::fs::read(&path)?;
+            match validate(&data) {
+                Ok(()) => return Ok(data),
+                Err(error) => warn!("Discarding invalid cache entry {}: {error:#}", path.display()),
             }
-            Ok(data)
         }
+
+        let data = fun()?;
+        self.write_internal(&path, &data)?;
+        Ok(data)
+    }
+
+    fn write_internal(&self, path: &Path, data: &[u8]) -> Result<()> {
+        let tmp_dir = self.root.join(".tmp");
+        std::fs::create_dir_all(&tmp_dir)?;
+        let mut tmp = tempfile::NamedTempFile::new_in(tmp_dir)?;
+        tmp.write_all(data)?;
+        match std::fs::rename(tmp.into_temp_path(), path) {
+            Ok(()) => (),
+            Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => (),
+            Err(e) => Err(e)?,
+        }
+        Ok(())
     }
 
+    /// Returns the entry stored under `key` in `subdir`, creating it with `fun` if it doesn't exist yet or
+    /// `validate` rejects it.
     pub fn read_or_create_key(
         &self,
         subdir: &str,
         key: &str,
+        validate: impl FnOnce(&[u8]) -> Result<()>,
         fun: impl FnOnce() -> Result<Vec<u8>>,
     ) -> Result<Vec<u8>> {
         self.read_or_write_internal(
             self.root.join(subdir).join(key),
             |p| p.try_exists().map_err(Into::into),
+            validate,
             fun,
         )
     }
 
+    /// Removes the entry stored under `key` in `subdir` and returns its contents, if it existed.
+    pub fn take_key(&self, subdir: &str, key: &str) -> Result<Option<Vec<u8>>> {
+        let path = self.root.join(subdir).join(key);
+        match std::fs::read(&path) {
+            Ok(data) => {
+                std::fs::remove_file(&path)?;
+                Ok(Some(data))
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    pub fn contains_key(&self, subdir: &str, key: &str) -> bool {
+        self.root.join(subdir).join(key).exists()
+    }
+
     pub fn read_or_create_with_ttl(
         &self,
         subpath: &str,
@@ -75,6 +113,7 @@ impl Cache {
                     .context("Failed to get modification time of cached file")?;
                 Ok(mtime + ttl >= SystemTime::now())
             },
+            |_| Ok(()),
             fun,
         )
     }
@@ -86,4 +125,79 @@ impl Cache {
             Err(e) => Err(e.into()),
         }
     }
+
+    pub fn write(&self, subpath: &str, data: &[u8]) -> Result<()> {
+        let path = self.root.join(subpath);
+        std::fs::create_dir_all(path.parent().unwrap())?;
+        self.write_internal(&path, data)
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Returns the total size of all files in the cache.
+    pub fn size(&self) -> Result<u64> {
+        let mut total = 0;
+        for entry in WalkDir::new(&self.root) {
+            let entry = match entry {
+                Ok(entry) => entry,
+                Err(e) if e.io_error().is_some_and(|e| e.kind() == std::io::ErrorKind::NotFound) => continue,
+                Err(e) => Err(e)?,
+            };
+            if entry.file_type().is_file() {
+                total += entry.metadata()?.len();
+            }
+        }
+        Ok(total)
+    }
+
+    /// Removes everything from the cache.
+    pub fn clear(&self) -> Result<()> {
+        match std::fs::remove_dir_all(&self.root) {
+            Ok(()) => Ok(()),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+            Err(e) => Err(e).context("Failed to remove cache directory"),
+        }
+    }
+
+    /// Removes leftover temporary files and entries older than [`STALE_AGE`], returning the number of bytes freed.
+    ///
+    /// Temporary files are only removed once they're a day old so writes that are still in progress survive.
+    pub fn clear_stale(&self) -> Result<u64> {
+        let tmp_dir = self.root.join(".tmp");
+        let now = SystemTime::now();
+        let mut freed = 0;
+
+        for entry in WalkDir::new(&self.root) {
+            let entry = match entry {
+                Ok(entry) => entry,
+                Err(e) if e.io_error().is_some_and(|e| e.kind() == std::io::ErrorKind::NotFound) => continue,
+                Err(e) => Err(e)?,
+            };
+            if !entry.file_type().is_file() {
+                continue;
+            }
+
+            let meta = entry.metadata()?;
+            let max_age = if entry.path().starts_with(&tmp_dir) {
+                STALE_TMP_AGE
+            } else {
+                STALE_AGE
+            };
+            let is_stale = meta
+                .modified()
+                .ok()
+                .and_then(|mtime| now.duration_since(mtime).ok())
+                .is_some_and(|age| age > max_age);
+
+            if is_stale {
+                std::fs::remove_file(entry.path())
+                    .with_context(|| format!("Failed to remove {}", entry.path().display()))?;
+                freed += meta.len();
+            }
+        }
+
+        Ok(freed)
+    }
 }
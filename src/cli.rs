@@ -2,17 +2,19 @@ use std::{ffi::OsStr, fs::File, io::Write, path::PathBuf};
 
 use anyhow::{bail, Context, Result};
 use clap::{Parser, Subcommand};
-use log::{error, info};
+use log::{error, info, warn};
 
 use crate::{
     lua::{
         io::{LuaDirectoryFS, LuaFS},
-        LuaContext, ModLuaRuntime,
+        LuaContext, LuaOutput, ModLuaRuntime,
     },
     util::{crc32_from_reader, to_human_size_units},
-    Mod, ModSource, Settings,
+    Mod, ModConfigurationState, ModOrder, ModSource, Settings,
 };
 
+mod watch;
+
 #[derive(Subcommand)]
 pub enum Command {
     Patch(PatchCommand),
@@ -24,6 +26,10 @@ pub enum Command {
     #[clap(name = "fetch-gdrive")]
     FetchGDrive(FetchGDriveCommand),
     Extract(ExtractCommand),
+    PrintOrder(PrintOrderCommand),
+    ImportOrder(ImportOrderCommand),
+    Validate(ValidateCommand),
+    Watch(WatchCommand),
 }
 
 #[derive(Parser)]
@@ -47,6 +53,12 @@ pub struct AppendCommand {
     document: PathBuf,
     /// Append script to execute on the document
     patch: PathBuf,
+    /// Keep XML comments in the patched document instead of removing them.
+    #[clap(long)]
+    keep_comments: bool,
+    /// Remove the DOCTYPE declaration from the patched document.
+    #[clap(long)]
+    strip_doctype: bool,
 }
 
 #[derive(Parser)]
@@ -86,16 +98,75 @@ pub struct FetchGDriveCommand {
 }
 
 #[derive(Parser)]
-/// Extracts an SIL archive to a directory.
+/// Extracts files from an SIL archive.
 ///
 /// For more SIL archive manipulation capabilities please use <https://github.com/afishhh/silpkg>.
 pub struct ExtractCommand {
-    out_path: PathBuf,
-    dat_path: PathBuf,
+    /// FTL data directory containing ftl.dat or a path to an SIL archive.
+    data_path: PathBuf,
+
+    /// Path of the file inside the archive to extract.
+    #[clap(required_unless_present = "all")]
+    archive_path: Option<String>,
+
+    /// File to write the extracted file to, will print to stdout if not set.
+    out_path: Option<PathBuf>,
+
+    /// Extract the whole archive into this directory instead.
+    #[clap(long, value_name = "DIR", conflicts_with_all = ["archive_path", "out_path"])]
+    all: Option<PathBuf>,
+}
+
+#[derive(Parser)]
+/// Prints the mods from the mod directory in the order they will be applied.
+pub struct PrintOrderCommand {
+    /// Print the order as JSON in the same format as modorder.json.
+    #[clap(long)]
+    json: bool,
+}
+
+#[derive(Parser)]
+/// Imports a Slipstream modorder.txt into the mod order.
+///
+/// Listed mods are enabled in the listed order, all other mods are disabled.
+pub struct ImportOrderCommand {
+    /// Path to the modorder.txt file, defaults to the one in the mod directory.
+    path: Option<PathBuf>,
+}
+
+#[derive(Parser)]
+/// Checks an XML append file for problems.
+pub struct ValidateCommand {
+    file: PathBuf,
+
+    /// Print diagnostics as JSON instead of human readable messages.
+    #[clap(long)]
+    json: bool,
+}
+
+#[derive(Parser)]
+/// Re-checks files in an unpacked mod directory whenever they change.
+///
+/// XML files are validated and appends are applied to their base file,
+/// taken from the mod itself or from ftl.dat if a data directory is available.
+pub struct WatchCommand {
+    mod_dir: PathBuf,
+
+    /// FTL data directory, will use the one from the config if not set.
+    #[clap(long = "data-dir", short = 'd')]
+    data_path: Option<PathBuf>,
 }
 
 #[derive(Parser)]
 pub struct Args {
+    /// Disable all network access, only previously cached data will be used.
+    #[clap(long, global = true)]
+    pub offline: bool,
+
+    /// Windowing system to use on Linux, overrides the setting.
+    #[clap(long, value_enum)]
+    pub backend: Option<crate::DisplayBackend>,
+
     #[command(subcommand)]
     pub command: Option<Command>,
 }
@@ -103,7 +174,10 @@ pub struct Args {
 pub fn main(command: Command) -> Result<()> {
     match command {
         Command::Patch(mut command) => {
-            let settings = Settings::load(&Settings::default_path()).unwrap_or_default();
+            let settings = Settings::load_or_default(&Settings::default_path());
+            crate::lua::set_time_limit(settings.lua_time_limit());
+            crate::lua::set_memory_limit(settings.lua_memory_limit());
+            crate::set_follow_symlinks(settings.follow_symlinks);
             let Some(data_dir) = command.data_path.or(settings.ftl_directory) else {
                 bail!("--data-dir not set and ftl data directory is not set in settings");
             };
@@ -140,6 +214,15 @@ pub fn main(command: Command) -> Result<()> {
                 }
             }
 
+            if !data_dir.join(crate::apply::BACKUP_FILENAME).exists() {
+                if let Some(reason) = crate::apply::detect_modified_archive(&data_dir)? {
+                    warn!(
+                        "ftl.dat appears to already be modified ({reason}), \
+                         these mods will be applied on top of the existing changes"
+                    );
+                }
+            }
+
             crate::apply::apply_ftl(
                 &data_dir,
                 command
@@ -161,12 +244,19 @@ pub fn main(command: Command) -> Result<()> {
                         info!("Preparing...")
                     }
                     crate::apply::ApplyStage::Mod { .. } => {}
-                    crate::apply::ApplyStage::Repacking => {
+                    crate::apply::ApplyStage::Repacking { entry_idx: 0, .. } => {
                         info!("Repacking...")
                     }
+                    crate::apply::ApplyStage::Repacking { .. } => {}
                     _ => unreachable!(),
                 },
-                true,
+                || false,
+                crate::apply::ApplyOptions {
+                    base: crate::apply::ApplyBase::Vanilla,
+                    repack: crate::apply::RepackMode::Always,
+                    keep_comments: settings.keep_xml_comments,
+                    keep_doctype: settings.keep_xml_doctype,
+                },
             )
         }
         Command::Append(command) => {
@@ -182,12 +272,17 @@ pub fn main(command: Command) -> Result<()> {
             let patch = std::fs::read_to_string(&command.patch).context("Failed to read patch file")?;
 
             let patched = match kind {
-                crate::apply::AppendType::Xml(xml_append_type) => {
-                    crate::apply::apply_one_xml(&source, &patch, xml_append_type)?
-                }
+                crate::apply::AppendType::Xml(xml_append_type) => crate::apply::apply_one_xml(
+                    &source,
+                    &patch,
+                    xml_append_type,
+                    command.keep_comments,
+                    !command.strip_doctype,
+                )?,
                 crate::apply::AppendType::LuaAppend => {
                     let runtime = ModLuaRuntime::new().context("Failed to initialize Lua runtime")?;
-                    crate::apply::apply_one_lua(&source, &patch, &runtime)?
+                    // Stdout is reserved for the patched document.
+                    crate::apply::apply_one_lua(&source, &patch, &runtime, &mut LuaOutput::Log, !command.strip_doctype)?
                 }
             };
 
@@ -224,6 +319,7 @@ pub fn main(command: Command) -> Result<()> {
                 let mut context = LuaContext {
                     document_root: None,
                     print_arena_stats: command.print_arena_stats,
+                    output: LuaOutput::Stdout,
                 };
 
                 if let Err(error) = runtime.run(&code, script_name, &mut context) {
@@ -276,15 +372,19 @@ pub fn main(command: Command) -> Result<()> {
             _ = std::io::stdout().flush();
 
             let response = crate::util::request_google_drive_download(&command.file_id)?;
-            let data = crate::util::download_body_with_progress(response, |current, total| {
-                let (n, unit) = to_human_size_units(current);
-                print!("\r\x1b[2KDownloaded {n:.3}{unit}");
-                if let Some(total) = total {
-                    let (n, unit) = to_human_size_units(total);
-                    print!("/{n:.3}{unit}");
-                }
-                _ = std::io::stdout().flush();
-            })?;
+            let data = crate::util::download_body_with_progress(
+                response,
+                crate::util::DEFAULT_DOWNLOAD_CONNECTIONS,
+                |current, total| {
+                    let (n, unit) = to_human_size_units(current);
+                    print!("\r\x1b[2KDownloaded {n:.3}{unit}");
+                    if let Some(total) = total {
+                        let (n, unit) = to_human_size_units(total);
+                        print!("/{n:.3}{unit}");
+                    }
+                    _ = std::io::stdout().flush();
+                },
+            )?;
             println!();
 
             output.write_all(&data).context("Failed to write output file")?;
@@ -292,16 +392,136 @@ pub fn main(command: Command) -> Result<()> {
             Ok(())
         }
         Command::Extract(command) => {
-            let mut pkg = silpkg::sync::Pkg::parse(File::open(command.dat_path).context("Failed to open data file")?)
+            let dat_path = if command.data_path.is_dir() {
+                command.data_path.join("ftl.dat")
+            } else {
+                command.data_path
+            };
+
+            let mut pkg = silpkg::sync::Pkg::parse(File::open(dat_path).context("Failed to open data file")?)
                 .context("Failed to parse data file")?;
-            for path in pkg.paths().cloned().collect::<Vec<_>>() {
-                let out = command.out_path.join(&path);
-                match std::fs::create_dir_all(out.parent().unwrap()) {
-                    Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => (),
-                    other => other.context("Failed to create output directory")?,
+
+            if let Some(out_dir) = command.all {
+                for path in pkg.paths().cloned().collect::<Vec<_>>() {
+                    let out = out_dir.join(&path);
+                    match std::fs::create_dir_all(out.parent().unwrap()) {
+                        Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => (),
+                        other => other.context("Failed to create output directory")?,
+                    }
+                    std::io::copy(&mut pkg.open(&path)?, &mut File::create(out)?)?;
+                }
+                return Ok(());
+            }
+
+            // Guaranteed by clap's required_unless_present.
+            let archive_path = command.archive_path.unwrap();
+            if pkg.metadata(&archive_path).is_none() {
+                bail!("{archive_path} does not exist in the archive");
+            }
+
+            let mut reader = pkg
+                .open(&archive_path)
+                .with_context(|| format!("Failed to open {archive_path}"))?;
+            match command.out_path {
+                Some(out_path) => {
+                    let mut file =
+                        File::create(&out_path).with_context(|| format!("Failed to create {}", out_path.display()))?;
+                    std::io::copy(&mut reader, &mut file).context("Failed to write output file")?;
+                }
+                None => {
+                    std::io::copy(&mut reader, &mut std::io::stdout().lock()).context("Failed to write to stdout")?;
+                }
+            }
+
+            Ok(())
+        }
+        Command::Validate(command) => {
+            let source = std::fs::read_to_string(&command.file).context("Failed to read input file")?;
+            let file_name = command.file.to_string_lossy();
+
+            let mut diagnostics = Vec::new();
+            let ok = crate::validate::xml::validate_xml(
+                &source,
+                speedy_xml::reader::Options::default().allow_top_level_text(true),
+                &mut diagnostics,
+            );
+
+            let lines = crate::validate::LineIndex::new(&source);
+            if command.json {
+                let json = crate::validate::to_json(&diagnostics, &lines, Some(&file_name));
+                serde_json::to_writer_pretty(std::io::stdout().lock(), &json)?;
+                println!();
+            } else {
+                let renderer = annotate_snippets::Renderer::styled();
+                for message in crate::validate::render_messages(&diagnostics, &lines, Some(&file_name)) {
+                    println!("{}", renderer.render(message));
                 }
-                std::io::copy(&mut pkg.open(&path)?, &mut File::create(out)?)?;
             }
+
+            if !ok {
+                std::process::exit(1)
+            }
+
+            Ok(())
+        }
+        Command::Watch(command) => {
+            let settings = Settings::load_or_default(&Settings::default_path());
+            let data_dir = command.data_path.or(settings.ftl_directory);
+            watch::watch(&command.mod_dir, data_dir.as_deref())
+        }
+        Command::PrintOrder(command) => {
+            let settings = Settings::load_or_default(&Settings::default_path());
+            let (config, mods) = crate::scan::scan_ordered(&settings).context("Failed to scan mod directory")?;
+            let mods = crate::apply::apply_order(mods)?;
+
+            if command.json {
+                let state = ModConfigurationState {
+                    hyperspace: config.hyperspace,
+                    order: ModOrder::from_mods(&mods),
+                    last_apply: config.last_apply,
+                };
+                serde_json::to_writer_pretty(std::io::stdout().lock(), &state)?;
+                println!();
+            } else {
+                for (i, m) in mods.iter().enumerate() {
+                    let title = m.title().ok().flatten();
+                    println!(
+                        "{:>3} [{}] {}{}",
+                        i + 1,
+                        if m.enabled { 'x' } else { ' ' },
+                        m.filename(),
+                        title.map(|title| format!(" ({title})")).unwrap_or_default()
+                    );
+                }
+            }
+
+            Ok(())
+        }
+        Command::ImportOrder(command) => {
+            let settings = Settings::load_or_default(&Settings::default_path());
+            let path = command
+                .path
+                .unwrap_or_else(|| settings.mod_directory.join(crate::scan::SLIPSTREAM_ORDER_FILENAME));
+            let text = std::fs::read_to_string(&path).with_context(|| format!("Failed to read {}", path.display()))?;
+            let (config, mut mods) = crate::scan::scan_ordered(&settings).context("Failed to scan mod directory")?;
+
+            for filename in crate::scan::apply_slipstream_order(&text, &mut mods) {
+                warn!("{filename} is listed in {} but is not installed", path.display());
+            }
+
+            crate::scan::write_mod_configuration(
+                &settings.mod_directory,
+                &ModConfigurationState {
+                    hyperspace: config.hyperspace,
+                    order: ModOrder::from_mods(&mods),
+                    last_apply: config.last_apply,
+                },
+            )?;
+            info!(
+                "Imported mod order with {} enabled mods",
+                mods.iter().filter(|m| m.enabled).count()
+            );
+
             Ok(())
         }
     }
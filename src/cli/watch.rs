@@ -0,0 +1,246 @@
+use std::{
+    collections::BTreeSet,
+    fs::File,
+    path::{Path, PathBuf},
+    sync::mpsc,
+    time::Duration,
+};
+
+use anyhow::{Context, Result};
+use log::{info, warn};
+use notify::{RecursiveMode, Watcher};
+use silpkg::sync::Pkg;
+
+use crate::{
+    apply::{self, AppendType},
+    lua::{LuaOutput, ModLuaRuntime},
+    validate::{render_messages, xml::validate_xml, LineIndex},
+};
+
+/// Changes arriving within this duration of each other are checked together.
+const DEBOUNCE: Duration = Duration::from_millis(250);
+
+enum WatchEvent {
+    Fs(notify::Result<notify::Event>),
+    Interrupt,
+}
+
+struct Checker {
+    mod_dir: PathBuf,
+    pkg: Option<Pkg<File>>,
+    renderer: annotate_snippets::Renderer,
+}
+
+impl Checker {
+    fn read_base(&mut self, base: &str) -> Result<Option<String>> {
+        // Files that replace a vanilla one take precedence over the original.
+        let local = self.mod_dir.join(base);
+        if local.exists() {
+            return std::fs::read_to_string(&local)
+                .map(Some)
+                .with_context(|| format!("Failed to read {}", local.display()));
+        }
+
+        let Some(pkg) = self.pkg.as_mut() else {
+            return Ok(None);
+        };
+
+        if pkg.metadata(base).is_none() {
+            return Ok(None);
+        }
+
+        let mut text = String::new();
+        std::io::Read::read_to_string(
+            &mut pkg
+                .open(base)
+                .with_context(|| format!("Failed to open {base} from ftl.dat"))?,
+            &mut text,
+        )
+        .with_context(|| format!("Failed to read {base} from ftl.dat"))?;
+        Ok(Some(text))
+    }
+
+    /// Checks the file at `path` and prints any problems found, returns whether it was OK.
+    fn check(&mut self, path: &Path) -> Result<bool> {
+        let Ok(relative) = path.strip_prefix(&self.mod_dir) else {
+            return Ok(true);
+        };
+        let name = relative
+            .components()
+            .map(|c| c.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/");
+
+        let kind = AppendType::from_filename(&name);
+        if kind.is_none() && !name.ends_with(".xml") {
+            return Ok(true);
+        }
+
+        let source = match std::fs::read_to_string(path) {
+            Ok(source) => source,
+            // The file was removed or renamed before we got to it.
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(true),
+            Err(e) => return Err(e).with_context(|| format!("Failed to read {name}")),
+        };
+
+        let mut valid = true;
+        if !matches!(kind, Some((_, AppendType::LuaAppend))) {
+            let mut diagnostics = Vec::new();
+            valid = validate_xml(
+                &source,
+                speedy_xml::reader::Options::default().allow_top_level_text(true),
+                &mut diagnostics,
+            );
+
+            let lines = LineIndex::new(&source);
+            for message in render_messages(&diagnostics, &lines, Some(&name)) {
+                println!("{}", self.renderer.render(message));
+            }
+        }
+
+        if let (true, Some((stem, kind))) = (valid, kind) {
+            let base = format!("{stem}.xml");
+            match self.read_base(&base)? {
+                Some(document) => {
+                    let result = match kind {
+                        AppendType::Xml(xml_append_type) => {
+                            apply::apply_one_xml(&document, &source, xml_append_type, false, true)
+                        }
+                        AppendType::LuaAppend => ModLuaRuntime::new()
+                            .context("Failed to initialize Lua runtime")
+                            .and_then(|runtime| {
+                                apply::apply_one_lua(&document, &source, &runtime, &mut LuaOutput::Stdout, true)
+                            }),
+                    };
+
+                    if let Err(error) = result {
+                        println!("{name}: failed to patch {base}: {error:?}");
+                        valid = false;
+                    }
+                }
+                None => warn!("{name}: base file {base} not found, only checking syntax"),
+            }
+        }
+
+        if valid {
+            info!("{name}: OK");
+        }
+
+        Ok(valid)
+    }
+}
+
+/// Re-checks files in `mod_dir` whenever they change until interrupted.
+pub fn watch(mod_dir: &Path, data_dir: Option<&Path>) -> Result<()> {
+    let mod_dir = mod_dir.canonicalize().context("Failed to canonicalize mod directory")?;
+
+    let pkg = match data_dir {
+        Some(data_dir) => {
+            let vanilla = data_dir.join(apply::BACKUP_FILENAME);
+            let path = if vanilla.exists() {
+                vanilla
+            } else {
+                data_dir.join("ftl.dat")
+            };
+            let file = File::open(&path).with_context(|| format!("Failed to open {}", path.display()))?;
+            Some(Pkg::parse(file).context("Failed to parse ftl.dat")?)
+        }
+        None => {
+            warn!("No FTL data directory set, appends will not be checked against their base files");
+            None
+        }
+    };
+
+    let mut checker = Checker {
+        mod_dir: mod_dir.clone(),
+        pkg,
+        renderer: annotate_snippets::Renderer::styled(),
+    };
+
+    let (tx, rx) = mpsc::channel();
+    let interrupt_tx = tx.clone();
+    ctrlc::set_handler(move || {
+        _ = interrupt_tx.send(WatchEvent::Interrupt);
+    })
+    .context("Failed to set Ctrl+C handler")?;
+    let mut watcher = notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+        _ = tx.send(WatchEvent::Fs(event));
+    })
+    .context("Failed to create filesystem watcher")?;
+    watcher
+        .watch(&mod_dir, RecursiveMode::Recursive)
+        .context("Failed to watch mod directory")?;
+
+    info!("Watching {} for changes, press Ctrl+C to exit", mod_dir.display());
+
+    let mut changed = BTreeSet::new();
+    loop {
+        let event = if changed.is_empty() {
+            rx.recv().ok()
+        } else {
+            match rx.recv_timeout(DEBOUNCE) {
+                Ok(event) => Some(event),
+                Err(mpsc::RecvTimeoutError::Timeout) => {
+                    for path in std::mem::take(&mut changed) {
+                        if let Err(error) = checker.check(&path) {
+                            warn!("{error:?}");
+                        }
+                    }
+                    continue;
+                }
+                Err(mpsc::RecvTimeoutError::Disconnected) => None,
+            }
+        };
+
+        match event {
+            Some(WatchEvent::Fs(Ok(event))) => {
+                if event.kind.is_create() || event.kind.is_modify() {
+                    changed.extend(event.paths.into_iter().filter(|path| path.is_file()));
+                }
+            }
+            Some(WatchEvent::Fs(Err(error))) => warn!("Filesystem watcher error: {error}"),
+            Some(WatchEvent::Interrupt) | None => break,
+        }
+    }
+
+    info!("Stopped watching");
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::Checker;
+
+    #[test]
+    fn append_is_checked_against_base() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(dir.path().join("data")).unwrap();
+        std::fs::write(
+            dir.path().join("data/blueprints.xml"),
+            r#"<FTL><shipBlueprint name="A"/></FTL>"#,
+        )
+        .unwrap();
+
+        let mut checker = Checker {
+            mod_dir: dir.path().to_owned(),
+            pkg: None,
+            renderer: annotate_snippets::Renderer::plain(),
+        };
+
+        let append = dir.path().join("data/blueprints.xml.append");
+        std::fs::write(
+            &append,
+            r#"<mod:findName name="A"><mod:setAttributes x="1"/></mod:findName>"#,
+        )
+        .unwrap();
+        assert!(checker.check(&append).unwrap());
+
+        // Only fails if the find actually runs against the base file.
+        std::fs::write(
+            &append,
+            r#"<mod:findName name="B" panic="true"><mod:removeTag/></mod:findName>"#,
+        )
+        .unwrap();
+        assert!(!checker.check(&append).unwrap());
+    }
+}
@@ -0,0 +1,91 @@
+use std::{backtrace::Backtrace, panic::PanicHookInfo, path::PathBuf, time::SystemTime};
+
+use log::error;
+
+use crate::VERSION;
+
+fn crash_report_dir() -> PathBuf {
+    dirs::data_local_dir().unwrap().join("ftlman/crashes")
+}
+
+fn write_report(info: &PanicHookInfo) -> std::io::Result<PathBuf> {
+    let now = SystemTime::now();
+    let timestamp = crate::logging::format_timestamp(now);
+
+    let thread = std::thread::current();
+    let message = info
+        .payload()
+        .downcast_ref::<&str>()
+        .copied()
+        .or_else(|| info.payload().downcast_ref::<String>().map(String::as_str))
+        .unwrap_or("<non-string panic payload>");
+    let location = info
+        .location()
+        .map_or_else(|| "<unknown>".to_owned(), |location| location.to_string());
+
+    let report = format!(
+        "ftlman v{VERSION} panicked at {timestamp}\n\
+         OS: {} ({})\n\
+         Thread: {}\n\
+         Location: {location}\n\
+         Message: {message}\n\n\
+         Backtrace:\n{}\n",
+        std::env::consts::OS,
+        std::env::consts::ARCH,
+        thread.name().unwrap_or("<unnamed>"),
+        Backtrace::force_capture()
+    );
+
+    let dir = crash_report_dir();
+    std::fs::create_dir_all(&dir)?;
+    // Colons aren't allowed in filenames on Windows.
+    let path = dir.join(format!("panic-{}.txt", timestamp.replace(':', "-")));
+    std::fs::write(&path, report)?;
+    Ok(path)
+}
+
+#[cfg(target_os = "windows")]
+fn show_message_box(text: &str) {
+    use winapi::um::winuser::{MessageBoxW, MB_ICONERROR, MB_OK};
+
+    let wide = |s: &str| s.encode_utf16().chain(std::iter::once(0)).collect::<Vec<u16>>();
+    let text = wide(text);
+    let caption = wide("FTL Manager crashed");
+    // SAFETY: Both strings are valid and null terminated.
+    unsafe {
+        MessageBoxW(
+            std::ptr::null_mut(),
+            text.as_ptr(),
+            caption.as_ptr(),
+            MB_OK | MB_ICONERROR,
+        );
+    }
+}
+
+/// Installs a panic hook that saves a crash report in addition to running the default hook.
+///
+/// Only panics on the main thread are fatal, ones in background threads are still saved
+/// but don't show a message box since the application keeps running.
+pub fn install_panic_hook() {
+    let default_hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        default_hook(info);
+
+        match write_report(info) {
+            Ok(path) => {
+                error!("Crash report saved to {}", path.display());
+
+                #[cfg(target_os = "windows")]
+                if std::thread::current().name() == Some("main") {
+                    show_message_box(&format!(
+                        "FTL Manager has crashed unexpectedly.\n\n\
+                         A crash report was saved to:\n{}\n\n\
+                         Please attach it when reporting this issue.",
+                        path.display()
+                    ));
+                }
+            }
+            Err(error) => error!("Failed to write crash report: {error}"),
+        }
+    }));
+}
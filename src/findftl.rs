@@ -1,7 +1,13 @@
-use std::path::PathBuf;
+use std::{
+    fmt::Display,
+    path::{Path, PathBuf},
+};
 
 use anyhow::{bail, Context, Result};
 use log::{info, warn};
+use walkdir::WalkDir;
+
+use crate::l;
 
 mod vdf;
 
@@ -117,3 +123,137 @@ pub fn find_steam_ftl() -> Result<Option<PathBuf>> {
 
     Ok(None)
 }
+
+/// Reason why a directory can't be used as the FTL data directory.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FtlDirectoryProblem {
+    DoesNotExist,
+    NotADirectory,
+    /// A path like `C:\...` was entered on a system that doesn't use such paths.
+    WindowsPath,
+    /// `ftl.dat` was found in a subdirectory, the path should point there instead.
+    InSubdirectory(PathBuf),
+    /// The directory contains the data files of an FTL version older than 1.6.
+    LegacyDataFiles,
+    NoFtlDat,
+}
+
+impl Display for FtlDirectoryProblem {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&match self {
+            FtlDirectoryProblem::DoesNotExist => l!("ftl-directory-does-not-exist"),
+            FtlDirectoryProblem::NotADirectory => l!("ftl-directory-not-a-directory"),
+            FtlDirectoryProblem::WindowsPath => l!("ftl-directory-windows-path"),
+            FtlDirectoryProblem::InSubdirectory(path) => {
+                l!("ftl-directory-in-subdirectory", "path" => path.display().to_string())
+            }
+            FtlDirectoryProblem::LegacyDataFiles => l!("ftl-directory-legacy-data-files"),
+            FtlDirectoryProblem::NoFtlDat => l!("ftl-directory-no-ftl-dat"),
+        })
+    }
+}
+
+/// Files FTL versions before 1.6 store their data in instead of a single `ftl.dat`.
+pub const LEGACY_DATA_FILES: &[&str] = &["data.dat", "resource.dat"];
+
+/// Whether `dir` contains the data files of an FTL version older than 1.6, which can't be patched.
+pub fn has_legacy_data_files(dir: &Path) -> bool {
+    LEGACY_DATA_FILES.iter().any(|name| dir.join(name).is_file())
+}
+
+/// How deep into the selected directory to look for a misplaced `ftl.dat`, enough to find it in
+/// `FTL.app/Contents/Resources` from the directory containing the app bundle.
+const FTL_DAT_SEARCH_DEPTH: usize = 4;
+/// Maximum number of entries visited while looking for a misplaced `ftl.dat`, so that selecting
+/// something like a home directory doesn't stall.
+const FTL_DAT_SEARCH_LIMIT: usize = 2000;
+
+fn looks_like_windows_path(path: &Path) -> bool {
+    let Some(path) = path.to_str() else {
+        return false;
+    };
+    let mut chars = path.chars();
+    let has_drive = chars.next().is_some_and(|c| c.is_ascii_alphabetic()) && chars.next() == Some(':');
+    has_drive || path.starts_with("\\\\")
+}
+
+/// Checks whether `path` is a directory containing FTL's `ftl.dat`.
+pub fn validate_ftl_directory(path: &Path) -> Result<(), FtlDirectoryProblem> {
+    if !cfg!(windows) && looks_like_windows_path(path) {
+        return Err(FtlDirectoryProblem::WindowsPath);
+    }
+
+    match path.metadata() {
+        Ok(meta) if !meta.is_dir() => return Err(FtlDirectoryProblem::NotADirectory),
+        Ok(_) => {}
+        Err(_) => return Err(FtlDirectoryProblem::DoesNotExist),
+    }
+
+    if path.join("ftl.dat").is_file() {
+        return Ok(());
+    }
+
+    if has_legacy_data_files(path) {
+        return Err(FtlDirectoryProblem::LegacyDataFiles);
+    }
+
+    let found = WalkDir::new(path)
+        .min_depth(2)
+        .max_depth(FTL_DAT_SEARCH_DEPTH)
+        .into_iter()
+        .take(FTL_DAT_SEARCH_LIMIT)
+        .filter_map(Result::ok)
+        .find(|entry| entry.file_name() == "ftl.dat" && entry.file_type().is_file());
+
+    match found.as_ref().and_then(|entry| entry.path().parent()) {
+        Some(parent) => Err(FtlDirectoryProblem::InSubdirectory(parent.to_path_buf())),
+        None => Err(FtlDirectoryProblem::NoFtlDat),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn ftl_directory_validation() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        std::fs::create_dir_all(root.join("FTL/data")).unwrap();
+        std::fs::write(root.join("FTL/data/ftl.dat"), b"").unwrap();
+        std::fs::write(root.join("file"), b"").unwrap();
+
+        assert_eq!(validate_ftl_directory(&root.join("FTL/data")), Ok(()));
+        assert_eq!(
+            validate_ftl_directory(&root.join("FTL")),
+            Err(FtlDirectoryProblem::InSubdirectory(root.join("FTL/data")))
+        );
+        assert_eq!(
+            validate_ftl_directory(&root.join("missing")),
+            Err(FtlDirectoryProblem::DoesNotExist)
+        );
+        assert_eq!(
+            validate_ftl_directory(&root.join("file")),
+            Err(FtlDirectoryProblem::NotADirectory)
+        );
+        std::fs::create_dir(root.join("legacy")).unwrap();
+        std::fs::write(root.join("legacy/data.dat"), b"").unwrap();
+        std::fs::write(root.join("legacy/resource.dat"), b"").unwrap();
+        assert_eq!(
+            validate_ftl_directory(&root.join("legacy")),
+            Err(FtlDirectoryProblem::LegacyDataFiles)
+        );
+
+        std::fs::create_dir(root.join("empty")).unwrap();
+        assert_eq!(
+            validate_ftl_directory(&root.join("empty")),
+            Err(FtlDirectoryProblem::NoFtlDat)
+        );
+        if !cfg!(windows) {
+            assert_eq!(
+                validate_ftl_directory(Path::new("C:\\Program Files (x86)\\Steam")),
+                Err(FtlDirectoryProblem::WindowsPath)
+            );
+        }
+    }
+}
@@ -1,8 +1,12 @@
-use std::{backtrace::BacktraceStatus, sync::Arc};
+use std::{backtrace::BacktraceStatus, path::Path, sync::Arc};
 
-use eframe::egui::{FontDefinitions, FontFamily};
+use anyhow::{bail, Context, Result};
+use eframe::egui::{FontData, FontDefinitions, FontFamily};
 use log::warn;
 
+/// Name of the font family used by the sandbox editor, it falls back to the monospace fonts.
+pub const EDITOR_FONT_FAMILY: &str = "editor";
+
 #[cfg(target_os = "linux")]
 mod backend {
     use anyhow::{bail, Result};
@@ -339,7 +343,19 @@ mod backend {
     }
 }
 
-pub fn create_font_definitions(language: &str) -> FontDefinitions {
+fn load_font_file(path: &Path) -> Result<FontData> {
+    let data = std::fs::read(path).with_context(|| format!("Failed to read {}", path.display()))?;
+
+    // egui panics when given an invalid font so at least check that this looks like one
+    const MAGICS: &[&[u8]] = &[b"\x00\x01\x00\x00", b"OTTO", b"true", b"ttcf"];
+    if !MAGICS.iter().any(|magic| data.starts_with(magic)) {
+        bail!("{} is not a TrueType or OpenType font", path.display());
+    }
+
+    Ok(FontData::from_owned(data))
+}
+
+pub fn create_font_definitions(language: &str, editor_font: Option<&Path>) -> FontDefinitions {
     let mut fonts = FontDefinitions::default();
 
     match backend::find_system_sans_serif(language) {
@@ -369,5 +385,20 @@ pub fn create_font_definitions(language: &str) -> FontDefinitions {
         }
     }
 
+    let mut editor_family = fonts.families.get(&FontFamily::Monospace).cloned().unwrap_or_default();
+    if let Some(path) = editor_font {
+        match load_font_file(path) {
+            Ok(data) => {
+                let name = "Editor Font";
+                fonts.font_data.insert(name.to_string(), Arc::new(data));
+                editor_family.insert(0, name.to_string());
+            }
+            Err(e) => warn!("Failed to load editor font, falling back to the default monospace font: {e:#}"),
+        }
+    }
+    fonts
+        .families
+        .insert(FontFamily::Name(EDITOR_FONT_FAMILY.into()), editor_family);
+
     fonts
 }
@@ -1,15 +1,112 @@
-use anyhow::{Context, Result};
+use std::{
+    fmt::Write as _,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{bail, Context, Result};
+use lazy_static::lazy_static;
+use log::{debug, warn};
+use parking_lot::RwLock;
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 
 use crate::{cache::CACHE, AGENT};
 
 const API_ROOT: &str = "https://api.github.com";
 
+/// How many times a failed request will be retried before giving up.
+pub const MAX_RETRIES: u32 = 3;
+/// Delay before the first retry, doubled after every subsequent attempt.
+pub const INITIAL_BACKOFF: Duration = Duration::from_millis(500);
+/// Requests won't be retried if the server asks us to wait longer than this.
+pub const MAX_RETRY_WAIT: Duration = Duration::from_secs(30);
+
+fn retry_delay(error: &ureq::Error, backoff: Duration) -> Option<Duration> {
+    match error {
+        ureq::Error::Status(429 | 403, response) => {
+            if let Some(seconds) = response
+                .header("Retry-After")
+                .and_then(|x| x.trim().parse::<u64>().ok())
+            {
+                return Some(Duration::from_secs(seconds));
+            }
+
+            // GitHub signals exhausted primary rate limits with these instead.
+            if response.header("X-RateLimit-Remaining") == Some("0") {
+                let reset = response
+                    .header("X-RateLimit-Reset")
+                    .and_then(|x| x.trim().parse::<u64>().ok())?;
+                let now = SystemTime::now().duration_since(UNIX_EPOCH).ok()?.as_secs();
+                return Some(Duration::from_secs(reset.saturating_sub(now)));
+            }
+
+            (response.status() == 429).then_some(backoff)
+        }
+        ureq::Error::Status(500.., _) | ureq::Error::Transport(_) => Some(backoff),
+        ureq::Error::Status(..) => None,
+    }
+}
+
+/// Performs an idempotent request, retrying it with exponential backoff on transient failures.
+pub fn call_with_retry(request: ureq::Request) -> Result<ureq::Response, ureq::Error> {
+    let mut backoff = INITIAL_BACKOFF;
+    let mut attempt = 0;
+
+    loop {
+        match request.clone().call() {
+            Ok(response) => return Ok(response),
+            Err(error) => {
+                let delay = retry_delay(&error, backoff).filter(|delay| *delay <= MAX_RETRY_WAIT);
+                match delay {
+                    Some(delay) if attempt < MAX_RETRIES => {
+                        attempt += 1;
+                        warn!(
+                            "Request to {} failed ({error}), retrying in {:.1}s ({attempt}/{MAX_RETRIES})",
+                            request.url(),
+                            delay.as_secs_f32()
+                        );
+                        std::thread::sleep(delay);
+                        backoff *= 2;
+                    }
+                    _ => return Err(error),
+                }
+            }
+        }
+    }
+}
+
+lazy_static! {
+    static ref ENV_TOKEN: Option<String> = {
+        let token = std::env::var("FTLMAN_GITHUB_TOKEN").ok().filter(|x| !x.is_empty());
+        if token.is_some() {
+            debug!("Using GitHub API token from FTLMAN_GITHUB_TOKEN");
+        }
+        token
+    };
+    static ref SETTINGS_TOKEN: RwLock<Option<String>> = RwLock::new(None);
+}
+
+/// Sets the API token configured in the settings, the environment variable takes precedence over it.
+pub fn set_token(token: Option<String>) {
+    let token = token.filter(|x| !x.is_empty());
+    debug!(
+        "GitHub API token from settings {}",
+        if token.is_some() { "set" } else { "not set" }
+    );
+    *SETTINGS_TOKEN.write() = token;
+}
+
 fn make_get(url: &str) -> ureq::Request {
-    AGENT
+    let request = AGENT
         .get(url)
         .set("Accept", "application/vnd.github+json")
-        .set("X-GitHub-Api-Version", "2022-11-28")
+        .set("X-GitHub-Api-Version", "2022-11-28");
+
+    let settings_token = SETTINGS_TOKEN.read();
+    match ENV_TOKEN.as_deref().or(settings_token.as_deref()) {
+        Some(token) => request.set("Authorization", &format!("Bearer {token}")),
+        None => request,
+    }
 }
 
 pub struct Repository {
@@ -25,6 +122,25 @@ impl Repository {
         }
     }
 
+    /// Recognizes links to a GitHub repository or to any page inside of one.
+    pub fn from_url(url: &str) -> Option<Self> {
+        let url = url.trim();
+        let rest = url.strip_prefix("https://").or_else(|| url.strip_prefix("http://"))?;
+        let rest = rest.strip_prefix("www.").unwrap_or(rest);
+        let mut components = rest.strip_prefix("github.com/")?.split(['/', '?', '#']);
+        let owner = components.next().filter(|x| !x.is_empty())?;
+        let name = components
+            .next()
+            .map(|x| x.trim_end_matches(".git"))
+            .filter(|x| !x.is_empty())?;
+
+        Some(Self::new(owner, name))
+    }
+
+    pub fn releases_url(&self) -> String {
+        format!("https://github.com/{}/{}/releases", self.owner, self.name)
+    }
+
     fn cache_subdir(&self) -> String {
         format!("github/{}/{}", self.owner, self.name)
     }
@@ -37,8 +153,15 @@ impl Repository {
     }
 
     pub fn releases(&self) -> Result<Vec<Release>> {
+        if crate::util::is_offline() {
+            return self
+                .cached_releases()?
+                .context("No cached releases are available in offline mode");
+        }
+
+        let releases_path = format!("{}/releases", self.cache_subdir());
         let bytes = CACHE.read_or_create_with_ttl(
-            &format!("{}/releases", self.cache_subdir()),
+            &releases_path,
             std::time::Duration::from_secs(10 * 60),
             || -> Result<_> {
                 let url: String = format!(
@@ -47,8 +170,40 @@ impl Repository {
                     repo = self.name
                 );
 
+                let meta_path = format!("{releases_path}.meta");
+                let previous = CACHE
+                    .read(&meta_path)?
+                    .and_then(|data| serde_json::from_slice::<CachedResponseMeta>(&data).ok())
+                    .filter(|meta| meta.schema == CachedResponseMeta::SCHEMA)
+                    .zip(CACHE.read(&releases_path)?);
+
+                let mut request = make_get(&url);
+                if let Some((meta, _)) = previous.as_ref() {
+                    if let Some(etag) = meta.etag.as_deref() {
+                        request = request.set("If-None-Match", etag);
+                    }
+                    if let Some(last_modified) = meta.last_modified.as_deref() {
+                        request = request.set("If-Modified-Since", last_modified);
+                    }
+                }
+
+                let response = call_with_retry(request)?;
+                if response.status() == 304 {
+                    if let Some((_, body)) = previous {
+                        debug!("GitHub releases for {}/{} not modified", self.owner, self.name);
+                        return Ok(body);
+                    }
+                }
+
+                let meta = CachedResponseMeta {
+                    schema: CachedResponseMeta::SCHEMA,
+                    etag: response.header("ETag").map(str::to_owned),
+                    last_modified: response.header("Last-Modified").map(str::to_owned),
+                };
+
                 let mut out = vec![];
-                make_get(&url).call()?.into_reader().read_to_end(&mut out)?;
+                response.into_reader().read_to_end(&mut out)?;
+                CACHE.write(&meta_path, &serde_json::to_vec(&meta)?)?;
                 Ok(out)
             },
         )?;
@@ -57,6 +212,20 @@ impl Repository {
     }
 }
 
+/// Stored alongside cached API responses to allow making conditional requests.
+#[derive(Debug, Serialize, Deserialize)]
+struct CachedResponseMeta {
+    /// Entries with a different schema are ignored, this should be bumped whenever
+    /// the format of cached responses or of this structure changes.
+    schema: u32,
+    etag: Option<String>,
+    last_modified: Option<String>,
+}
+
+impl CachedResponseMeta {
+    const SCHEMA: u32 = 1;
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Release {
     pub url: String,
@@ -75,6 +244,51 @@ pub struct ReleaseAsset {
     pub name: String,
     pub label: Option<String>,
     pub content_type: String,
+    #[serde(default)]
+    pub size: Option<u64>,
+    /// Digest of the asset in the form `algorithm:hex`, only present for assets uploaded recently enough.
+    #[serde(default)]
+    pub digest: Option<String>,
+    #[serde(default)]
+    pub updated_at: Option<String>,
+}
+
+impl ReleaseAsset {
+    /// Returns a string identifying the contents of this asset, suitable for use as a filename.
+    ///
+    /// This is the digest if GitHub reported one and the asset's id and modification time otherwise.
+    pub fn cache_key(&self) -> String {
+        match (&self.digest, &self.updated_at) {
+            (Some(digest), _) => digest.clone(),
+            (None, Some(updated_at)) => format!("{}-{updated_at}", self.id),
+            (None, None) => self.id.to_string(),
+        }
+        .replace(|c: char| !c.is_ascii_alphanumeric() && c != '-', "_")
+    }
+
+    /// Checks downloaded asset data against the size and digest reported by GitHub, if available.
+    pub fn verify(&self, data: &[u8]) -> Result<()> {
+        if let Some(size) = self.size {
+            if data.len() as u64 != size {
+                bail!("Expected {size} bytes but received {}", data.len());
+            }
+        }
+
+        if let Some(expected) = self.digest.as_deref().and_then(|digest| digest.strip_prefix("sha256:")) {
+            let actual = Sha256::digest(data)
+                .iter()
+                .fold(String::with_capacity(64), |mut hex, byte| {
+                    _ = write!(hex, "{byte:02x}");
+                    hex
+                });
+
+            if !actual.eq_ignore_ascii_case(expected) {
+                bail!("SHA-256 mismatch, expected {expected} but got {actual}");
+            }
+        }
+
+        Ok(())
+    }
 }
 
 impl Release {
@@ -82,3 +296,62 @@ impl Release {
         crate::util::find_semver_in_string(&self.tag_name).or_else(|| crate::util::find_semver_in_string(&self.name))
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn asset(digest: Option<&str>, updated_at: Option<&str>) -> ReleaseAsset {
+        ReleaseAsset {
+            url: String::new(),
+            browser_download_url: String::new(),
+            id: 1234,
+            name: "asset.zip".to_owned(),
+            label: None,
+            content_type: "application/zip".to_owned(),
+            size: None,
+            digest: digest.map(str::to_owned),
+            updated_at: updated_at.map(str::to_owned),
+        }
+    }
+
+    #[test]
+    fn asset_cache_key() {
+        assert_eq!(asset(Some("sha256:abcd"), None).cache_key(), "sha256_abcd");
+        assert_eq!(
+            asset(None, Some("2024-01-02T03:04:05Z")).cache_key(),
+            "1234-2024-01-02T03_04_05Z"
+        );
+        assert_eq!(asset(None, None).cache_key(), "1234");
+        assert_ne!(
+            asset(None, Some("2024-01-02T03:04:05Z")).cache_key(),
+            asset(None, Some("2024-02-02T03:04:05Z")).cache_key()
+        );
+    }
+
+    #[test]
+    fn repository_from_url() {
+        for url in [
+            "https://github.com/owner/repo",
+            "http://www.github.com/owner/repo/",
+            " https://github.com/owner/repo.git ",
+            "https://github.com/owner/repo/releases/tag/v1.0.0",
+            "https://github.com/owner/repo#readme",
+        ] {
+            let repository = Repository::from_url(url).unwrap();
+            assert_eq!(
+                (repository.owner.as_str(), repository.name.as_str()),
+                ("owner", "repo"),
+                "{url}"
+            );
+        }
+
+        for url in [
+            "https://github.com/owner",
+            "https://subsetgames.com/forum/viewtopic.php?t=1",
+            "https://gitlab.com/owner/repo",
+        ] {
+            assert!(Repository::from_url(url).is_none(), "{url}");
+        }
+    }
+}
@@ -0,0 +1,101 @@
+use std::ops::Range;
+
+use crate::validate::xml::MOD_TAG_ATTRIBUTES;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CompletionKind {
+    Tag,
+    Attribute,
+}
+
+#[derive(Debug, Clone)]
+pub struct Completion {
+    pub kind: CompletionKind,
+    /// Byte range of the partially typed word that will be replaced.
+    pub range: Range<usize>,
+    pub candidates: Vec<&'static str>,
+}
+
+impl Completion {
+    /// Returns the text to insert in place of `range` and the byte offset
+    /// of the cursor relative to the start of the inserted text.
+    pub fn insertion(&self, candidate: &str, text: &str) -> (String, usize) {
+        match self.kind {
+            CompletionKind::Tag => {
+                if text[self.range.end..].starts_with('>') {
+                    (candidate.to_owned(), candidate.len())
+                } else {
+                    (format!("{candidate}>"), candidate.len())
+                }
+            }
+            CompletionKind::Attribute => (format!("{candidate}=\"\""), candidate.len() + 2),
+        }
+    }
+}
+
+fn is_name_char(c: char) -> bool {
+    c.is_alphanumeric() || matches!(c, '-' | '_' | '.' | ':')
+}
+
+/// Determines what can be completed at byte offset `cursor` in an XML append document.
+pub fn complete_at(text: &str, cursor: usize) -> Option<Completion> {
+    let before = text.get(..cursor)?;
+    let tag_start = before.rfind('<')?;
+    let in_tag = &before[tag_start + 1..];
+    if in_tag.contains('>') {
+        return None;
+    }
+
+    let word_start = before.trim_end_matches(|c| is_name_char(c) && c != ':').len();
+    let word = &before[word_start..];
+
+    if let Some(tag_prefix) = in_tag.strip_prefix("mod:") {
+        if tag_prefix.chars().all(is_name_char) {
+            let candidates: Vec<_> = MOD_TAG_ATTRIBUTES
+                .iter()
+                .map(|&(name, _)| name)
+                .filter(|name| name.starts_with(tag_prefix) && *name != tag_prefix)
+                .collect();
+
+            return (!candidates.is_empty()).then(|| Completion {
+                kind: CompletionKind::Tag,
+                range: tag_start + 5..cursor,
+                candidates,
+            });
+        }
+
+        let tag_name_end = tag_prefix.find(|c: char| !is_name_char(c)).unwrap_or(tag_prefix.len());
+        let tag_name = &tag_prefix[..tag_name_end];
+        let &(_, attributes) = MOD_TAG_ATTRIBUTES.iter().find(|&&(name, _)| name == tag_name)?;
+        let attributes_text = &tag_prefix[tag_name_end..];
+
+        // Don't complete inside of attribute values.
+        if attributes_text.matches('"').count() % 2 != 0 || attributes_text.matches('\'').count() % 2 != 0 {
+            return None;
+        }
+
+        if !before[..word_start].ends_with(char::is_whitespace) {
+            return None;
+        }
+
+        let candidates: Vec<_> = attributes
+            .unwrap_or_default()
+            .iter()
+            .copied()
+            .filter(|name| name.starts_with(word) && *name != word)
+            .filter(|name| {
+                !attributes_text
+                    .split(|c: char| c.is_whitespace() || c == '=')
+                    .any(|present| present == *name)
+            })
+            .collect();
+
+        return (!candidates.is_empty()).then(|| Completion {
+            kind: CompletionKind::Attribute,
+            range: word_start..cursor,
+            candidates,
+        });
+    }
+
+    None
+}
@@ -0,0 +1,312 @@
+//! Incremental syntax highlighting for large texts.
+//!
+//! `egui_extras` caches highlighting results for whole texts in a frame cache, which means any edit
+//! re-highlights everything and the cache gets evicted every time another viewport finishes a pass.
+//! This instead splits the text into segments at line boundaries where no multi-line construct is
+//! open, only highlights segments that changed and keeps the resulting galley around until the text
+//! changes. Laying out the combined job is cheap since egui caches galleys per paragraph.
+
+use std::{collections::HashMap, ops::Range, sync::Arc};
+
+use eframe::egui::{
+    self,
+    text::{CCursor, CursorRange, LayoutJob},
+    text_selection::visuals::paint_text_selection,
+    Color32, Galley, Ui,
+};
+use egui_extras::syntax_highlighting;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum LexState {
+    Text,
+    Tag { quote: Option<u8> },
+    Comment,
+    CData,
+    ProcessingInstruction,
+}
+
+/// Splits `text` into segments that end with a newline outside of any tag, comment or CDATA section.
+///
+/// Only XML is split, text in other languages is returned as a single segment.
+fn segments<'a>(text: &'a str, language: &str) -> impl Iterator<Item = &'a str> {
+    let bytes = text.as_bytes();
+    let mut state = LexState::Text;
+    let mut start = 0;
+    let mut i = if language == "xml" { 0 } else { bytes.len() };
+
+    std::iter::from_fn(move || {
+        while i < bytes.len() {
+            let rest = &bytes[i..];
+            let (next, skip) = match state {
+                LexState::Text if rest.starts_with(b"<!--") => (LexState::Comment, 4),
+                LexState::Text if rest.starts_with(b"<![CDATA[") => (LexState::CData, 9),
+                LexState::Text if rest.starts_with(b"<?") => (LexState::ProcessingInstruction, 2),
+                LexState::Text if rest[0] == b'<' => (LexState::Tag { quote: None }, 1),
+                LexState::Text if rest[0] == b'\n' => {
+                    i += 1;
+                    let segment = &text[start..i];
+                    start = i;
+                    return Some(segment);
+                }
+                LexState::Tag { quote: None } if rest[0] == b'>' => (LexState::Text, 1),
+                LexState::Tag { quote: None } if matches!(rest[0], b'"' | b'\'') => {
+                    (LexState::Tag { quote: Some(rest[0]) }, 1)
+                }
+                LexState::Tag { quote: Some(quote) } if rest[0] == quote => (LexState::Tag { quote: None }, 1),
+                LexState::Comment if rest.starts_with(b"-->") => (LexState::Text, 3),
+                LexState::CData if rest.starts_with(b"]]>") => (LexState::Text, 3),
+                LexState::ProcessingInstruction if rest.starts_with(b"?>") => (LexState::Text, 2),
+                _ => (state, 1),
+            };
+            state = next;
+            i += skip;
+        }
+
+        (start < bytes.len()).then(|| {
+            let segment = &text[start..];
+            start = bytes.len();
+            segment
+        })
+    })
+}
+
+fn append_job(out: &mut LayoutJob, job: &LayoutJob) {
+    let offset = out.text.len();
+    out.text.push_str(&job.text);
+    out.sections
+        .extend(job.sections.iter().map(|section| egui::text::LayoutSection {
+            byte_range: section.byte_range.start + offset..section.byte_range.end + offset,
+            ..section.clone()
+        }));
+}
+
+/// Everything other than the text that affects the highlighted galley.
+#[derive(Clone, PartialEq)]
+struct CacheKey {
+    language: &'static str,
+    dark_mode: bool,
+    font_id: egui::FontId,
+}
+
+#[derive(Default)]
+pub struct HighlightCache {
+    key: Option<CacheKey>,
+    // Highlighted segments of the last text, keyed by their contents.
+    segments: HashMap<String, LayoutJob>,
+    // The last text along with its galley and the wrap width it was laid out for.
+    text: String,
+    galley: Option<(f32, Arc<Galley>)>,
+}
+
+impl HighlightCache {
+    pub fn layout(&mut self, ui: &Ui, text: &str, wrap_width: f32, language: &'static str) -> Arc<Galley> {
+        let key = CacheKey {
+            language,
+            dark_mode: ui.visuals().dark_mode,
+            font_id: egui::TextStyle::Monospace.resolve(ui.style()),
+        };
+        if self.key.as_ref() != Some(&key) {
+            self.segments.clear();
+            self.text.clear();
+            self.galley = None;
+            self.key = Some(key);
+        }
+
+        if let Some((width, galley)) = self.galley.as_ref().filter(|_| self.text == text) {
+            if *width == wrap_width {
+                return galley.clone();
+            }
+        }
+
+        let mut job = LayoutJob::default();
+        if self.text != text {
+            let theme = syntax_highlighting::CodeTheme::from_style(ui.style());
+            let mut highlighted_segments = HashMap::with_capacity(self.segments.len());
+            for segment in segments(text, language) {
+                let highlighted = match self.segments.remove(segment) {
+                    Some(highlighted) => highlighted,
+                    None => match highlighted_segments.get(segment) {
+                        Some(highlighted) => highlighted.clone(),
+                        None => syntax_highlighting::highlight(ui.ctx(), ui.style(), &theme, segment, language),
+                    },
+                };
+                append_job(&mut job, &highlighted);
+                highlighted_segments.insert(segment.to_owned(), highlighted);
+            }
+            self.segments = highlighted_segments;
+            self.text = text.to_owned();
+        } else {
+            for segment in segments(text, language) {
+                append_job(&mut job, &self.segments[segment]);
+            }
+        }
+
+        job.wrap.max_width = wrap_width;
+        let galley = ui.fonts(|f| f.layout_job(job));
+        self.galley = Some((wrap_width, galley.clone()));
+        galley
+    }
+}
+
+/// Returns the part of `job` after byte offset `start`.
+fn clip_job(job: &LayoutJob, start: usize) -> LayoutJob {
+    LayoutJob {
+        text: job.text[start..].to_owned(),
+        sections: job
+            .sections
+            .iter()
+            .filter(|section| section.byte_range.end > start)
+            .map(|section| egui::text::LayoutSection {
+                byte_range: section.byte_range.start.max(start) - start..section.byte_range.end - start,
+                ..section.clone()
+            })
+            .collect(),
+        ..Default::default()
+    }
+}
+
+/// A read-only view of a possibly very large text that only highlights and lays out the lines
+/// that are currently scrolled into view.
+///
+/// Lines are never wrapped so that every line has the same height.
+#[derive(Default)]
+pub struct VirtualText {
+    text: String,
+    language: &'static str,
+    line_starts: Vec<usize>,
+    // Offsets at which highlighting can start without any preceding context, see [`segments`].
+    segment_starts: Vec<usize>,
+    // Byte range of the lines the cached galley was laid out for.
+    window: Option<(Range<usize>, CacheKey, Arc<Galley>)>,
+}
+
+impl VirtualText {
+    fn update(&mut self, text: &str, language: &'static str) {
+        if self.text == text && self.language == language {
+            return;
+        }
+
+        self.text = text.to_owned();
+        self.language = language;
+        self.line_starts = std::iter::once(0)
+            .chain(text.match_indices('\n').map(|(i, _)| i + 1))
+            .collect();
+        self.segment_starts = std::iter::once(0)
+            .chain(segments(text, language).scan(0, |offset, segment| {
+                *offset += segment.len();
+                Some(*offset)
+            }))
+            .collect();
+        self.window = None;
+    }
+
+    fn window_galley(&mut self, ui: &Ui, window: Range<usize>, key: &CacheKey) -> Arc<Galley> {
+        if let Some((_, _, galley)) = self
+            .window
+            .as_ref()
+            .filter(|(range, cached_key, _)| *range == window && cached_key == key)
+        {
+            return galley.clone();
+        }
+
+        let context_start = self.segment_starts[self.segment_starts.partition_point(|&s| s <= window.start) - 1];
+        let theme = syntax_highlighting::CodeTheme::from_style(ui.style());
+        let job = syntax_highlighting::highlight(
+            ui.ctx(),
+            ui.style(),
+            &theme,
+            &self.text[context_start..window.end],
+            self.language,
+        );
+
+        let mut job = clip_job(&job, window.start - context_start);
+        job.wrap.max_width = f32::INFINITY;
+        let galley = ui.fonts(|f| f.layout_job(job));
+        self.window = Some((window, key.clone(), galley.clone()));
+        galley
+    }
+
+    /// Shows `text` in a scroll area with the byte ranges in `highlights` painted in `highlight_color`.
+    ///
+    /// If `scroll_to` is set the line containing that byte offset is scrolled into the middle of the view.
+    pub fn show(
+        &mut self,
+        ui: &mut Ui,
+        text: &str,
+        language: &'static str,
+        highlights: &[Range<usize>],
+        highlight_color: Color32,
+        scroll_to: Option<usize>,
+    ) {
+        self.update(text, language);
+
+        let key = CacheKey {
+            language,
+            dark_mode: ui.visuals().dark_mode,
+            font_id: egui::TextStyle::Monospace.resolve(ui.style()),
+        };
+        let row_height = ui.fonts(|f| f.row_height(&key.font_id));
+
+        let mut area = egui::ScrollArea::both().auto_shrink(false);
+        if let Some(offset) = scroll_to {
+            let line = self.line_starts.partition_point(|&start| start <= offset) - 1;
+            area = area.vertical_scroll_offset((line as f32 * row_height - ui.available_height() / 2.0).max(0.0));
+        }
+
+        ui.scope(|ui| {
+            // Rows have to be laid out back to back for the galley to line up with them.
+            ui.spacing_mut().item_spacing.y = 0.0;
+
+            area.show_rows(ui, row_height, self.line_starts.len(), |ui, rows| {
+                if rows.is_empty() {
+                    return;
+                }
+
+                // The newline at the end of the last visible line is left out.
+                let window = self.line_starts[rows.start]
+                    ..self.line_starts.get(rows.end).map_or(self.text.len(), |&next| next - 1);
+                let mut galley = self.window_galley(ui, window.clone(), &key);
+
+                let mut visuals = ui.visuals().clone();
+                visuals.selection.bg_fill = highlight_color;
+                for range in highlights {
+                    let start = range.start.clamp(window.start, window.end);
+                    let end = range.end.clamp(window.start, window.end);
+                    // Also skips ranges outside of the window since they get clamped to one of its ends.
+                    if start == end {
+                        continue;
+                    }
+
+                    let start_char = self.text[window.start..start].chars().count();
+                    let end_char = start_char + self.text[start..end].chars().count();
+                    paint_text_selection(
+                        &mut galley,
+                        &visuals,
+                        &CursorRange {
+                            primary: galley.from_ccursor(CCursor::new(start_char)),
+                            secondary: galley.from_ccursor(CCursor::new(end_char)),
+                        },
+                        None,
+                    );
+                }
+
+                ui.add(egui::Label::new(galley));
+            });
+        });
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::segments;
+
+    #[test]
+    fn segments_split_outside_of_multiline_constructs() {
+        let text = "<a>\n<!-- x\ny -->\n<b c=\"\n\"/>\ntext";
+        assert_eq!(
+            segments(text, "xml").collect::<Vec<_>>(),
+            ["<a>\n", "<!-- x\ny -->\n", "<b c=\"\n\"/>\n", "text"]
+        );
+        assert_eq!(segments(text, "lua").collect::<Vec<_>>(), [text]);
+    }
+}
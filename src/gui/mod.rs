@@ -1,5 +1,8 @@
 pub mod ansi;
+mod completion;
+mod highlight;
 mod sandbox;
+mod undo;
 mod window;
 pub use sandbox::*;
 pub use window::*;
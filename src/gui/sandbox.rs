@@ -1,41 +1,46 @@
 use std::{
     borrow::Cow,
-    collections::HashMap,
+    collections::{BTreeMap, HashMap},
+    ffi::OsStr,
     ops::Range,
-    path::Path,
+    path::{Path, PathBuf},
     sync::{
-        atomic::{AtomicBool, Ordering},
+        atomic::{AtomicBool, AtomicU32, Ordering},
         mpsc, Arc,
     },
     time::Instant,
 };
 
-use annotate_snippets::{Message, Renderer};
+use annotate_snippets::Renderer;
 use anyhow::{anyhow, Context, Error, Result};
 use eframe::egui::{
-    self, scroll_area,
+    self,
     text::{CCursor, LayoutJob},
-    text_selection::visuals::paint_text_selection,
-    vec2, Color32, Id, Layout, Margin, TextEdit, Ui, Vec2,
+    vec2, Color32, Id, Layout, Margin, RichText, TextEdit, Ui, Vec2,
 };
 use egui_extras::syntax_highlighting;
-use log::debug;
+use log::{debug, warn};
 use parking_lot::Mutex;
 use regex::Regex;
 use silpkg::sync::Pkg;
 use speedy_xml::reader::Options;
 
 use crate::{
-    apply::{self, LuaPkgFS},
-    gui::ansi::layout_ansi,
+    apply::{self, preview::FindPreview, AppendType, LuaPkgFS, XmlAppendType},
+    gui::{
+        ansi::layout_ansi,
+        completion::{self, Completion},
+        highlight::{HighlightCache, VirtualText},
+        undo::UndoStack,
+    },
     l,
     lua::{
         io::{LuaFS, LuaFileStats, LuaFileType},
-        ModLuaRuntime,
+        LuaOutput, ModLuaRuntime,
     },
+    pathedit::PathEdit,
     render_error_chain,
-    util::StringArena,
-    validate::xml::validate_xml,
+    validate::{render_messages, xml::validate_xml, LineIndex},
 };
 
 use super::WindowState;
@@ -86,7 +91,15 @@ type SharedArc = Arc<Shared>;
 enum PatchWorkerCommand {
     Patch {
         mode: PatchMode,
+        keep_comments: bool,
+        keep_doctype: bool,
+        patch: String,
+        source_path: String,
+        waker: egui::Context,
+    },
+    Find {
         patch: String,
+        cursor: usize,
         source_path: String,
         waker: egui::Context,
     },
@@ -117,45 +130,52 @@ impl PatchWorker {
         csend
     }
 
+    fn read_source(&mut self, path: &str) -> std::io::Result<String> {
+        self.pkg
+            .open(path)
+            .map_err(std::io::Error::from)
+            .and_then(std::io::read_to_string)
+    }
+
     fn main(&mut self) {
         while let Ok(command) = self.receiver.recv() {
             match command {
                 PatchWorkerCommand::Patch {
                     mode,
+                    keep_comments,
+                    keep_doctype,
                     patch,
                     source_path,
                     waker,
                 } => {
                     let start = Instant::now();
-                    let source_text = match self
-                        .pkg
-                        .open(&source_path)
-                        .map_err(std::io::Error::from)
-                        .and_then(std::io::read_to_string)
-                    {
+                    let source_text = match self.read_source(&source_path) {
                         Ok(text) => text,
                         Err(err) => {
                             *self.shared.output.lock() = Output {
                                 patch: Some(PatchOutput::Error(err.into())),
                                 diagnostics: None,
+                                script_output: None,
+                                find: None,
                             };
                             self.shared.running.store(false, Ordering::Release);
                             continue;
                         }
                     };
 
-                    let mut messages = Vec::new();
-                    let message_strings = StringArena::new();
+                    let mut diagnostics = Vec::new();
+                    let mut script_output = LuaOutput::Capture(String::new());
                     let result = match mode {
                         PatchMode::XmlAppend => {
-                            if validate_xml(
-                                &patch,
-                                Options::default().allow_top_level_text(true),
-                                &mut messages,
-                                &message_strings,
-                                None,
-                            ) {
-                                apply::apply_one_xml(&source_text, &patch, apply::XmlAppendType::Append).map_err(Some)
+                            if validate_xml(&patch, Options::default().allow_top_level_text(true), &mut diagnostics) {
+                                apply::apply_one_xml(
+                                    &source_text,
+                                    &patch,
+                                    apply::XmlAppendType::Append,
+                                    keep_comments,
+                                    keep_doctype,
+                                )
+                                .map_err(Some)
                             } else {
                                 Err(None)
                             }
@@ -168,7 +188,13 @@ impl PatchWorker {
                                     overlay: HashMap::new(),
                                 };
                                 match rt.with_filesystems([("pkg", &mut overlay as &mut dyn LuaFS)], || {
-                                    Ok(apply::apply_one_lua(&source_text, &patch, &rt))
+                                    Ok(apply::apply_one_lua(
+                                        &source_text,
+                                        &patch,
+                                        &rt,
+                                        &mut script_output,
+                                        keep_doctype,
+                                    ))
                                 }) {
                                     Ok(Ok(ok)) => Ok(ok),
                                     Err(err) => Err(anyhow::Error::from(err)),
@@ -182,22 +208,10 @@ impl PatchWorker {
 
                     let mut output = self.shared.output.lock();
 
-                    let mut message_output = LayoutJob::default();
                     let renderer = Renderer::styled();
-                    let mut push_message = |message: Message<'_>| {
-                        if let Some(last) = message_output.sections.last_mut() {
-                            message_output.text.push('\n');
-                            last.byte_range.end += 1;
-                        }
-
-                        layout_ansi(
-                            &mut message_output,
-                            &renderer.render(message).to_string(),
-                            egui::FontId {
-                                family: egui::FontFamily::Monospace,
-                                ..Default::default()
-                            },
-                        );
+                    let font_id = egui::FontId {
+                        family: egui::FontFamily::Monospace,
+                        ..Default::default()
                     };
 
                     match result {
@@ -215,12 +229,45 @@ impl PatchWorker {
                         }
                     };
 
-                    for message in messages {
-                        push_message(message)
-                    }
+                    let lines = LineIndex::new(&patch);
+                    output.diagnostics = Some(
+                        render_messages(&diagnostics, &lines, None)
+                            .into_iter()
+                            .zip(&diagnostics)
+                            .map(|(message, diagnostic)| {
+                                let mut job = LayoutJob::default();
+                                layout_ansi(&mut job, &renderer.render(message).to_string(), font_id.clone());
+                                OutputDiagnostic {
+                                    message: job,
+                                    span: diagnostic.primary.span.clone(),
+                                }
+                            })
+                            .collect(),
+                    );
+                    output.script_output = match script_output {
+                        LuaOutput::Capture(text) if !text.is_empty() => Some(text),
+                        _ => None,
+                    };
 
-                    output.diagnostics = Some(message_output);
+                    self.shared.running.store(false, Ordering::Release);
+                    waker.request_repaint();
+                }
+                PatchWorkerCommand::Find {
+                    patch,
+                    cursor,
+                    source_path,
+                    waker,
+                } => {
+                    let find = self.read_source(&source_path).ok().and_then(|source| {
+                        let preview = apply::preview::preview_find_at(&source, &patch, cursor).transpose()?;
+                        Some(FindOutput {
+                            source,
+                            preview,
+                            scroll_pending: true,
+                        })
+                    });
 
+                    self.shared.output.lock().find = find;
                     self.shared.running.store(false, Ordering::Release);
                     waker.request_repaint();
                 }
@@ -254,35 +301,178 @@ impl PatchMode {
     }
 }
 
+static EDITOR_FONT_SIZE: AtomicU32 = AtomicU32::new(DEFAULT_EDITOR_FONT_SIZE.to_bits());
+
+pub const DEFAULT_EDITOR_FONT_SIZE: f32 = 12.0;
+
+/// Sets the size of the font used for code in the sandbox.
+pub fn set_editor_font_size(size: f32) {
+    EDITOR_FONT_SIZE.store(size.to_bits(), Ordering::Relaxed);
+}
+
+/// Makes code editors inside `ui` use the configured editor font.
+fn use_editor_font(ui: &mut Ui) {
+    let font_id = egui::FontId::new(
+        f32::from_bits(EDITOR_FONT_SIZE.load(Ordering::Relaxed)),
+        egui::FontFamily::Name(crate::fonts::EDITOR_FONT_FAMILY.into()),
+    );
+    ui.style_mut().text_styles.insert(egui::TextStyle::Monospace, font_id);
+}
+
+/// Returns a layouter for [`TextEdit`]s that highlights code in the given language.
+pub fn code_layouter() -> impl Fn(&Ui, &str, f32, &'static str) -> Arc<egui::Galley> {
+    // The theme determines the font, so it's created from the style of the ui being laid out
+    // in case it overrides the monospace font like the sandbox editor does.
+    move |ui: &Ui, text: &str, width: f32, language: &'static str| {
+        let theme = syntax_highlighting::CodeTheme::from_style(ui.style());
+        let mut layout_job = syntax_highlighting::highlight(ui.ctx(), ui.style(), &theme, text, language);
+        layout_job.wrap.max_width = width;
+        ui.fonts(|f| f.layout_job(layout_job))
+    }
+}
+
+/// Shows diagnostics as clickable labels, returns the span of the one that was clicked.
+fn show_diagnostics(ui: &mut Ui, diagnostics: &[OutputDiagnostic]) -> Option<Range<usize>> {
+    let mut clicked = None;
+    for diagnostic in diagnostics {
+        if ui
+            .add(
+                egui::Label::new(diagnostic.message.clone())
+                    .selectable(false)
+                    .sense(egui::Sense::click()),
+            )
+            .on_hover_cursor(egui::CursorIcon::PointingHand)
+            .clicked()
+        {
+            clicked = Some(diagnostic.span.clone());
+        }
+    }
+    clicked
+}
+
+/// Shows the source file with the elements matched by a find highlighted.
+fn render_find_source(ui: &mut Ui, find: &mut FindOutput, view: &mut VirtualText) {
+    let Ok(preview) = &find.preview else {
+        return;
+    };
+
+    use_editor_font(ui);
+    let scroll = std::mem::take(&mut find.scroll_pending);
+    view.show(
+        ui,
+        &find.source,
+        "xml",
+        &preview.matches,
+        Color32::GOLD.gamma_multiply(0.5),
+        preview.matches.first().filter(|_| scroll).map(|range| range.start),
+    );
+}
+
+/// Paints 1-based line numbers right-aligned to `right` next to the first row of every line in `galley`.
+fn paint_line_numbers(ui: &Ui, galley: &egui::Galley, galley_pos: egui::Pos2, right: f32) {
+    let font_id = egui::TextStyle::Monospace.resolve(ui.style());
+    let row_height = ui.fonts(|f| f.row_height(&font_id));
+    let color = ui.visuals().weak_text_color();
+    let clip = ui.clip_rect();
+
+    let mut line = 1;
+    let mut line_start = true;
+    for row in &galley.rows {
+        let top = galley_pos.y + row.min_y();
+        if top > clip.max.y {
+            break;
+        }
+
+        if line_start && top + row_height >= clip.min.y {
+            ui.painter().text(
+                egui::pos2(right, top),
+                egui::Align2::RIGHT_TOP,
+                line.to_string(),
+                font_id.clone(),
+                color,
+            );
+        }
+
+        line_start = row.ends_with_newline;
+        if row.ends_with_newline {
+            line += 1;
+        }
+    }
+}
+
 pub struct Sandbox {
     // If None then the window is closed.
     worker: Option<mpsc::SyncSender<PatchWorkerCommand>>,
+    ftl_path: Option<PathBuf>,
+    use_vanilla: bool,
     shared: SharedArc,
 
     pkg_names: Vec<String>,
     filtered_pkg_names: Vec<(usize, String)>,
+    pkg_tree: BTreeMap<String, FileTreeNode>,
+    show_file_tree: bool,
 
     search_text: String,
     patch_text: String,
 
+    patch_file_path: String,
+    // Contents of the patch file as of the last load or save, None if no file is associated with the editor.
+    patch_file_saved_text: Option<String>,
+    patch_file_warning: Option<String>,
+
     patch_mode: PatchMode,
     patch_on_change: bool,
     always_show_diagnostics: bool,
+    keep_comments: bool,
+    keep_doctype: bool,
 
     current_file: Option<usize>,
     output_find_box: (String, Option<Regex>, usize),
     output_find_matches: Vec<Range<usize>>,
-    // HACK: I don't even want to write about this anymore.
-    output_scroll_id: Option<Id>,
 
     // Whether the patch XML was changed since the last update was ran.
     needs_update: bool,
+
+    completion: Option<Completion>,
+    completion_selected: usize,
+
+    // Contents of the go to line prompt, None if it's closed.
+    goto_line: Option<String>,
+    // Byte range in the patch text that should be selected and scrolled to on the next frame.
+    pending_jump: Option<Range<usize>>,
+
+    undo: UndoStack,
+    // These are kept across frames so that large files don't have to be highlighted again every frame.
+    editor_highlight: HighlightCache,
+    output_view: VirtualText,
+    source_view: VirtualText,
+
+    show_find_source: bool,
+    // Editor cursor position the current find preview was requested for.
+    find_cursor: Option<usize>,
 }
 
 #[derive(Default)]
 struct Output {
     patch: Option<PatchOutput>,
-    diagnostics: Option<LayoutJob>,
+    diagnostics: Option<Vec<OutputDiagnostic>>,
+    // Text printed by a Lua patch, None if nothing was printed.
+    script_output: Option<String>,
+    // Matches of the find under the editor cursor, None if the cursor isn't inside of one.
+    find: Option<FindOutput>,
+}
+
+struct FindOutput {
+    source: String,
+    preview: Result<FindPreview>,
+    // Whether the source view should scroll to the first match on the next frame.
+    scroll_pending: bool,
+}
+
+struct OutputDiagnostic {
+    message: LayoutJob,
+    // Byte range of the primary annotation in the patch text this diagnostic was produced for.
+    span: Range<usize>,
 }
 
 enum PatchOutput {
@@ -290,6 +480,94 @@ enum PatchOutput {
     Error(Error),
 }
 
+enum FileTreeNode {
+    File(usize),
+    Directory {
+        children: BTreeMap<String, FileTreeNode>,
+        // Whether any file below this directory matches the current search text, see `update_matches`.
+        matches: bool,
+    },
+}
+
+impl FileTreeNode {
+    fn from_names(names: &[String]) -> BTreeMap<String, FileTreeNode> {
+        let mut root = BTreeMap::new();
+
+        for (i, name) in names.iter().enumerate() {
+            let mut current = &mut root;
+            let mut it = name.split('/');
+            let last = it.next_back().unwrap();
+            for component in it {
+                match current
+                    .entry(component.to_owned())
+                    .or_insert_with(|| FileTreeNode::Directory {
+                        children: BTreeMap::new(),
+                        matches: true,
+                    }) {
+                    FileTreeNode::Directory { children, .. } => current = children,
+                    // Pkg archives can't contain a file that overlaps a directory.
+                    FileTreeNode::File(_) => unreachable!(),
+                }
+            }
+            current.insert(last.to_owned(), FileTreeNode::File(i));
+        }
+
+        root
+    }
+
+    // Recomputes which directories contain files matching `filter`, has to be called whenever it changes.
+    fn update_matches(tree: &mut BTreeMap<String, FileTreeNode>, names: &[String], filter: &str) -> bool {
+        let mut any = false;
+        for node in tree.values_mut() {
+            any |= match node {
+                FileTreeNode::File(i) => names[*i].contains(filter),
+                FileTreeNode::Directory { children, matches } => {
+                    *matches = Self::update_matches(children, names, filter);
+                    *matches
+                }
+            };
+        }
+        any
+    }
+
+    fn matches(&self, names: &[String], filter: &str) -> bool {
+        match self {
+            FileTreeNode::File(i) => names[*i].contains(filter),
+            FileTreeNode::Directory { matches, .. } => *matches,
+        }
+    }
+}
+
+// Directory matches are cached and only expanded directories are laid out, so this stays cheap for large archives.
+fn render_file_tree(
+    ui: &mut Ui,
+    tree: &BTreeMap<String, FileTreeNode>,
+    names: &[String],
+    filter: &str,
+    current: Option<usize>,
+    clicked: &mut Option<usize>,
+) {
+    for (name, node) in tree {
+        if !node.matches(names, filter) {
+            continue;
+        }
+
+        match node {
+            &FileTreeNode::File(i) => {
+                let is_current = current.is_some_and(|n| n == i);
+                if ui.selectable_label(is_current, name).clicked() && !is_current {
+                    *clicked = Some(i);
+                }
+            }
+            FileTreeNode::Directory { children, .. } => {
+                egui::CollapsingHeader::new(name)
+                    .open((!filter.is_empty()).then_some(true))
+                    .show(ui, |ui| render_file_tree(ui, children, names, filter, current, clicked));
+            }
+        }
+    }
+}
+
 // HACK?: kind of hard to refactor into a function
 macro_rules! rebuild_filtered_names {
     ($self: ident) => {
@@ -300,6 +578,7 @@ macro_rules! rebuild_filtered_names {
             .filter(|(_, s)| s.contains(&$self.search_text))
             .map(|(i, s)| (i, s.clone()))
             .collect();
+        FileTreeNode::update_matches(&mut $self.pkg_tree, &$self.pkg_names, &$self.search_text);
     };
 }
 
@@ -307,14 +586,24 @@ impl Sandbox {
     pub fn new() -> Self {
         Self {
             worker: None,
+            ftl_path: None,
+            use_vanilla: false,
             pkg_names: Vec::new(),
             filtered_pkg_names: Vec::new(),
+            pkg_tree: BTreeMap::new(),
+            show_file_tree: false,
             search_text: String::new(),
             patch_text: String::new(),
 
+            patch_file_path: String::new(),
+            patch_file_saved_text: None,
+            patch_file_warning: None,
+
             patch_mode: PatchMode::XmlAppend,
             patch_on_change: true,
             always_show_diagnostics: false,
+            keep_comments: false,
+            keep_doctype: true,
 
             current_file: None,
             shared: Arc::new(Shared {
@@ -323,23 +612,187 @@ impl Sandbox {
             }),
             output_find_box: (String::new(), None, 0),
             output_find_matches: Vec::new(),
-            output_scroll_id: None,
             needs_update: false,
+
+            completion: None,
+            completion_selected: 0,
+
+            goto_line: None,
+            pending_jump: None,
+
+            undo: UndoStack::default(),
+            editor_highlight: HighlightCache::default(),
+            output_view: VirtualText::default(),
+            source_view: VirtualText::default(),
+
+            show_find_source: false,
+            find_cursor: None,
         }
     }
 
     pub fn open(&mut self, path: &Path) -> Result<()> {
         let previously_open_name = self.current_file.map(|c| self.pkg_names[c].clone());
 
-        let pkg = Pkg::parse(std::fs::File::open(path.join("ftl.dat"))?)?;
+        let vanilla_path = path.join(apply::BACKUP_FILENAME);
+        let pkg_path = if self.use_vanilla && vanilla_path.exists() {
+            vanilla_path
+        } else {
+            path.join("ftl.dat")
+        };
+
+        let pkg = Pkg::parse(std::fs::File::open(pkg_path)?)?;
         self.pkg_names = pkg.paths().filter(|&name| name.ends_with(".xml")).cloned().collect();
         self.pkg_names.sort_unstable();
+        self.pkg_tree = FileTreeNode::from_names(&self.pkg_names);
         rebuild_filtered_names!(self);
         *self.shared.output.lock() = Output::default();
         self.current_file =
             previously_open_name.and_then(|previous_name| self.pkg_names.iter().position(|c| c == &previous_name));
         self.needs_update = true;
         self.worker = Some(PatchWorker::start(pkg, self.shared.clone()));
+        self.ftl_path = Some(path.to_path_buf());
+
+        Ok(())
+    }
+
+    fn is_patch_file_dirty(&self) -> bool {
+        self.patch_file_saved_text
+            .as_ref()
+            .is_some_and(|saved| saved != &self.patch_text)
+    }
+
+    fn load_patch_file(&mut self) -> Result<()> {
+        let path = PathBuf::from(&self.patch_file_path);
+        let text = apply::read_encoded_text(std::fs::File::open(&path).context("Failed to open patch file")?)
+            .context("Failed to read patch file")?;
+
+        self.patch_text = text.clone();
+        // Recorded as a regular edit so that accidentally opening a file can be undone.
+        self.undo.record(&self.patch_text);
+        self.patch_file_saved_text = Some(text);
+        self.patch_file_warning = None;
+        self.needs_update = true;
+
+        let Some(name) = path.file_name().and_then(OsStr::to_str) else {
+            return Ok(());
+        };
+
+        if let Some((real_stem, kind)) = AppendType::from_filename(name) {
+            match kind {
+                AppendType::Xml(XmlAppendType::Append) => self.patch_mode = PatchMode::XmlAppend,
+                AppendType::LuaAppend => self.patch_mode = PatchMode::LuaAppend,
+                // Not supported by the sandbox, leave the mode as is.
+                AppendType::Xml(XmlAppendType::RawAppend) => (),
+            }
+
+            let real_path = path.with_file_name(format!("{real_stem}.xml"));
+            if let Some(idx) = self.pkg_names.iter().position(|name| real_path.ends_with(name)) {
+                self.current_file = Some(idx);
+            } else {
+                warn!("Ignoring {name} with non-existent base file");
+                self.patch_file_warning = Some(l!("sandbox-file-no-base", "name" => name).into_owned());
+            }
+        }
+
+        Ok(())
+    }
+
+    fn accept_completion(&mut self, ctx: &egui::Context, editor_id: Id) -> bool {
+        let Some(completion) = self.completion.take() else {
+            return false;
+        };
+
+        let Some(candidate) = completion.candidates.get(self.completion_selected) else {
+            return false;
+        };
+
+        if self.patch_text.get(completion.range.clone()).is_none() {
+            return false;
+        }
+
+        let (insertion, cursor_offset) = completion.insertion(candidate, &self.patch_text);
+        self.patch_text.replace_range(completion.range.clone(), &insertion);
+
+        let cursor = self.patch_text[..completion.range.start + cursor_offset]
+            .chars()
+            .count();
+        let mut state = egui::TextEdit::load_state(ctx, editor_id).unwrap_or_default();
+        state
+            .cursor
+            .set_char_range(Some(egui::text::CCursorRange::one(CCursor::new(cursor))));
+        state.store(ctx, editor_id);
+
+        true
+    }
+
+    /// Selects a byte range of the patch text in the editor and focuses it.
+    ///
+    /// Returns the new cursor position, or None if the range is no longer valid for the current text.
+    fn select_in_editor(&self, ctx: &egui::Context, editor_id: Id, range: Range<usize>) -> Option<CCursor> {
+        let start = CCursor::new(self.patch_text.get(..range.start)?.chars().count());
+        let end = CCursor::new(start.index + self.patch_text.get(range)?.chars().count());
+
+        let mut state = egui::TextEdit::load_state(ctx, editor_id).unwrap_or_default();
+        state.cursor.set_char_range(Some(egui::text::CCursorRange {
+            primary: start,
+            secondary: end,
+        }));
+        state.store(ctx, editor_id);
+        ctx.memory_mut(|m| m.request_focus(editor_id));
+
+        Some(start)
+    }
+
+    fn render_goto_line(&mut self, ctx: &egui::Context) {
+        let Some(text) = self.goto_line.as_mut() else {
+            return;
+        };
+
+        let lines = LineIndex::new(&self.patch_text);
+        let offset = text
+            .trim()
+            .parse::<usize>()
+            .ok()
+            .and_then(|line| lines.line_offset(line));
+        let (mut submit, mut close) = (false, false);
+
+        egui::Window::new(l!("sandbox-goto-line-title"))
+            .collapsible(false)
+            .resizable(false)
+            .anchor(egui::Align2::CENTER_TOP, vec2(0.0, 80.0))
+            .show(ctx, |ui| {
+                let response = ui.add(
+                    TextEdit::singleline(text)
+                        .id_source("sandbox goto line text edit")
+                        .hint_text(l!("sandbox-goto-line-hint", "count" => lines.line_count()))
+                        .desired_width(200.),
+                );
+
+                submit = ui.input(|i| i.key_pressed(egui::Key::Enter));
+                close = ui.input(|i| i.key_pressed(egui::Key::Escape));
+                if !submit && !close {
+                    response.request_focus();
+                }
+
+                if !text.trim().is_empty() && offset.is_none() {
+                    ui.label(
+                        RichText::new(l!("sandbox-goto-line-invalid", "count" => lines.line_count()))
+                            .color(ui.visuals().warn_fg_color),
+                    );
+                }
+            });
+
+        if submit && offset.is_some() {
+            self.pending_jump = offset.map(|offset| offset..offset);
+            self.goto_line = None;
+        } else if close {
+            self.goto_line = None;
+        }
+    }
+
+    fn save_patch_file(&mut self) -> Result<()> {
+        std::fs::write(&self.patch_file_path, &self.patch_text).context("Failed to write patch file")?;
+        self.patch_file_saved_text = Some(self.patch_text.clone());
 
         Ok(())
     }
@@ -357,12 +810,21 @@ impl WindowState for Sandbox {
     }
 
     fn render(&mut self, ctx: &egui::Context) {
-        let Some(worker) = self.worker.as_mut() else { return };
+        if self.worker.is_none() {
+            return;
+        }
 
         egui::TopBottomPanel::top("sandbox header").show(ctx, |ui| {
             ui.add_space(5.);
             ui.horizontal(|ui| {
-                let height = ui.heading(l!("sandbox-title")).rect.height();
+                let height = ui
+                    .heading(if self.is_patch_file_dirty() {
+                        format!("{}*", l!("sandbox-title"))
+                    } else {
+                        l!("sandbox-title").into_owned()
+                    })
+                    .rect
+                    .height();
                 ui.allocate_ui_with_layout(
                     egui::vec2(ui.available_width(), height),
                     Layout::right_to_left(egui::Align::Center),
@@ -382,9 +844,77 @@ impl WindowState for Sandbox {
 
                         ui.checkbox(&mut self.patch_on_change, l!("sandbox-patch-on-change"));
                         ui.checkbox(&mut self.always_show_diagnostics, l!("sandbox-diagnostics-panel"));
+                        self.needs_update |= ui
+                            .checkbox(&mut self.keep_comments, l!("sandbox-keep-comments"))
+                            .changed();
+                        self.needs_update |= ui
+                            .checkbox(&mut self.keep_doctype, l!("sandbox-keep-doctype"))
+                            .changed();
+
+                        if let Some(ftl_path) = self.ftl_path.clone() {
+                            let vanilla_exists = ftl_path.join(apply::BACKUP_FILENAME).exists();
+                            if ui
+                                .add_enabled(
+                                    vanilla_exists,
+                                    egui::Checkbox::new(&mut self.use_vanilla, l!("sandbox-use-vanilla")),
+                                )
+                                .on_hover_text(l!("sandbox-use-vanilla-tooltip"))
+                                .on_disabled_hover_text(l!("sandbox-use-vanilla-missing"))
+                                .changed()
+                            {
+                                if let Err(error) = self.open(&ftl_path) {
+                                    *self.shared.output.lock() = Output {
+                                        patch: Some(PatchOutput::Error(error)),
+                                        diagnostics: None,
+                                        script_output: None,
+                                        find: None,
+                                    };
+                                }
+                            }
+                        }
                     },
                 )
             });
+
+            ui.horizontal(|ui| {
+                ui.label(l!("sandbox-file-label"));
+                PathEdit::new(&mut self.patch_file_path)
+                    .id("sandbox patch file pathedit")
+                    .desired_width(320.)
+                    .completion_filter(|p| {
+                        p.is_dir()
+                            || p.file_name()
+                                .and_then(OsStr::to_str)
+                                .is_some_and(|name| AppendType::from_filename(name).is_some())
+                    })
+                    .show(ui);
+
+                let has_path = !self.patch_file_path.is_empty();
+                if ui
+                    .add_enabled(has_path, egui::Button::new(l!("sandbox-file-open")))
+                    .clicked()
+                {
+                    if let Err(error) = self.load_patch_file() {
+                        self.patch_file_warning = Some(format!("{error:#}"));
+                    }
+                }
+
+                let save_shortcut = ui.input_mut(|i| i.consume_key(egui::Modifiers::COMMAND, egui::Key::S));
+                if ui
+                    .add_enabled(has_path, egui::Button::new(l!("sandbox-file-save")))
+                    .clicked()
+                    || (has_path && save_shortcut)
+                {
+                    if let Err(error) = self.save_patch_file() {
+                        self.patch_file_warning = Some(format!("{error:#}"));
+                    }
+                }
+            });
+
+            if let Some(warning) = self.patch_file_warning.as_ref() {
+                ui.label(RichText::new(warning).color(ui.visuals().warn_fg_color));
+            }
+
             ui.add_space(5.);
         });
 
@@ -403,44 +933,59 @@ impl WindowState for Sandbox {
                     }
                 });
 
+                ui.checkbox(&mut self.show_file_tree, l!("sandbox-file-tree"));
+
                 ui.add_space(5.);
 
-                egui::ScrollArea::vertical().show_rows(
-                    ui,
-                    ui.spacing().interact_size.y,
-                    self.filtered_pkg_names.len(),
-                    |ui, range| {
-                        for &(i, ref name) in self.filtered_pkg_names.iter().skip(range.start).take(range.len()) {
-                            if !name.contains(&self.search_text) {
-                                continue;
-                            }
+                if self.show_file_tree {
+                    egui::ScrollArea::vertical().show(ui, |ui| {
+                        let mut clicked = None;
+                        render_file_tree(
+                            ui,
+                            &self.pkg_tree,
+                            &self.pkg_names,
+                            &self.search_text,
+                            self.current_file,
+                            &mut clicked,
+                        );
 
-                            let is_current = self.current_file.is_some_and(|n| n == i);
-                            if ui.selectable_label(is_current, name).clicked() && !is_current {
-                                self.needs_update = true;
-                                self.current_file = Some(i);
-                                ctx.request_repaint();
-                            }
+                        if let Some(i) = clicked {
+                            self.needs_update = true;
+                            self.current_file = Some(i);
+                            ctx.request_repaint();
                         }
-                    },
-                );
+                    });
+                } else {
+                    egui::ScrollArea::vertical().show_rows(
+                        ui,
+                        ui.spacing().interact_size.y,
+                        self.filtered_pkg_names.len(),
+                        |ui, range| {
+                            for &(i, ref name) in self.filtered_pkg_names.iter().skip(range.start).take(range.len()) {
+                                if !name.contains(&self.search_text) {
+                                    continue;
+                                }
+
+                                let is_current = self.current_file.is_some_and(|n| n == i);
+                                if ui.selectable_label(is_current, name).clicked() && !is_current {
+                                    self.needs_update = true;
+                                    self.current_file = Some(i);
+                                    ctx.request_repaint();
+                                }
+                            }
+                        },
+                    );
+                }
             });
         });
 
-        let theme = syntax_highlighting::CodeTheme::from_style(&ctx.style());
-        let layouter = move |ui: &Ui, text: &str, width: f32, language: &'static str| {
-            let mut layout_job = syntax_highlighting::highlight(ui.ctx(), ui.style(), &theme, text, language);
-            layout_job.wrap.max_width = width;
-            ui.fonts(|f| f.layout_job(layout_job))
-        };
-
-        if let Some(output) =
-            Some(&mut *self.shared.output.lock()).filter(|o| o.patch.is_some() || o.diagnostics.is_some())
+        if let Some(output) = Some(&mut *self.shared.output.lock())
+            .filter(|o| o.patch.is_some() || o.diagnostics.is_some() || o.script_output.is_some() || o.find.is_some())
         {
             egui::SidePanel::right("sandbox output")
                 .min_width(300.0)
                 .show(ctx, |ui| {
-                    if let Some(job) = output.diagnostics.as_ref().filter(|_| {
+                    if let Some(diagnostics) = output.diagnostics.as_ref().filter(|_| {
                         output.patch.is_some()
                             // Currently no diagnostics are supported with Lua
                             && self.patch_mode == PatchMode::XmlAppend
@@ -467,14 +1012,69 @@ impl WindowState for Sandbox {
                                 egui::ScrollArea::vertical().show(ui, |ui| {
                                     ui.set_min_width(ui.available_width());
 
-                                    ui.label(eframe::egui::WidgetText::LayoutJob(job.clone()));
+                                    if let Some(span) = show_diagnostics(ui, diagnostics) {
+                                        self.pending_jump = Some(span);
+                                    }
+                                });
+                            });
+                    }
+
+                    if let Some(text) = output
+                        .script_output
+                        .as_deref()
+                        .filter(|_| self.patch_mode == PatchMode::LuaAppend)
+                    {
+                        egui::TopBottomPanel::bottom("sandbox script output panel")
+                            .resizable(true)
+                            .height_range(egui::Rangef::new(60.0, ui.available_height() - 100.0))
+                            .show_inside(ui, |ui| {
+                                ui.set_min_width(ui.available_width());
+                                ui.set_min_height(ui.available_height());
+
+                                ui.label(l!("sandbox-script-output"));
+                                egui::ScrollArea::vertical().stick_to_bottom(true).show(ui, |ui| {
+                                    ui.set_min_width(ui.available_width());
+
+                                    ui.label(egui::RichText::new(text).monospace());
                                 });
                             });
                     }
 
                     ui.add_space(ui.spacing().window_margin.top.into());
 
+                    let xml_mode = self.patch_mode == PatchMode::XmlAppend;
+                    if let Some(find) = output.find.as_mut().filter(|_| xml_mode) {
+                        ui.horizontal(|ui| {
+                            match &find.preview {
+                                Ok(preview) => ui.label(l!(
+                                    "sandbox-find-matches",
+                                    "find" => preview.find.as_str(),
+                                    "count" => preview.matches.len()
+                                )),
+                                Err(error) => {
+                                    ui.label(RichText::new(format!("{error:#}")).color(ui.visuals().warn_fg_color))
+                                }
+                            };
+
+                            ui.with_layout(Layout::right_to_left(egui::Align::Center), |ui| {
+                                if ui
+                                    .checkbox(&mut self.show_find_source, l!("sandbox-find-show-source"))
+                                    .on_hover_text(l!("sandbox-find-show-source-tooltip"))
+                                    .changed()
+                                {
+                                    find.scroll_pending = true;
+                                }
+                            });
+                        });
+                    }
+
+                    let show_find_source = xml_mode
+                        && self.show_find_source
+                        && output.find.as_ref().is_some_and(|find| find.preview.is_ok());
                     match &mut output.patch {
+                        _ if show_find_source => {
+                            render_find_source(ui, output.find.as_mut().unwrap(), &mut self.source_view)
+                        }
                         Some(PatchOutput::Xml {
                             content: xml,
                             find_invalidated,
@@ -484,7 +1084,6 @@ impl WindowState for Sandbox {
                             ui.with_layout(Layout::bottom_up(egui::Align::Min), |ui| {
                                 ui.add_space(ui.spacing().window_margin.bottom.into());
 
-                                let mut selection_cursor = None;
                                 let mut do_scroll = false;
                                 let (needle, regex, idx) = &mut self.output_find_box;
 
@@ -495,6 +1094,14 @@ impl WindowState for Sandbox {
                                     find_size,
                                     egui::Layout::right_to_left(egui::Align::Center),
                                     |ui| {
+                                        // The output view only lays out visible lines, so it cannot be selected.
+                                        if ui
+                                            .button(l!("sandbox-copy-output"))
+                                            .on_hover_text(l!("sandbox-copy-output-tooltip"))
+                                            .clicked()
+                                        {
+                                            ui.ctx().copy_text(xml.to_string());
+                                        }
                                         // This is a right arrow in the egui font
                                         let button_rr = ui.button("➡");
                                         // This is a left arrow in the egui font
@@ -578,42 +1185,8 @@ impl WindowState for Sandbox {
                                     },
                                 );
 
-                                if let Some(range) = self.output_find_matches.get(*idx).cloned() {
-                                    // why does it work this way??
-                                    let start_cc = xml[..range.start].chars().count();
-                                    let end_cc = start_cc + xml[range.start..range.end].chars().count();
-                                    let ccrange = egui::text::CCursorRange {
-                                        primary: CCursor::new(start_cc),
-                                        secondary: CCursor::new(end_cc),
-                                    };
-                                    selection_cursor = Some(ccrange);
-                                }
-
-                                let mut galley = layouter(ui, xml, ui.available_width(), "xml");
-                                let selection_crange = selection_cursor.map(|ccrange| egui::text::CursorRange {
-                                    primary: galley.from_ccursor(ccrange.primary),
-                                    secondary: galley.from_ccursor(ccrange.secondary),
-                                });
-
-                                if let (Some(crange), Some((id, Some(mut state)))) = (
-                                    selection_crange.filter(|_| do_scroll),
-                                    self.output_scroll_id.map(|id| (id, scroll_area::State::load(ctx, id))),
-                                ) {
-                                    let scroll_y = galley.pos_from_cursor(&crange.primary).min.y;
-                                    state.offset.y = (scroll_y - ui.available_height() / 2.0)
-                                        .clamp(0.0, galley.size().y - ui.available_height());
-                                    state.store(ctx, id);
-                                }
-
-                                // sequel, this time with match highlighting
-                                let mut layouter2 = |_: &Ui, _: &str, _: f32| {
-                                    if let Some(crange) = selection_crange {
-                                        let mut v = ui.visuals().clone();
-                                        v.selection.bg_fill = Color32::GREEN;
-                                        paint_text_selection(&mut galley, &v, &crange, None);
-                                    }
-                                    galley.clone()
-                                };
+                                let current_match = self.output_find_matches.get(*idx).cloned();
+                                let scroll_to = current_match.as_ref().filter(|_| do_scroll).map(|range| range.start);
 
                                 let output_size = ui.available_size();
                                 // HACK: This manual placement stops egui layout code from completely
@@ -623,34 +1196,33 @@ impl WindowState for Sandbox {
                                     .movable(false)
                                     .fixed_pos(top)
                                     .show(ctx, |ui| {
+                                        use_editor_font(ui);
                                         ui.set_min_size(output_size);
                                         ui.set_max_size(output_size);
 
-                                        self.output_scroll_id = Some(
-                                            egui::ScrollArea::vertical()
-                                                .show(ui, |ui| {
-                                                    ui.set_min_width(ui.available_width());
-
-                                                    egui::TextEdit::multiline(&mut xml.as_str())
-                                                        .layouter(&mut layouter2)
-                                                        .code_editor()
-                                                        .show(ui)
-                                                })
-                                                .id,
+                                        self.output_view.show(
+                                            ui,
+                                            xml,
+                                            "xml",
+                                            current_match.as_slice(),
+                                            Color32::GREEN,
+                                            scroll_to,
                                         );
                                     });
                             });
                         }
                         Some(PatchOutput::Error(error)) => render_error_chain(ui, error.chain().map(|e| e.to_string())),
                         None => {
-                            if let Some(job) = output.diagnostics.as_ref() {
+                            if let Some(diagnostics) = output.diagnostics.as_ref() {
                                 ui.set_min_width(ui.available_width());
 
                                 egui::ScrollArea::vertical().show(ui, |ui| {
                                     // This prevents diagnostics from shrinking the panel
                                     ui.set_min_width(ui.available_width());
 
-                                    ui.label(eframe::egui::WidgetText::LayoutJob(job.clone()));
+                                    if let Some(span) = show_diagnostics(ui, diagnostics) {
+                                        self.pending_jump = Some(span);
+                                    }
                                 });
                             }
                         }
@@ -658,30 +1230,180 @@ impl WindowState for Sandbox {
                 });
         }
 
+        if ctx.input_mut(|i| i.consume_key(egui::Modifiers::COMMAND, egui::Key::G)) {
+            self.goto_line = Some(String::new());
+        }
+        self.render_goto_line(ctx);
+
         egui::CentralPanel::default().show(ctx, |ui| {
-            let changed = egui::ScrollArea::vertical()
-                .show(ui, |ui| {
-                    ui.add_sized(
-                        ui.available_size(),
-                        egui::TextEdit::multiline(&mut self.patch_text)
-                            .id(egui::Id::new("xml sandbox patch editor"))
-                            .hint_text(match self.patch_mode {
-                                PatchMode::XmlAppend => l!("sandbox-editor-hint-xml-append"),
-                                PatchMode::LuaAppend => l!("sandbox-editor-hint-lua-append"),
-                            })
-                            .layouter(&mut |ui, text, width| layouter(ui, text, width, self.patch_mode.language()))
-                            .code_editor(),
+            use_editor_font(ui);
+            let editor_id = egui::Id::new("xml sandbox patch editor");
+
+            let mut changed = false;
+            // These have to be consumed before the editor sees them, otherwise its own undo would kick in.
+            if ctx.memory(|m| m.has_focus(editor_id)) {
+                let (redo, undo) = ui.input_mut(|i| {
+                    (
+                        i.consume_key(egui::Modifiers::COMMAND | egui::Modifiers::SHIFT, egui::Key::Z)
+                            || i.consume_key(egui::Modifiers::COMMAND, egui::Key::Y),
+                        i.consume_key(egui::Modifiers::COMMAND, egui::Key::Z),
                     )
+                });
+
+                let cursor = if undo {
+                    self.undo.undo(&mut self.patch_text)
+                } else if redo {
+                    self.undo.redo(&mut self.patch_text)
+                } else {
+                    None
+                };
+
+                if let Some(cursor) = cursor {
+                    self.pending_jump = Some(cursor..cursor);
+                    self.completion = None;
+                    changed = true;
+                }
+            }
+
+            let jump = self
+                .pending_jump
+                .take()
+                .and_then(|range| self.select_in_editor(ctx, editor_id, range));
+
+            if let Some(count) = self.completion.as_ref().map(|c| c.candidates.len()) {
+                let (mut accept, mut dismiss) = (false, false);
+                ui.input_mut(|i| {
+                    if i.consume_key(egui::Modifiers::NONE, egui::Key::ArrowDown) {
+                        self.completion_selected = (self.completion_selected + 1) % count;
+                    }
+                    if i.consume_key(egui::Modifiers::NONE, egui::Key::ArrowUp) {
+                        self.completion_selected = self.completion_selected.checked_sub(1).unwrap_or(count - 1);
+                    }
+                    accept = i.consume_key(egui::Modifiers::NONE, egui::Key::Enter)
+                        || i.consume_key(egui::Modifiers::NONE, egui::Key::Tab);
+                    dismiss = i.consume_key(egui::Modifiers::NONE, egui::Key::Escape);
+                });
+
+                if dismiss {
+                    self.completion = None;
+                } else if accept {
+                    changed |= self.accept_completion(ctx, editor_id);
+                }
+            }
+
+            let output = egui::ScrollArea::vertical()
+                .show(ui, |ui| {
+                    let line_count = self.patch_text.bytes().filter(|&b| b == b'\n').count() + 1;
+                    let font_id = egui::TextStyle::Monospace.resolve(ui.style());
+                    let digit_width = ui.fonts(|f| f.glyph_width(&font_id, '0'));
+                    let gutter_width = (line_count.ilog10() + 1) as f32 * digit_width + ui.spacing().item_spacing.x;
+
+                    ui.horizontal_top(|ui| {
+                        let gutter_left = ui.cursor().min.x;
+                        ui.add_space(gutter_width);
+
+                        let output = ui
+                            .allocate_ui_with_layout(
+                                ui.available_size(),
+                                Layout::centered_and_justified(egui::Direction::TopDown),
+                                |ui| {
+                                    egui::TextEdit::multiline(&mut self.patch_text)
+                                        .id(editor_id)
+                                        .hint_text(match self.patch_mode {
+                                            PatchMode::XmlAppend => l!("sandbox-editor-hint-xml-append"),
+                                            PatchMode::LuaAppend => l!("sandbox-editor-hint-lua-append"),
+                                        })
+                                        .layouter(&mut |ui, text, width| {
+                                            self.editor_highlight
+                                                .layout(ui, text, width, self.patch_mode.language())
+                                        })
+                                        .code_editor()
+                                        .show(ui)
+                                },
+                            )
+                            .inner;
+
+                        paint_line_numbers(
+                            ui,
+                            &output.galley,
+                            output.galley_pos,
+                            gutter_left + gutter_width - ui.spacing().item_spacing.x,
+                        );
+
+                        if let Some(cursor) = jump {
+                            let rect = output
+                                .galley
+                                .pos_from_cursor(&output.galley.from_ccursor(cursor))
+                                .translate(output.galley_pos.to_vec2());
+                            ui.scroll_to_rect(rect, Some(egui::Align::Center));
+                        }
+
+                        output
+                    })
+                    .inner
                 })
-                .inner
-                .changed();
+                .inner;
+            let text_changed = output.response.changed();
+            changed |= text_changed;
+            if changed {
+                self.undo.record(&self.patch_text);
+            }
+            if text_changed {
+                self.find_cursor = None;
+            }
+
+            let cursor_range = output.cursor_range.filter(|_| output.response.has_focus());
+            let cursor_offset = cursor_range.map(|range| {
+                self.patch_text
+                    .char_indices()
+                    .nth(range.primary.ccursor.index)
+                    .map_or(self.patch_text.len(), |(i, _)| i)
+            });
+
+            match cursor_range.zip(cursor_offset) {
+                Some((range, cursor))
+                    if self.patch_mode == PatchMode::XmlAppend && range.primary.ccursor == range.secondary.ccursor =>
+                {
+                    // Only pop up after typing, moving the cursor around should not trigger completion.
+                    if text_changed || self.completion.is_some() {
+                        let previous = self.completion.take();
+                        self.completion = completion::complete_at(&self.patch_text, cursor);
+                        if previous.map(|c| c.candidates) != self.completion.as_ref().map(|c| c.candidates.clone()) {
+                            self.completion_selected = 0;
+                        }
+                    }
 
-            if let Some(current_index) = self.current_file {
+                    if let Some(completion) = self.completion.as_ref() {
+                        let pos =
+                            output.galley_pos + output.galley.pos_from_cursor(&range.primary).left_bottom().to_vec2();
+
+                        egui::Area::new(editor_id.with("completion popup"))
+                            .order(egui::Order::Foreground)
+                            .interactable(false)
+                            .fixed_pos(pos)
+                            .show(ctx, |ui| {
+                                egui::Frame::popup(ui.style()).show(ui, |ui| {
+                                    for (i, candidate) in completion.candidates.iter().enumerate() {
+                                        ui.selectable_label(
+                                            i == self.completion_selected,
+                                            egui::RichText::new(*candidate).monospace(),
+                                        );
+                                    }
+                                });
+                            });
+                    }
+                }
+                _ => self.completion = None,
+            }
+
+            if let (Some(current_index), Some(worker)) = (self.current_file, self.worker.as_ref()) {
                 self.needs_update |= changed & self.patch_on_change;
                 if self.needs_update && !self.shared.running.swap(true, Ordering::AcqRel) {
                     if worker
                         .send(PatchWorkerCommand::Patch {
                             mode: self.patch_mode,
+                            keep_comments: self.keep_comments,
+                            keep_doctype: self.keep_doctype,
                             patch: self.patch_text.clone(),
                             waker: ctx.clone(),
                             source_path: self.pkg_names[current_index].clone(),
@@ -691,9 +1413,32 @@ impl WindowState for Sandbox {
                         *self.shared.output.lock() = Output {
                             patch: Some(PatchOutput::Error(anyhow!("Patch thread disconnected!"))),
                             diagnostics: None,
+                            script_output: None,
+                            find: None,
                         };
                     }
                     self.needs_update = false;
+                    // The patch may have changed, so the find preview has to be refreshed too.
+                    self.find_cursor = None;
+                }
+
+                if let Some(cursor) = cursor_offset
+                    .filter(|&cursor| self.patch_mode == PatchMode::XmlAppend && self.find_cursor != Some(cursor))
+                {
+                    if !self.shared.running.swap(true, Ordering::AcqRel) {
+                        self.find_cursor = Some(cursor);
+                        if worker
+                            .send(PatchWorkerCommand::Find {
+                                patch: self.patch_text.clone(),
+                                cursor,
+                                source_path: self.pkg_names[current_index].clone(),
+                                waker: ctx.clone(),
+                            })
+                            .is_err()
+                        {
+                            self.shared.running.store(false, Ordering::Release);
+                        }
+                    }
                 }
             }
         });
@@ -0,0 +1,186 @@
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
+
+/// Maximum number of edits kept in the undo stack, the oldest ones are dropped first.
+const MAX_EDITS: usize = 1000;
+/// Single character edits made within this long of each other are undone together.
+const COALESCE_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// A replacement of `removed` at byte offset `offset` with `inserted`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Edit {
+    offset: usize,
+    removed: String,
+    inserted: String,
+}
+
+impl Edit {
+    fn between(old: &str, new: &str) -> Option<Self> {
+        let prefix = old
+            .char_indices()
+            .zip(new.chars())
+            .find(|&((_, a), b)| a != b)
+            .map_or(old.len().min(new.len()), |((i, _), _)| i);
+        if prefix == old.len() && prefix == new.len() {
+            return None;
+        }
+
+        let suffix = old[prefix..]
+            .chars()
+            .rev()
+            .zip(new[prefix..].chars().rev())
+            .take_while(|(a, b)| a == b)
+            .map(|(a, _)| a.len_utf8())
+            .sum::<usize>();
+
+        Some(Self {
+            offset: prefix,
+            removed: old[prefix..old.len() - suffix].to_owned(),
+            inserted: new[prefix..new.len() - suffix].to_owned(),
+        })
+    }
+
+    fn is_single_char(text: &str) -> bool {
+        let mut chars = text.chars();
+        chars.next().is_some_and(|c| c != '\n') && chars.next().is_none()
+    }
+
+    /// Merges `next` into this edit if they're consecutive single character insertions or deletions.
+    fn coalesce(&mut self, next: &Edit) -> bool {
+        if self.removed.is_empty() && next.removed.is_empty() && Self::is_single_char(&next.inserted) {
+            if next.offset == self.offset + self.inserted.len() {
+                self.inserted.push_str(&next.inserted);
+                return true;
+            }
+        } else if self.inserted.is_empty() && next.inserted.is_empty() && Self::is_single_char(&next.removed) {
+            // Backspace
+            if next.offset + next.removed.len() == self.offset {
+                self.offset = next.offset;
+                self.removed.insert_str(0, &next.removed);
+                return true;
+            }
+            // Delete
+            if next.offset == self.offset {
+                self.removed.push_str(&next.removed);
+                return true;
+            }
+        }
+
+        false
+    }
+
+    fn apply(&self, text: &mut String) {
+        text.replace_range(self.offset..self.offset + self.removed.len(), &self.inserted);
+    }
+
+    fn inverse(&self) -> Self {
+        Self {
+            offset: self.offset,
+            removed: self.inserted.clone(),
+            inserted: self.removed.clone(),
+        }
+    }
+}
+
+/// Undo history of a text buffer that stores the differences between successive versions
+/// instead of whole copies of the text.
+#[derive(Default)]
+pub struct UndoStack {
+    // The text as of the last recorded edit.
+    text: String,
+    undo: VecDeque<Edit>,
+    redo: Vec<Edit>,
+    last_edit: Option<Instant>,
+}
+
+impl UndoStack {
+    /// Clears the history and starts tracking `text`.
+    #[cfg(test)]
+    pub fn reset(&mut self, text: &str) {
+        self.text = text.to_owned();
+        self.undo.clear();
+        self.redo.clear();
+        self.last_edit = None;
+    }
+
+    /// Records the difference between the previously recorded text and `text` as a new edit.
+    pub fn record(&mut self, text: &str) {
+        let Some(edit) = Edit::between(&self.text, text) else {
+            return;
+        };
+        edit.apply(&mut self.text);
+        self.redo.clear();
+
+        let now = Instant::now();
+        let recent = self.last_edit.is_some_and(|last| now - last < COALESCE_TIMEOUT);
+        self.last_edit = Some(now);
+        if recent && self.undo.back_mut().is_some_and(|last| last.coalesce(&edit)) {
+            return;
+        }
+
+        if self.undo.len() == MAX_EDITS {
+            self.undo.pop_front();
+        }
+        self.undo.push_back(edit);
+    }
+
+    fn step(&mut self, text: &mut String, edit: Edit) -> usize {
+        edit.apply(text);
+        edit.apply(&mut self.text);
+        // Edits made after an undo or redo should never be merged into it.
+        self.last_edit = None;
+        edit.offset + edit.inserted.len()
+    }
+
+    /// Reverts the last edit made to `text`, returns the byte offset the cursor should be moved to.
+    pub fn undo(&mut self, text: &mut String) -> Option<usize> {
+        self.record(text);
+        let edit = self.undo.pop_back()?;
+        self.redo.push(edit.clone());
+        Some(self.step(text, edit.inverse()))
+    }
+
+    /// Reapplies the last undone edit to `text`, returns the byte offset the cursor should be moved to.
+    pub fn redo(&mut self, text: &mut String) -> Option<usize> {
+        let edit = self.redo.pop()?;
+        self.undo.push_back(edit.clone());
+        Some(self.step(text, edit))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::UndoStack;
+
+    #[test]
+    fn typing_is_coalesced() {
+        let mut stack = UndoStack::default();
+        let mut text = String::from("<a/>");
+        stack.reset(&text);
+
+        for c in "<b".chars() {
+            text.push(c);
+            stack.record(&text);
+        }
+        text.push('\n');
+        stack.record(&text);
+        text.pop();
+        stack.record(&text);
+
+        assert_eq!(stack.undo(&mut text), Some(7));
+        assert_eq!(text, "<a/><b\n");
+        assert_eq!(stack.undo(&mut text), Some(6));
+        assert_eq!(text, "<a/><b");
+        assert_eq!(stack.undo(&mut text), Some(4));
+        assert_eq!(text, "<a/>");
+        assert_eq!(stack.undo(&mut text), None);
+
+        assert_eq!(stack.redo(&mut text), Some(6));
+        assert_eq!(text, "<a/><b");
+        text.insert(0, 'x');
+        stack.record(&text);
+        assert_eq!(stack.redo(&mut text), None);
+    }
+}
@@ -43,6 +43,17 @@ impl Version {
         })
     }
 
+    fn semver(&self) -> semver::Version {
+        match self {
+            Version::Steam1_6_14Win => semver::Version::new(1, 6, 14),
+            Version::Steam1_6_13Linux | Version::Gog1_6_13B => semver::Version::new(1, 6, 13),
+            Version::Downgraded1_6_9Win | Version::Gog1_6_9 => semver::Version::new(1, 6, 9),
+            Version::Humble1_6_12 | Version::Epic1_6_12 | Version::Origin1_6_12 | Version::Microsoft1_6_12 => {
+                semver::Version::new(1, 6, 12)
+            }
+        }
+    }
+
     fn name(&self) -> &'static str {
         match self {
             Version::Steam1_6_14Win => "Steam 1.6.14 Windows",
@@ -76,6 +87,9 @@ pub struct Patch {
     source: PatchLocation,
 }
 
+const PATCH_CACHE_DIR: &str = "ftl-patch";
+const BPS_MAGIC: &[u8] = b"BPS1";
+
 const PATCHES: &[Patch] = &[
     Patch {
         from: Version::Steam1_6_14Win,
@@ -108,13 +122,28 @@ impl Patch {
         self.from.name()
     }
 
+    /// Name of the version the executable is downgraded to by this patch.
+    pub fn target_version_name(&self) -> &'static str {
+        Version::Downgraded1_6_9Win.name()
+    }
+
     pub fn is_remote(&self) -> bool {
         !matches!(self.source, PatchLocation::HyperspaceZip { .. })
     }
 
+    /// Whether applying this patch will require downloading it separately from Hyperspace.
+    pub fn needs_download(&self) -> bool {
+        self.is_remote() && !CACHE.contains_key(PATCH_CACHE_DIR, &self.cache_key())
+    }
+
+    fn cache_key(&self) -> String {
+        (self.from as usize).to_string()
+    }
+
     pub fn fetch_or_load_cached<S: Read + Seek>(
         self,
         hyperspace_zip: &mut zip::ZipArchive<S>,
+        max_connections: usize,
         mut on_progress: impl FnMut(u64, u64),
     ) -> Result<Patcher> {
         let mut data = Vec::new();
@@ -123,18 +152,32 @@ impl Patch {
                 hyperspace_zip.by_name(path)?.read_to_end(&mut data)?;
             }
             PatchLocation::GoogleDrive { file_id } => {
-                data = CACHE.read_or_create_key("ftl-patch", &(self.from as usize).to_string(), || {
-                    let response = crate::util::request_google_drive_download(file_id)?;
-                    let patch = crate::util::download_body_with_progress(response, move |current, total| {
-                        if let Some(total) = total {
-                            on_progress(current, total);
+                data = CACHE.read_or_create_key(
+                    PATCH_CACHE_DIR,
+                    &self.cache_key(),
+                    |data| {
+                        if !data.starts_with(BPS_MAGIC) {
+                            bail!("Not a BPS patch");
                         }
-                    })?;
-                    let mut archive =
-                        ZipArchive::new(std::io::Cursor::new(patch)).context("Failed to open patch zip archive")?;
-                    archive.by_name("patch/patch.bps")?.read_to_end(&mut data)?;
-                    Ok(data)
-                })?;
+                        Ok(())
+                    },
+                    || {
+                        let response = crate::util::request_google_drive_download(file_id)?;
+                        let patch = crate::util::download_body_with_progress(
+                            response,
+                            max_connections,
+                            move |current, total| {
+                                if let Some(total) = total {
+                                    on_progress(current, total);
+                                }
+                            },
+                        )?;
+                        let mut archive =
+                            ZipArchive::new(std::io::Cursor::new(patch)).context("Failed to open patch zip archive")?;
+                        archive.by_name("patch/patch.bps")?.read_to_end(&mut data)?;
+                        Ok(data)
+                    },
+                )?;
             }
         };
 
@@ -168,6 +211,16 @@ enum Platform {
     Linux,
 }
 
+/// Detects the version of the FTL installation in `ftl` from the size of its executable.
+pub fn detect_ftl_version(ftl: &Path) -> Result<Option<semver::Version>> {
+    let Some(exe_path) = find_ftl_exe(ftl).context("An error occurred while looking for FTL executable")? else {
+        return Ok(None);
+    };
+
+    let size = exe_path.metadata().context("Failed to stat FTL executable")?.len();
+    Ok(Version::from_executable_size(size).map(|version| version.semver()))
+}
+
 #[derive(Clone)]
 pub struct Installer {
     platform: Platform,
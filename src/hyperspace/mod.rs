@@ -1,7 +1,8 @@
-use std::io::{Cursor, Read};
+use std::io::{Cursor, Read, Seek};
 
 use anyhow::{bail, Context, Result};
 use lazy_static::lazy_static;
+use log::warn;
 use serde::{Deserialize, Serialize};
 use zip::ZipArchive;
 
@@ -81,27 +82,93 @@ impl HyperspaceRelease {
         self.version.as_ref()
     }
 
-    pub fn fetch_zip(&self, progress_callback: impl Fn(u64, u64)) -> Result<Vec<u8>> {
-        let download_url = match self.release.assets.len().cmp(&1) {
+    fn asset(&self) -> Result<&github::ReleaseAsset> {
+        match self.release.assets.len().cmp(&1) {
             std::cmp::Ordering::Less => {
                 bail!("Hyperspace release contains no assets")
             }
-            std::cmp::Ordering::Equal => self.release.assets[0].browser_download_url.clone(),
+            std::cmp::Ordering::Equal => Ok(&self.release.assets[0]),
             std::cmp::Ordering::Greater => {
                 bail!("Hyperspace release contains more than one asset")
             }
-        };
+        }
+    }
+
+    /// Key under which this release's zip is cached, changes whenever the asset is replaced upstream.
+    pub fn cache_key(&self) -> Result<String> {
+        Ok(self.asset()?.cache_key())
+    }
 
-        let response = AGENT.get(&download_url).call()?;
+    /// Key under which the zip was cached by older versions of ftlman, only used to migrate existing entries.
+    pub fn legacy_cache_key(&self) -> &str {
+        self.name()
+    }
+
+    /// Checks that `data` is the zip of this release and that it contains Hyperspace.ftl.
+    pub fn verify_zip(&self, data: &[u8]) -> Result<()> {
+        self.asset()?.verify(data)?;
+        let mut zip = ZipArchive::new(Cursor::new(data)).context("Not a valid zip archive")?;
+        self.extract_hyperspace_ftl(&mut zip)?;
+        Ok(())
+    }
 
-        crate::util::download_body_with_progress(response, |current, total| {
+    /// Downloads the Hyperspace zip, trying each of `mirrors` in order if the download from GitHub fails.
+    ///
+    /// Mirrors are base URLs that replace `https://github.com` in the asset's download URL.
+    pub fn fetch_zip(
+        &self,
+        max_connections: usize,
+        mirrors: &[String],
+        progress_callback: impl Fn(u64, u64),
+    ) -> Result<Vec<u8>> {
+        let asset = self.asset()?;
+
+        crate::util::ensure_online()?;
+
+        let urls = std::iter::once(asset.browser_download_url.clone()).chain(
+            mirrors
+                .iter()
+                .map(|mirror| mirror.trim())
+                .filter(|mirror| !mirror.is_empty())
+                .filter_map(|mirror| mirror_url(mirror, &asset.browser_download_url)),
+        );
+
+        let mut last_error = None;
+        for url in urls {
+            match self.fetch_zip_from(&url, max_connections, &progress_callback) {
+                Ok(data) => return Ok(data),
+                Err(error) => {
+                    warn!("Failed to download Hyperspace from {url}: {error:#}");
+                    last_error = Some(error);
+                }
+            }
+        }
+
+        Err(last_error.unwrap())
+    }
+
+    fn fetch_zip_from(
+        &self,
+        url: &str,
+        max_connections: usize,
+        progress_callback: &impl Fn(u64, u64),
+    ) -> Result<Vec<u8>> {
+        let response = github::call_with_retry(AGENT.get(url))?;
+
+        let data = crate::util::download_body_with_progress(response, max_connections, |current, total| {
             if let Some(total) = total {
                 progress_callback(current, total);
             }
-        })
+        })?;
+
+        // This is called while populating the cache so any error here prevents a bad download from being persisted.
+        self.verify_zip(&data)
+            .context("Downloaded Hyperspace zip failed verification")?;
+
+        Ok(data)
     }
 
-    pub fn extract_hyperspace_ftl(&self, zip: &mut ZipArchive<Cursor<Vec<u8>>>) -> Result<Vec<u8>> {
+    pub fn extract_hyperspace_ftl(&self, zip: &mut ZipArchive<impl Read + Seek>) -> Result<Vec<u8>> {
         let mut buf = vec![];
 
         zip.by_name("Hyperspace.ftl")
@@ -113,6 +180,12 @@ impl HyperspaceRelease {
     }
 }
 
+/// Returns the URL `url` would have on a mirror of GitHub, or [`None`] if it isn't a GitHub URL.
+fn mirror_url(mirror: &str, url: &str) -> Option<String> {
+    let path = url.strip_prefix("https://github.com/")?;
+    Some(format!("{}/{path}", mirror.trim_end_matches('/')))
+}
+
 pub fn fetch_hyperspace_releases() -> Result<Vec<HyperspaceRelease>> {
     Ok(HYPERSPACE_REPOSITORY
         .releases()?
@@ -101,21 +101,67 @@ pub fn current_language() -> &'static str {
     unsafe { *LOCALISER.get().unwrap().current_locale.load(Ordering::Acquire) }
 }
 
+/// Languages written right-to-left, a translation for one of these will have its layout mirrored.
+const RTL_LANGUAGES: &[&str] = &["ar", "fa", "he", "ps", "ur", "yi"];
+
+pub fn is_rtl() -> bool {
+    RTL_LANGUAGES.contains(&current_language())
+}
+
+pub fn available_languages() -> impl Iterator<Item = &'static str> {
+    LOCALE_DEFINITIONS.iter().map(|(name, _)| *name)
+}
+
+/// Returns the name of `language` in that language, or `language` itself if it's unknown.
+pub fn language_name(language: &str) -> Cow<'static, str> {
+    let Some(bundle) = LOCALISER.get().unwrap().bundles.get(language) else {
+        return Cow::Owned(language.to_owned());
+    };
+    match bundle.get_message("language-name").and_then(|message| message.value()) {
+        Some(pattern) => bundle.format_pattern(pattern, None, &mut vec![]),
+        None => Cow::Owned(language.to_owned()),
+    }
+}
+
+fn default_locale_pointer() -> *mut &'static str {
+    get_system_language()
+        .as_deref()
+        .and_then(|name| match get_locale_pointer(name) {
+            Some(value) => Some(value),
+            None => {
+                warn!("Failed to get locale for system language {name}");
+                None
+            }
+        })
+        .unwrap_or(&LOCALE_DEFINITIONS[0].0 as *const _ as *mut _)
+}
+
+/// Switches the current language, `None` selects the system language.
+///
+/// Note that fonts also depend on the language and have to be recreated separately.
+pub fn set_language(name: Option<&str>) {
+    let pointer = match name {
+        Some(name) => match get_locale_pointer(name) {
+            Some(pointer) => pointer,
+            None => {
+                warn!("Unknown language {name}, using the system language instead");
+                default_locale_pointer()
+            }
+        },
+        None => default_locale_pointer(),
+    };
+
+    LOCALISER
+        .get()
+        .unwrap()
+        .current_locale
+        .store(pointer, Ordering::Release);
+}
+
 pub fn init() {
     LOCALISER
         .set(Localiser {
-            current_locale: AtomicPtr::new(
-                get_system_language()
-                    .as_deref()
-                    .and_then(|name| match get_locale_pointer(name) {
-                        Some(value) => Some(value),
-                        None => {
-                            warn!("Failed to get locale for system language {name}");
-                            None
-                        }
-                    })
-                    .unwrap_or(&LOCALE_DEFINITIONS[0].0 as *const _ as *mut _),
-            ),
+            current_locale: AtomicPtr::new(default_locale_pointer()),
             bundles: LOCALE_DEFINITIONS
                 .iter()
                 .map(|(name, ftl)| {
@@ -193,6 +239,21 @@ pub fn resolve(id: &str, args: Option<&FluentArgs>) -> Cow<'static, str> {
     result
 }
 
+/// Formats a size in bytes with IEC units according to the current locale.
+pub fn format_size(bytes: u64) -> String {
+    let (value, unit) = crate::util::to_human_size_units(bytes);
+    let number = format!("{value:.2}").replace('.', &resolve("decimal-separator", None));
+
+    resolve(
+        "size-format",
+        Some(&fluent::fluent_args! {
+            "value" => number,
+            "unit" => resolve(&format!("size-unit-{}", unit.to_ascii_lowercase()), None),
+        }),
+    )
+    .into_owned()
+}
+
 #[macro_export]
 macro_rules! l {
     ($id: expr) => {
@@ -0,0 +1,215 @@
+use std::{
+    fs::File,
+    io::Write,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use log::{Log, Metadata, Record};
+use parking_lot::Mutex;
+
+/// Log files larger than this are rotated on startup and while logging.
+pub const MAX_LOG_FILE_SIZE: u64 = 4 * 1024 * 1024;
+/// Number of rotated log files kept in addition to the current one.
+pub const KEPT_LOG_FILES: usize = 3;
+
+pub fn default_log_file_path() -> PathBuf {
+    dirs::data_local_dir().unwrap().join("ftlman/logs/ftlman.log")
+}
+
+/// Returns the path of the log file if logging to a file was requested.
+///
+/// The `FTLMAN_LOG_FILE` environment variable takes precedence over the setting, it can either
+/// be set to a path or to `1` to use the default location.
+pub fn log_file_path(setting: bool) -> Option<PathBuf> {
+    match std::env::var_os("FTLMAN_LOG_FILE") {
+        Some(value) if value == "0" || value.is_empty() => None,
+        Some(value) if value == "1" => Some(default_log_file_path()),
+        Some(value) => Some(PathBuf::from(value)),
+        None => setting.then(default_log_file_path),
+    }
+}
+
+struct RotatingFile {
+    path: PathBuf,
+    file: File,
+    size: u64,
+}
+
+impl RotatingFile {
+    fn open(path: PathBuf) -> std::io::Result<Self> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+
+        if path.metadata().is_ok_and(|meta| meta.len() >= MAX_LOG_FILE_SIZE) {
+            rotate(&path)?;
+        }
+
+        let file = File::options().create(true).append(true).open(&path)?;
+        let size = file.metadata()?.len();
+        Ok(Self { path, file, size })
+    }
+
+    fn write_line(&mut self, line: &str) -> std::io::Result<()> {
+        if self.size >= MAX_LOG_FILE_SIZE {
+            rotate(&self.path)?;
+            self.file = File::options().create(true).append(true).open(&self.path)?;
+            self.size = 0;
+        }
+
+        self.file.write_all(line.as_bytes())?;
+        self.size += line.len() as u64;
+        Ok(())
+    }
+}
+
+fn rotated_path(path: &Path, n: usize) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_owned();
+    name.push(format!(".{n}"));
+    path.with_file_name(name)
+}
+
+/// Shifts `file.log.N` to `file.log.N+1`, dropping the oldest one, then moves `file.log` to `file.log.1`.
+fn rotate(path: &Path) -> std::io::Result<()> {
+    for n in (1..KEPT_LOG_FILES).rev() {
+        match std::fs::rename(rotated_path(path, n), rotated_path(path, n + 1)) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e),
+            _ => (),
+        }
+    }
+
+    std::fs::rename(path, rotated_path(path, 1))
+}
+
+/// Formats a unix timestamp as an ISO 8601 UTC date and time.
+pub fn format_timestamp(time: SystemTime) -> String {
+    let duration = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let secs = duration.as_secs();
+    let (days, day_secs) = ((secs / 86400) as i64, secs % 86400);
+
+    // https://howardhinnant.github.io/date_algorithms.html#civil_from_days
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{year:04}-{month:02}-{day:02}T{:02}:{:02}:{:02}.{:03}Z",
+        day_secs / 3600,
+        day_secs / 60 % 60,
+        day_secs % 60,
+        duration.subsec_millis()
+    )
+}
+
+/// Forwards records to the terminal logger and additionally writes them to a file without any styling.
+struct TeeLogger {
+    terminal: env_logger::Logger,
+    file: Option<Mutex<RotatingFile>>,
+}
+
+impl Log for TeeLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        self.terminal.enabled(metadata)
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.terminal.matches(record) {
+            return;
+        }
+
+        self.terminal.log(record);
+
+        if let Some(file) = self.file.as_ref() {
+            let timestamp = format_timestamp(SystemTime::now());
+            let mut text = String::new();
+            for line in record.args().to_string().split('\n') {
+                text.push_str(&format!(
+                    "{timestamp} [{} {}] {line}\n",
+                    record.level(),
+                    record.module_path().unwrap_or("?")
+                ));
+            }
+            // There's nowhere to report a failure to write logs to.
+            _ = file.lock().write_line(&text);
+        }
+    }
+
+    fn flush(&self) {
+        self.terminal.flush();
+        if let Some(file) = self.file.as_ref() {
+            _ = file.lock().file.flush();
+        }
+    }
+}
+
+pub fn init(log_file: Option<PathBuf>) {
+    let terminal = env_logger::builder()
+        .format(|f, record| {
+            let module = record
+                .module_path()
+                .map(|x| x.split_once("::").map(|(m, _)| m).unwrap_or(x))
+                .filter(|x| *x != env!("CARGO_PKG_NAME"));
+
+            for line in record.args().to_string().split('\n') {
+                write!(f, "\x1b[90m[")?;
+                f.default_level_style(record.level()).write_to(f)?;
+                write!(f, "{}", record.level())?;
+
+                if let Some(module) = module {
+                    write!(f, " {}", module)?;
+                }
+
+                write!(f, "\x1b[90m]\x1b[0m")?;
+
+                writeln!(f, " {line}")?;
+            }
+
+            Ok(())
+        })
+        .filter_level(log::LevelFilter::Info)
+        .filter_module(env!("CARGO_PKG_NAME"), {
+            #[cfg(debug_assertions)]
+            let v = log::LevelFilter::Debug;
+            #[cfg(not(debug_assertions))]
+            let v = log::LevelFilter::Info;
+            v
+        })
+        .parse_default_env()
+        .build();
+
+    let (file, file_error) = match log_file.map(|path| (RotatingFile::open(path.clone()), path)) {
+        Some((Ok(file), _)) => (Some(Mutex::new(file)), None),
+        Some((Err(error), path)) => (None, Some((path, error))),
+        None => (None, None),
+    };
+
+    log::set_max_level(terminal.filter());
+    log::set_boxed_logger(Box::new(TeeLogger { terminal, file })).expect("logger already initialized");
+
+    if let Some((path, error)) = file_error {
+        log::warn!("Could not open log file {}: {error}", path.display());
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::{Duration, UNIX_EPOCH};
+
+    use super::format_timestamp;
+
+    #[test]
+    fn timestamp() {
+        assert_eq!(format_timestamp(UNIX_EPOCH), "1970-01-01T00:00:00.000Z");
+        assert_eq!(
+            format_timestamp(UNIX_EPOCH + Duration::from_millis(1_709_210_096_789)),
+            "2024-02-29T12:34:56.789Z"
+        );
+    }
+}
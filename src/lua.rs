@@ -1,4 +1,10 @@
-use std::ops::Deref;
+use std::{
+    cell::Cell,
+    ops::Deref,
+    rc::Rc,
+    sync::atomic::{AtomicU64, AtomicUsize, Ordering},
+    time::{Duration, Instant},
+};
 
 use gc_arena::{DynamicRootSet, Rootable};
 use mlua::prelude::*;
@@ -11,6 +17,49 @@ mod xml;
 
 type LuaArena = gc_arena::Arena<Rootable![DynamicRootSet<'_>]>;
 
+/// Default for the maximum amount of time a single script may run for.
+pub const DEFAULT_TIME_LIMIT: Duration = Duration::from_secs(30);
+/// Default for the maximum amount of memory used by the Lua heap and the DOM arena combined.
+pub const DEFAULT_MEMORY_LIMIT: usize = 1024 * 1024 * 1024;
+/// How many VM instructions are executed between checks of the time and memory limits.
+const LIMIT_CHECK_INTERVAL: u32 = 10_000;
+
+/// Time limit in milliseconds, zero means no limit.
+static TIME_LIMIT_MS: AtomicU64 = AtomicU64::new(DEFAULT_TIME_LIMIT.as_millis() as u64);
+
+/// Sets the maximum amount of time scripts started after this call are allowed to run for.
+pub fn set_time_limit(limit: Option<Duration>) {
+    TIME_LIMIT_MS.store(
+        limit.map_or(0, |limit| limit.as_millis().max(1) as u64),
+        Ordering::Relaxed,
+    );
+}
+
+fn time_limit() -> Option<Duration> {
+    match TIME_LIMIT_MS.load(Ordering::Relaxed) {
+        0 => None,
+        ms => Some(Duration::from_millis(ms)),
+    }
+}
+
+/// Memory limit in bytes, zero means no limit.
+static MEMORY_LIMIT: AtomicUsize = AtomicUsize::new(DEFAULT_MEMORY_LIMIT);
+
+/// Sets the maximum amount of memory scripts started after this call are allowed to use.
+///
+/// The Lua heap is limited precisely while the DOM arena is only checked periodically,
+/// so the actual usage may slightly exceed this.
+pub fn set_memory_limit(limit: Option<usize>) {
+    MEMORY_LIMIT.store(limit.map_or(0, |limit| limit.max(1)), Ordering::Relaxed);
+}
+
+fn memory_limit() -> Option<usize> {
+    match MEMORY_LIMIT.load(Ordering::Relaxed) {
+        0 => None,
+        bytes => Some(bytes),
+    }
+}
+
 trait LuaExt {
     fn gc(&self) -> mlua::AppDataRef<LuaArena>;
     fn protect_table(&self, table: &LuaTable) -> LuaResult<()>;
@@ -73,11 +122,36 @@ impl LuaExt for Lua {
 pub struct ModLuaRuntime {
     lua: Lua,
     lib_table: LuaTable,
+    /// Highest memory usage observed while running scripts, see [`Self::memory_usage`].
+    peak_memory: Rc<Cell<usize>>,
+}
+
+/// Destination of text printed by scripts through `print` or `mod.debug.pretty_print`.
+#[derive(Debug, Default)]
+pub enum LuaOutput {
+    Stdout,
+    #[default]
+    Log,
+    /// Collected into the string, one line per call.
+    Capture(String),
+}
+
+/// Writes a line of script output to the destination set for the currently running script.
+pub fn write_output(lua: &Lua, text: &str) {
+    match lua.app_data_mut::<LuaOutput>().as_deref_mut() {
+        Some(LuaOutput::Stdout) => println!("{text}"),
+        Some(LuaOutput::Capture(buffer)) => {
+            buffer.push_str(text);
+            buffer.push('\n');
+        }
+        Some(LuaOutput::Log) | None => log::info!(target: "lua", "{text}"),
+    }
 }
 
 pub struct LuaContext {
     pub document_root: Option<xml::DynamicElement>,
     pub print_arena_stats: bool,
+    pub output: LuaOutput,
 }
 
 impl ModLuaRuntime {
@@ -100,6 +174,21 @@ impl ModLuaRuntime {
         lua.protect_table(&lua.globals().raw_get::<LuaTable>("math")?)?;
         // This is replaced by the script environment table later.
         lua.globals().raw_remove("_G")?;
+        let tostring = lua.globals().raw_get::<LuaFunction>("tostring")?;
+        lua.globals().raw_set(
+            "print",
+            lua.create_function(move |lua, args: LuaMultiValue| {
+                let mut line = String::new();
+                for (i, value) in args.into_iter().enumerate() {
+                    if i > 0 {
+                        line.push('\t');
+                    }
+                    line.push_str(&tostring.call::<LuaString>(value)?.to_string_lossy());
+                }
+                write_output(lua, &line);
+                Ok(())
+            })?,
+        )?;
 
         // This function causes HRTB deduction problems so no, I cannot replace the closure.
         #[allow(clippy::redundant_closure)]
@@ -142,13 +231,37 @@ impl ModLuaRuntime {
         lua.protect_table(&lib_table)
             .context("Failed to make builtin mod table read-only")?;
 
-        Ok(Self { lua, lib_table })
+        Ok(Self {
+            lua,
+            lib_table,
+            peak_memory: Rc::new(Cell::new(0)),
+        })
     }
 
     pub fn arena(&self) -> impl Deref<Target = LuaArena> + use<'_> {
         self.lua.gc()
     }
 
+    fn current_memory_usage(lua: &Lua) -> usize {
+        // The arena may be borrowed if the hook runs while a builtin calls back into Lua.
+        let arena = lua
+            .try_app_data_ref::<LuaArena>()
+            .ok()
+            .flatten()
+            .map_or(0, |gc| gc.metrics().total_allocation());
+        lua.used_memory() + arena
+    }
+
+    /// Memory currently used by the Lua heap and the DOM arena in bytes.
+    pub fn memory_usage(&self) -> usize {
+        Self::current_memory_usage(&self.lua)
+    }
+
+    /// Highest memory usage observed while running scripts in this runtime, in bytes.
+    pub fn peak_memory_usage(&self) -> usize {
+        self.peak_memory.get().max(self.memory_usage())
+    }
+
     pub fn with_filesystems<'a, R>(
         &self,
         iter: impl IntoIterator<Item = (impl IntoLua, &'a mut (dyn io::LuaFS + 'a))>,
@@ -184,13 +297,57 @@ impl ModLuaRuntime {
             )?;
         }
 
-        lua.load(code)
+        let time_limit = time_limit();
+        let memory_limit = memory_limit();
+        // The arena is not counted here, it is checked in the hook below.
+        lua.set_memory_limit(memory_limit.unwrap_or(0))?;
+
+        let start = Instant::now();
+        let peak_memory = self.peak_memory.clone();
+        lua.set_hook(
+            mlua::HookTriggers::new().every_nth_instruction(LIMIT_CHECK_INTERVAL),
+            move |lua, debug| {
+                let location = || {
+                    let source = debug.source();
+                    format!("{}:{}", source.short_src.as_deref().unwrap_or("?"), debug.curr_line())
+                };
+
+                if let Some(limit) = time_limit.filter(|&limit| start.elapsed() > limit) {
+                    return Err(LuaError::runtime(format!(
+                        "script exceeded the time limit of {:.1}s in {}",
+                        limit.as_secs_f32(),
+                        location()
+                    )));
+                }
+
+                let usage = Self::current_memory_usage(lua);
+                peak_memory.set(peak_memory.get().max(usage));
+                if let Some(limit) = memory_limit.filter(|&limit| usage > limit) {
+                    return Err(LuaError::runtime(format!(
+                        "script exceeded the memory limit of {} MiB in {}",
+                        limit / (1024 * 1024),
+                        location()
+                    )));
+                }
+
+                Ok(mlua::VmState::Continue)
+            },
+        );
+
+        lua.set_app_data(std::mem::take(&mut context.output));
+        let result = lua
+            .load(code)
             .set_name(filename)
             .set_mode(mlua::ChunkMode::Text)
             .set_environment(env)
-            .exec()?;
+            .exec();
+        lua.remove_hook();
+        context.output = lua.remove_app_data::<LuaOutput>().unwrap_or_default();
+        result?;
 
         if context.print_arena_stats {
+            println!("lua heap bytes: {}", lua.used_memory());
+            println!("peak memory usage: {}", self.peak_memory_usage());
             let mut gc = lua.app_data_mut::<LuaArena>().unwrap();
             println!("allocated bytes: {:?}", gc.metrics().total_allocation());
             println!("allocated bytes (gc only): {:?}", gc.metrics().total_gc_allocation());
@@ -3,6 +3,7 @@ use std::{collections::HashSet, ffi::c_void, fmt::Write, io::IsTerminal};
 use mlua::prelude::*;
 use serde::Deserialize;
 
+use super::{write_output, LuaOutput};
 #[cfg(debug_assertions)]
 use super::{LuaArena, LuaExt};
 
@@ -347,11 +348,12 @@ pub fn extend_debug_library(lua: &Lua, table: LuaTable) -> LuaResult<()> {
 
     table.raw_set(
         "pretty_print",
-        lua.create_function(|_, (value, options): (LuaValue, LuaValue)| {
+        lua.create_function(|lua, (value, options): (LuaValue, LuaValue)| {
             let options = if options.is_nil() {
+                let to_stdout = matches!(lua.app_data_ref::<LuaOutput>().as_deref(), Some(LuaOutput::Stdout));
                 PrettyPrintOptions {
                     indent: Some("\t".to_owned()),
-                    colors: if std::io::stdout().is_terminal() {
+                    colors: if to_stdout && std::io::stdout().is_terminal() {
                         Some(Colors::Ansi)
                     } else {
                         None
@@ -365,12 +367,33 @@ pub fn extend_debug_library(lua: &Lua, table: LuaTable) -> LuaResult<()> {
 
             let mut output = String::new();
             PrettyPrinter::new(options).pretty_print(&mut output, value).unwrap();
-            println!("{output}");
+            write_output(lua, &output);
 
             Ok(())
         })?,
     )?;
 
+    table.raw_set(
+        "inspect",
+        lua.create_function(|_, value: LuaValue| {
+            let summary = match &value {
+                LuaValue::UserData(userdata) => super::xml::inspect_node(userdata),
+                _ => None,
+            };
+
+            Ok(match summary {
+                Some(summary) => summary,
+                None => {
+                    let mut output = format!("{}: ", value.type_name());
+                    PrettyPrinter::new(PrettyPrintOptions::default())
+                        .pretty_print(&mut output, value)
+                        .unwrap();
+                    output
+                }
+            })
+        })?,
+    )?;
+
     table.raw_set(
         "_compare",
         lua.create_function(|_lua, (a, b): (LuaValue, LuaValue)| {
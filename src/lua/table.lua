@@ -29,3 +29,24 @@ function mod.table.compare_arrays(first, second)
     return 0
   end
 end
+
+-- Iterates over the keys of a table in unspecified order, like pairs.
+function mod.table.keys(table)
+  local key = nil
+  local done = false
+  return function()
+    if done then return nil end
+    key = next(table, key)
+    done = key == nil
+    return key
+  end
+end
+
+-- Iterates over the values of a table in unspecified order, like pairs.
+function mod.table.values(table)
+  local keys = mod.table.keys(table)
+  return function()
+    local key = keys()
+    if key ~= nil then return rawget(table, key) end
+  end
+end
@@ -12,3 +12,35 @@ function mod.util.readonly(table)
   setmetatable(proxy, mt)
   return proxy
 end
+
+-- Recursively copies tables, preserving cycles and shared references within the copied value.
+-- XML nodes are copied along with their descendants and the copies have no parent.
+function mod.util.deepcopy(value)
+  local copies = {}
+
+  local function copy(value)
+    if type(value) == "userdata" then
+      return mod.xml._deepcopy(value) or value
+    elseif type(value) ~= "table" then
+      return value
+    elseif copies[value] then
+      return copies[value]
+    end
+
+    local result = {}
+    copies[value] = result
+    for k, v in next, value do
+      result[copy(k)] = copy(v)
+    end
+
+    -- Protected tables hide their metatable, copies of them are just plain tables.
+    local metatable = getmetatable(value)
+    if type(metatable) == "table" then
+      setmetatable(result, metatable)
+    end
+
+    return result
+  end
+
+  return copy(value)
+end
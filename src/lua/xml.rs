@@ -9,7 +9,8 @@ use mlua::{prelude::*, FromLua, UserData, UserDataFields};
 use crate::xmltree::{
     self,
     dom::{
-        self, node_insert_after, node_insert_before, ElementChildren, GcElement, GcNode, GcText, NodeExt, NodeTraits,
+        self, node_insert_after, node_insert_before, ElementChildren, GcComment, GcElement, GcNode, GcText, NodeExt,
+        NodeTraits,
     },
 };
 
@@ -54,6 +55,16 @@ fn validate_xml_name(name: &str) -> LuaResult<()> {
     Ok(())
 }
 
+fn validate_xml_qualified_name(name: &str) -> LuaResult<()> {
+    match name.split_once(':') {
+        Some((prefix, local)) => {
+            validate_xml_name(prefix)?;
+            validate_xml_name(local)
+        }
+        None => validate_xml_name(name),
+    }
+}
+
 fn element_tostring(element: &dom::Element, output: &mut String) {
     output.push('<');
     append_qualified_name(element, output);
@@ -72,6 +83,40 @@ fn element_tostring(element: &dom::Element, output: &mut String) {
     }
 }
 
+/// Returns a short structural summary of a DOM node for `mod.debug.inspect`,
+/// or [`None`] if the user data is not a node.
+pub fn inspect_node(userdata: &LuaAnyUserData) -> Option<String> {
+    const MAX_CONTENT_CHARS: usize = 40;
+
+    let summarize_content = |kind: &str, content: &str| {
+        let mut truncated = content.chars().take(MAX_CONTENT_CHARS).collect::<String>();
+        if truncated.len() < content.len() {
+            truncated.push_str("...");
+        }
+        format!("{kind} {truncated:?} ({} characters)", content.chars().count())
+    };
+
+    if let Ok(element) = userdata.borrow::<LuaElement>() {
+        let element = unsafe { element.get() }.borrow();
+        let mut name = String::new();
+        append_qualified_name(&element, &mut name);
+        Some(format!(
+            "element <{name}> ({} attributes, {} children)",
+            element.attributes.len(),
+            element.children().count()
+        ))
+    } else if let Ok(text) = userdata.borrow::<LuaText>() {
+        Some(summarize_content("text", &unsafe { *text.0.as_ptr() }.borrow().content))
+    } else if let Ok(comment) = userdata.borrow::<LuaComment>() {
+        Some(summarize_content(
+            "comment",
+            &unsafe { *comment.0.as_ptr() }.borrow().content,
+        ))
+    } else {
+        None
+    }
+}
+
 trait LuaNode: Sized {
     unsafe fn get_node<'gc>(&self) -> GcNode<'gc>;
 }
@@ -136,11 +181,33 @@ fn add_node_methods<T: LuaNode, M: LuaUserDataMethods<T>>(methods: &mut M) {
                         Ok(LuaValue::Nil)
                     }
                 }
+                "comment" => {
+                    if let Some(value) = dom::Comment::downcast_gc(dynamic) {
+                        LuaComment(roots.stash(mc, value)).into_lua(lua)
+                    } else {
+                        Ok(LuaValue::Nil)
+                    }
+                }
                 _ => Err(LuaError::runtime("invalid type passed to Node:as cast")),
             }
         })
     });
 
+    // Shallow clones of elements copy the name and attributes but not the children.
+    // The clone is always detached, regardless of whether the original had a parent.
+    methods.add_method("clone", |lua, this, mode: String| {
+        let deep = match mode.as_str() {
+            "deep" => true,
+            "shallow" => false,
+            _ => return Err(LuaError::runtime("invalid mode passed to Node:clone")),
+        };
+
+        lua.gc().mutate(|mc, roots| {
+            let clone = dom::clone_node(mc, unsafe { this.get_node() }, deep);
+            gc_into_lua(mc, roots, lua, clone)
+        })
+    });
+
     methods.add_method("detach", |lua, this, _: ()| {
         lua.gc().mutate(|mc, _| {
             detach_any(mc, unsafe { this.get_node() });
@@ -148,6 +215,14 @@ fn add_node_methods<T: LuaNode, M: LuaUserDataMethods<T>>(methods: &mut M) {
         Ok(())
     });
 
+    // Same as detach, named after the DOM ChildNode.remove() method.
+    methods.add_method("remove", |lua, this, _: ()| {
+        lua.gc().mutate(|mc, _| {
+            detach_any(mc, unsafe { this.get_node() });
+        });
+        Ok(())
+    });
+
     // TODO: factor this stuff out into a generic function or smth
     methods.add_method(
         "before",
@@ -232,6 +307,42 @@ impl UserData for LuaText {
     }
 }
 
+#[derive(Clone, FromLua)]
+#[repr(transparent)]
+struct LuaComment(DynamicRoot<Rootable![GcComment<'_>]>);
+
+impl_lua_node!(LuaComment);
+
+fn validate_comment_content(content: &str) -> LuaResult<()> {
+    if content.contains("--") || content.ends_with('-') {
+        return Err(LuaError::runtime(
+            "comment content must not contain \"--\" or end with \"-\"",
+        ));
+    }
+
+    Ok(())
+}
+
+impl UserData for LuaComment {
+    fn add_fields<F: LuaUserDataFields<Self>>(fields: &mut F) {
+        add_node_fields(fields, "comment");
+        fields.add_field_method_get("content", |_, this| {
+            Ok(unsafe { *this.0.as_ptr() }.borrow().content.to_owned())
+        });
+        fields.add_field_method_set("content", |_, this, new: String| {
+            validate_comment_content(&new)?;
+            unsafe { (*this.0.as_ptr()).as_ref_cell() }.borrow_mut().content = new;
+            Ok(())
+        });
+    }
+    fn add_methods<M: LuaUserDataMethods<Self>>(methods: &mut M) {
+        add_node_methods(methods);
+        methods.add_meta_method("__tostring", |_, this, _: ()| {
+            Ok(format!("#comment {:?}", &unsafe { *this.0.as_ptr() }.borrow().content))
+        });
+    }
+}
+
 #[derive(Clone, FromLua)]
 #[repr(transparent)]
 pub struct LuaElement(pub DynamicElement);
@@ -256,6 +367,9 @@ fn todo_gc_into_lua<'gc>(
         dom::NodeKind::Text => LuaText(roots.stash(mc, unsafe { dom::Text::downcast_gc_unchecked(node) }))
             .into_lua(lua)
             .map(Some),
+        dom::NodeKind::Comment => LuaComment(roots.stash(mc, unsafe { dom::Comment::downcast_gc_unchecked(node) }))
+            .into_lua(lua)
+            .map(Some),
         // TODO:
         _ => Ok(None),
     }
@@ -294,6 +408,57 @@ fn detach_any<'gc>(mc: &Mutation<'gc>, node: GcNode<'gc>) {
     }
 }
 
+// Inserts `node` before or after `reference`, which must be a child of `parent`.
+// A nil reference inserts at the end for insertBefore and at the start for insertAfter
+// as per DOM conventions. Nodes that already have a parent are moved.
+fn element_insert_relative<'gc>(
+    mc: &Mutation<'gc>,
+    parent: dom::GcElement<'gc>,
+    node: GcNode<'gc>,
+    reference: Option<LuaConcreteNode>,
+    after: bool,
+) -> LuaResult<()> {
+    let reference = reference.map(|reference| reference.into_node(mc));
+    let method = if after { "insertAfter" } else { "insertBefore" };
+
+    if let Some(reference) = reference {
+        if !reference
+            .borrow()
+            .parent()
+            .is_some_and(|reference_parent| Gc::ptr_eq(reference_parent, parent))
+        {
+            return Err(LuaError::runtime(format!(
+                "reference node passed to Element:{method} is not a child of this element"
+            )));
+        }
+
+        if Gc::ptr_eq(reference, node) {
+            return Ok(());
+        }
+    }
+
+    let mut current = Some(parent);
+    while let Some(ancestor) = current {
+        if Gc::ptr_eq(unsize_node!(ancestor), node) {
+            return Err(LuaError::runtime(format!(
+                "node passed to Element:{method} is an ancestor of this element"
+            )));
+        }
+        current = ancestor.borrow().parent();
+    }
+
+    detach_any(mc, node);
+
+    match (reference, after) {
+        (Some(reference), false) => node_insert_before(reference, mc, node),
+        (Some(reference), true) => node_insert_after(reference, mc, node),
+        (None, false) => parent.borrow_mut(mc).append_child(mc, node),
+        (None, true) => parent.borrow_mut(mc).prepend_child(mc, node),
+    }
+
+    Ok(())
+}
+
 impl IntoLua for DynamicIntoLua {
     fn into_lua<'gc>(self, lua: &Lua) -> LuaResult<LuaValue> {
         lua.gc().mutate(|mc, roots| {
@@ -307,6 +472,7 @@ impl IntoLua for DynamicIntoLua {
 enum LuaConcreteNode {
     Element(LuaElement),
     Text(LuaText),
+    Comment(LuaComment),
 }
 
 #[derive(Clone)]
@@ -337,13 +503,16 @@ impl FromLua for LuaConcreteNode {
         let type_name = value.type_name();
         match LuaElement::from_lua(value.clone(), lua) {
             Ok(element) => Ok(Self::Element(element)),
-            Err(_) => match LuaText::from_lua(value, lua) {
+            Err(_) => match LuaText::from_lua(value.clone(), lua) {
                 Ok(content) => Ok(Self::Text(content)),
-                Err(_) => Err(mlua::Error::FromLuaConversionError {
-                    from: type_name,
-                    to: "XML Node".to_string(),
-                    message: None,
-                }),
+                Err(_) => match LuaComment::from_lua(value, lua) {
+                    Ok(comment) => Ok(Self::Comment(comment)),
+                    Err(_) => Err(mlua::Error::FromLuaConversionError {
+                        from: type_name,
+                        to: "XML Node".to_string(),
+                        message: None,
+                    }),
+                },
             },
         }
     }
@@ -363,6 +532,7 @@ impl LuaConcreteNode {
         match self {
             LuaConcreteNode::Element(e) => unsize_node!(unsafe { *e.0.as_ptr() }),
             LuaConcreteNode::Text(t) => unsize_node!(unsafe { *t.0.as_ptr() }),
+            LuaConcreteNode::Comment(c) => unsize_node!(unsafe { *c.0.as_ptr() }),
         }
     }
 }
@@ -410,12 +580,17 @@ impl UserData for LuaElement {
             })
         });
 
+        // Like in the DOM this concatenates all descendant text in document order,
+        // CDATA sections count as text while comments are skipped.
         fields.add_field_method_get("textContent", |_, this| {
             let this = unsafe { *this.0.as_ptr() };
             let mut output = String::new();
             for child in this.borrow().descendants() {
-                if let Some(content) = dom::Text::downcast_ref(&*child.borrow()).map(|x| x.content.as_str()) {
-                    output.push_str(content);
+                let child = child.borrow();
+                if let Some(text) = dom::Text::downcast_ref(&*child) {
+                    output.push_str(&text.content);
+                } else if let Some(cdata) = dom::CData::downcast_ref(&*child) {
+                    output.push_str(&cdata.content);
                 }
             }
             Ok(output)
@@ -474,6 +649,58 @@ impl UserData for LuaElement {
             },
         );
 
+        // Attribute names are always qualified names (e.g. "mod:type") and are compared
+        // verbatim, no namespace resolution takes place. attributes() iterates in document
+        // order, new attributes are added at the end.
+        methods.add_method("getAttribute", |_, this, name: String| {
+            Ok(unsafe { this.get().borrow() }.attributes.get(&name).cloned())
+        });
+
+        methods.add_method("setAttribute", |_, this, (name, value): (String, String)| {
+            validate_xml_qualified_name(&name)?;
+            // SAFETY: No write barrier has to be triggered as no Gc pointers are modified.
+            unsafe { this.get().as_ref_cell() }
+                .borrow_mut()
+                .attributes
+                .insert(name, value);
+            Ok(())
+        });
+
+        methods.add_method("removeAttribute", |_, this, name: String| {
+            // SAFETY: See above
+            Ok(unsafe { this.get().as_ref_cell() }
+                .borrow_mut()
+                .attributes
+                .shift_remove(&name)
+                .is_some())
+        });
+
+        methods.add_method("attributes", |lua, this, _: ()| {
+            LuaAttributes {
+                element: this.clone(),
+                raw: true,
+            }
+            .into_iterator(lua)
+        });
+
+        methods.add_method(
+            "insertBefore",
+            |lua, this, (node, reference): (NodeImplicitlyConvertible, Option<LuaConcreteNode>)| {
+                lua.gc().mutate(|mc, roots| {
+                    element_insert_relative(mc, *roots.fetch(&this.0), node.into_node(mc), reference, false)
+                })
+            },
+        );
+
+        methods.add_method(
+            "insertAfter",
+            |lua, this, (node, reference): (NodeImplicitlyConvertible, Option<LuaConcreteNode>)| {
+                lua.gc().mutate(|mc, roots| {
+                    element_insert_relative(mc, *roots.fetch(&this.0), node.into_node(mc), reference, true)
+                })
+            },
+        );
+
         methods.add_method("firstElementChild", |lua, this, _: ()| {
             Ok(lua.gc().mutate(|mc, roots| {
                 roots
@@ -582,17 +809,18 @@ impl LuaAttributes {
     fn into_iterator(self, lua: &Lua) -> LuaResult<LuaFunction> {
         let mut current = unsafe { self.element.get().borrow() }
             .attributes
-            .first_key_value()
+            .first()
             .map(|(key, _)| key.clone());
 
         lua.create_function_mut(move |lua, _: ()| {
             if let Some(previous) = current.take() {
                 let e = unsafe { self.element.get().borrow() };
-                let mut it = e.attributes.range(previous..);
-                match it.next() {
+                // Iteration stops if the next attribute was removed in the meantime.
+                let index = e.attributes.get_index_of(&previous);
+                match index.and_then(|index| e.attributes.get_index(index)) {
                     Some((key, value)) => {
                         let lua_key = lua.create_string(key)?;
-                        current = it.next().map(|(key, _)| key.clone());
+                        current = e.attributes.get_index(index.unwrap() + 1).map(|(key, _)| key.clone());
                         (lua_key, self.process_value(lua, value)?).into_lua_multi(lua)
                     }
                     None => LuaValue::Nil.into_lua_multi(lua),
@@ -634,7 +862,7 @@ impl UserData for LuaAttributes {
             let mut element = unsafe { gc.as_ref_cell().borrow_mut() };
 
             if value.is_nil() {
-                element.attributes.remove(&key);
+                element.attributes.shift_remove(&key);
                 return Ok(());
             }
 
@@ -738,12 +966,49 @@ pub fn create_xml_lib(lua: &Lua) -> LuaResult<LuaTable> {
             Ok(LuaElement(lua.gc().mutate(|mc, roots| {
                 roots.stash(
                     mc,
-                    dom::Element::create(mc, prefix, name, Option::unwrap_or_default(attributes)),
+                    dom::Element::create(mc, prefix, name, attributes.into_iter().flatten().collect()),
                 )
             })))
         })?,
     )?;
 
+    // Appending a string to an element already creates a text node implicitly,
+    // this is for when an explicit node object is needed.
+    table.raw_set(
+        "text",
+        lua.create_function(|lua, content: String| {
+            Ok(LuaText(
+                lua.gc()
+                    .mutate(|mc, roots| roots.stash(mc, dom::Text::create(mc, content))),
+            ))
+        })?,
+    )?;
+
+    table.raw_set(
+        "comment",
+        lua.create_function(|lua, content: String| {
+            validate_comment_content(&content)?;
+            Ok(LuaComment(lua.gc().mutate(|mc, roots| {
+                roots.stash(mc, dom::Comment::create(mc, content))
+            })))
+        })?,
+    )?;
+
+    // Used by mod.util.deepcopy, returns nil for values that aren't nodes.
+    table.raw_set(
+        "_deepcopy",
+        lua.create_function(|lua, value: LuaValue| {
+            let Ok(node) = LuaConcreteNode::from_lua(value, lua) else {
+                return Ok(LuaValue::Nil);
+            };
+
+            lua.gc().mutate(|mc, roots| {
+                let clone = dom::clone_node(mc, node.into_node(mc), true);
+                gc_into_lua(mc, roots, lua, clone)
+            })
+        })?,
+    )?;
+
     table.raw_set(
         "parse",
         lua.create_function(|lua, xml: LuaString| {
@@ -1,16 +1,21 @@
 #![feature(offset_of_enum)] // :)
 
 use std::{
+    borrow::Cow,
     collections::HashMap,
     fmt::{Debug, Display},
     fs::File,
-    io::{BufReader, Cursor, Read, Seek, Write},
+    io::{BufReader, Cursor, Read, Seek},
     path::{Path, PathBuf},
     process::ExitCode,
-    sync::{atomic::AtomicU64, Arc},
+    sync::{
+        atomic::{AtomicBool, AtomicU64},
+        Arc,
+    },
+    time::{Duration, Instant, SystemTime},
 };
 
-use anyhow::{Context, Result};
+use anyhow::{bail, Context, Result};
 use clap::Parser;
 use eframe::{
     egui::{self, RichText, Sense, Ui, Visuals},
@@ -23,12 +28,12 @@ use egui_dnd::DragDropItem;
 use gui::{DeferredWindow, WindowState};
 use hyperspace::HyperspaceRelease;
 use lazy_static::lazy_static;
-use log::{debug, error};
+use log::{debug, error, warn};
 use once_cell::sync::OnceCell;
 use parking_lot::Mutex;
 use poll_promise::Promise;
 use serde::{Deserialize, Serialize};
-use util::{to_human_size_units, SloppyVersion};
+use util::SloppyVersion;
 use walkdir::WalkDir;
 use zip::ZipArchive;
 
@@ -40,6 +45,7 @@ use pathedit::PathEdit;
 mod apply;
 mod bps;
 mod cache;
+mod crash;
 mod findftl;
 mod fonts;
 mod github;
@@ -47,8 +53,10 @@ mod gui;
 mod hyperspace;
 mod i18n;
 mod lazy;
+mod logging;
 mod lua;
 mod scan;
+mod selfupdate;
 mod util;
 mod validate;
 mod xmltree;
@@ -60,6 +68,8 @@ const VERSION: &str = env!("CARGO_PKG_VERSION");
 const SETTINGS_LOCATION: &str = "ftlman/settings.json";
 const EFRAME_PERSISTENCE_LOCATION: &str = "ftlman/eguistate.ron";
 const MOD_ORDER_FILENAME: &str = "modorder.json";
+/// How long to wait after the last change to the mod list before automatically applying.
+const AUTO_APPLY_DELAY: Duration = Duration::from_secs(2);
 
 lazy_static! {
     static ref USER_AGENT: String = format!("FTL Manager v{}", crate::VERSION);
@@ -70,43 +80,19 @@ lazy_static! {
 }
 
 fn main() -> ExitCode {
-    env_logger::builder()
-        .format(|f, record| {
-            let module = record
-                .module_path()
-                .map(|x| x.split_once("::").map(|(m, _)| m).unwrap_or(x))
-                .filter(|x| *x != env!("CARGO_PKG_NAME"));
-
-            for line in record.args().to_string().split('\n') {
-                write!(f, "\x1b[90m[")?;
-                f.default_level_style(record.level()).write_to(f)?;
-                write!(f, "{}", record.level())?;
-
-                if let Some(module) = module {
-                    write!(f, " {}", module)?;
-                }
-
-                write!(f, "\x1b[90m]\x1b[0m")?;
-
-                writeln!(f, " {line}")?;
-            }
-
-            Ok(())
-        })
-        .filter_level(log::LevelFilter::Info)
-        .filter_module(module_path!(), {
-            #[cfg(debug_assertions)]
-            let v = log::LevelFilter::Debug;
-            #[cfg(not(debug_assertions))]
-            let v = log::LevelFilter::Info;
-            v
-        })
-        .parse_default_env()
-        .init();
+    // Errors are reported later, once logging is initialized.
+    let settings = Settings::load(&Settings::default_path()).ok().flatten();
+    logging::init(logging::log_file_path(
+        settings.as_ref().is_some_and(|settings| settings.log_to_file),
+    ));
 
     i18n::init();
 
     let args = cli::Args::parse();
+    if args.offline {
+        util::set_offline(true);
+    }
+
     if let Some(command) = args.command {
         if let Err(error) = cli::main(command) {
             error!("{error}");
@@ -118,6 +104,20 @@ fn main() -> ExitCode {
         return ExitCode::SUCCESS;
     }
 
+    crash::install_panic_hook();
+
+    #[cfg(windows)]
+    selfupdate::remove_old_executables();
+
+    // Transparent windows render incorrectly with some compositors so only request one when it's actually needed.
+    let transparent = settings.as_ref().is_some_and(|settings| settings.theme.opacity < 1.0);
+    let backend = args
+        .backend
+        .or(settings.map(|settings| settings.display_backend))
+        .unwrap_or_default();
+    #[cfg(target_os = "linux")]
+    log::info!("Using {backend:?} display backend");
+
     if let Err(error) = eframe::run_native(
         // Windows will display special characters like "POP DIRECTIONAL ISOLATE" in the title...
         // Remove them so it doesn't do that.
@@ -129,24 +129,123 @@ fn main() -> ExitCode {
             viewport: egui::ViewportBuilder::default()
                 .with_inner_size(Vec2::new(620., 480.))
                 .with_min_inner_size(Vec2::new(620., 480.))
-                .with_transparent(true)
+                .with_transparent(transparent)
                 .with_resizable(true),
             persistence_path: Some(dirs::data_local_dir().unwrap().join(EFRAME_PERSISTENCE_LOCATION)),
+            event_loop_builder: backend.event_loop_hook(),
 
             ..Default::default()
         },
         Box::new(|cc| {
-            cc.egui_ctx
-                .set_fonts(fonts::create_font_definitions(i18n::current_language()));
+            #[cfg(target_os = "linux")]
+            if std::env::var_os(X11_FALLBACK_ENV).is_some() {
+                log::info!("Started successfully using the X11 backend");
+            }
+
             Ok(Box::new(App::new(cc).expect("Failed to set up application state")))
         }),
     ) {
+        #[cfg(target_os = "linux")]
+        if let Some(code) = retry_with_x11(&error, backend) {
+            return code;
+        }
+
         error!("{error}");
     }
 
     ExitCode::SUCCESS
 }
 
+/// Set in the environment of the child process spawned by [`retry_with_x11`].
+#[cfg(target_os = "linux")]
+const X11_FALLBACK_ENV: &str = "FTLMAN_X11_FALLBACK";
+
+/// Restarts the application without `WAYLAND_DISPLAY` if the error looks like Wayland failed to initialize.
+///
+/// winit doesn't allow creating a second event loop in the same process so this has to re-execute ourselves.
+/// Nothing is retried if a backend was explicitly selected.
+#[cfg(target_os = "linux")]
+fn retry_with_x11(error: &eframe::Error, backend: DisplayBackend) -> Option<ExitCode> {
+    if backend != DisplayBackend::Auto
+        || std::env::var_os("WAYLAND_DISPLAY").is_none()
+        || std::env::var_os(X11_FALLBACK_ENV).is_some()
+    {
+        return None;
+    }
+
+    let message = error.to_string().to_lowercase();
+    if !["wayland", "library", "dlopen", ".so"]
+        .iter()
+        .any(|needle| message.contains(needle))
+    {
+        return None;
+    }
+
+    warn!("Failed to initialize Wayland ({error}), retrying with the X11 backend");
+
+    let status = std::env::current_exe().and_then(|exe| {
+        std::process::Command::new(exe)
+            .args(std::env::args_os().skip(1))
+            .env_remove("WAYLAND_DISPLAY")
+            .env(X11_FALLBACK_ENV, "1")
+            .status()
+    });
+
+    match status {
+        Ok(status) if status.success() => Some(ExitCode::SUCCESS),
+        Ok(_) => Some(ExitCode::FAILURE),
+        Err(error) => {
+            error!("Failed to restart with the X11 backend: {error}");
+            None
+        }
+    }
+}
+
+/// Windowing system used on Linux, ignored on other platforms.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum DisplayBackend {
+    /// Let winit decide, prefers Wayland if available.
+    #[default]
+    Auto,
+    Wayland,
+    X11,
+}
+
+impl DisplayBackend {
+    #[cfg(target_os = "linux")]
+    const ALL: [DisplayBackend; 3] = [DisplayBackend::Auto, DisplayBackend::Wayland, DisplayBackend::X11];
+
+    #[cfg(target_os = "linux")]
+    fn name(self) -> std::borrow::Cow<'static, str> {
+        match self {
+            DisplayBackend::Auto => l!("settings-display-backend-auto"),
+            DisplayBackend::Wayland => l!("settings-display-backend-wayland"),
+            DisplayBackend::X11 => l!("settings-display-backend-x11"),
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    fn event_loop_hook(self) -> Option<eframe::EventLoopBuilderHook> {
+        use eframe::egui_winit::winit::platform::{wayland::EventLoopBuilderExtWayland, x11::EventLoopBuilderExtX11};
+
+        match self {
+            DisplayBackend::Auto => None,
+            DisplayBackend::Wayland => Some(Box::new(|builder| {
+                builder.with_wayland();
+            })),
+            DisplayBackend::X11 => Some(Box::new(|builder| {
+                builder.with_x11();
+            })),
+        }
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    fn event_loop_hook(self) -> Option<eframe::EventLoopBuilderHook> {
+        None
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 struct ThemeSetting {
     colors: ThemeColorscheme,
@@ -190,6 +289,22 @@ fn value_false() -> bool {
     false
 }
 
+fn default_lua_time_limit() -> u64 {
+    lua::DEFAULT_TIME_LIMIT.as_secs()
+}
+
+fn default_lua_memory_limit() -> u64 {
+    (lua::DEFAULT_MEMORY_LIMIT / (1024 * 1024)) as u64
+}
+
+fn default_editor_font_size() -> f32 {
+    gui::DEFAULT_EDITOR_FONT_SIZE
+}
+
+fn default_download_connections() -> usize {
+    util::DEFAULT_DOWNLOAD_CONNECTIONS
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Settings {
     mod_directory: PathBuf,
@@ -201,12 +316,67 @@ pub struct Settings {
     zips_are_mods: bool,
     #[serde(default = "value_true")]
     ftl_is_zip: bool,
+    /// Whether to look for mods in subdirectories of the mod directory that aren't mods themselves.
+    #[serde(default)]
+    scan_recursively: bool,
+    /// Whether symbolic links inside directory mods are followed, they are ignored otherwise.
+    ///
+    /// Links to mods in the mod directory itself are always followed.
+    #[serde(default)]
+    follow_symlinks: bool,
     #[serde(default = "value_true")]
     repack_ftl_data: bool,
+    #[serde(default = "value_true")]
+    skip_unneeded_repack: bool,
     #[serde(default = "value_false")]
     disable_hs_installer: bool,
+    #[serde(default = "default_download_connections")]
+    download_connections: usize,
+    /// Maximum number of threads used for concurrent file operations and downloads, zero means the
+    /// available parallelism.
+    #[serde(default)]
+    max_threads: usize,
+    /// Base URLs of GitHub mirrors tried in order when downloading Hyperspace from GitHub fails.
+    #[serde(default)]
+    hyperspace_mirrors: Vec<String>,
+    #[serde(default)]
+    github_token: Option<String>,
+    #[serde(default = "value_false")]
+    offline: bool,
+    /// Whether to look for newer releases of mods hosted on GitHub after scanning.
+    #[serde(default)]
+    check_mod_updates: bool,
+    #[serde(default = "value_false")]
+    auto_apply: bool,
+    #[serde(default = "value_false")]
+    log_to_file: bool,
+    #[serde(default)]
+    display_backend: DisplayBackend,
+    /// Maximum time in seconds a single Lua script may run for, zero disables the limit.
+    #[serde(default = "default_lua_time_limit")]
+    lua_time_limit: u64,
+    /// Whether comments are kept in XML files modified by appends, they are stripped otherwise.
+    #[serde(default)]
+    keep_xml_comments: bool,
+    /// Whether DOCTYPE declarations are kept in XML files written by mods, they are removed otherwise.
+    #[serde(default = "value_true")]
+    keep_xml_doctype: bool,
+    /// Maximum memory in MiB used by Lua scripts during a single patch, zero disables the limit.
+    #[serde(default = "default_lua_memory_limit")]
+    lua_memory_limit: u64,
+    // None means the system language will be used
+    #[serde(default)]
+    language: Option<String>,
     #[serde(default)]
     theme: ThemeSetting,
+    /// Font file used for code in the sandbox instead of the default monospace font.
+    #[serde(default)]
+    editor_font: Option<PathBuf>,
+    #[serde(default = "default_editor_font_size")]
+    editor_font_size: f32,
+    /// Whether the first-run FTL directory selection has been completed or skipped.
+    #[serde(default)]
+    seen_onboarding: bool,
 }
 
 impl Settings {
@@ -214,18 +384,48 @@ impl Settings {
         dirs::config_local_dir().unwrap().join(SETTINGS_LOCATION)
     }
 
-    pub fn load(path: &Path) -> Option<Settings> {
-        if path.exists() {
-            serde_json::de::from_reader(File::open(path).unwrap()).unwrap()
-        } else {
-            None
+    /// Returns `None` if the settings file doesn't exist.
+    pub fn load(path: &Path) -> Result<Option<Settings>> {
+        match File::open(path) {
+            Ok(file) => serde_json::de::from_reader(BufReader::new(file))
+                .with_context(|| format!("Failed to parse {}", path.display())),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e).with_context(|| format!("Failed to open {}", path.display())),
         }
     }
 
+    /// Like [`Self::load`] but falls back to the default settings if they can't be read.
+    pub fn load_or_default(path: &Path) -> Settings {
+        Self::load(path)
+            .unwrap_or_else(|e| {
+                warn!("Using default settings: {e:#}");
+                None
+            })
+            .unwrap_or_default()
+    }
+
     pub fn save(&self, path: &Path) -> std::io::Result<()> {
         std::fs::create_dir_all(path.parent().unwrap())?;
-        serde_json::ser::to_writer(File::create(path)?, self)?;
-        Ok(())
+        util::write_atomically(path, |writer| Ok(serde_json::ser::to_writer(writer, self)?))
+    }
+
+    fn max_threads(&self) -> usize {
+        match self.max_threads {
+            0 => std::thread::available_parallelism().map_or(4, |n| n.get()),
+            n => n,
+        }
+    }
+
+    fn download_connections(&self) -> usize {
+        self.download_connections.min(self.max_threads())
+    }
+
+    fn lua_time_limit(&self) -> Option<Duration> {
+        (self.lua_time_limit > 0).then(|| Duration::from_secs(self.lua_time_limit))
+    }
+
+    fn lua_memory_limit(&self) -> Option<usize> {
+        (self.lua_memory_limit > 0).then(|| self.lua_memory_limit as usize * 1024 * 1024)
     }
 
     // On Linux + Steam the files we're interested in are located in <FTL>/data but users
@@ -233,13 +433,17 @@ impl Settings {
     // This will also fix paths acquired through automatic detection of an FTL installation.
     fn fix_ftl_directrory(&mut self) {
         if let Some(path) = self.ftl_directory.as_mut() {
-            if path.join("data/ftl.dat").exists() {
-                path.push("data")
-            }
+            fix_ftl_directory_path(path);
         }
     }
 }
 
+fn fix_ftl_directory_path(path: &mut PathBuf) {
+    if path.join("data/ftl.dat").exists() || findftl::has_legacy_data_files(&path.join("data")) {
+        path.push("data")
+    }
+}
+
 impl Default for Settings {
     fn default() -> Self {
         Self {
@@ -248,12 +452,32 @@ impl Default for Settings {
             zips_are_mods: true,
             dirs_are_mods: true,
             ftl_is_zip: true,
+            scan_recursively: false,
+            follow_symlinks: false,
             repack_ftl_data: true,
+            skip_unneeded_repack: true,
             disable_hs_installer: false,
+            download_connections: util::DEFAULT_DOWNLOAD_CONNECTIONS,
+            max_threads: 0,
+            hyperspace_mirrors: Vec::new(),
+            github_token: None,
+            offline: false,
+            check_mod_updates: false,
+            auto_apply: false,
+            log_to_file: false,
+            display_backend: DisplayBackend::Auto,
+            lua_time_limit: default_lua_time_limit(),
+            lua_memory_limit: default_lua_memory_limit(),
+            keep_xml_comments: false,
+            keep_xml_doctype: true,
+            language: None,
             theme: ThemeSetting {
                 colors: ThemeColorscheme::Dark,
                 opacity: 1.,
             },
+            editor_font: None,
+            editor_font_size: default_editor_font_size(),
+            seen_onboarding: false,
         }
     }
 }
@@ -273,23 +497,31 @@ pub struct SharedState {
     locked: bool,
     // this is a value in the range 0-1 that is used as the progress value in the applying popup
     apply_stage: Option<ApplyStage>,
+    // number of scanned and total entries in the mod directory while scanning
+    scan_progress: Option<(usize, usize)>,
+    // set by the UI to ask the apply thread to stop at the next opportunity
+    cancel_requested: bool,
 
     ctx: egui::Context,
     hyperspace: Option<HyperspaceState>,
 
     mods: Vec<Mod>,
+    // when mods were last applied successfully, persisted along with the mod order
+    last_apply: Option<LastApply>,
 }
 
 enum CurrentTask {
     Scan(Promise<Result<()>>),
     Apply(Promise<Result<()>>),
+    // Copies dropped mods into the mod directory and then rescans it.
+    Install(Promise<Result<()>>),
     None,
 }
 
 impl CurrentTask {
     pub fn is_idle(&self) -> bool {
         match self {
-            CurrentTask::Scan(p) | CurrentTask::Apply(p) => p.ready().is_some(),
+            CurrentTask::Scan(p) | CurrentTask::Apply(p) | CurrentTask::Install(p) => p.ready().is_some(),
             CurrentTask::None => true,
         }
     }
@@ -304,9 +536,46 @@ impl CurrentTask {
 
 static ERROR_IDX: AtomicU64 = AtomicU64::new(0);
 
+/// Layout that places widgets horizontally in the reading direction of the current language.
+fn reading_layout(align: egui::Align) -> egui::Layout {
+    if i18n::is_rtl() {
+        egui::Layout::right_to_left(align)
+    } else {
+        egui::Layout::left_to_right(align)
+    }
+}
+
+/// Like [`Ui::horizontal`] but places widgets in the reading direction of the current language.
+///
+/// A bare [`reading_layout`] centred on the cross axis would grow to fill all of the remaining height, so this
+/// allocates a row of the same height [`Ui::horizontal`] would.
+fn reading_row<R>(ui: &mut Ui, add_contents: impl FnOnce(&mut Ui) -> R) -> egui::InnerResponse<R> {
+    ui.allocate_ui_with_layout(
+        Vec2::new(ui.available_width(), ui.spacing().interact_size.y),
+        reading_layout(egui::Align::Center),
+        add_contents,
+    )
+}
+
+/// Layout that places widgets horizontally against the reading direction of the current language.
+///
+/// Used for things that should be pushed to the end of a row, like buttons on the opposite side of a title.
+fn reverse_reading_layout(align: egui::Align) -> egui::Layout {
+    if i18n::is_rtl() {
+        egui::Layout::left_to_right(align)
+    } else {
+        egui::Layout::right_to_left(align)
+    }
+}
+
 fn render_error_chain<S: AsRef<str>>(ui: &mut Ui, it: impl ExactSizeIterator<Item = S>) {
     let mut job = LayoutJob {
         wrap: egui::text::TextWrapping::from_wrap_mode_and_width(egui::TextWrapMode::Wrap, ui.available_width()),
+        halign: if i18n::is_rtl() {
+            egui::Align::RIGHT
+        } else {
+            egui::Align::LEFT
+        },
         ..LayoutJob::default()
     };
 
@@ -384,9 +653,22 @@ struct App {
     last_hovered_mod: Option<usize>,
     shared: Arc<Mutex<SharedState>>,
     hyperspace_installer: Option<Result<Result<hyperspace::Installer, String>>>,
+    // version of the FTL installation in the configured FTL directory, if it was recognized
+    ftl_version: Option<semver::Version>,
+    // why the configured FTL directory is unusable, if it is
+    ftl_directory_problem: Option<findftl::FtlDirectoryProblem>,
 
     hyperspace_releases: ResettableLazy<Promise<Result<Vec<HyperspaceRelease>>>>,
     ignore_releases_fetch_error: bool,
+    // result of the last manual ftlman update check, Ok(None) means ftlman is up to date
+    last_ftlman_release: Option<Promise<Result<Option<github::Release>>>>,
+    // download and installation of the release found by the update check
+    self_update: Option<Promise<Result<()>>>,
+    // total size of the cache directory, recalculated when the settings are opened or the cache is cleared
+    cache_size: Option<Promise<Result<u64>>>,
+    confirm_clear_cache: bool,
+    // removal of cache entries requested from the settings, runs in the background since it walks the whole cache
+    cache_clear: Option<Promise<Result<()>>>,
 
     current_task: CurrentTask,
     settings_path: PathBuf,
@@ -398,6 +680,19 @@ struct App {
 
     error_popups: Vec<ErrorPopup>,
 
+    // enabled mods as of the last frame and when they should be applied if auto-apply is on
+    auto_apply_configuration: Option<Vec<String>>,
+    auto_apply_deadline: Option<Instant>,
+
+    // why ftl.dat looks already modified when there's no vanilla backup, shown until the user decides what to do
+    modified_archive_prompt: Option<String>,
+
+    // raw metadata.xml of a mod opened from the info panel
+    metadata_viewer: Option<MetadataViewer>,
+
+    // path being entered in the first-run FTL directory selection and its validation result, None if it isn't shown
+    onboarding_ftl_directory: Option<(String, Result<PathBuf, findftl::FtlDirectoryProblem>)>,
+
     // % of window width
     vertical_divider_pos: f32,
 }
@@ -405,8 +700,42 @@ struct App {
 impl App {
     fn new(cc: &eframe::CreationContext<'_>) -> Result<Self> {
         let settings_path = Settings::default_path();
-        let mut settings = Settings::load(&settings_path).unwrap_or_default();
         let mut error_popups = Vec::new();
+        let mut settings = match Settings::load(&settings_path) {
+            Ok(settings) => settings.unwrap_or_default(),
+            Err(error) => {
+                let error = match util::backup_bad_file(&settings_path) {
+                    Ok(backup_path) => error.context(format!(
+                        "The unreadable settings file was moved to {}",
+                        backup_path.display()
+                    )),
+                    Err(backup_error) => {
+                        error!("{backup_error:#}");
+                        error
+                    }
+                };
+                error_popups.push(ErrorPopup::create_and_log(
+                    l!("settings-load-failed").into_owned(),
+                    &error.context("Could not read settings, defaults will be used instead"),
+                ));
+                Settings::default()
+            }
+        };
+        github::set_token(settings.github_token.clone());
+        lua::set_time_limit(settings.lua_time_limit());
+        lua::set_memory_limit(settings.lua_memory_limit());
+        if settings.language.is_some() {
+            i18n::set_language(settings.language.as_deref());
+        }
+        cc.egui_ctx.set_fonts(fonts::create_font_definitions(
+            i18n::current_language(),
+            settings.editor_font.as_deref(),
+        ));
+        gui::set_editor_font_size(settings.editor_font_size);
+        if settings.offline {
+            util::set_offline(true);
+        }
+        set_follow_symlinks(settings.follow_symlinks);
         if settings.mod_directory == Settings::default().mod_directory {
             std::fs::create_dir_all(&settings.mod_directory)?;
         }
@@ -426,59 +755,411 @@ impl App {
         let shared = Arc::new(Mutex::new(SharedState {
             locked: false,
             apply_stage: None,
+            scan_progress: None,
+            cancel_requested: false,
             ctx: cc.egui_ctx.clone(),
             hyperspace: None,
             mods: vec![],
+            last_apply: None,
         }));
         let mut app = App {
             last_hovered_mod: None,
             shared: shared.clone(),
             hyperspace_installer: None,
+            ftl_version: None,
+            ftl_directory_problem: None,
 
             hyperspace_releases: ResettableLazy::new(|| {
                 Promise::spawn_thread("fetch hyperspace releases", hyperspace::fetch_hyperspace_releases)
             }),
             ignore_releases_fetch_error: false,
+            last_ftlman_release: None,
+            self_update: None,
 
             current_task: CurrentTask::None,
             visuals: settings.theme.visuals(),
             settings_path,
             settings,
             settings_open: false,
+            cache_size: None,
+            confirm_clear_cache: false,
+            cache_clear: None,
 
             sandbox: DeferredWindow::new(egui::ViewportId::from_hash_of("sandbox viewport"), gui::Sandbox::new()),
 
             error_popups,
 
+            auto_apply_configuration: None,
+            auto_apply_deadline: None,
+
+            modified_archive_prompt: None,
+
+            metadata_viewer: None,
+
+            onboarding_ftl_directory: None,
+
             vertical_divider_pos: 0.50,
         };
+        if !app.settings.seen_onboarding && app.settings.ftl_directory.is_none() {
+            app.onboarding_ftl_directory = Some((String::new(), Err(findftl::FtlDirectoryProblem::DoesNotExist)));
+        }
 
-        let settings = app.settings.clone();
-        app.current_task = CurrentTask::Scan(Promise::spawn_thread("task", move || {
-            scan::scan(settings, shared, true)
-        }));
+        app.detect_ftl_version();
+        app.validate_ftl_directory();
+
+        app.current_task = spawn_scan_task(&app.settings, &shared, true);
 
         Ok(app)
     }
 }
 
-impl eframe::App for App {
-    fn save(&mut self, _storage: &mut dyn eframe::Storage) {
+fn spawn_scan_task(settings: &Settings, shared: &Arc<Mutex<SharedState>>, first: bool) -> CurrentTask {
+    let settings = settings.clone();
+    let shared = shared.clone();
+    CurrentTask::Scan(Promise::spawn_thread("task", move || {
+        scan::scan(settings, shared, first)
+    }))
+}
+
+/// Copies a mod file or directory into the mod directory.
+fn install_dropped_mod(settings: &Settings, path: &Path) -> Result<()> {
+    if ModSource::new(settings, path.to_path_buf()).is_none() {
+        bail!("{} is not a supported mod file or directory", path.display());
+    }
+
+    let target = settings
+        .mod_directory
+        .join(path.file_name().context("Path has no filename")?);
+    if target.try_exists()? {
+        bail!("{} already exists in the mod directory", target.display());
+    }
+
+    if path.is_dir() {
+        for entry in WalkDir::new(path) {
+            let entry = entry?;
+            let entry_target = target.join(entry.path().strip_prefix(path).unwrap());
+            if entry.file_type().is_dir() {
+                std::fs::create_dir_all(&entry_target)?;
+            } else {
+                std::fs::copy(entry.path(), &entry_target)
+                    .with_context(|| format!("Failed to copy {}", entry.path().display()))?;
+            }
+        }
+    } else {
+        std::fs::copy(path, &target).with_context(|| format!("Failed to copy {}", path.display()))?;
+    }
+
+    Ok(())
+}
+
+/// Installs `paths` in the background and rescans the mod directory if any of them were installed.
+///
+/// Mods that fail to install don't stop the others, their errors are reported together once all are done.
+fn spawn_install_task(settings: &Settings, shared: &Arc<Mutex<SharedState>>, paths: Vec<PathBuf>) -> CurrentTask {
+    let settings = settings.clone();
+    let shared = shared.clone();
+    CurrentTask::Install(Promise::spawn_thread("task", move || {
+        let mut failures = Vec::new();
+        for path in &paths {
+            if let Err(error) = install_dropped_mod(&settings, path) {
+                failures.push(format!("Could not install {}: {error:#}", path.display()));
+            }
+        }
+
+        if failures.len() < paths.len() {
+            scan::scan(settings, shared, false)?;
+        }
+
+        if failures.is_empty() {
+            Ok(())
+        } else {
+            Err(anyhow::anyhow!(failures.join("\n")))
+        }
+    }))
+}
+
+#[derive(Clone, Copy)]
+enum ApplyMode {
+    /// Patch all enabled mods into the game.
+    Full,
+    /// Apply only Hyperspace on top of the current, possibly already patched, ftl.dat.
+    HyperspaceOnly,
+}
+
+fn spawn_apply_task(
+    ctx: &egui::Context,
+    settings: &Settings,
+    shared: &Arc<Mutex<SharedState>>,
+    hyperspace_installer: &Option<Result<Result<hyperspace::Installer, String>>>,
+    mode: ApplyMode,
+) -> CurrentTask {
+    let ctx = ctx.clone();
+    let ftl_path = settings.ftl_directory.clone().unwrap();
+    let shared = shared.clone();
+    let settings = settings.clone();
+    let hs = match hyperspace_installer {
+        Some(Ok(Ok(installer))) => Some(installer.clone()),
+        _ => None,
+    };
+    CurrentTask::Apply(Promise::spawn_thread("task", move || {
+        let result = match mode {
+            ApplyMode::Full => apply::apply(ftl_path, shared, hs, settings),
+            ApplyMode::HyperspaceOnly => match hs {
+                Some(installer) => apply::apply_hyperspace_only(ftl_path, shared, installer, settings),
+                None => Err(anyhow::anyhow!("The Hyperspace installer is not available")),
+            },
+        };
+        ctx.request_repaint();
+        result
+    }))
+}
+
+impl App {
+    fn detect_ftl_version(&mut self) {
+        self.ftl_version = self.settings.ftl_directory.as_deref().and_then(|path| {
+            hyperspace::detect_ftl_version(path).unwrap_or_else(|error| {
+                warn!("Failed to detect FTL version: {error:#}");
+                None
+            })
+        });
+    }
+
+    fn validate_ftl_directory(&mut self) {
+        self.ftl_directory_problem = self
+            .settings
+            .ftl_directory
+            .as_deref()
+            .and_then(|path| findftl::validate_ftl_directory(path).err());
+    }
+
+    fn render_modified_archive_prompt(&mut self, ctx: &egui::Context) {
+        let Some(reason) = self.modified_archive_prompt.as_ref() else {
+            return;
+        };
+
+        let mut close = false;
+        egui::Window::new(l!("modified-archive-title"))
+            .collapsible(false)
+            .resizable(false)
+            .anchor(egui::Align2::CENTER_CENTER, egui::Vec2::ZERO)
+            .show(ctx, |ui| {
+                ui.label(l!("modified-archive-explanation", "reason" => reason.as_str()));
+                ui.horizontal(|ui| {
+                    if ui
+                        .button(l!("modified-archive-adopt"))
+                        .on_hover_text(l!("modified-archive-adopt-tooltip"))
+                        .clicked()
+                    {
+                        close = true;
+                        let ftl_path = self.settings.ftl_directory.clone().unwrap();
+                        match apply::adopt_current_archive(&ftl_path) {
+                            Ok(()) => {
+                                self.current_task = spawn_apply_task(
+                                    ctx,
+                                    &self.settings,
+                                    &self.shared,
+                                    &self.hyperspace_installer,
+                                    ApplyMode::Full,
+                                );
+                            }
+                            Err(error) => self.error_popups.push(ErrorPopup::create_and_log(
+                                l!("modified-archive-adopt-failed").into_owned(),
+                                &error,
+                            )),
+                        }
+                    }
+                    if ui.button(l!("modified-archive-cancel")).clicked() {
+                        close = true;
+                    }
+                });
+            });
+
+        if close {
+            self.modified_archive_prompt = None;
+        }
+    }
+
+    fn render_onboarding(&mut self, ctx: &egui::Context) {
+        let Some((buf, validation)) = self.onboarding_ftl_directory.as_mut() else {
+            return;
+        };
+
+        let mut finished = false;
+        egui::Window::new(l!("onboarding-title"))
+            .collapsible(false)
+            .resizable(false)
+            .anchor(egui::Align2::CENTER_CENTER, egui::Vec2::ZERO)
+            .show(ctx, |ui| {
+                ui.label(l!("onboarding-intro"));
+                ui.add_space(4.);
+                ui.label(if cfg!(target_os = "windows") {
+                    l!("onboarding-hint-windows")
+                } else if cfg!(target_os = "macos") {
+                    l!("onboarding-hint-macos")
+                } else {
+                    l!("onboarding-hint-linux")
+                });
+                ui.add_space(4.);
+
+                if PathEdit::new(buf)
+                    .id("pathedit onboarding ftl dir")
+                    .desired_width(320.)
+                    .completion_filter(|p| p.is_dir())
+                    .show(ui)
+                    .changed()
+                {
+                    let mut candidate = PathBuf::from(&*buf);
+                    fix_ftl_directory_path(&mut candidate);
+                    *validation = findftl::validate_ftl_directory(&candidate).map(|()| candidate);
+                }
+
+                let valid = !buf.is_empty() && validation.is_ok();
+                match validation {
+                    _ if buf.is_empty() => {}
+                    Ok(_) => {
+                        ui.label(RichText::new(l!("onboarding-valid")).color(ui.visuals().strong_text_color()));
+                    }
+                    Err(problem) => {
+                        ui.label(RichText::new(problem.to_string()).color(ui.visuals().error_fg_color));
+                    }
+                }
+
+                ui.horizontal(|ui| {
+                    if ui
+                        .add_enabled(valid, egui::Button::new(l!("onboarding-continue")))
+                        .clicked()
+                    {
+                        self.settings.ftl_directory = validation.as_ref().ok().cloned();
+                        self.hyperspace_installer = None;
+                        finished = true;
+                    }
+                    if ui.button(l!("onboarding-skip")).clicked() {
+                        finished = true;
+                    }
+                });
+            });
+
+        if finished {
+            self.onboarding_ftl_directory = None;
+            self.settings.seen_onboarding = true;
+            self.detect_ftl_version();
+            self.validate_ftl_directory();
+        }
+    }
+
+    fn update_auto_apply(&mut self, ctx: &egui::Context, is_sandbox_open: bool) {
+        if !self.settings.auto_apply {
+            self.auto_apply_configuration = None;
+            self.auto_apply_deadline = None;
+            return;
+        }
+
+        let configuration = {
+            let lock = self.shared.lock();
+            if lock.locked || !self.current_task.is_idle() {
+                // The mod list may change arbitrarily while a task is running, so take a
+                // fresh snapshot once it's done instead of treating that as a user change.
+                self.auto_apply_configuration = None;
+                return;
+            }
+
+            lock.mods
+                .iter()
+                .filter(|m| m.enabled)
+                .map(|m| m.filename().to_owned())
+                .collect::<Vec<_>>()
+        };
+
+        match self.auto_apply_configuration.as_ref() {
+            None => self.auto_apply_configuration = Some(configuration),
+            Some(previous) if *previous != configuration => {
+                self.auto_apply_configuration = Some(configuration);
+                self.auto_apply_deadline = Some(Instant::now() + AUTO_APPLY_DELAY);
+            }
+            Some(_) => {}
+        }
+
+        let Some(deadline) = self.auto_apply_deadline else {
+            return;
+        };
+
+        let now = Instant::now();
+        if now < deadline {
+            ctx.request_repaint_after(deadline - now);
+        } else if is_sandbox_open {
+            ctx.request_repaint_after(AUTO_APPLY_DELAY);
+        } else {
+            self.auto_apply_deadline = None;
+            let directory_ok = self.settings.ftl_directory.as_ref().is_some_and(|d| d.exists())
+                && self.ftl_directory_problem.is_none();
+            if directory_ok {
+                debug!("Automatically applying mods");
+                self.current_task = spawn_apply_task(
+                    ctx,
+                    &self.settings,
+                    &self.shared,
+                    &self.hyperspace_installer,
+                    ApplyMode::Full,
+                );
+            }
+        }
+    }
+
+    fn handle_dropped_files(&mut self, ctx: &egui::Context) {
+        let (is_hovering, dropped) = ctx.input(|i| (!i.raw.hovered_files.is_empty(), i.raw.dropped_files.clone()));
+
+        if is_hovering {
+            let painter = ctx.layer_painter(egui::LayerId::new(
+                egui::Order::Foreground,
+                egui::Id::new("file drop overlay"),
+            ));
+            let screen_rect = ctx.screen_rect();
+            painter.rect_filled(screen_rect, 0.0, egui::Color32::from_black_alpha(192));
+            painter.text(
+                screen_rect.center(),
+                egui::Align2::CENTER_CENTER,
+                l!("mods-drop-hint"),
+                egui::TextStyle::Heading.resolve(&ctx.style()),
+                egui::Color32::WHITE,
+            );
+        }
+
+        let paths = dropped.into_iter().filter_map(|file| file.path).collect::<Vec<_>>();
+        if paths.is_empty() {
+            return;
+        }
+
+        if !self.current_task.is_idle() || self.shared.lock().locked {
+            self.error_popups.push(ErrorPopup::create_and_log(
+                l!("mods-install-failed").into_owned(),
+                &anyhow::anyhow!("Mods cannot be installed while another operation is in progress"),
+            ));
+            return;
+        }
+
+        self.last_hovered_mod = None;
+        self.current_task = spawn_install_task(&self.settings, &self.shared, paths);
+    }
+}
+
+impl App {
+    fn persist(&self) {
         debug!("Saving settings");
         self.settings
             .save(&self.settings_path)
             .unwrap_or_else(|e| error!("Failed to save settings: {e}"));
         debug!("Saving mod order");
         let order = self.shared.lock().mod_configuration();
-        match std::fs::File::create(self.settings.mod_directory.join(MOD_ORDER_FILENAME)) {
-            Ok(f) => {
-                if let Err(e) = serde_json::to_writer(f, &order) {
-                    error!("Failed to write mod order: {e}")
-                }
-            }
-            Err(e) => error!("Failed to open mod order file: {e}"),
+        if let Err(e) = scan::write_mod_configuration(&self.settings.mod_directory, &order) {
+            error!("{e:#}")
         }
     }
+}
+
+impl eframe::App for App {
+    fn save(&mut self, _storage: &mut dyn eframe::Storage) {
+        self.persist();
+    }
 
     fn auto_save_interval(&self) -> std::time::Duration {
         std::time::Duration::from_secs(120)
@@ -491,22 +1172,26 @@ impl eframe::App for App {
     fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
         ctx.set_visuals(self.visuals.clone());
 
+        self.handle_dropped_files(ctx);
+
         let is_sandbox_open = self.sandbox.state().is_open();
+        self.update_auto_apply(ctx, is_sandbox_open);
 
         egui::TopBottomPanel::top("app_main_top_panel").show(ctx, |ui| {
             ui.add_space(5.);
 
-            ui.horizontal(|ui| {
+            reading_row(ui, |ui| {
                 ui.heading(l!("name",
                     "version" => VERSION
                 ));
 
-                ui.with_layout(egui::Layout::right_to_left(eframe::emath::Align::Center), |ui| {
+                ui.with_layout(reverse_reading_layout(egui::Align::Center), |ui| {
                     if ui
                         .add_enabled(!self.settings_open, egui::Button::new(l!("settings-button")))
                         .clicked()
                     {
                         self.settings_open = true;
+                        self.cache_size = None;
                     }
 
                     if ui
@@ -531,7 +1216,7 @@ impl eframe::App for App {
 
         egui::CentralPanel::default().show(ctx, |ui| {
             ui.vertical(|ui| {
-                ui.horizontal(|ui| {
+                reading_row(ui, |ui| {
                     ui.label(l!("mods-title"));
 
                     let mut lock = self.shared.lock();
@@ -546,27 +1231,76 @@ impl eframe::App for App {
                         }
                     });
 
-                    ui.with_layout(egui::Layout::right_to_left(eframe::emath::Align::Min), |ui| {
+                    if ui
+                        .button(l!("mods-copy-list"))
+                        .on_hover_text_at_pointer(l!("mods-copy-list-tooltip"))
+                        .clicked()
+                    {
+                        ui.ctx().copy_text(format_mod_list(lock.hyperspace.as_ref(), &lock.mods));
+                    }
+
+                    if ui
+                        .add_enabled(modifiable, egui::Button::new(l!("mods-import-order")))
+                        .on_hover_text_at_pointer(l!("mods-import-order-tooltip"))
+                        .clicked()
+                    {
+                        let path = self.settings.mod_directory.join(scan::SLIPSTREAM_ORDER_FILENAME);
+                        match std::fs::read_to_string(&path) {
+                            Ok(text) => {
+                                let missing = scan::apply_slipstream_order(&text, &mut lock.mods);
+                                if !missing.is_empty() {
+                                    self.error_popups.push(ErrorPopup::create_and_log(
+                                        l!("mods-import-order-missing").into_owned(),
+                                        &anyhow::anyhow!(missing.join(", ")),
+                                    ));
+                                }
+                            }
+                            Err(e) => self.error_popups.push(ErrorPopup::create_and_log(
+                                l!("mods-import-order-failed").into_owned(),
+                                &anyhow::Error::new(e).context(format!("Failed to read {}", path.display())),
+                            )),
+                        }
+                    }
+
+                    ui.with_layout(reverse_reading_layout(egui::Align::Min), |ui| {
                         let apply = ui
                             .add_enabled(
-                                modifiable && self.settings.ftl_directory.is_some() && !is_sandbox_open,
+                                modifiable
+                                    && self.settings.ftl_directory.is_some()
+                                    && self.ftl_directory_problem.is_none()
+                                    && !is_sandbox_open,
                                 egui::Button::new(l!("mods-apply-button")),
                             )
                             .on_hover_text_at_pointer(l!("mods-apply-tooltip"));
                         if apply.clicked() {
-                            let ctx = ctx.clone();
-                            let ftl_path = self.settings.ftl_directory.clone().unwrap();
-                            let shared = self.shared.clone();
-                            let settings = self.settings.clone();
-                            let hs = match self.hyperspace_installer {
-                                Some(Ok(Ok(ref installer))) => Some(installer.clone()),
-                                _ => None,
-                            };
-                            self.current_task = CurrentTask::Apply(Promise::spawn_thread("task", move || {
-                                let result = apply::apply(ftl_path, shared, hs, settings);
-                                ctx.request_repaint();
-                                result
-                            }));
+                            self.current_task = spawn_apply_task(
+                                ctx,
+                                &self.settings,
+                                &self.shared,
+                                &self.hyperspace_installer,
+                                ApplyMode::Full,
+                            );
+                        }
+
+                        if let Some(Ok(Ok(_))) = &self.hyperspace_installer {
+                            let hyperspace_only = ui
+                                .add_enabled(
+                                    modifiable
+                                        && self.settings.ftl_directory.is_some()
+                                        && self.ftl_directory_problem.is_none()
+                                        && !is_sandbox_open,
+                                    egui::Button::new(l!("mods-apply-hyperspace-only-button")),
+                                )
+                                .on_hover_text_at_pointer(l!("mods-apply-hyperspace-only-tooltip"));
+                            if hyperspace_only.clicked() {
+                                self.current_task = spawn_apply_task(
+                                    ctx,
+                                    &self.settings,
+                                    &self.shared,
+                                    &self.hyperspace_installer,
+                                    ApplyMode::HyperspaceOnly,
+                                );
+                            }
                         }
 
                         let scan = ui
@@ -575,25 +1309,35 @@ impl eframe::App for App {
 
                         if scan.clicked() && !lock.locked {
                             self.last_hovered_mod = None;
-                            let settings = self.settings.clone();
-                            let shared = self.shared.clone();
-                            self.current_task = CurrentTask::Scan(Promise::spawn_thread("task", move || {
-                                scan::scan(settings, shared, false)
-                            }));
+                            self.current_task = spawn_scan_task(&self.settings, &self.shared, false);
+                        }
+
+                        if !lock.locked {
+                            if let Some(last_apply) = &lock.last_apply {
+                                ui.label(
+                                    RichText::new(l!(
+                                        "mods-last-applied",
+                                        "time" => format_elapsed(last_apply.elapsed()),
+                                        "count" => last_apply.mod_count
+                                    ))
+                                    .weak(),
+                                );
+                                // Keep the elapsed time roughly up to date.
+                                ctx.request_repaint_after(Duration::from_secs(30));
+                            }
                         }
 
                         if lock.locked {
+                            let mut cancel_clicked = false;
                             if let Some(stage) = &lock.apply_stage {
                                 match stage {
                                     ApplyStage::Downloading { is_patch, version, progress } => {
                                         if let Some((downloaded, total)) = *progress {
-                                            let (dl_iec, dl_sfx) = to_human_size_units(downloaded);
-                                            let (tot_iec, tot_sfx) = to_human_size_units(total);
                                             ui.add(egui::ProgressBar::new(downloaded as f32 / total as f32).text(l!(
                                                     if *is_patch { "status-patch-download2" } else { "status-hyperspace-download2" },
                                                     "version" => version.as_ref(),
-                                                    "done" => format!("{dl_iec:.2}{dl_sfx}"),
-                                                    "total" => format!("{tot_iec:.2}{tot_sfx}"),
+                                                    "done" => i18n::format_size(downloaded),
+                                                    "total" => i18n::format_size(total),
                                             )));
                                         } else {
                                             ui.strong(l!(
@@ -610,9 +1354,17 @@ impl eframe::App for App {
                                         ui.spinner();
                                         ui.strong(l!("status-preparing"));
                                     }
-                                    ApplyStage::Repacking => {
-                                        ui.spinner();
-                                        ui.strong(l!("status-repacking"));
+                                    ApplyStage::Repacking {
+                                        entry_idx,
+                                        entries_total,
+                                    } => {
+                                        ui.add(
+                                            egui::ProgressBar::new(*entry_idx as f32 / *entries_total as f32)
+                                                .text(l!("status-repacking")),
+                                        );
+                                        cancel_clicked = ui
+                                            .add_enabled(!lock.cancel_requested, egui::Button::new(l!("status-cancel")))
+                                            .clicked();
                                     }
                                     ApplyStage::Mod {
                                         mod_name,
@@ -626,10 +1378,22 @@ impl eframe::App for App {
                                         ));
                                     }
                                 };
+                            } else if let Some((scanned, total)) = lock.scan_progress.filter(|&(_, total)| total > 0) {
+                                ui.add(
+                                    egui::ProgressBar::new(scanned as f32 / total as f32).text(l!(
+                                        "status-scanning-mods2",
+                                        "done" => scanned,
+                                        "total" => total
+                                    )),
+                                );
                             } else {
                                 ui.spinner();
                                 ui.strong(l!("status-scanning-mods"));
                             }
+
+                            if cancel_clicked {
+                                lock.cancel_requested = true;
+                            }
                         }
                     });
 
@@ -637,17 +1401,27 @@ impl eframe::App for App {
                         CurrentTask::Scan(p) => p
                             .ready()
                             .and_then(|x| x.as_ref().err())
-                            .map(|x| ("Could not scan mod folder", x)),
+                            .map(|x| (Cow::Borrowed("Could not scan mod folder"), x)),
                         CurrentTask::Apply(p) => p
                             .ready()
                             .and_then(|x| x.as_ref().err())
-                            .map(|x| ("Could not apply mods", x)),
+                            .map(|x| (Cow::Borrowed("Could not apply mods"), x)),
+                        CurrentTask::Install(p) => p
+                            .ready()
+                            .and_then(|x| x.as_ref().err())
+                            .map(|x| (l!("mods-install-failed"), x)),
                         CurrentTask::None => None,
                     } {
                         lock.apply_stage = None;
-                        self.error_popups
-                            .push(ErrorPopup::create_and_log(title.to_string(), error));
-                        self.current_task = CurrentTask::None;
+                        lock.scan_progress = None;
+                        if let Some(modified) = error.downcast_ref::<apply::ModifiedArchiveError>() {
+                            warn!("{modified}");
+                            self.modified_archive_prompt = Some(modified.reason.clone());
+                        } else {
+                            self.error_popups
+                                .push(ErrorPopup::create_and_log(title.into_owned(), error));
+                        }
+                        self.current_task = CurrentTask::None;
                         // TODO: Make this cleaner
                         lock.locked = false;
                     }
@@ -676,8 +1450,12 @@ impl eframe::App for App {
                         ui.add_enabled_ui(!shared.locked && self.current_task.is_idle(), |ui| {
                             ui.horizontal(|ui| {
                                 let Some(ftl_directory) = self.settings.ftl_directory.as_mut().filter(|d| d.exists()) else {
+                                    let message = match &self.ftl_directory_problem {
+                                        Some(problem) => format!("{}: {problem}", l!("invalid-ftl-directory")),
+                                        None => l!("invalid-ftl-directory").into_owned(),
+                                    };
                                     ui.label(
-                                        RichText::new(l!("invalid-ftl-directory"))
+                                        RichText::new(message)
                                             .color(ui.visuals().error_fg_color)
                                             .strong(),
                                     );
@@ -715,6 +1493,12 @@ impl eframe::App for App {
                                         shared.hyperspace.as_ref().map(|x| x.release.name()).unwrap_or("None"),
                                     );
 
+                                    if util::is_offline() {
+                                        ui.add_enabled_ui(false, |ui| combobox.show_ui(ui, |_| {}));
+                                        ui.label(RichText::new(l!("hyperspace-offline")).weak());
+                                        return;
+                                    }
+
                                     let mut clicked = None;
                                     match self.hyperspace_releases.ready() {
                                         Some(Ok(releases)) => {
@@ -824,6 +1608,24 @@ impl eframe::App for App {
                                         }
                                     }
 
+                                    if let (Some(Ok(Ok(installer))), Some(_)) =
+                                        (&self.hyperspace_installer, &shared.hyperspace)
+                                    {
+                                        if let Some(patch) = installer.required_patch() {
+                                            let text = if patch.needs_download() {
+                                                l!("hyperspace-patch-download",
+                                                    "from" => patch.source_version_name(),
+                                                    "to" => patch.target_version_name())
+                                            } else {
+                                                l!("hyperspace-patch",
+                                                    "from" => patch.source_version_name(),
+                                                    "to" => patch.target_version_name())
+                                            };
+                                            ui.label(RichText::new(text).weak())
+                                                .on_hover_text(l!("hyperspace-patch-tooltip"));
+                                        }
+                                    }
+
                                     if self.hyperspace_releases.ready().is_none() {
                                         ui.label(l!("hyperspace-fetching-releases"));
                                         ui.spinner();
@@ -842,7 +1644,7 @@ impl eframe::App for App {
                                     let dnd_response = egui_dnd::dnd(ui, "mod list dnd").show(
                                         shared.mods[row_range.clone()].iter_mut(),
                                         |ui, item, handle, _item_state| {
-                                            ui.horizontal(|ui| {
+                                            reading_row(ui, |ui| {
                                                 handle.ui(ui, |ui| {
                                                     let label = ui.selectable_label(
                                                         item.enabled,
@@ -868,10 +1670,35 @@ impl eframe::App for App {
                                                     if label.clicked() {
                                                         item.enabled = !item.enabled;
                                                     }
+
+                                                    label.context_menu(|ui| {
+                                                        let path = item.source.path();
+                                                        if ui
+                                                            .add_enabled(path.is_some(), egui::Button::new(l!("mod-open-folder")))
+                                                            .clicked()
+                                                        {
+                                                            if let Some(folder) = path.and_then(Path::parent) {
+                                                                if let Err(e) = open::that_detached(folder) {
+                                                                    error!("Failed to open {folder:?}: {e}");
+                                                                }
+                                                            }
+                                                            ui.close_menu();
+                                                        }
+
+                                                        if ui
+                                                            .add_enabled(path.is_some(), egui::Button::new(l!("mod-copy-path")))
+                                                            .clicked()
+                                                        {
+                                                            if let Some(path) = path {
+                                                                ui.ctx().copy_text(path.display().to_string());
+                                                            }
+                                                            ui.close_menu();
+                                                        }
+                                                    });
                                                 });
 
                                                 ui.with_layout(
-                                                    egui::Layout::right_to_left(eframe::emath::Align::Center),
+                                                    reverse_reading_layout(egui::Align::Center),
                                                     |ui| {
                                                         if let Some(title) = item.title().unwrap_or(None) {
                                                             ui.label(ui.fonts(|f| {
@@ -930,15 +1757,31 @@ impl eframe::App for App {
                     if let Some(idx) = self.last_hovered_mod {
                         if let Some(metadata) = shared.mods[idx].metadata().ok().flatten() {
                             ui.vertical(|ui| {
-                                ui.with_layout(egui::Layout::right_to_left(egui::Align::Min), |ui| {
-                                    ui.label(RichText::new(format!("v{}", metadata.version)).heading());
+                                ui.with_layout(reverse_reading_layout(egui::Align::Min), |ui| {
+                                    let version = RichText::new(format!("v{}", metadata.version)).heading();
+                                    match invalid_version_warning("version", &metadata.version) {
+                                        Some(warning) => {
+                                            ui.label(version.color(ui.visuals().warn_fg_color)).on_hover_text(warning);
+                                        }
+                                        None => {
+                                            ui.label(version);
+                                        }
+                                    }
 
                                     ui.style_mut().wrap_mode = Some(egui::TextWrapMode::Wrap);
-                                    ui.with_layout(egui::Layout::left_to_right(egui::Align::Min), |ui| {
+                                    ui.with_layout(reading_layout(egui::Align::Min), |ui| {
                                         ui.label(RichText::new(&metadata.title).heading().strong())
                                     });
                                 });
 
+                                if let Some(preview) = shared.mods[idx].preview(ctx) {
+                                    ui.add(
+                                        egui::Image::new(preview)
+                                            .max_width(ui.available_width())
+                                            .max_height(160.0),
+                                    );
+                                }
+
                                 ui.style_mut().wrap_mode = Some(egui::TextWrapMode::Wrap);
                                 ui.spacing_mut().item_spacing.y = 6.0;
 
@@ -952,6 +1795,45 @@ impl eframe::App for App {
 
                                 key_value(ui, &l!("mod-meta-authors"), &metadata.author);
 
+                                if let Some(update) = &shared.mods[idx].available_update {
+                                    ui.hyperlink_to(
+                                        RichText::new(l!("mod-meta-update-available", "version" => update.version.to_string()))
+                                            .color(ui.visuals().warn_fg_color),
+                                        &update.url,
+                                    );
+                                }
+
+                                if let Some(required) = &metadata.required_ftl_version {
+                                    let response = key_value(ui, &l!("mod-meta-ftl-req"), &required.to_string());
+                                    if let Some(warning) = invalid_version_warning("requiredFtlVersion", required) {
+                                        response.response.on_hover_text(warning);
+                                    }
+                                    if let (SloppyVersion::Semver(required), Some(detected)) = (required, &self.ftl_version) {
+                                        if required != detected {
+                                            ui.label(
+                                                RichText::new(l!("mod-meta-ftl-mismatch", "version" => detected.to_string()))
+                                                    .color(ui.visuals().warn_fg_color),
+                                            );
+                                        }
+                                    }
+                                }
+
+                                if !metadata.dependencies.list.is_empty() {
+                                    key_value(ui, &l!("mod-meta-dependencies"), &metadata.dependencies.list.join(", "));
+                                }
+
+                                for problem in dependency_problems(&shared.mods, idx) {
+                                    let (key, dependency) = match problem {
+                                        DependencyProblem::Missing(name) => ("mod-meta-dependency-missing", name),
+                                        DependencyProblem::Disabled(name) => ("mod-meta-dependency-disabled", name),
+                                        DependencyProblem::OrderedAfter(name) => ("mod-meta-dependency-order", name),
+                                    };
+                                    ui.label(
+                                        RichText::new(l!(key, "dependency" => dependency))
+                                            .color(ui.visuals().warn_fg_color),
+                                    );
+                                }
+
                                 if let Some(hs_metadata) = shared.mods[idx].hs_metadata().ok().flatten() {
                                     if let Some(req_version) = hs_metadata.required_hyperspace.as_ref() {
                                         key_value(ui, &l!("mod-meta-hs-req"), &req_version.to_string());
@@ -972,12 +1854,24 @@ impl eframe::App for App {
                                     ui.hyperlink_to(RichText::new(url.clone()), url);
                                 }
 
+                                render_duplicate_paths(ui, &shared.mods[idx].duplicate_paths);
+                                render_missing_append_bases(ui, &shared.mods[idx].missing_append_bases);
+
+                                if ui.button(l!("mod-meta-view-raw")).clicked() {
+                                    self.metadata_viewer = Some(MetadataViewer::load(&shared.mods[idx]));
+                                }
+
                                 egui::ScrollArea::vertical().show(ui, |ui| {
                                     ui.monospace(&metadata.description);
                                 });
                             });
                         } else {
                             ui.monospace(l!("mod-meta-none"));
+                            render_duplicate_paths(ui, &shared.mods[idx].duplicate_paths);
+                            render_missing_append_bases(ui, &shared.mods[idx].missing_append_bases);
+                            if shared.mods[idx].metadata().is_err() && ui.button(l!("mod-meta-view-raw")).clicked() {
+                                self.metadata_viewer = Some(MetadataViewer::load(&shared.mods[idx]));
+                            }
                         }
                     } else {
                         ui.monospace(l!("mod-meta-hint"));
@@ -988,6 +1882,15 @@ impl eframe::App for App {
             self.error_popups.retain(|popup| popup.render(ui));
         });
 
+        if let Some(viewer) = &self.metadata_viewer {
+            if !viewer.render(ctx) {
+                self.metadata_viewer = None;
+            }
+        }
+
+        self.render_onboarding(ctx);
+        self.render_modified_archive_prompt(ctx);
+
         if self.settings_open {
             egui::Window::new(l!("settings-title"))
                 .collapsible(false)
@@ -1017,12 +1920,21 @@ impl eframe::App for App {
                     filters_changed |= ui
                         .checkbox(&mut self.settings.ftl_is_zip, l!("settings-ftl-is-zip"))
                         .changed();
+                    filters_changed |= ui
+                        .checkbox(&mut self.settings.scan_recursively, l!("settings-scan-recursively"))
+                        .on_hover_text(l!("settings-scan-recursively-tooltip"))
+                        .changed();
+                    if ui
+                        .checkbox(&mut self.settings.follow_symlinks, l!("settings-follow-symlinks"))
+                        .on_hover_text(l!("settings-follow-symlinks-tooltip"))
+                        .changed()
+                    {
+                        set_follow_symlinks(self.settings.follow_symlinks);
+                        filters_changed = true;
+                    }
 
                     if filters_changed {
-                        let settings = self.settings.clone();
-                        let shared = self.shared.clone();
-                        self.current_task =
-                            CurrentTask::Scan(Promise::spawn_thread("task", || scan::scan(settings, shared, false)));
+                        self.current_task = spawn_scan_task(&self.settings, &self.shared, false);
                     }
 
                     ui.horizontal(|ui| {
@@ -1049,15 +1961,37 @@ impl eframe::App for App {
                             self.hyperspace_installer = Some(hyperspace::Installer::create(Path::new(&ftl_dir_buf)));
                             self.settings.ftl_directory = Some(PathBuf::from(ftl_dir_buf));
                         }
+                        self.ftl_directory_problem = None;
                     }
 
                     if ftl_dir_pathedit.lost_focus() {
                         self.settings.fix_ftl_directrory();
+                        self.detect_ftl_version();
+                        self.validate_ftl_directory();
+                    }
+
+                    if let Some(problem) = &self.ftl_directory_problem {
+                        ui.label(RichText::new(problem.to_string()).color(ui.visuals().error_fg_color));
                     }
 
                     ui.checkbox(&mut self.settings.repack_ftl_data, l!("settings-repack-archive"))
                         .on_hover_text(l!("settings-repack-archive-tooltip"));
 
+                    ui.add_enabled(
+                        self.settings.repack_ftl_data,
+                        egui::Checkbox::new(
+                            &mut self.settings.skip_unneeded_repack,
+                            l!("settings-skip-unneeded-repack"),
+                        ),
+                    )
+                    .on_hover_text(l!("settings-skip-unneeded-repack-tooltip"));
+
+                    ui.checkbox(&mut self.settings.keep_xml_comments, l!("settings-keep-xml-comments"))
+                        .on_hover_text(l!("settings-keep-xml-comments-tooltip"));
+
+                    ui.checkbox(&mut self.settings.keep_xml_doctype, l!("settings-keep-xml-doctype"))
+                        .on_hover_text(l!("settings-keep-xml-doctype-tooltip"));
+
                     if ui
                         .checkbox(
                             &mut self.settings.disable_hs_installer,
@@ -1070,6 +2004,318 @@ impl eframe::App for App {
                         ctx.request_repaint();
                     }
 
+                    ui.add(
+                        egui::Slider::new(&mut self.settings.download_connections, 1..=16)
+                            .text(l!("settings-download-connections")),
+                    )
+                    .on_hover_text(l!("settings-download-connections-tooltip"));
+
+                    ui.add(egui::Slider::new(&mut self.settings.max_threads, 0..=64).text(l!("settings-max-threads")))
+                        .on_hover_text(l!("settings-max-threads-tooltip"));
+
+                    ui.label(l!("settings-hyperspace-mirrors"));
+                    let mut mirrors_buf = self.settings.hyperspace_mirrors.join("\n");
+                    if ui
+                        .add(egui::TextEdit::multiline(&mut mirrors_buf).desired_rows(2))
+                        .on_hover_text(l!("settings-hyperspace-mirrors-tooltip"))
+                        .changed()
+                    {
+                        self.settings.hyperspace_mirrors = mirrors_buf.split('\n').map(str::to_owned).collect();
+                    }
+
+                    ui.checkbox(&mut self.settings.auto_apply, l!("settings-auto-apply"))
+                        .on_hover_text(l!("settings-auto-apply-tooltip"));
+
+                    if ui
+                        .add(
+                            egui::Slider::new(&mut self.settings.lua_time_limit, 0..=300)
+                                .text(l!("settings-lua-time-limit"))
+                                .suffix("s"),
+                        )
+                        .on_hover_text(l!("settings-lua-time-limit-tooltip"))
+                        .changed()
+                    {
+                        lua::set_time_limit(self.settings.lua_time_limit());
+                    }
+
+                    if ui
+                        .add(
+                            egui::Slider::new(&mut self.settings.lua_memory_limit, 0..=8192)
+                                .text(l!("settings-lua-memory-limit"))
+                                .suffix(" MiB"),
+                        )
+                        .on_hover_text(l!("settings-lua-memory-limit-tooltip"))
+                        .changed()
+                    {
+                        lua::set_memory_limit(self.settings.lua_memory_limit());
+                    }
+
+                    if ui
+                        .checkbox(&mut self.settings.offline, l!("settings-offline"))
+                        .on_hover_text(l!("settings-offline-tooltip"))
+                        .changed()
+                    {
+                        util::set_offline(self.settings.offline);
+                        self.hyperspace_releases.take();
+                        self.ignore_releases_fetch_error = false;
+                        ctx.request_repaint();
+                    }
+
+                    ui.checkbox(&mut self.settings.check_mod_updates, l!("settings-check-mod-updates"))
+                        .on_hover_text(l!("settings-check-mod-updates-tooltip"));
+
+                    ui.checkbox(&mut self.settings.log_to_file, l!("settings-log-to-file"))
+                        .on_hover_text(l!(
+                            "settings-log-to-file-tooltip",
+                            "path" => logging::default_log_file_path().display().to_string()
+                        ));
+
+                    ui.horizontal(|ui| {
+                        let mut token_buf = self.settings.github_token.clone().unwrap_or_default();
+                        ui.label(l!("settings-github-token"));
+                        if ui
+                            .add(egui::TextEdit::singleline(&mut token_buf).password(true))
+                            .on_hover_text(l!("settings-github-token-tooltip"))
+                            .changed()
+                        {
+                            self.settings.github_token = Some(token_buf).filter(|x| !x.is_empty());
+                            github::set_token(self.settings.github_token.clone());
+                        }
+                    });
+
+                    ui.horizontal(|ui| {
+                        let checking = self.last_ftlman_release.as_ref().is_some_and(|p| p.ready().is_none());
+                        if ui
+                            .add_enabled(!checking, egui::Button::new(l!("settings-check-updates")))
+                            .clicked()
+                        {
+                            self.last_ftlman_release =
+                                Some(Promise::spawn_thread("fetch ftlman releases", fetch_ftlman_update));
+                        }
+
+                        match self.last_ftlman_release.as_ref().map(Promise::ready) {
+                            Some(None) => {
+                                ui.spinner();
+                            }
+                            Some(Some(Ok(None))) => {
+                                ui.label(l!("settings-up-to-date", "version" => VERSION));
+                            }
+                            Some(Some(Ok(Some(release)))) => {
+                                ui.hyperlink_to(
+                                    RichText::new(
+                                        l!("settings-update-available", "version" => release.tag_name.as_str()),
+                                    )
+                                    .color(ui.visuals().warn_fg_color),
+                                    ftlman_repository().releases_url(),
+                                );
+
+                                match self.self_update.as_ref().map(Promise::ready) {
+                                    None if selfupdate::is_portable() => {
+                                        if ui.button(l!("settings-install-update")).clicked() {
+                                            let release = release.clone();
+                                            self.self_update = Some(Promise::spawn_thread("self update", move || {
+                                                selfupdate::install(&release)
+                                            }));
+                                        }
+                                    }
+                                    None => {}
+                                    Some(None) => {
+                                        ui.spinner();
+                                    }
+                                    Some(Some(Ok(()))) => {
+                                        if ui.button(l!("settings-restart-update")).clicked() {
+                                            self.persist();
+                                            if let Err(error) = selfupdate::restart() {
+                                                self.error_popups.push(ErrorPopup::create_and_log(
+                                                    l!("settings-update-failed").into_owned(),
+                                                    &error,
+                                                ));
+                                            }
+                                        }
+                                    }
+                                    Some(Some(Err(error))) => {
+                                        ui.label(
+                                            RichText::new(format!("{error:#}")).color(ui.visuals().error_fg_color),
+                                        );
+                                    }
+                                }
+                            }
+                            Some(Some(Err(error))) => {
+                                ui.label(RichText::new(format!("{error:#}")).color(ui.visuals().error_fg_color));
+                            }
+                            None => {}
+                        }
+                    });
+
+                    ui.horizontal(|ui| {
+                        ui.label(l!("settings-cache", "path" => cache::CACHE.root().display().to_string()));
+                        match self
+                            .cache_size
+                            .get_or_insert_with(|| Promise::spawn_thread("cache size", || cache::CACHE.size()))
+                            .ready()
+                        {
+                            Some(Ok(size)) => {
+                                ui.label(i18n::format_size(*size));
+                            }
+                            Some(Err(error)) => {
+                                ui.label(RichText::new(format!("{error:#}")).color(ui.visuals().error_fg_color));
+                            }
+                            None => {
+                                ui.spinner();
+                            }
+                        }
+                    });
+
+                    ui.horizontal(|ui| {
+                        // Applies and downloads write into the cache, so it can't be touched while they're running.
+                        let can_clear = self.current_task.is_idle() && !self.shared.lock().locked;
+
+                        if let Some(promise) = &self.cache_clear {
+                            match promise.ready() {
+                                Some(result) => {
+                                    if let Err(error) = result {
+                                        self.error_popups.push(ErrorPopup::create_and_log(
+                                            l!("settings-clear-cache-failed").into_owned(),
+                                            error,
+                                        ));
+                                    }
+                                    self.cache_clear = None;
+                                    self.cache_size = None;
+                                }
+                                None => {
+                                    ui.spinner();
+                                }
+                            }
+                        } else if self.confirm_clear_cache {
+                            ui.label(
+                                RichText::new(l!("settings-clear-cache-confirm")).color(ui.visuals().warn_fg_color),
+                            );
+                            if ui
+                                .add_enabled(can_clear, egui::Button::new(l!("settings-clear-cache-yes")))
+                                .clicked()
+                            {
+                                self.cache_clear = Some(Promise::spawn_thread("cache clear", || cache::CACHE.clear()));
+                                self.confirm_clear_cache = false;
+                            }
+                            if ui.button(l!("settings-clear-cache-no")).clicked() {
+                                self.confirm_clear_cache = false;
+                            }
+                        } else {
+                            if ui
+                                .add_enabled(can_clear, egui::Button::new(l!("settings-clear-cache")))
+                                .on_hover_text(l!("settings-clear-cache-tooltip"))
+                                .clicked()
+                            {
+                                self.confirm_clear_cache = true;
+                            }
+                            if ui
+                                .add_enabled(can_clear, egui::Button::new(l!("settings-clear-stale-cache")))
+                                .on_hover_text(l!("settings-clear-stale-cache-tooltip"))
+                                .clicked()
+                            {
+                                self.cache_clear = Some(Promise::spawn_thread("cache clear", || {
+                                    cache::CACHE.clear_stale().map(|freed| {
+                                        log::info!("Removed {freed} bytes of stale cache entries");
+                                    })
+                                }));
+                            }
+                        }
+                    });
+
+                    ui.horizontal(|ui| {
+                        let config_dir = dirs::config_local_dir().map(|dir| dir.join("ftlman"));
+                        let data_dir = dirs::data_local_dir().map(|dir| dir.join("ftlman"));
+                        let settings_dir = self.settings_path.parent().map(Path::to_path_buf);
+                        for (label, dir) in [
+                            (l!("settings-open-config-dir"), config_dir),
+                            (l!("settings-open-data-dir"), data_dir),
+                            (l!("settings-open-settings-dir"), settings_dir),
+                        ] {
+                            let button =
+                                ui.add_enabled(dir.as_ref().is_some_and(|dir| dir.is_dir()), egui::Button::new(label));
+                            let Some(dir) = dir else { continue };
+                            if button.on_hover_text(dir.display().to_string()).clicked() {
+                                if let Err(e) = open::that_detached(&dir) {
+                                    error!("Failed to open {dir:?}: {e}");
+                                }
+                            }
+                        }
+                    });
+
+                    let mut language_changed = false;
+                    egui::ComboBox::from_label(l!("settings-language"))
+                        .selected_text(match self.settings.language.as_deref() {
+                            Some(language) => i18n::language_name(language),
+                            None => l!("settings-language-system"),
+                        })
+                        .show_ui(ui, |ui| {
+                            language_changed |= ui
+                                .selectable_value(&mut self.settings.language, None, l!("settings-language-system"))
+                                .changed();
+                            for language in i18n::available_languages() {
+                                language_changed |= ui
+                                    .selectable_value(
+                                        &mut self.settings.language,
+                                        Some(language.to_owned()),
+                                        i18n::language_name(language),
+                                    )
+                                    .changed();
+                            }
+                        });
+
+                    if language_changed {
+                        i18n::set_language(self.settings.language.as_deref());
+                        ctx.set_fonts(fonts::create_font_definitions(
+                            i18n::current_language(),
+                            self.settings.editor_font.as_deref(),
+                        ));
+                    }
+
+                    #[cfg(target_os = "linux")]
+                    egui::ComboBox::from_label(l!("settings-display-backend"))
+                        .selected_text(self.settings.display_backend.name())
+                        .show_ui(ui, |ui| {
+                            for backend in DisplayBackend::ALL {
+                                ui.selectable_value(&mut self.settings.display_backend, backend, backend.name());
+                            }
+                        })
+                        .response
+                        .on_hover_text(l!("settings-display-backend-tooltip"));
+
+                    ui.label(l!("settings-editor-font"));
+                    let mut editor_font_buf = self
+                        .settings
+                        .editor_font
+                        .as_ref()
+                        .map(|x| x.to_string_lossy().into_owned())
+                        .unwrap_or_default();
+                    let editor_font_pathedit = PathEdit::new(&mut editor_font_buf)
+                        .id("pathedit editor font")
+                        .desired_width(320.)
+                        .show(ui)
+                        .on_hover_text(l!("settings-editor-font-tooltip"));
+                    if editor_font_pathedit.changed() {
+                        self.settings.editor_font =
+                            Some(PathBuf::from(editor_font_buf)).filter(|p| !p.as_os_str().is_empty());
+                    }
+                    if editor_font_pathedit.lost_focus() {
+                        ctx.set_fonts(fonts::create_font_definitions(
+                            i18n::current_language(),
+                            self.settings.editor_font.as_deref(),
+                        ));
+                    }
+
+                    if ui
+                        .add(
+                            egui::Slider::new(&mut self.settings.editor_font_size, 8.0..=32.0)
+                                .step_by(1.0)
+                                .text(l!("settings-editor-font-size")),
+                        )
+                        .changed()
+                    {
+                        gui::set_editor_font_size(self.settings.editor_font_size);
+                    }
+
                     let mut visuals_changed = false;
                     egui::ComboBox::from_label(l!("settings-colorscheme"))
                         .selected_text(format!("{}", &mut self.settings.theme.colors))
@@ -1103,6 +2349,7 @@ impl eframe::App for App {
                                     }
                                 }),
                         )
+                        .on_hover_text(l!("settings-background-opacity-tooltip"))
                         .changed();
 
                     if visuals_changed {
@@ -1115,23 +2362,141 @@ impl eframe::App for App {
     }
 }
 
+/// Formats the enabled mods in order as plain text meant to be pasted in support threads.
+fn format_mod_list(hyperspace: Option<&HyperspaceState>, mods: &[Mod]) -> String {
+    use std::fmt::Write;
+
+    let mut output = String::new();
+    match hyperspace {
+        Some(HyperspaceState { release }) => writeln!(output, "Hyperspace: {}", release.name()),
+        None => writeln!(output, "Hyperspace: none"),
+    }
+    .unwrap();
+
+    for (i, m) in mods.iter().filter(|m| m.enabled).enumerate() {
+        write!(output, "{}. {}", i + 1, m.filename()).unwrap();
+        if let Some(metadata) = m.metadata().ok().flatten() {
+            write!(output, " ({} v{})", metadata.title, metadata.version).unwrap();
+        }
+        output.push('\n');
+    }
+
+    output
+}
+
 impl SharedState {
     fn mod_configuration(&self) -> ModConfigurationState {
         ModConfigurationState {
             hyperspace: self.hyperspace.clone(),
-            order: ModOrder(
-                self.mods
-                    .iter()
-                    .map(|x| ModOrderElement {
-                        filename: x.filename().to_string(),
-                        enabled: x.enabled,
-                    })
-                    .collect(),
-            ),
+            order: ModOrder::from_mods(&self.mods),
+            last_apply: self.last_apply.clone(),
         }
     }
 }
 
+fn render_duplicate_paths(ui: &mut Ui, paths: &[PathBuf]) {
+    if paths.is_empty() {
+        return;
+    }
+
+    ui.label(RichText::new(l!("mod-meta-duplicate-filename")).color(ui.visuals().warn_fg_color));
+    for path in paths {
+        ui.monospace(path.display().to_string());
+    }
+}
+
+fn render_missing_append_bases(ui: &mut Ui, missing: &[String]) {
+    if missing.is_empty() {
+        return;
+    }
+
+    ui.label(RichText::new(l!("mod-meta-missing-bases")).color(ui.visuals().warn_fg_color));
+    for name in missing {
+        ui.monospace(name);
+    }
+}
+
+/// Returns a warning explaining that the metadata `field` isn't a well-formed version, if it isn't.
+fn invalid_version_warning(field: &str, version: &SloppyVersion) -> Option<String> {
+    match version {
+        SloppyVersion::Semver(_) => None,
+        SloppyVersion::Invalid(value) => {
+            Some(l!("mod-meta-invalid-version", "field" => field, "value" => value.as_str()).into_owned())
+        }
+    }
+}
+
+/// Read-only view of a mod's raw mod-appendix/metadata.xml along with any problems found while parsing it.
+struct MetadataViewer {
+    filename: String,
+    content: Result<String, String>,
+    problems: Vec<String>,
+}
+
+impl MetadataViewer {
+    fn load(m: &Mod) -> Self {
+        let content = m
+            .read_raw_metadata()
+            .map_err(|error| format!("{error:#}"))
+            .and_then(|content| content.ok_or_else(|| l!("mod-meta-none").into_owned()));
+
+        let mut problems = Vec::new();
+        match m.metadata() {
+            Ok(Some(metadata)) => {
+                let versions = [
+                    ("version", Some(&metadata.version)),
+                    ("requiredFtlVersion", metadata.required_ftl_version.as_ref()),
+                ];
+                for (field, version) in versions {
+                    if let Some(warning) = version.and_then(|version| invalid_version_warning(field, version)) {
+                        problems.push(warning);
+                    }
+                }
+            }
+            Ok(None) => {}
+            Err(error) => problems.push(format!("{error:#}")),
+        }
+
+        Self {
+            filename: m.filename().to_owned(),
+            content,
+            problems,
+        }
+    }
+
+    /// Returns whether the viewer is still open.
+    fn render(&self, ctx: &egui::Context) -> bool {
+        let mut open = true;
+        egui::Window::new(l!("mod-meta-raw-title", "mod" => self.filename.as_str()))
+            .id(egui::Id::new("metadata viewer"))
+            .open(&mut open)
+            .default_size([480., 360.])
+            .show(ctx, |ui| {
+                for problem in &self.problems {
+                    ui.label(RichText::new(problem).color(ui.visuals().warn_fg_color));
+                }
+
+                match &self.content {
+                    Ok(content) => {
+                        let layouter = gui::code_layouter();
+                        egui::ScrollArea::both().show(ui, |ui| {
+                            ui.add(
+                                egui::TextEdit::multiline(&mut content.as_str())
+                                    .code_editor()
+                                    .desired_width(f32::INFINITY)
+                                    .layouter(&mut |ui, text, width| layouter(ui, text, width, "xml")),
+                            );
+                        });
+                    }
+                    Err(error) => {
+                        ui.label(RichText::new(error).color(ui.visuals().error_fg_color));
+                    }
+                }
+            });
+        open
+    }
+}
+
 #[derive(Clone)]
 struct Mod {
     source: ModSource,
@@ -1143,6 +2508,37 @@ struct Mod {
     cached_metadata: OnceCell<Option<Metadata>>,
     /// Additional metadata for Hyperspace mods
     cached_hs_metadata: OnceCell<Option<HsMetadata>>,
+    /// Texture loaded from mod-appendix/preview.png
+    cached_preview: OnceCell<Option<egui::TextureHandle>>,
+    /// Append files whose base file doesn't exist, filled in at the end of a scan
+    missing_append_bases: Vec<String>,
+    /// Newer release found by the update check, if enabled
+    available_update: Option<ModUpdate>,
+    /// Paths of other mods with the same filename, see [`scan::mark_duplicate_filenames`]
+    duplicate_paths: Vec<PathBuf>,
+}
+
+fn ftlman_repository() -> github::Repository {
+    github::Repository::new("afishhh", "ftlman")
+}
+
+/// Returns the newest ftlman release if it is newer than the running version.
+fn fetch_ftlman_update() -> Result<Option<github::Release>> {
+    let current = semver::Version::parse(VERSION).unwrap();
+    Ok(ftlman_repository()
+        .releases()
+        .context("Failed to fetch ftlman releases")?
+        .into_iter()
+        .filter_map(|release| Some((release.find_semver_in_metadata()?, release)))
+        .filter(|(version, _)| *version > current)
+        .max_by(|(a, _), (b, _)| a.cmp(b))
+        .map(|(_, release)| release))
+}
+
+#[derive(Clone)]
+struct ModUpdate {
+    version: semver::Version,
+    url: String,
 }
 
 impl DragDropItem for &mut Mod {
@@ -1173,9 +2569,59 @@ struct HyperspaceState {
 struct ModConfigurationState {
     hyperspace: Option<HyperspaceState>,
     order: ModOrder,
+    #[serde(default)]
+    last_apply: Option<LastApply>,
+}
+
+/// When mods were last applied successfully and how many were enabled.
+#[derive(Clone, Serialize, Deserialize)]
+struct LastApply {
+    /// Seconds since the Unix epoch
+    timestamp: u64,
+    mod_count: usize,
+}
+
+impl LastApply {
+    fn now(mod_count: usize) -> Self {
+        Self {
+            timestamp: SystemTime::now()
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .map_or(0, |d| d.as_secs()),
+            mod_count,
+        }
+    }
+
+    fn elapsed(&self) -> Duration {
+        SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH + Duration::from_secs(self.timestamp))
+            .unwrap_or_default()
+    }
+}
+
+/// Formats a duration coarsely for displaying how long ago something happened.
+fn format_elapsed(elapsed: Duration) -> String {
+    let minutes = elapsed.as_secs() / 60;
+    match minutes {
+        0 => l!("duration-under-minute"),
+        1..60 => l!("duration-minutes", "value" => minutes),
+        60..1440 => l!("duration-hours", "value" => minutes / 60),
+        _ => l!("duration-days", "value" => minutes / 1440),
+    }
+    .into_owned()
 }
 
 impl ModOrder {
+    fn from_mods(mods: &[Mod]) -> Self {
+        Self(
+            mods.iter()
+                .map(|x| ModOrderElement {
+                    filename: x.filename().to_string(),
+                    enabled: x.enabled,
+                })
+                .collect(),
+        )
+    }
+
     fn into_order_map(self) -> HashMap<String, (usize, bool)> {
         self.0
             .into_iter()
@@ -1185,6 +2631,13 @@ impl ModOrder {
     }
 }
 
+static FOLLOW_SYMLINKS: AtomicBool = AtomicBool::new(false);
+
+/// Sets whether [`OpenModHandle::paths`] follows symbolic links inside directory mods.
+pub fn set_follow_symlinks(value: bool) {
+    FOLLOW_SYMLINKS.store(value, std::sync::atomic::Ordering::Relaxed);
+}
+
 #[derive(Clone)]
 pub enum ModSource {
     Directory { path: PathBuf },
@@ -1202,9 +2655,41 @@ pub enum OpenModHandle<'a> {
     },
     Zip {
         archive: ZipArchive<Box<dyn ReadSeek + Send + Sync + 'a>>,
+        /// Prefix of all paths inside the archive that belong to the mod, see [`find_mod_root`].
+        root: String,
     },
 }
 
+/// Some mods are distributed with all of their files nested inside a subdirectory,
+/// this finds the shortest prefix that contains both `mod-appendix/metadata.xml` and `data/`.
+///
+/// Returns an empty string if the mod's files are at the root or no such prefix exists,
+/// otherwise the returned prefix will end with a slash.
+fn find_mod_root<'a>(names: impl IntoIterator<Item = &'a str>) -> String {
+    const METADATA_PATH: &str = "mod-appendix/metadata.xml";
+
+    let names = names.into_iter().collect::<Vec<_>>();
+    if names
+        .iter()
+        .any(|name| name.starts_with("data/") || name.starts_with("mod-appendix/"))
+    {
+        return String::new();
+    }
+
+    names
+        .iter()
+        .filter_map(|name| name.strip_suffix(METADATA_PATH))
+        .filter(|prefix| prefix.ends_with('/'))
+        .filter(|prefix| {
+            names
+                .iter()
+                .any(|name| name.strip_prefix(prefix).is_some_and(|rest| rest.starts_with("data/")))
+        })
+        .min_by_key(|prefix| prefix.len())
+        .unwrap_or_default()
+        .to_owned()
+}
+
 impl ModSource {
     pub fn filename(&self) -> &str {
         match self {
@@ -1217,6 +2702,14 @@ impl ModSource {
         }
     }
 
+    /// Path to the file or directory this mod was loaded from, `None` for in-memory mods.
+    pub fn path(&self) -> Option<&Path> {
+        match self {
+            ModSource::Directory { path } | ModSource::Zip { path } => Some(path),
+            ModSource::InMemoryZip { .. } => None,
+        }
+    }
+
     pub fn new(settings: &Settings, path: PathBuf) -> Option<Self> {
         if path.is_dir() {
             if settings.dirs_are_mods {
@@ -1236,17 +2729,36 @@ impl ModSource {
     }
 
     pub fn open(&self) -> Result<OpenModHandle<'_>> {
-        Ok(match self {
-            Self::Directory { path } => OpenModHandle::Directory { path: path.clone() },
-            Self::Zip { path } => OpenModHandle::Zip {
-                archive: zip::ZipArchive::new(Box::new(std::fs::File::open(path)?) as Box<dyn ReadSeek + Send + Sync>)?,
-            },
-            Self::InMemoryZip { data, .. } => OpenModHandle::Zip {
-                archive: zip::ZipArchive::new(
-                    Box::new(Cursor::new(data.as_slice())) as Box<dyn ReadSeek + Send + Sync>
-                )?,
-            },
-        })
+        fn open_zip<'a>(reader: Box<dyn ReadSeek + Send + Sync + 'a>) -> Result<OpenModHandle<'a>> {
+            let archive = zip::ZipArchive::new(reader)?;
+            let root = find_mod_root(archive.file_names());
+            Ok(OpenModHandle::Zip { archive, root })
+        }
+
+        match self {
+            Self::Directory { path } => {
+                let mut names = vec![];
+                if !path.join("data").is_dir() && !path.join("mod-appendix").is_dir() {
+                    for entry in std::fs::read_dir(path)? {
+                        let entry = entry?;
+                        if let Some(name) = entry.file_name().to_str().filter(|_| entry.path().is_dir()) {
+                            if entry.path().join("data").is_dir() {
+                                names.push(format!("{name}/data/"));
+                            }
+                            if entry.path().join("mod-appendix/metadata.xml").is_file() {
+                                names.push(format!("{name}/mod-appendix/metadata.xml"));
+                            }
+                        }
+                    }
+                }
+
+                Ok(OpenModHandle::Directory {
+                    path: path.join(find_mod_root(names.iter().map(String::as_str))),
+                })
+            }
+            Self::Zip { path } => open_zip(Box::new(std::fs::File::open(path)?)),
+            Self::InMemoryZip { data, .. } => open_zip(Box::new(Cursor::new(data.as_slice()))),
+        }
     }
 }
 
@@ -1254,7 +2766,7 @@ impl OpenModHandle<'_> {
     pub fn open(&mut self, name: &str) -> Result<Box<dyn Read + '_>> {
         Ok(match self {
             OpenModHandle::Directory { path } => Box::new(std::fs::File::open(path.join(name))?),
-            OpenModHandle::Zip { archive } => Box::new(archive.by_name(name)?),
+            OpenModHandle::Zip { archive, root } => Box::new(archive.by_name(&format!("{root}{name}"))?),
         })
     }
 
@@ -1265,7 +2777,7 @@ impl OpenModHandle<'_> {
                 Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
                 Err(e) => return Err(e.into()),
             }),
-            OpenModHandle::Zip { archive } => Box::new(match archive.by_name(name) {
+            OpenModHandle::Zip { archive, root } => Box::new(match archive.by_name(&format!("{root}{name}")) {
                 Ok(handle) => handle,
                 Err(zip::result::ZipError::FileNotFound) => return Ok(None),
                 Err(e) => return Err(e.into()),
@@ -1278,8 +2790,16 @@ impl OpenModHandle<'_> {
             Self::Directory { path } => {
                 let mut out = Vec::new();
 
-                for result in WalkDir::new(&path).into_iter() {
-                    let entry = result?;
+                let follow_links = FOLLOW_SYMLINKS.load(std::sync::atomic::Ordering::Relaxed);
+                for result in WalkDir::new(&path).follow_links(follow_links).into_iter() {
+                    let entry = match result {
+                        Ok(entry) => entry,
+                        Err(error) if error.loop_ancestor().is_some() => {
+                            warn!("Skipping symbolic link loop: {error}");
+                            continue;
+                        }
+                        Err(error) => return Err(error.into()),
+                    };
 
                     if entry.file_type().is_file() {
                         let components = entry.path().strip_prefix(&path).unwrap().components();
@@ -1301,20 +2821,14 @@ impl OpenModHandle<'_> {
 
                 Ok(out)
             }
-            Self::Zip { archive } => {
+            Self::Zip { archive, root } => {
                 let mut out = Vec::new();
 
                 for name in archive.file_names().map(|s| s.to_string()).collect::<Vec<String>>() {
-                    if !name.ends_with('/') {
-                        out.push(
-                            archive
-                                .by_name(&name)?
-                                .enclosed_name()
-                                .unwrap()
-                                .to_str()
-                                .unwrap()
-                                .to_string(),
-                        );
+                    if !name.ends_with('/') && name.starts_with(root.as_str()) {
+                        let enclosed = archive.by_name(&name)?.enclosed_name().unwrap();
+                        let enclosed = enclosed.to_str().unwrap();
+                        out.push(enclosed.strip_prefix(root.as_str()).unwrap_or(enclosed).to_string());
                     }
                 }
 
@@ -1337,6 +2851,11 @@ impl Mod {
         Ok(self.title()?.unwrap_or_else(|| self.filename()))
     }
 
+    /// Whether `name` from another mod's dependency list refers to this mod, either by filename or by id.
+    fn is_referred_to_by(&self, name: &str) -> bool {
+        self.filename() == name || self.metadata().ok().flatten().and_then(|m| m.id.as_deref()) == Some(name)
+    }
+
     fn new(source: ModSource) -> Mod {
         Self::new_with_enabled(source, false)
     }
@@ -1348,6 +2867,10 @@ impl Mod {
             is_hyperspace_ftl: false,
             cached_metadata: Default::default(),
             cached_hs_metadata: Default::default(),
+            cached_preview: Default::default(),
+            missing_append_bases: Vec::new(),
+            available_update: None,
+            duplicate_paths: Vec::new(),
         }
     }
 
@@ -1373,6 +2896,9 @@ impl Mod {
                         SloppyVersion::Invalid(s) => SloppyVersion::Invalid(s.trim().to_string()),
                     };
                     metadata.description = metadata.description.trim().to_string();
+                    if let Some(SloppyVersion::Invalid(s)) = metadata.required_ftl_version.as_mut() {
+                        *s = s.trim().to_string();
+                    }
 
                     metadata
                 }))
@@ -1380,6 +2906,61 @@ impl Mod {
             .map(Option::as_ref)
     }
 
+    fn read_raw_metadata(&self) -> Result<Option<String>> {
+        let mut handle = self.source.open()?;
+        let Some(mut reader) = handle.open_if_exists("mod-appendix/metadata.xml")? else {
+            return Ok(None);
+        };
+        let mut content = String::new();
+        reader
+            .read_to_string(&mut content)
+            .context("Failed to read mod-appendix/metadata.xml")?;
+        Ok(Some(content))
+    }
+
+    fn preview(&self, ctx: &egui::Context) -> Option<&egui::TextureHandle> {
+        self.cached_preview
+            .get_or_init(|| {
+                let mut handle = self.source.open().ok()?;
+                let mut data = Vec::new();
+                handle
+                    .open_if_exists("mod-appendix/preview.png")
+                    .ok()??
+                    .read_to_end(&mut data)
+                    .ok()?;
+
+                let image = match image::load_from_memory_with_format(&data, image::ImageFormat::Png) {
+                    Ok(image) => image.to_rgba8(),
+                    Err(error) => {
+                        warn!("Failed to decode preview image of {}: {error}", self.filename());
+                        return None;
+                    }
+                };
+
+                Some(ctx.load_texture(
+                    format!("mod preview {}", self.filename()),
+                    egui::ColorImage::from_rgba_unmultiplied(
+                        [image.width() as usize, image.height() as usize],
+                        image.as_flat_samples().as_slice(),
+                    ),
+                    egui::TextureOptions::LINEAR,
+                ))
+            })
+            .as_ref()
+    }
+
+    /// Priority from mod-appendix/priority.txt, mods with a higher priority are applied later.
+    fn apply_priority(&self) -> Result<i32> {
+        let Some(handle) = self.source.open()?.open_if_exists("mod-appendix/priority.txt")? else {
+            return Ok(0);
+        };
+
+        let text = std::io::read_to_string(handle)?;
+        text.trim()
+            .parse()
+            .with_context(|| format!("Invalid apply priority {:?} in {}", text.trim(), self.filename()))
+    }
+
     fn hs_metadata(&self) -> Result<Option<&HsMetadata>> {
         self.cached_hs_metadata
             .get_or_try_init(|| {
@@ -1445,6 +3026,65 @@ struct Metadata {
     author: String,
     version: SloppyVersion,
     description: String,
+    #[serde(rename = "requiredFtlVersion", default)]
+    required_ftl_version: Option<SloppyVersion>,
+    /// Identifier other mods can use to refer to this one in their dependencies
+    #[serde(default)]
+    id: Option<String>,
+    #[serde(default)]
+    dependencies: Dependencies,
+}
+
+#[derive(Clone, Default, Deserialize)]
+struct Dependencies {
+    /// Filenames or ids of mods that must be enabled and placed above this one
+    #[serde(rename = "dependency", default)]
+    list: Vec<String>,
+}
+
+enum DependencyProblem<'a> {
+    Missing(&'a str),
+    Disabled(&'a str),
+    OrderedAfter(&'a str),
+}
+
+impl std::fmt::Display for DependencyProblem<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DependencyProblem::Missing(name) => write!(f, "requires {name} which is not installed"),
+            DependencyProblem::Disabled(name) => write!(f, "requires {name} which is not enabled"),
+            DependencyProblem::OrderedAfter(name) => write!(f, "requires {name} which is ordered after it"),
+        }
+    }
+}
+
+/// Checks the declared dependencies of `mods[idx]` against the rest of the mod list.
+///
+/// This is purely advisory, nothing prevents mods with unsatisfied dependencies from being applied.
+fn dependency_problems(mods: &[Mod], idx: usize) -> Vec<DependencyProblem<'_>> {
+    let Some(metadata) = mods[idx].metadata().ok().flatten() else {
+        return Vec::new();
+    };
+
+    metadata
+        .dependencies
+        .list
+        .iter()
+        .filter_map(|dependency| {
+            let Some((dependency_idx, m)) = mods.iter().enumerate().find(|(_, m)| m.is_referred_to_by(dependency))
+            else {
+                return Some(DependencyProblem::Missing(dependency));
+            };
+
+            if !m.enabled {
+                Some(DependencyProblem::Disabled(dependency))
+            } else if dependency_idx > idx {
+                Some(DependencyProblem::OrderedAfter(dependency))
+            } else {
+                None
+            }
+        })
+        .collect()
 }
 
 #[derive(Clone)]
@@ -1452,3 +3092,189 @@ struct HsMetadata {
     required_hyperspace: Option<semver::VersionReq>,
     overwrites_hyperspace_xml: bool,
 }
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use super::*;
+
+    fn make_zip(files: &[(&str, &str)]) -> Vec<u8> {
+        let mut writer = zip::ZipWriter::new(Cursor::new(Vec::new()));
+        for (name, content) in files {
+            writer
+                .start_file(*name, zip::write::SimpleFileOptions::default())
+                .unwrap();
+            writer.write_all(content.as_bytes()).unwrap();
+        }
+        writer.finish().unwrap().into_inner()
+    }
+
+    #[test]
+    fn nested_mod_root() {
+        let m = Mod::new(ModSource::InMemoryZip {
+            filename: "nested.zip".to_string(),
+            data: make_zip(&[
+                ("readme.txt", "Unrelated file"),
+                (
+                    "Nested Mod/mod-appendix/metadata.xml",
+                    "<metadata><title>Nested</title><author>Someone</author>\
+                     <version>1.0</version><description>A nested mod</description></metadata>",
+                ),
+                ("Nested Mod/data/blueprints.xml.append", "<ftl></ftl>"),
+            ]),
+        });
+
+        assert_eq!(m.title().unwrap(), Some("Nested"));
+
+        let mut handle = m.source.open().unwrap();
+        let mut paths = handle.paths().unwrap();
+        paths.sort();
+        assert_eq!(paths, ["data/blueprints.xml.append", "mod-appendix/metadata.xml"]);
+        assert_eq!(
+            std::io::read_to_string(handle.open("data/blueprints.xml.append").unwrap()).unwrap(),
+            "<ftl></ftl>"
+        );
+    }
+
+    fn make_mod(filename: &str, metadata: &str, enabled: bool) -> Mod {
+        Mod::new_with_enabled(
+            ModSource::InMemoryZip {
+                filename: filename.to_string(),
+                data: make_zip(&[(
+                    "mod-appendix/metadata.xml",
+                    &format!(
+                        "<metadata><title>{filename}</title><author>Someone</author>\
+                         <version>1.0</version><description>Test</description>{metadata}</metadata>"
+                    ),
+                )]),
+            },
+            enabled,
+        )
+    }
+
+    #[test]
+    fn dependency_problems_are_detected() {
+        let mods = [
+            make_mod("base.zip", "<id>base</id>", true),
+            make_mod(
+                "dependent.zip",
+                "<dependencies><dependency>base</dependency><dependency>later.zip</dependency>\
+                 <dependency>disabled.zip</dependency><dependency>missing.zip</dependency></dependencies>",
+                true,
+            ),
+            make_mod("later.zip", "", true),
+            make_mod("disabled.zip", "", false),
+        ];
+
+        let problems = dependency_problems(&mods, 1)
+            .into_iter()
+            .map(|p| p.to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            problems,
+            [
+                "requires later.zip which is ordered after it",
+                "requires disabled.zip which is not enabled",
+                "requires missing.zip which is not installed"
+            ]
+        );
+        assert!(dependency_problems(&mods, 0).is_empty());
+    }
+
+    #[test]
+    fn mod_list_contains_enabled_mods_in_order() {
+        let mods = [
+            make_mod("first.zip", "", true),
+            make_mod("disabled.zip", "", false),
+            Mod::new_with_enabled(
+                ModSource::InMemoryZip {
+                    filename: "no-metadata.zip".to_string(),
+                    data: make_zip(&[("data/blueprints.xml.append", "")]),
+                },
+                true,
+            ),
+        ];
+
+        assert_eq!(
+            format_mod_list(None, &mods),
+            "Hyperspace: none\n1. first.zip (first.zip v1.0.0)\n2. no-metadata.zip\n"
+        );
+    }
+
+    #[test]
+    fn apply_order_puts_hyperspace_first_and_sorts_by_priority() {
+        let with_priority = |filename: &str, priority: &str| {
+            Mod::new_with_enabled(
+                ModSource::InMemoryZip {
+                    filename: filename.to_string(),
+                    data: make_zip(&[("mod-appendix/priority.txt", priority)]),
+                },
+                true,
+            )
+        };
+        let mods = vec![
+            with_priority("late.zip", "10"),
+            with_priority("a.zip", "0"),
+            Mod {
+                is_hyperspace_ftl: true,
+                ..with_priority("hyperspace.ftl", "100")
+            },
+            with_priority("early.zip", " -5\n"),
+            make_mod("b.zip", "", true),
+        ];
+
+        let order = apply::apply_order(mods).unwrap();
+        assert_eq!(
+            order.iter().map(|m| m.filename()).collect::<Vec<_>>(),
+            ["hyperspace.ftl", "early.zip", "a.zip", "b.zip", "late.zip"]
+        );
+    }
+
+    #[test]
+    fn malformed_settings_are_an_error() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("settings.json");
+        assert!(Settings::load(&path).unwrap().is_none());
+
+        std::fs::write(&path, r#"{"mod_directory": "/mods", "ftl_dir"#).unwrap();
+        assert!(Settings::load(&path).is_err());
+        assert_eq!(
+            Settings::load_or_default(&path).mod_directory,
+            Settings::default().mod_directory
+        );
+
+        let backup_path = util::backup_bad_file(&path).unwrap();
+        assert_eq!(backup_path, dir.path().join("settings.json.bad"));
+        assert!(!path.exists() && backup_path.exists());
+    }
+
+    #[test]
+    fn settings_save_replaces_existing_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("settings.json");
+        std::fs::write(&path, "previous contents that are longer than the new ones will be").unwrap();
+
+        let settings = Settings {
+            auto_apply: true,
+            ..Default::default()
+        };
+        settings.save(&path).unwrap();
+
+        assert!(Settings::load(&path).unwrap().unwrap().auto_apply);
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn mod_root_prefers_top_level() {
+        assert_eq!(
+            find_mod_root(["data/a.xml", "other/data/b.xml", "other/mod-appendix/metadata.xml"]),
+            ""
+        );
+        assert_eq!(
+            find_mod_root(["a/b/data/x.xml", "a/b/mod-appendix/metadata.xml"]),
+            "a/b/"
+        );
+        assert_eq!(find_mod_root(["a/mod-appendix/metadata.xml", "b/data/x.xml"]), "");
+    }
+}
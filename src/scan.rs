@@ -1,9 +1,27 @@
-use std::{collections::HashMap, sync::Arc};
+use std::{
+    collections::{HashMap, HashSet},
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::SystemTime,
+};
 
 use anyhow::{Context, Result};
+use log::{debug, info, warn};
 use parking_lot::Mutex;
 
-use crate::{Mod, ModConfigurationState, ModSource, Settings, SharedState, MOD_ORDER_FILENAME};
+use crate::{
+    apply::{self, BACKUP_FILENAME},
+    github,
+    util::{self, SloppyVersion},
+    Mod, ModConfigurationState, ModSource, ModUpdate, Settings, SharedState, MOD_ORDER_FILENAME,
+};
+
+/// Name of the file Slipstream stores its mod order in.
+pub const SLIPSTREAM_ORDER_FILENAME: &str = "modorder.txt";
+
+/// The path list of the last archive read by [`archive_paths`] along with its modification time.
+#[allow(clippy::type_complexity)]
+static ARCHIVE_PATHS: Mutex<Option<(PathBuf, SystemTime, Arc<HashSet<String>>)>> = parking_lot::const_mutex(None);
 
 pub fn scan(settings: Settings, state: Arc<Mutex<SharedState>>, first: bool) -> Result<()> {
     let mut lock = state.lock();
@@ -21,39 +39,501 @@ pub fn scan(settings: Settings, state: Arc<Mutex<SharedState>>, first: bool) ->
     lock.ctx.request_repaint();
     drop(lock);
 
-    let mod_config_state = match std::fs::File::open(settings.mod_directory.join(MOD_ORDER_FILENAME)) {
-        Ok(f) => serde_json::from_reader(std::io::BufReader::new(f))
-            .with_context(|| format!("Failed to deserialize mod order from {MOD_ORDER_FILENAME}"))?,
-        Err(e) if e.kind() == std::io::ErrorKind::NotFound => ModConfigurationState::default(),
-        Err(e) => return Err(e).context("Failed to open mod order file"),
-    };
+    let mod_config_state = read_mod_configuration_or_backup(&settings.mod_directory)?;
     if first {
-        state.lock().hyperspace = mod_config_state.hyperspace;
+        let mut lock = state.lock();
+        lock.hyperspace = mod_config_state.hyperspace;
+        lock.last_apply = mod_config_state.last_apply;
     }
     let mod_order_map = mod_config_state.order.into_order_map();
 
-    for result in std::fs::read_dir(&settings.mod_directory).context("Failed to open mod directory")? {
-        let entry = result.context("Failed to read entry from mod directory")?;
+    let paths = find_mod_paths(&settings)?;
+    let total = paths.len();
+    state.lock().scan_progress = Some((0, total));
 
-        if let Some(mut m) = ModSource::new(&settings, entry.path()).map(Mod::new) {
-            let filename = m.filename();
-            m.enabled = old
-                .get(filename)
-                .map_or(mod_order_map.get(filename).map(|x| x.1).unwrap_or(false), |o| o.enabled);
+    for (i, path) in paths.into_iter().enumerate() {
+        let m = load_mod(&settings, path, &mod_order_map, |filename| {
+            old.get(filename).map(|o| o.enabled)
+        });
 
-            let mut lock = state.lock();
+        let mut lock = state.lock();
+        if let Some(m) = m {
             lock.mods.push(m);
-            lock.mods
-                .sort_by_cached_key(|m| mod_order_map.get(m.filename()).map(|x| x.0).unwrap_or(usize::MAX));
-            lock.ctx.request_repaint();
+            sort_by_mod_order(&mut lock.mods, &mod_order_map);
         }
+        lock.scan_progress = Some((i + 1, total));
+        lock.ctx.request_repaint();
     }
 
+    mark_duplicate_filenames(&mut state.lock().mods);
+
     {
         let mut lock = state.lock();
         lock.locked = false;
+        lock.scan_progress = None;
         lock.ctx.request_repaint();
     }
 
+    // The mod list is already usable at this point, these only fill in details so they're done on a detached
+    // thread that doesn't hold up the scan task. Both match mods by filename so a rescan in the meantime is fine.
+    {
+        let state = state.clone();
+        let settings = settings.clone();
+        std::thread::spawn(move || {
+            prewarm_metadata(&state, settings.max_threads());
+
+            if let Some(ftl_directory) = settings.ftl_directory.as_deref() {
+                match archive_paths(ftl_directory) {
+                    Ok(known_paths) => check_append_bases(&state, &known_paths),
+                    Err(error) => warn!("Failed to read archive file list, not checking append files: {error:#}"),
+                }
+            }
+        });
+    }
+
+    // This happens after unlocking since it may have to wait for the network.
+    if settings.check_mod_updates {
+        check_updates(&state);
+    }
+
     Ok(())
 }
+
+/// Looks for newer releases of mods whose thread url points to a GitHub repository.
+///
+/// This is best-effort, mods that can't be checked for any reason are skipped.
+fn check_updates(state: &Mutex<SharedState>) {
+    let candidates = state
+        .lock()
+        .mods
+        .iter()
+        .filter_map(|m| {
+            let metadata = m.metadata().ok().flatten()?;
+            let SloppyVersion::Semver(current) = &metadata.version else {
+                return None;
+            };
+            let repository = github::Repository::from_url(metadata.thread_url.as_deref()?)?;
+            Some((m.filename().to_string(), current.clone(), repository))
+        })
+        .collect::<Vec<_>>();
+
+    for (filename, current, repository) in candidates {
+        let latest = match repository.releases() {
+            Ok(releases) => releases
+                .iter()
+                .filter_map(github::Release::find_semver_in_metadata)
+                .max(),
+            Err(error) => {
+                debug!("Could not check {filename} for updates: {error:#}");
+                continue;
+            }
+        };
+
+        if let Some(latest) = latest.filter(|latest| *latest > current) {
+            info!("Update available for {filename}: {current} -> {latest}");
+            let mut lock = state.lock();
+            if let Some(m) = lock.mods.iter_mut().find(|m| m.filename() == filename) {
+                m.available_update = Some(ModUpdate {
+                    version: latest,
+                    url: repository.releases_url(),
+                });
+            }
+            lock.ctx.request_repaint();
+        }
+    }
+}
+
+/// Fills in [`Mod::duplicate_paths`] for mods that share a filename with another mod.
+///
+/// The mod order is keyed by filename so such mods can't be told apart when it is saved or loaded.
+pub fn mark_duplicate_filenames(mods: &mut [Mod]) {
+    let paths = mods
+        .iter()
+        .map(|m| m.source.path().map_or_else(|| m.filename().into(), Path::to_path_buf))
+        .collect::<Vec<_>>();
+    let mut indices_by_filename: HashMap<String, Vec<usize>> = HashMap::new();
+    for (i, m) in mods.iter().enumerate() {
+        indices_by_filename.entry(m.filename().to_string()).or_default().push(i);
+    }
+
+    for (filename, indices) in indices_by_filename.iter().filter(|(_, indices)| indices.len() > 1) {
+        warn!(
+            "Multiple mods are named {filename}, their order can't be saved correctly: {}",
+            indices
+                .iter()
+                .map(|&i| paths[i].display().to_string())
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
+    }
+
+    for (i, m) in mods.iter_mut().enumerate() {
+        m.duplicate_paths = indices_by_filename[m.filename()]
+            .iter()
+            .filter(|&&j| j != i)
+            .map(|&j| paths[j].clone())
+            .collect();
+    }
+}
+
+/// Loads the metadata of all scanned mods on a pool of threads so the info panel doesn't have to do it lazily.
+///
+/// This works on copies of the mods and only fills in cells that are still empty, so any metadata that was
+/// loaded by the UI in the meantime is left alone.
+fn prewarm_metadata(state: &Mutex<SharedState>, max_threads: usize) {
+    let queue = Mutex::new(
+        state
+            .lock()
+            .mods
+            .iter()
+            .filter(|m| m.cached_metadata.get().is_none() || m.cached_hs_metadata.get().is_none())
+            .cloned()
+            .collect::<Vec<Mod>>(),
+    );
+    let warmed = Mutex::new(Vec::new());
+    let threads = max_threads.min(queue.lock().len());
+
+    std::thread::scope(|scope| {
+        for _ in 0..threads {
+            scope.spawn(|| loop {
+                let Some(m) = queue.lock().pop() else {
+                    break;
+                };
+
+                // Errors are not cached, they'll be reported when the mod is hovered instead.
+                _ = m.metadata();
+                _ = m.hs_metadata();
+                warmed.lock().push(m);
+            });
+        }
+    });
+
+    let mut lock = state.lock();
+    for warm in warmed.into_inner() {
+        let Some(m) = lock.mods.iter_mut().find(|m| m.filename() == warm.filename()) else {
+            continue;
+        };
+
+        if let Some(metadata) = warm.cached_metadata.into_inner() {
+            _ = m.cached_metadata.set(metadata);
+        }
+        if let Some(hs_metadata) = warm.cached_hs_metadata.into_inner() {
+            _ = m.cached_hs_metadata.set(hs_metadata);
+        }
+    }
+    lock.ctx.request_repaint();
+}
+
+/// Reads the list of files in the unpatched archive, the result is cached until the archive changes.
+fn archive_paths(ftl_directory: &Path) -> Result<Arc<HashSet<String>>> {
+    let path = [BACKUP_FILENAME, "ftl.dat"]
+        .into_iter()
+        .map(|name| ftl_directory.join(name))
+        .find(|path| path.exists())
+        .context("ftl.dat not found")?;
+    let modified = std::fs::metadata(&path)
+        .and_then(|m| m.modified())
+        .with_context(|| format!("Failed to stat {}", path.display()))?;
+
+    let mut cache = ARCHIVE_PATHS.lock();
+    if let Some((cached_path, cached_modified, paths)) = cache.as_ref() {
+        if *cached_path == path && *cached_modified == modified {
+            return Ok(paths.clone());
+        }
+    }
+
+    let file = std::fs::File::open(&path).with_context(|| format!("Failed to open {}", path.display()))?;
+    let pkg = silpkg::sync::Pkg::parse(file).with_context(|| format!("Failed to parse {}", path.display()))?;
+    let paths = Arc::new(pkg.paths().cloned().collect::<HashSet<String>>());
+    *cache = Some((path, modified, paths.clone()));
+
+    Ok(paths)
+}
+
+/// Records append files whose base file doesn't exist in [`Mod::missing_append_bases`].
+///
+/// Files added by any scanned mod count as existing since mods often append to each other's files,
+/// hyperspace.xml is also always assumed to exist because Hyperspace.ftl is not part of the mod directory.
+fn check_append_bases(state: &Mutex<SharedState>, archive_paths: &HashSet<String>) {
+    let sources = state
+        .lock()
+        .mods
+        .iter()
+        .map(|m| (m.filename().to_string(), m.source.clone()))
+        .collect::<Vec<_>>();
+
+    let mut listings = Vec::new();
+    for (filename, source) in sources {
+        match source.open().and_then(|mut handle| handle.paths()) {
+            Ok(paths) => listings.push((filename, paths)),
+            Err(error) => warn!("Failed to list files of {filename}: {error:#}"),
+        }
+    }
+
+    let mut known_paths = archive_paths.clone();
+    known_paths.insert("data/hyperspace.xml".to_owned());
+    known_paths.extend(
+        listings
+            .iter()
+            .flat_map(|(_, paths)| paths)
+            .filter(|path| apply::AppendType::from_filename(path).is_none())
+            .cloned(),
+    );
+
+    let mut lock = state.lock();
+    for (filename, paths) in listings {
+        let missing = apply::missing_append_bases(&paths, &known_paths);
+        if let Some(m) = lock.mods.iter_mut().find(|m| m.filename() == filename) {
+            m.missing_append_bases = missing;
+        }
+    }
+    lock.ctx.request_repaint();
+}
+
+/// Lists the entries of the mod directory that may be mods.
+///
+/// With [`Settings::scan_recursively`] directories that don't look like a mod are descended into instead,
+/// directories that do are never descended into so their own `data/` directory isn't mistaken for a mod.
+fn find_mod_paths(settings: &Settings) -> Result<Vec<PathBuf>> {
+    let mut out = Vec::new();
+    let mut visited = HashSet::new();
+    let mut pending = vec![settings.mod_directory.clone()];
+
+    while let Some(directory) = pending.pop() {
+        // Guards against symbolic link loops.
+        if !visited.insert(directory.canonicalize().unwrap_or_else(|_| directory.clone())) {
+            continue;
+        }
+
+        for result in
+            std::fs::read_dir(&directory).with_context(|| format!("Failed to open {}", directory.display()))?
+        {
+            let entry = result.with_context(|| format!("Failed to read entry from {}", directory.display()))?;
+            let path = entry.path();
+
+            // Links to mods are always followed, Settings::follow_symlinks only applies to links inside of mods.
+            if settings.scan_recursively && path.is_dir() && !is_mod_directory(&path) {
+                pending.push(path);
+            } else {
+                out.push(path);
+            }
+        }
+    }
+
+    // Makes the order of newly found mods independent of the traversal order.
+    out.sort();
+    Ok(out)
+}
+
+fn is_mod_directory(path: &Path) -> bool {
+    path.join("data").is_dir() || path.join("mod-appendix").is_dir()
+}
+
+/// Reads the mod order file from `mod_directory`, returning the default configuration if it doesn't exist.
+pub fn read_mod_configuration(mod_directory: &Path) -> Result<ModConfigurationState> {
+    match std::fs::File::open(mod_directory.join(MOD_ORDER_FILENAME)) {
+        Ok(f) => serde_json::from_reader(std::io::BufReader::new(f))
+            .with_context(|| format!("Failed to deserialize mod order from {MOD_ORDER_FILENAME}")),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(ModConfigurationState::default()),
+        Err(e) => Err(e).context("Failed to open mod order file"),
+    }
+}
+
+/// Like [`read_mod_configuration`] but if the file can't be parsed it is moved to `modorder.json.bad` and the
+/// default configuration is returned, otherwise the next save would silently replace it.
+///
+/// Only the GUI uses this since it saves the mod order, read-only commands report the error instead.
+fn read_mod_configuration_or_backup(mod_directory: &Path) -> Result<ModConfigurationState> {
+    read_mod_configuration(mod_directory).or_else(|error| {
+        if error.downcast_ref::<serde_json::Error>().is_none() {
+            return Err(error);
+        }
+
+        warn!("{error:#}");
+        let backup_path = util::backup_bad_file(&mod_directory.join(MOD_ORDER_FILENAME))?;
+        warn!(
+            "Moved unreadable mod order to {}, using default order",
+            backup_path.display()
+        );
+        Ok(ModConfigurationState::default())
+    })
+}
+
+pub fn write_mod_configuration(mod_directory: &Path, state: &ModConfigurationState) -> Result<()> {
+    util::write_atomically(&mod_directory.join(MOD_ORDER_FILENAME), |writer| {
+        Ok(serde_json::to_writer(writer, state)?)
+    })
+    .context("Failed to write mod order")
+}
+
+/// Reorders `mods` according to the contents of a Slipstream modorder.txt file.
+///
+/// Listed mods are enabled and moved to the top in the order they are listed in, all other mods are
+/// disabled and keep their relative order. Returns listed filenames that don't match any mod.
+pub fn apply_slipstream_order(text: &str, mods: &mut Vec<Mod>) -> Vec<String> {
+    let mut remaining = std::mem::take(mods);
+    let mut missing = Vec::new();
+
+    for filename in text.lines().map(str::trim).filter(|line| !line.is_empty()) {
+        if let Some(idx) = remaining.iter().position(|m| m.filename() == filename) {
+            let mut m = remaining.remove(idx);
+            m.enabled = true;
+            mods.push(m);
+        } else if !mods.iter().any(|m| m.filename() == filename) {
+            missing.push(filename.to_owned());
+        }
+    }
+
+    mods.extend(remaining.into_iter().map(|mut m| {
+        m.enabled = false;
+        m
+    }));
+
+    missing
+}
+
+/// Creates the mod at `path` if it is one.
+///
+/// Mods are enabled according to `previously_enabled` if it returns a value for their filename,
+/// otherwise according to the mod order.
+fn load_mod(
+    settings: &Settings,
+    path: PathBuf,
+    mod_order_map: &HashMap<String, (usize, bool)>,
+    previously_enabled: impl FnOnce(&str) -> Option<bool>,
+) -> Option<Mod> {
+    ModSource::new(settings, path).map(Mod::new).map(|mut m| {
+        let filename = m.filename();
+        m.enabled = previously_enabled(filename).unwrap_or_else(|| mod_order_map.get(filename).is_some_and(|x| x.1));
+        m
+    })
+}
+
+/// Sorts mods by their position in the mod order, mods missing from it are moved to the end.
+fn sort_by_mod_order(mods: &mut [Mod], mod_order_map: &HashMap<String, (usize, bool)>) {
+    mods.sort_by_cached_key(|m| mod_order_map.get(m.filename()).map(|x| x.0).unwrap_or(usize::MAX));
+}
+
+/// Like [`scan`] but without any GUI state, returns the configuration along with all mods in their configured order.
+pub fn scan_ordered(settings: &Settings) -> Result<(ModConfigurationState, Vec<Mod>)> {
+    let mod_config_state = read_mod_configuration(&settings.mod_directory)?;
+    let mod_order_map = mod_config_state.order.clone().into_order_map();
+
+    let mut mods = Vec::new();
+    for path in find_mod_paths(settings)? {
+        mods.extend(load_mod(settings, path, &mod_order_map, |_| None));
+    }
+    sort_by_mod_order(&mut mods, &mod_order_map);
+    mark_duplicate_filenames(&mut mods);
+
+    Ok((mod_config_state, mods))
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::PathBuf;
+
+    use super::{
+        apply_slipstream_order, mark_duplicate_filenames, read_mod_configuration, read_mod_configuration_or_backup,
+        scan_ordered,
+    };
+    use crate::{Mod, ModSource, Settings, MOD_ORDER_FILENAME};
+
+    #[test]
+    fn slipstream_order_is_imported() {
+        let mut mods = [("a.zip", true), ("b.zip", false), ("c.zip", false), ("d.zip", true)]
+            .into_iter()
+            .map(|(path, enabled)| Mod::new_with_enabled(ModSource::Zip { path: path.into() }, enabled))
+            .collect::<Vec<_>>();
+
+        let missing = apply_slipstream_order("c.zip\r\nmissing.ftl\n\nb.zip\nc.zip\n", &mut mods);
+
+        assert_eq!(missing, ["missing.ftl"]);
+        assert_eq!(
+            mods.iter().map(|m| (m.filename(), m.enabled)).collect::<Vec<_>>(),
+            [("c.zip", true), ("b.zip", true), ("a.zip", false), ("d.zip", false)]
+        );
+    }
+
+    #[test]
+    fn malformed_mod_order_is_backed_up() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(MOD_ORDER_FILENAME);
+        std::fs::write(&path, r#"{"order": [{"filename": "a.zip", "enab"#).unwrap();
+
+        assert!(read_mod_configuration(dir.path()).is_err());
+        assert!(path.exists());
+
+        let config = read_mod_configuration_or_backup(dir.path()).unwrap();
+        assert!(config.hyperspace.is_none());
+        assert!(config.order.0.is_empty());
+        assert!(!path.exists());
+        assert!(dir.path().join("modorder.json.bad").exists());
+    }
+
+    #[test]
+    fn duplicate_filenames_are_marked() {
+        let mut mods =
+            ["a/Mod.zip", "b/Mod.zip", "b/Other.zip"].map(|path| Mod::new(ModSource::Zip { path: path.into() }));
+
+        mark_duplicate_filenames(&mut mods);
+
+        assert_eq!(mods[0].duplicate_paths, [PathBuf::from("b/Mod.zip")]);
+        assert_eq!(mods[1].duplicate_paths, [PathBuf::from("a/Mod.zip")]);
+        assert!(mods[2].duplicate_paths.is_empty());
+    }
+
+    #[test]
+    fn recursive_scan_finds_nested_mods() {
+        let dir = tempfile::tempdir().unwrap();
+        for path in [
+            "Top.zip",
+            "Category/Nested.ftl",
+            "Category/Dir Mod/data/blueprints.xml.append",
+        ] {
+            let path = dir.path().join(path);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, "").unwrap();
+        }
+
+        let mut settings = Settings {
+            mod_directory: dir.path().to_path_buf(),
+            ..Default::default()
+        };
+        let filenames = |settings: &Settings| {
+            let (_, mods) = scan_ordered(settings).unwrap();
+            mods.iter().map(|m| m.filename().to_string()).collect::<Vec<_>>()
+        };
+
+        assert_eq!(filenames(&settings), ["Category", "Top.zip"]);
+        settings.scan_recursively = true;
+        assert_eq!(filenames(&settings), ["Dir Mod", "Nested.ftl", "Top.zip"]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn linked_mods_are_found_by_default() {
+        let dir = tempfile::tempdir().unwrap();
+        let mod_directory = dir.path().join("mods");
+        std::fs::create_dir_all(dir.path().join("elsewhere/Dir Mod/data")).unwrap();
+        std::fs::create_dir(&mod_directory).unwrap();
+        std::fs::write(dir.path().join("elsewhere/Linked.zip"), "").unwrap();
+        std::os::unix::fs::symlink(
+            dir.path().join("elsewhere/Linked.zip"),
+            mod_directory.join("Linked.zip"),
+        )
+        .unwrap();
+        std::os::unix::fs::symlink(dir.path().join("elsewhere/Dir Mod"), mod_directory.join("Dir Mod")).unwrap();
+
+        let settings = Settings {
+            mod_directory,
+            ..Default::default()
+        };
+        assert!(!settings.follow_symlinks);
+        let (_, mods) = scan_ordered(&settings).unwrap();
+        assert_eq!(
+            mods.iter().map(|m| m.filename()).collect::<Vec<_>>(),
+            ["Dir Mod", "Linked.zip"]
+        );
+    }
+}
@@ -0,0 +1,199 @@
+//! In-place updates of portable installations, i.e. ones unpacked from a release archive.
+
+use std::{
+    io::{Cursor, Read, Write},
+    path::{Path, PathBuf},
+};
+
+use anyhow::{bail, Context, Result};
+use log::info;
+
+use crate::{github, AGENT};
+
+/// Name of the release asset built for the current platform, see build_release.sh.
+const ASSET_NAME: Option<&str> = if cfg!(all(target_os = "windows", target_arch = "x86_64")) {
+    Some("ftlman-x86_64-pc-windows-gnu.zip")
+} else if cfg!(all(target_os = "linux", target_arch = "x86_64")) {
+    Some("ftlman-x86_64-unknown-linux-gnu.tar.gz")
+} else if cfg!(all(target_os = "macos", target_arch = "x86_64")) {
+    Some("ftlman-x86_64-apple-darwin.tar.gz")
+} else if cfg!(all(target_os = "macos", target_arch = "aarch64")) {
+    Some("ftlman-aarch64-apple-darwin.tar.gz")
+} else {
+    None
+};
+
+/// Executables contained in the Windows release archive, the GUI wrapper starts whichever of
+/// ftlman.com or ftlman.exe exists next to it.
+const WINDOWS_EXECUTABLES: &[&str] = &["ftlman.exe", "ftlman_gui.exe"];
+
+/// Running executables can't be deleted or overwritten on Windows, so they're renamed to
+/// `<name>.old` instead and removed on the next start.
+const OLD_SUFFIX: &str = ".old";
+
+fn current_exe() -> Result<PathBuf> {
+    std::env::current_exe().context("Failed to get path to current executable")
+}
+
+/// Whether this executable looks like it was unpacked from a release archive instead of
+/// being installed by cargo or Nix, only the former can be replaced by [`install`].
+pub fn is_portable() -> bool {
+    let Ok(exe) = current_exe() else {
+        return false;
+    };
+
+    let cargo_bin = std::env::var_os("CARGO_HOME")
+        .map(PathBuf::from)
+        .or_else(|| dirs::home_dir().map(|home| home.join(".cargo")))
+        .map(|cargo_home| cargo_home.join("bin"));
+
+    ASSET_NAME.is_some()
+        && !exe.starts_with("/nix/store")
+        && !cargo_bin.is_some_and(|cargo_bin| exe.starts_with(cargo_bin))
+}
+
+/// Downloads the release asset for this platform and replaces the current executable with it.
+///
+/// The update takes effect after [`restart`].
+pub fn install(release: &github::Release) -> Result<()> {
+    let name = ASSET_NAME.context("Updating is not supported on this platform")?;
+    let asset = release
+        .assets
+        .iter()
+        .find(|asset| asset.name == name)
+        .with_context(|| format!("Release {} has no {name} asset", release.tag_name))?;
+
+    crate::util::ensure_online()?;
+    info!("Downloading {}", asset.browser_download_url);
+    let response = github::call_with_retry(AGENT.get(&asset.browser_download_url))?;
+    let mut data = Vec::new();
+    response
+        .into_reader()
+        .read_to_end(&mut data)
+        .context("Failed to download update")?;
+    asset.verify(&data).context("Downloaded update failed verification")?;
+
+    let exe = current_exe()?;
+    let dir = exe.parent().context("Executable path has no parent")?;
+    for (filename, contents) in unpack(&data)? {
+        // On Unix the archive contains a single executable which may have been renamed by the user.
+        let target = if cfg!(windows) {
+            dir.join(&filename)
+        } else {
+            exe.clone()
+        };
+        replace_file(&target, &contents).with_context(|| format!("Failed to replace {}", target.display()))?;
+        info!("Updated {}", target.display());
+    }
+
+    Ok(())
+}
+
+/// Returns the executables contained in a release archive along with their filenames.
+fn unpack(data: &[u8]) -> Result<Vec<(String, Vec<u8>)>> {
+    let mut out = Vec::new();
+
+    if cfg!(windows) {
+        let mut zip = zip::ZipArchive::new(Cursor::new(data)).context("Update is not a valid zip archive")?;
+        for name in WINDOWS_EXECUTABLES {
+            let mut contents = Vec::new();
+            zip.by_name(name)
+                .with_context(|| format!("Update archive doesn't contain {name}"))?
+                .read_to_end(&mut contents)?;
+            out.push((name.to_string(), contents));
+        }
+    } else {
+        let mut archive = tar::Archive::new(flate2::read::GzDecoder::new(data));
+        for entry in archive.entries().context("Update is not a valid tar archive")? {
+            let mut entry = entry?;
+            if entry.path()?.as_os_str() == "ftlman" {
+                let mut contents = Vec::new();
+                entry.read_to_end(&mut contents)?;
+                out.push(("ftlman".to_owned(), contents));
+            }
+        }
+    }
+
+    if out.is_empty() {
+        bail!("Update archive doesn't contain any executables");
+    }
+
+    Ok(out)
+}
+
+fn old_path(path: &Path) -> PathBuf {
+    let mut old = path.as_os_str().to_owned();
+    old.push(OLD_SUFFIX);
+    PathBuf::from(old)
+}
+
+fn replace_file(target: &Path, contents: &[u8]) -> Result<()> {
+    let mut tmp = tempfile::NamedTempFile::new_in(target.parent().unwrap())?;
+    tmp.write_all(contents)?;
+    tmp.as_file().sync_all()?;
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        tmp.as_file().set_permissions(std::fs::Permissions::from_mode(0o755))?;
+    }
+
+    if cfg!(windows) && target.exists() {
+        let old = old_path(target);
+        _ = std::fs::remove_file(&old);
+        std::fs::rename(target, &old).context("Failed to move running executable out of the way")?;
+    }
+
+    // Renaming over the running executable is fine on Unix, the old inode lives on until we exit.
+    tmp.persist(target).map_err(|e| e.error)?;
+    Ok(())
+}
+
+/// Removes executables left behind by a previous update on Windows.
+#[cfg(windows)]
+pub fn remove_old_executables() {
+    let Some(dir) = current_exe().ok().and_then(|exe| exe.parent().map(Path::to_path_buf)) else {
+        return;
+    };
+
+    for name in WINDOWS_EXECUTABLES {
+        let old = old_path(&dir.join(name));
+        match std::fs::remove_file(&old) {
+            Ok(()) => info!("Removed {}", old.display()),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => log::warn!("Failed to remove {}: {e}", old.display()),
+        }
+    }
+}
+
+/// Starts the updated executable with the same arguments and exits.
+pub fn restart() -> Result<std::convert::Infallible> {
+    let exe = current_exe()?;
+    // On Windows the running executable has been renamed by the update.
+    let exe = exe
+        .to_str()
+        .and_then(|exe| exe.strip_suffix(OLD_SUFFIX))
+        .map_or(exe.clone(), PathBuf::from);
+
+    let mut command = std::process::Command::new(exe);
+    command.args(std::env::args_os().skip(1));
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::CommandExt;
+        Err(command.exec()).context("Failed to execute updated ftlman")
+    }
+
+    #[cfg(not(unix))]
+    {
+        #[cfg(windows)]
+        {
+            use std::os::windows::process::CommandExt;
+            // DETACHED_PROCESS, same as the GUI wrapper
+            command.creation_flags(0x00000008);
+        }
+
+        command.spawn().context("Failed to start updated ftlman")?;
+        std::process::exit(0)
+    }
+}
@@ -1,5 +1,11 @@
-use std::{cell::UnsafeCell, fmt::Display, hash::Hasher as _, io::Read};
-
+use std::{
+    fmt::Display,
+    hash::Hasher as _,
+    io::{BufWriter, Read, Write},
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result};
 use lazy_static::lazy_static;
 use regex::Regex;
 use serde::{Deserialize, Serialize};
@@ -46,6 +52,32 @@ pub fn crc32_from_reader(reader: &mut impl Read) -> std::io::Result<u32> {
     Ok(writer.crc.finalize())
 }
 
+/// Writes to a temporary file next to `path` and then renames it over `path`.
+///
+/// This way a crash in the middle of writing can't leave behind a truncated file.
+pub fn write_atomically(path: &Path, write: impl FnOnce(&mut dyn Write) -> std::io::Result<()>) -> std::io::Result<()> {
+    let mut tmp = tempfile::NamedTempFile::new_in(path.parent().unwrap_or(Path::new(".")))?;
+    {
+        let mut writer = BufWriter::new(tmp.as_file_mut());
+        write(&mut writer)?;
+        writer.flush()?;
+    }
+    tmp.as_file().sync_all()?;
+    // This also replaces existing files on Windows, where a plain rename would fail.
+    tmp.persist(path).map_err(|e| e.error)?;
+    Ok(())
+}
+
+/// Moves an unreadable file to `<path>.bad` so it doesn't get overwritten, returns the new path.
+pub fn backup_bad_file(path: &Path) -> Result<PathBuf> {
+    let mut backup_path = path.as_os_str().to_owned();
+    backup_path.push(".bad");
+    let backup_path = PathBuf::from(backup_path);
+    std::fs::rename(path, &backup_path)
+        .with_context(|| format!("Failed to move {} to {}", path.display(), backup_path.display()))?;
+    Ok(backup_path)
+}
+
 #[derive(Debug, Clone)]
 pub enum SloppyVersion {
     Semver(semver::Version),
@@ -137,35 +169,3 @@ pub fn find_semver_in_string(string: &str) -> Option<semver::Version> {
         // NOTE: I think this may only fail if integer overflow occurs (the regex should check everything else)
         .find_map(|m| semver::Version::parse(m.as_str()).ok())
 }
-
-pub struct StringArena {
-    strings: UnsafeCell<Vec<*mut str>>,
-}
-
-impl<'a> StringArena {
-    pub fn new() -> Self {
-        Self {
-            strings: UnsafeCell::default(),
-        }
-    }
-
-    #[expect(dead_code)]
-    pub fn insert(&self, string: String) -> &str {
-        let ptr = Box::into_raw(string.into_boxed_str());
-        // SAFETY: No reference to self.strings is handed out and the returned
-        //         string has its lifetime tied to self.
-        unsafe {
-            (*self.strings.get()).push(ptr);
-            &*ptr
-        }
-    }
-}
-
-impl Drop for StringArena {
-    fn drop(&mut self) {
-        for ptr in std::mem::take(self.strings.get_mut()) {
-            // SAFETY: *const str was acquired via Box::into_raw above.
-            unsafe { drop(Box::from_raw(ptr)) };
-        }
-    }
-}
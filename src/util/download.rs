@@ -1,8 +1,50 @@
-use anyhow::Result;
+use std::{
+    io::Read,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc,
+    },
+};
+
+use anyhow::{bail, Context, Result};
+use log::warn;
 use ureq::Response;
 
+use crate::AGENT;
+
+pub const DEFAULT_DOWNLOAD_CONNECTIONS: usize = 4;
+
+static OFFLINE: AtomicBool = AtomicBool::new(false);
+
+/// Enables or disables offline mode, while enabled [`ensure_online`] will fail
+/// and anything that can fall back to cached data should do so.
+pub fn set_offline(value: bool) {
+    OFFLINE.store(value, Ordering::Relaxed);
+}
+
+pub fn is_offline() -> bool {
+    OFFLINE.load(Ordering::Relaxed)
+}
+
+/// Should be called before making any network request.
+pub fn ensure_online() -> Result<()> {
+    if is_offline() {
+        bail!("Network access is disabled in offline mode");
+    }
+    Ok(())
+}
+
+// Splitting small files into ranges only adds request overhead.
+const MIN_PARALLEL_DOWNLOAD_SIZE: u64 = 4 * 1024 * 1024;
+
+/// Downloads the body of `response`.
+///
+/// If the server supports range requests and `max_connections` is greater than one the
+/// body will be downloaded in up to `max_connections` chunks concurrently, otherwise
+/// it is read from `response` directly.
 pub fn download_body_with_progress(
-    response: Response,
+    mut response: Response,
+    max_connections: usize,
     mut on_progress: impl FnMut(u64, Option<u64>),
 ) -> Result<Vec<u8>> {
     let is_chunked = response
@@ -14,6 +56,25 @@ pub fn download_body_with_progress(
         .filter(|_| !is_chunked)
         .and_then(|x| x.parse::<u64>().ok());
 
+    let accepts_ranges = response
+        .header("Accept-Ranges")
+        .is_some_and(|x| x.eq_ignore_ascii_case("bytes"));
+
+    if let Some(length) = content_length.filter(|&l| accepts_ranges && l >= MIN_PARALLEL_DOWNLOAD_SIZE) {
+        if max_connections > 1 {
+            // get_url returns the url after following any redirects
+            let url = response.get_url().to_owned();
+            drop(response);
+            match download_ranges_with_progress(&url, length, max_connections, &mut on_progress) {
+                Ok(data) => return Ok(data),
+                Err(error) => {
+                    warn!("Parallel download failed, falling back to a single connection: {error:#}");
+                    response = AGENT.get(&url).call()?;
+                }
+            }
+        }
+    }
+
     let mut reader = response.into_reader();
 
     const BUFFER_SIZE: usize = 4096;
@@ -32,3 +93,65 @@ pub fn download_body_with_progress(
 
     Ok(out)
 }
+
+fn download_ranges_with_progress(
+    url: &str,
+    length: u64,
+    connections: usize,
+    mut on_progress: impl FnMut(u64, Option<u64>),
+) -> Result<Vec<u8>> {
+    let mut out = vec![0; length as usize];
+    let chunk_size = out.len().div_ceil(connections);
+    let (progress_sender, progress_receiver) = mpsc::channel::<usize>();
+
+    std::thread::scope(|scope| {
+        let handles = out
+            .chunks_mut(chunk_size)
+            .enumerate()
+            .map(|(i, chunk)| {
+                let progress_sender = progress_sender.clone();
+                scope.spawn(move || -> Result<()> {
+                    let start = i * chunk_size;
+                    let end = start + chunk.len() - 1;
+                    let response = AGENT
+                        .get(url)
+                        .set("Range", &format!("bytes={start}-{end}"))
+                        .call()
+                        .with_context(|| format!("Failed to request byte range {start}-{end}"))?;
+
+                    if response.status() != 206 {
+                        bail!("Server responded with status {} to a range request", response.status());
+                    }
+
+                    let mut reader = response.into_reader();
+                    let mut position = 0;
+                    while position < chunk.len() {
+                        let nread = reader.read(&mut chunk[position..])?;
+                        if nread == 0 {
+                            bail!("Connection closed before byte range {start}-{end} was received");
+                        }
+                        position += nread;
+                        _ = progress_sender.send(nread);
+                    }
+
+                    Ok(())
+                })
+            })
+            .collect::<Vec<_>>();
+
+        drop(progress_sender);
+
+        let mut current = 0;
+        for nread in progress_receiver {
+            current += nread as u64;
+            on_progress(current, Some(length));
+        }
+
+        for handle in handles {
+            handle.join().expect("download thread panicked")?;
+        }
+
+        Ok(())
+    })
+    .map(|()| out)
+}
@@ -8,6 +8,8 @@ lazy_static! {
 }
 
 pub fn request_google_drive_download(file_id: &str) -> Result<ureq::Response> {
+    super::ensure_online()?;
+
     let initial_response = crate::AGENT
         .get("https://drive.google.com/uc?export=download")
         .query("id", file_id)
@@ -1 +1,288 @@
+use std::{borrow::Cow, ops::Range};
+
+use annotate_snippets::{Level, Message, Snippet};
+use serde::Serialize;
+
 pub mod xml;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Error,
+    Warning,
+    Info,
+}
+
+impl Severity {
+    fn level(self) -> Level {
+        match self {
+            Severity::Error => Level::Error,
+            Severity::Warning => Level::Warning,
+            Severity::Info => Level::Info,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Annotation<'a> {
+    pub severity: Severity,
+    pub span: Range<usize>,
+    pub label: Cow<'a, str>,
+}
+
+/// A single problem found by a validator.
+#[derive(Debug, Clone)]
+pub struct Diagnostic<'a> {
+    pub severity: Severity,
+    pub title: &'static str,
+    /// The annotation pointing at the problem itself, has the same severity as the diagnostic.
+    pub primary: Annotation<'a>,
+    /// Additional annotations pointing at related locations.
+    pub related: Vec<Annotation<'a>>,
+    /// Whether unannotated lines should be folded when rendered.
+    pub fold: bool,
+}
+
+impl<'a> Diagnostic<'a> {
+    pub fn new(severity: Severity, title: &'static str, span: Range<usize>, label: impl Into<Cow<'a, str>>) -> Self {
+        Self {
+            severity,
+            title,
+            primary: Annotation {
+                severity,
+                span,
+                label: label.into(),
+            },
+            related: Vec::new(),
+            fold: true,
+        }
+    }
+
+    pub fn with_related(mut self, span: Range<usize>, label: impl Into<Cow<'a, str>>) -> Self {
+        self.related.push(Annotation {
+            severity: Severity::Info,
+            span,
+            label: label.into(),
+        });
+        self
+    }
+
+    pub fn with_fold(mut self, fold: bool) -> Self {
+        self.fold = fold;
+        self
+    }
+
+    fn annotations(&self) -> impl Iterator<Item = &Annotation<'a>> {
+        self.related.iter().chain(std::iter::once(&self.primary))
+    }
+}
+
+/// Maps byte offsets in a source file to lines and columns.
+pub struct LineIndex<'a> {
+    source: &'a str,
+    newlines: Vec<usize>,
+}
+
+impl<'a> LineIndex<'a> {
+    pub fn new(source: &'a str) -> Self {
+        Self {
+            source,
+            newlines: source
+                .bytes()
+                .enumerate()
+                .filter_map(|(i, b)| (b == b'\n').then_some(i))
+                .collect(),
+        }
+    }
+
+    fn line_idx(&self, offset: usize) -> usize {
+        match self.newlines.binary_search(&offset) {
+            Ok(i) | Err(i) => i,
+        }
+    }
+
+    fn line_start(&self, line_idx: usize) -> usize {
+        line_idx.checked_sub(1).map_or(0, |p| self.newlines[p] + 1)
+    }
+
+    /// Returns the 1-based line and column (in characters) of a byte offset.
+    pub fn line_col(&self, offset: usize) -> (usize, usize) {
+        let line_idx = self.line_idx(offset);
+        let line_start = self.line_start(line_idx);
+        (line_idx + 1, self.source[line_start..offset].chars().count() + 1)
+    }
+
+    /// Returns the byte offset at which a 1-based line starts, or None if there's no such line.
+    pub fn line_offset(&self, line: usize) -> Option<usize> {
+        let line_idx = line.checked_sub(1)?;
+        (line_idx <= self.newlines.len()).then(|| self.line_start(line_idx))
+    }
+
+    /// Returns the number of lines in the source.
+    pub fn line_count(&self) -> usize {
+        self.newlines.len() + 1
+    }
+
+    fn snippet(&self, start: usize, origin: Option<&'a str>) -> Snippet<'a> {
+        let line_idx = self.line_idx(start);
+        let snippet = Snippet::source(self.source).line_start(line_idx + 1);
+
+        if let Some(origin) = origin {
+            snippet.origin(origin)
+        } else {
+            snippet
+        }
+    }
+}
+
+/// Converts diagnostics to `annotate_snippets` messages for human readable output.
+pub fn render_messages<'a>(
+    diagnostics: &'a [Diagnostic<'a>],
+    lines: &LineIndex<'a>,
+    origin: Option<&'a str>,
+) -> Vec<Message<'a>> {
+    diagnostics
+        .iter()
+        .map(|diagnostic| {
+            let start = diagnostic.annotations().map(|x| x.span.start).min().unwrap();
+            let snippet = diagnostic.annotations().fold(
+                lines.snippet(start, origin).fold(diagnostic.fold),
+                |snippet, annotation| {
+                    snippet.annotation(
+                        annotation
+                            .severity
+                            .level()
+                            .span(annotation.span.clone())
+                            .label(&annotation.label),
+                    )
+                },
+            );
+            diagnostic.severity.level().title(diagnostic.title).snippet(snippet)
+        })
+        .collect()
+}
+
+#[derive(Debug, Serialize)]
+pub struct JsonSpan {
+    pub start: usize,
+    pub end: usize,
+    pub line: usize,
+    pub column: usize,
+    pub end_line: usize,
+    pub end_column: usize,
+}
+
+#[derive(Debug, Serialize)]
+pub struct JsonAnnotation<'a> {
+    pub severity: Severity,
+    pub label: &'a str,
+    pub span: JsonSpan,
+}
+
+/// Machine readable form of a [`Diagnostic`], meant for editor integrations.
+#[derive(Debug, Serialize)]
+pub struct JsonDiagnostic<'a> {
+    pub file: Option<&'a str>,
+    pub severity: Severity,
+    pub message: &'a str,
+    pub label: &'a str,
+    pub span: JsonSpan,
+    pub related: Vec<JsonAnnotation<'a>>,
+}
+
+fn json_span(lines: &LineIndex, span: &Range<usize>) -> JsonSpan {
+    let (line, column) = lines.line_col(span.start);
+    let (end_line, end_column) = lines.line_col(span.end);
+    JsonSpan {
+        start: span.start,
+        end: span.end,
+        line,
+        column,
+        end_line,
+        end_column,
+    }
+}
+
+pub fn to_json<'a>(
+    diagnostics: &'a [Diagnostic<'a>],
+    lines: &LineIndex,
+    file: Option<&'a str>,
+) -> Vec<JsonDiagnostic<'a>> {
+    diagnostics
+        .iter()
+        .map(|diagnostic| JsonDiagnostic {
+            file,
+            severity: diagnostic.severity,
+            message: diagnostic.title,
+            label: &diagnostic.primary.label,
+            span: json_span(lines, &diagnostic.primary.span),
+            related: diagnostic
+                .related
+                .iter()
+                .map(|annotation| JsonAnnotation {
+                    severity: annotation.severity,
+                    label: &annotation.label,
+                    span: json_span(lines, &annotation.span),
+                })
+                .collect(),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::{to_json, Diagnostic, LineIndex, Severity};
+
+    #[test]
+    fn line_col() {
+        let lines = LineIndex::new("ab\ncd\n");
+        assert_eq!(lines.line_col(0), (1, 1));
+        assert_eq!(lines.line_col(2), (1, 3));
+        assert_eq!(lines.line_col(3), (2, 1));
+        assert_eq!(lines.line_col(5), (2, 3));
+        assert_eq!(lines.line_col(6), (3, 1));
+    }
+
+    #[test]
+    fn line_col_crlf() {
+        let lines = LineIndex::new("ab\r\ncd");
+        // The carriage return is part of the first line.
+        assert_eq!(lines.line_col(2), (1, 3));
+        assert_eq!(lines.line_col(3), (1, 4));
+        assert_eq!(lines.line_col(4), (2, 1));
+        assert_eq!(lines.line_col(6), (2, 3));
+    }
+
+    #[test]
+    fn line_col_counts_characters() {
+        let source = "żółw\n→x";
+        let lines = LineIndex::new(source);
+        assert_eq!(lines.line_col(source.find('w').unwrap()), (1, 4));
+        assert_eq!(lines.line_col(source.find('x').unwrap()), (2, 2));
+        assert_eq!(lines.line_col(source.len()), (2, 3));
+    }
+
+    #[test]
+    fn json_shape() {
+        let source = "<a>\n<b/>\n</a>";
+        let lines = LineIndex::new(source);
+        let diagnostics = [Diagnostic::new(Severity::Warning, "title", 4..8, "primary").with_related(0..3, "related")];
+
+        let json = serde_json::to_value(to_json(&diagnostics, &lines, Some("test.xml"))).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!([{
+                "file": "test.xml",
+                "severity": "warning",
+                "message": "title",
+                "label": "primary",
+                "span": { "start": 4, "end": 8, "line": 2, "column": 1, "end_line": 2, "end_column": 5 },
+                "related": [{
+                    "severity": "info",
+                    "label": "related",
+                    "span": { "start": 0, "end": 3, "line": 1, "column": 1, "end_line": 1, "end_column": 4 },
+                }],
+            }])
+        );
+    }
+}
@@ -1,120 +1,197 @@
-use std::collections::HashMap;
+use std::{borrow::Cow, collections::HashMap, ops::Range};
 
-use annotate_snippets::{Level, Message, Snippet};
 use speedy_xml::{
+    escape::unknown_entities,
     reader::{ErrorKind, Options},
     Reader,
 };
 
-use crate::util::StringArena;
+use super::{Diagnostic, Severity};
 
-pub fn validate_xml<'a>(
-    source: &'a str,
-    options: Options,
+const FIND_ATTRIBUTES: &[&str] = &["reverse", "start", "limit", "panic"];
 
-    messages: &mut Vec<Message<'a>>,
-    _strings: &'a StringArena,
-    origin: Option<&'a str>,
-) -> bool {
+/// Attributes recognized on `mod:` tags by the append engine.
+/// [`None`] means that the tag accepts arbitrary attributes.
+///
+/// `prefix` and `child-prefix` restrict matches to elements with the given namespace prefix
+/// (an empty value only matches unprefixed elements), a fixed `type` may also be written as `prefix:name`.
+/// Without either the prefix of matched elements is ignored.
+///
+/// `recursive` on `findWithChildLike` makes the child filter match any descendant,
+/// by default only direct children are considered.
+///
+/// `mode` on `setValue` is one of `replace` (the default, removes all text and CDATA),
+/// `append` or `prepend`, which add to the last or first text node respectively and
+/// leave other children untouched. A new text node is created if there isn't one.
+///
+/// Values in `setAttributes` can refer to groups captured by the regexes of the enclosing find tag
+/// using `$1`, `${1}` or `${name}` (`$$` is a literal `$`). Groups are numbered consecutively across
+/// all regexes in the order type, prefix, selector attributes (or `name` for `findName`) and selector value,
+/// for `findWithChildLike` the groups of the matching child's selector follow the parent's own.
+/// References to groups that don't exist are kept as is.
+///
+/// `removeChildren` removes all children of the matched element, or only child elements if `elementsOnly` is true.
+///
+/// `comment` appends its text as an XML comment to the matched element, or to the root if used at the top level.
+/// These comments are kept even if other comments are stripped.
+pub const MOD_TAG_ATTRIBUTES: &[(&str, Option<&[&str]>)] = &[
+    (
+        "findName",
+        Some(&["name", "type", "prefix", "regex", "reverse", "start", "limit", "panic"]),
+    ),
+    (
+        "findLike",
+        Some(&["type", "prefix", "regex", "reverse", "start", "limit", "panic"]),
+    ),
+    (
+        "findWithChildLike",
+        Some(&[
+            "type",
+            "prefix",
+            "child-type",
+            "child-prefix",
+            "recursive",
+            "regex",
+            "reverse",
+            "start",
+            "limit",
+            "panic",
+        ]),
+    ),
+    ("findComposite", Some(FIND_ATTRIBUTES)),
+    ("par", Some(&["op"])),
+    ("selector", None),
+    ("setAttributes", None),
+    ("removeAttributes", None),
+    ("setValue", Some(&["mode"])),
+    ("removeTag", Some(&[])),
+    ("removeChildren", Some(&["elementsOnly"])),
+    ("insertByFind", Some(&["addAnyway"])),
+    ("comment", Some(&[])),
+];
+
+/// Warns about references in `raw` that can't be expanded.
+///
+/// These make XML append patches fail to apply, everywhere else they are kept as is.
+fn check_entities(raw: &str, offset: usize, diagnostics: &mut Vec<Diagnostic<'_>>) {
+    for entity in unknown_entities(raw) {
+        diagnostics.push(Diagnostic::new(
+            Severity::Warning,
+            "unknown entity",
+            offset + entity.start..offset + entity.end,
+            "not a predefined entity or valid character reference, XML appends containing it are rejected",
+        ));
+    }
+}
+
+pub fn validate_xml<'a>(source: &'a str, options: Options, diagnostics: &mut Vec<Diagnostic<'a>>) -> bool {
     let mut reader = Reader::with_options(source, options.allow_unmatched_closing_tags(true));
     let mut element_stack = Vec::new();
+    // Fixed names required by findName tags directly inside of each enclosing AND par, along
+    // with the depth of element_stack at which the par's children reside.
+    let mut and_par_stack: Vec<(usize, Vec<(Cow<'a, str>, Range<usize>)>)> = Vec::new();
     let mut parsing_would_succeed = true;
-    let newlines = {
-        let mut result = Vec::new();
-
-        for (i, b) in reader.buffer().bytes().enumerate() {
-            if b == b'\n' {
-                result.push(i);
-            }
-        }
-
-        result
-    };
-
-    let source = reader.buffer();
-    let make_snippet = |start: usize, end: Option<usize>| {
-        let line_idx = match newlines.binary_search(&start) {
-            Ok(i) | Err(i) => i,
-        };
-        let line_start = line_idx.checked_sub(1).map_or(0, |p| newlines[p] + 1);
-        let end_line_end = end.map(|end| match newlines.binary_search(&end) {
-            Ok(i) => newlines[i],
-            Err(i) => newlines.get(i).copied().unwrap_or(source.len()),
-        });
-
-        let snippet = Snippet::source(if let Some(end) = end_line_end {
-            &source[line_start..end]
-        } else {
-            source
-        })
-        .line_start(line_idx + 1);
-
-        if let Some(origin) = origin {
-            snippet.origin(origin)
-        } else {
-            snippet
-        }
-    };
 
     loop {
         match reader.next() {
             Some(Ok(event)) => match event {
-                speedy_xml::reader::Event::Start(start) => {
-                    element_stack.push(start);
+                speedy_xml::reader::Event::Start(start) | speedy_xml::reader::Event::Empty(start) => {
+                    let depth = element_stack.len();
+                    if !start.is_empty() {
+                        element_stack.push(start);
+                    }
+
+                    if start.prefix() == Some("mod") {
+                        check_never_matching(
+                            start,
+                            &reader,
+                            depth,
+                            &mut and_par_stack,
+                            |title, span, label, previous| {
+                                let mut diagnostic = Diagnostic::new(Severity::Warning, title, span, label);
+                                if let Some(previous) = previous {
+                                    diagnostic = diagnostic.with_related(previous, "conflicts with this name");
+                                }
+                                diagnostics.push(diagnostic);
+                            },
+                        );
+                    }
+
+                    let known_attributes = match start.prefix() {
+                        Some("mod") => MOD_TAG_ATTRIBUTES
+                            .iter()
+                            .find(|&&(name, _)| name == start.name())
+                            .and_then(|&(_, attributes)| attributes),
+                        _ => None,
+                    };
 
                     let mut seen = HashMap::new();
                     for attribute in start.attributes() {
                         let current = attribute.name_position_in(&reader);
+                        let value_end = attribute.position_in(&reader).end - 1;
+                        check_entities(
+                            attribute.raw_value(),
+                            value_end - attribute.raw_value().len(),
+                            diagnostics,
+                        );
+
+                        if known_attributes.is_some_and(|known| !known.contains(&attribute.name())) {
+                            diagnostics.push(Diagnostic::new(
+                                Severity::Warning,
+                                "unused attribute",
+                                current.clone(),
+                                format!("not recognized by mod:{}", start.name()),
+                            ));
+                        }
+
                         if let Some(previous) = seen.insert(attribute.name(), current.clone()) {
-                            messages.push(
-                                Level::Warning.title("duplicate attribute").snippet(
-                                    make_snippet(previous.start, None)
-                                        .fold(true)
-                                        .annotation(Level::Info.span(previous).label("previous occurrence here"))
-                                        .annotation(
-                                            Level::Warning
-                                                .span(current)
-                                                .label("attribute with the same name redeclared here"),
-                                        ),
-                                ),
+                            diagnostics.push(
+                                Diagnostic::new(
+                                    Severity::Warning,
+                                    "duplicate attribute",
+                                    current,
+                                    "attribute with the same name redeclared here",
+                                )
+                                .with_related(previous, "previous occurrence here"),
                             );
                         }
                     }
                 }
-                speedy_xml::reader::Event::End(end) => match element_stack.pop() {
-                    Some(start) if start.prefix() != end.prefix() || start.name() != end.name() => {
-                        let start_span = start.position_in(&reader);
-                        let end_span = end.position_in(&reader);
-                        messages.push(
-                            Level::Warning
-                                .title("element closing tag doesn't match opening tag")
-                                .snippet(
-                                    make_snippet(start_span.start, None)
-                                        .fold(true)
-                                        .annotation(Level::Info.span(start_span).label("opening tag here"))
-                                        .annotation(
-                                            Level::Warning.span(end_span).label("doesn't match this closing tag"),
-                                        ),
-                                ),
-                        );
+                speedy_xml::reader::Event::End(end) => {
+                    match element_stack.pop() {
+                        Some(start) if start.prefix() != end.prefix() || start.name() != end.name() => {
+                            diagnostics.push(
+                                Diagnostic::new(
+                                    Severity::Warning,
+                                    "element closing tag doesn't match opening tag",
+                                    end.position_in(&reader),
+                                    "doesn't match this closing tag",
+                                )
+                                .with_related(start.position_in(&reader), "opening tag here"),
+                            );
+                        }
+                        Some(_) => (),
+                        None => {
+                            diagnostics.push(Diagnostic::new(
+                                Severity::Error,
+                                "unmatched end tag",
+                                end.position_in(&reader),
+                                "end tag doesn't have a corresponding opening tag",
+                            ));
+                            parsing_would_succeed = false;
+                        }
                     }
-                    Some(_) => (),
-                    None => {
-                        let end_span = end.position_in(&reader);
-                        messages.push(
-                            Level::Error.title("unmatched end tag").snippet(
-                                make_snippet(end_span.start, None).fold(true).annotation(
-                                    Level::Error
-                                        .span(end_span)
-                                        .label("end tag doesn't have a corresponding opening tag"),
-                                ),
-                            ),
-                        );
-                        parsing_would_succeed = false;
+
+                    while and_par_stack
+                        .last()
+                        .is_some_and(|&(depth, _)| depth > element_stack.len())
+                    {
+                        and_par_stack.pop();
                     }
-                },
-                speedy_xml::reader::Event::Empty(_) => (),
-                speedy_xml::reader::Event::Text(_text) => (),
+                }
+                speedy_xml::reader::Event::Text(text) => {
+                    check_entities(text.raw_content(), text.position_in(&reader).start, diagnostics)
+                }
                 speedy_xml::reader::Event::CData(_cdata) => (),
                 speedy_xml::reader::Event::Comment(_comment) => (),
                 speedy_xml::reader::Event::Doctype(_doctype) => (),
@@ -122,29 +199,29 @@ pub fn validate_xml<'a>(
             Some(Err(e)) => {
                 parsing_would_succeed = false;
 
-                let snippet = match e.kind() {
-                    // This is handled after the whole document is parsed instead.
-                    ErrorKind::UnclosedElement => continue,
-                    _ => make_snippet(e.span().start, None)
-                        .fold(true)
-                        .annotation(Level::Error.span(e.span()).label(e.kind().message())),
-                };
+                // This is handled after the whole document is parsed instead.
+                if e.kind() == ErrorKind::UnclosedElement {
+                    continue;
+                }
 
-                messages.push(Level::Error.title("parse error").snippet(snippet));
+                diagnostics.push(Diagnostic::new(
+                    Severity::Error,
+                    "parse error",
+                    e.span(),
+                    e.kind().message(),
+                ));
             }
             None => {
                 for unclosed in element_stack {
-                    let span = unclosed.position_in(&reader);
-                    messages.push(
-                        Level::Error.title("unclosed element").snippet(
-                            make_snippet(span.start, None)
-                                .annotation(Level::Info.span(span).label("opened here"))
-                                .annotation(
-                                    Level::Error
-                                        .span(source.len()..source.len())
-                                        .label("encountered end of file before closing tag"),
-                                ),
-                        ),
+                    diagnostics.push(
+                        Diagnostic::new(
+                            Severity::Error,
+                            "unclosed element",
+                            source.len()..source.len(),
+                            "encountered end of file before closing tag",
+                        )
+                        .with_related(unclosed.position_in(&reader), "opened here")
+                        .with_fold(false),
                     );
                 }
 
@@ -155,3 +232,51 @@ pub fn validate_xml<'a>(
 
     parsing_would_succeed
 }
+
+fn check_never_matching<'a>(
+    start: speedy_xml::reader::StartEvent<'a>,
+    reader: &Reader<'a>,
+    depth: usize,
+    and_par_stack: &mut Vec<(usize, Vec<(Cow<'a, str>, Range<usize>)>)>,
+    mut warn: impl FnMut(&'static str, Range<usize>, &'static str, Option<Range<usize>>),
+) {
+    let attribute = |name: &str| start.attributes().find(|attribute| attribute.name() == name);
+
+    match start.name() {
+        "findName" | "findLike" | "findWithChildLike" | "findComposite" => {
+            if let Some(limit) = attribute("limit").filter(|limit| limit.value().trim() == "0") {
+                warn(
+                    "find can never match",
+                    limit.position_in(reader),
+                    "limit of zero means no elements will ever be selected",
+                    None,
+                );
+            }
+        }
+        "par" if !start.is_empty() => {
+            if attribute("op").is_some_and(|op| op.value() == "AND") {
+                and_par_stack.push((depth + 1, Vec::new()));
+            }
+        }
+        _ => (),
+    }
+
+    // Regex filters could potentially match the same element so they are not considered here.
+    if start.name() == "findName" && attribute("regex").is_none_or(|regex| regex.value() != "true") {
+        if let (Some((par_depth, names)), Some(name)) = (and_par_stack.last_mut(), attribute("name")) {
+            if *par_depth == depth {
+                let value = name.value();
+                let span = name.position_in(reader);
+                if let Some((_, previous)) = names.iter().find(|(other, _)| *other != value) {
+                    warn(
+                        "find can never match",
+                        span.clone(),
+                        "an AND par requires the name to equal two different strings",
+                        Some(previous.clone()),
+                    );
+                }
+                names.push((value, span));
+            }
+        }
+    }
+}
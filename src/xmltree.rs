@@ -1,12 +1,15 @@
 //! An XML Tree implementation backed by `quick-xml`.
 //! Based on the [`xmltree`](https://github.com/eminence/xmltree-rs).
 
-use std::{borrow::Cow, collections::BTreeMap, ops::Deref};
+use std::{borrow::Cow, ops::Deref};
 
 pub mod builder;
 pub mod dom;
 pub mod emitter;
 
+/// Element attributes, kept in document order so re-emitted elements don't reorder them.
+pub type Attributes = indexmap::IndexMap<String, String>;
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum Node {
     Element(Element),
@@ -43,7 +46,7 @@ impl Node {
 pub struct Element {
     pub prefix: Option<String>,
     pub name: String,
-    pub attributes: BTreeMap<String, String>,
+    pub attributes: Attributes,
     pub children: Vec<Node>,
 }
 
@@ -53,12 +56,7 @@ impl builder::TreeBuilder for SimpleTreeBuilder {
     type Element = Element;
     type Node = Node;
 
-    fn create_element(
-        &mut self,
-        prefix: Option<&str>,
-        name: &str,
-        attributes: BTreeMap<String, String>,
-    ) -> Self::Element {
+    fn create_element(&mut self, prefix: Option<&str>, name: &str, attributes: Attributes) -> Self::Element {
         Element {
             prefix: prefix.map(ToOwned::to_owned),
             name: name.to_owned(),
@@ -110,10 +108,7 @@ impl emitter::TreeEmitter for SimpleTreeEmitter {
         element.name.as_str()
     }
 
-    fn element_attributes<'a>(
-        &self,
-        element: &Self::Element<'a>,
-    ) -> impl Deref<Target = BTreeMap<String, String>> + 'a {
+    fn element_attributes<'a>(&self, element: &Self::Element<'a>) -> impl Deref<Target = Attributes> + 'a {
         &element.attributes
     }
 
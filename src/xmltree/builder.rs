@@ -1,18 +1,15 @@
-use std::{borrow::Cow, collections::BTreeMap};
+use std::borrow::Cow;
 
 use log::warn;
 use speedy_xml::reader::{self, Error as ParseError, Event, Reader};
 
+use super::Attributes;
+
 pub trait TreeBuilder {
     type Element;
     type Node;
 
-    fn create_element(
-        &mut self,
-        prefix: Option<&str>,
-        name: &str,
-        attributes: BTreeMap<String, String>,
-    ) -> Self::Element;
+    fn create_element(&mut self, prefix: Option<&str>, name: &str, attributes: Attributes) -> Self::Element;
     fn cdata_to_node(&mut self, content: &str) -> Self::Node;
     fn text_to_node(&mut self, content: Cow<str>) -> Self::Node;
     fn comment_to_node(&mut self, content: &str) -> Self::Node;
@@ -32,7 +29,7 @@ macro_rules! build_loop_match {
     ($builder: expr, $reader: expr, output = $output_where: ident($output: expr), End($end_name: tt) => $end: expr, Eof => $eof: expr) => {
         match $reader.next().transpose()? {
             Some(ref event @ (Event::Start(ref x) | Event::Empty(ref x))) => {
-                let mut attributes = BTreeMap::new();
+                let mut attributes = Attributes::new();
                 for attr in x.attributes() {
                     attributes.insert(
                         attr.name().to_owned(),
@@ -64,7 +61,8 @@ macro_rules! build_loop_match {
             Some(Event::Comment(comment)) => build_loop_match!(@output $builder,
                 $output_where $output, $builder.comment_to_node(comment.content())
             ),
-            Some(Event::Doctype(_)) => warn!("Ignoring XML doctype"),
+            // Not part of the tree, the apply pipeline copies it over separately if needed.
+            Some(Event::Doctype(_)) => (),
             None => $eof
         }
     }
@@ -1,6 +1,5 @@
 use std::{
     cell::{Ref, RefMut},
-    collections::BTreeMap,
     iter::FusedIterator,
     ops::Deref,
 };
@@ -10,7 +9,7 @@ use gc_arena::{
     Collect, Gc, Mutation,
 };
 
-use crate::xmltree;
+use crate::xmltree::{self, Attributes};
 
 #[derive(Debug, Collect, Clone, Copy, PartialEq, Eq)]
 #[repr(u8)]
@@ -115,6 +114,10 @@ impl<'gc, T: Node<'gc> + ?Sized> NodeExt<'gc> for T {
             }
         }
         header.parent = None;
+        // NOTE: An ElementChildren iterator that already moved past this node
+        //       is unaffected since it doesn't look at the current node again.
+        header.previous = None;
+        header.next = None;
     }
 
     // Unsafe because it create a Gc pointer for an arbitrary 'gc.
@@ -145,6 +148,8 @@ pub fn node_insert_before<'gc>(this: GcNode<'gc>, mc: &Mutation<'gc>, node: GcNo
         inserted_header.previous = Some(previous);
         inserted_header.next = Some(this);
     } else if let Some(parent) = header.parent {
+        // The header has to be released here since prepend_child will borrow it again.
+        drop(header);
         parent.borrow_mut(mc).prepend_child(mc, node);
     }
 }
@@ -160,6 +165,8 @@ pub fn node_insert_after<'gc>(this: GcNode<'gc>, mc: &Mutation<'gc>, node: GcNod
         inserted_header.previous = Some(this);
         inserted_header.next = Some(next);
     } else if let Some(parent) = header.parent {
+        // See node_insert_before
+        drop(header);
         parent.borrow_mut(mc).append_child(mc, node);
     }
 }
@@ -227,6 +234,7 @@ define_simple_node!(Comment, { content: String });
 pub type GcNode<'gc> = GcRefLock<'gc, dyn Node<'gc>>;
 pub type GcElement<'gc> = GcRefLock<'gc, Element<'gc>>;
 pub type GcText<'gc> = GcRefLock<'gc, Text<'gc>>;
+pub type GcComment<'gc> = GcRefLock<'gc, Comment<'gc>>;
 
 #[derive(Collect)]
 #[repr(C)]
@@ -306,7 +314,8 @@ pub struct Element<'gc> {
     _header: NodeHeader<'gc>,
     pub prefix: Option<String>,
     pub name: String,
-    pub attributes: BTreeMap<String, String>,
+    #[collect(require_static)]
+    pub attributes: Attributes,
     first_child: Option<GcNode<'gc>>,
     // NOTE: This will be None if the element has only one child
     // TODO: Reconsider above statement.
@@ -319,7 +328,7 @@ impl Clone for Element<'_> {
             _header: unsafe { NodeHeader::new(NodeKind::Element) },
             prefix: self.prefix.clone(),
             name: self.name.clone(),
-            attributes: BTreeMap::new(),
+            attributes: Attributes::new(),
             first_child: None,
             last_child: None,
         }
@@ -344,7 +353,7 @@ impl<'gc> Element<'gc> {
         mc: &Mutation<'gc>,
         prefix: Option<String>,
         name: String,
-        attributes: BTreeMap<String, String>,
+        attributes: Attributes,
     ) -> GcRefLock<'gc, Element<'gc>> {
         GcRefLock::new(
             mc,
@@ -536,17 +545,47 @@ pub fn to_tree(node: GcNode) -> xmltree::Node {
     }
 }
 
+/// Creates a detached copy of a node, elements are copied along with all their descendants if `deep` is true.
+pub fn clone_node<'gc>(mc: &Mutation<'gc>, node: GcNode<'gc>, deep: bool) -> GcNode<'gc> {
+    let node = node.borrow();
+    match node.kind() {
+        NodeKind::Element => unsize_node!(unsafe { Element::downcast_ref_unchecked(&*node) }.clone_detached(mc, deep)),
+        NodeKind::Comment => unsize_node!(Comment::create(
+            mc,
+            unsafe { Comment::downcast_ref_unchecked(&*node) }.content.clone()
+        )),
+        NodeKind::CData => unsize_node!(CData::create(
+            mc,
+            unsafe { CData::downcast_ref_unchecked(&*node) }.content.clone()
+        )),
+        NodeKind::Text => unsize_node!(Text::create(
+            mc,
+            unsafe { Text::downcast_ref_unchecked(&*node) }.content.clone()
+        )),
+    }
+}
+
+impl<'gc> Element<'gc> {
+    /// Creates a copy of this element with the same name and attributes but without a parent.
+    /// Children are only copied if `deep` is true.
+    pub fn clone_detached(&self, mc: &Mutation<'gc>, deep: bool) -> GcElement<'gc> {
+        let clone = Element::create(mc, self.prefix.clone(), self.name.clone(), self.attributes.clone());
+        if deep {
+            let mut borrowed = clone.unlock(mc).borrow_mut();
+            for child in self.children() {
+                borrowed.append_child(mc, clone_node(mc, child, true));
+            }
+        }
+        clone
+    }
+}
+
 struct DomTreeBuilder<'a, 'gc>(pub &'a Mutation<'gc>);
 impl<'gc> TreeBuilder for DomTreeBuilder<'_, 'gc> {
     type Element = GcElement<'gc>;
     type Node = GcNode<'gc>;
 
-    fn create_element(
-        &mut self,
-        prefix: Option<&str>,
-        name: &str,
-        attributes: BTreeMap<String, String>,
-    ) -> Self::Element {
+    fn create_element(&mut self, prefix: Option<&str>, name: &str, attributes: Attributes) -> Self::Element {
         Element::create(self.0, prefix.map(ToOwned::to_owned), name.to_owned(), attributes)
     }
 
@@ -592,10 +631,7 @@ impl TreeEmitter for DomTreeEmitter {
         Ref::map(element.borrow(), |e| e.name.as_str())
     }
 
-    fn element_attributes<'a>(
-        &self,
-        element: &Self::Element<'a>,
-    ) -> impl Deref<Target = std::collections::BTreeMap<std::string::String, std::string::String>> + 'a {
+    fn element_attributes<'a>(&self, element: &Self::Element<'a>) -> impl Deref<Target = Attributes> + 'a {
         Ref::map(element.borrow(), |element| &element.attributes)
     }
 
@@ -1,7 +1,9 @@
-use std::{collections::BTreeMap, io::Write, ops::Deref};
+use std::{io::Write, ops::Deref};
 
 use speedy_xml::writer::Writer;
 
+use super::Attributes;
+
 pub enum NodeContent<E, S> {
     Element(E),
     Text(S),
@@ -20,8 +22,7 @@ pub trait TreeEmitter {
     }
     fn element_prefix<'a>(&self, element: &Self::Element<'a>) -> Option<impl Deref<Target = str> + 'a>;
     fn element_name<'a>(&self, element: &Self::Element<'a>) -> impl Deref<Target = str> + 'a;
-    fn element_attributes<'a>(&self, element: &Self::Element<'a>)
-        -> impl Deref<Target = BTreeMap<String, String>> + 'a;
+    fn element_attributes<'a>(&self, element: &Self::Element<'a>) -> impl Deref<Target = Attributes> + 'a;
     fn node_to_content<'a>(
         &self,
         node: &Self::Node<'a>,